@@ -0,0 +1,4 @@
+-- Track row changes on dependencies like every other child table
+
+ALTER TABLE db_dependencies ALTER COLUMN created_at TYPE TIMESTAMPTZ;
+ALTER TABLE db_dependencies ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ DEFAULT NOW();
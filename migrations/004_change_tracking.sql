@@ -0,0 +1,28 @@
+-- Record which push last changed each row, and keep a log of deleted entities,
+-- so clients can pull only what changed since a given diagram version.
+
+ALTER TABLE db_tables ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+ALTER TABLE db_relationships ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+ALTER TABLE db_dependencies ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+ALTER TABLE areas ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+ALTER TABLE db_custom_types ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+ALTER TABLE notes ADD COLUMN IF NOT EXISTS diagram_version INTEGER;
+
+UPDATE db_tables t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+UPDATE db_relationships t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+UPDATE db_dependencies t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+UPDATE areas t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+UPDATE db_custom_types t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+UPDATE notes t SET diagram_version = COALESCE(d.version, 1) FROM diagrams d WHERE d.id = t.diagram_id;
+
+CREATE TABLE IF NOT EXISTS deleted_entities (
+    id BIGSERIAL PRIMARY KEY,
+    diagram_id TEXT NOT NULL REFERENCES diagrams(id) ON DELETE CASCADE,
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id TEXT NOT NULL,
+    diagram_version INTEGER NOT NULL,
+    deleted_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_deleted_entities_diagram_version ON deleted_entities(diagram_id, diagram_version);
+CREATE INDEX IF NOT EXISTS idx_deleted_entities_deleted_at ON deleted_entities(diagram_id, deleted_at);
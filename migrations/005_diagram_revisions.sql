@@ -0,0 +1,10 @@
+-- Snapshot of every pushed diagram version, used as the common ancestor when
+-- merging pushes made against an older version
+
+CREATE TABLE IF NOT EXISTS diagram_revisions (
+    diagram_id TEXT NOT NULL REFERENCES diagrams(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    snapshot JSONB NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (diagram_id, version)
+);
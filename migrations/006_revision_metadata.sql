@@ -0,0 +1,7 @@
+-- Who made each revision and why, for the revision history
+
+ALTER TABLE diagram_revisions ADD COLUMN IF NOT EXISTS author VARCHAR(255);
+ALTER TABLE diagram_revisions ADD COLUMN IF NOT EXISTS message TEXT;
+
+UPDATE diagram_revisions SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE diagram_revisions ALTER COLUMN created_at SET NOT NULL;
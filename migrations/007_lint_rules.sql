@@ -0,0 +1,12 @@
+-- Per-diagram changes to the defaults of the schema lint rules; NULL keeps
+-- the rule's default
+
+CREATE TABLE IF NOT EXISTS diagram_lint_rules (
+    diagram_id TEXT NOT NULL REFERENCES diagrams(id) ON DELETE CASCADE,
+    rule VARCHAR(64) NOT NULL,
+    enabled BOOLEAN,
+    severity VARCHAR(16),
+    options JSONB,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (diagram_id, rule)
+);
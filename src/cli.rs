@@ -0,0 +1,104 @@
+//! Command-line subcommands, run instead of the server when given:
+//!
+//! ```text
+//! chartdb-backend introspect <connection-string> [--diagram <id>] [--name <name>]
+//!                            [--schema <schema>]... [--author <author>] [--message <message>]
+//! chartdb-backend drift <diagram-id> <connection-string> [--schema <schema>]...
+//! ```
+
+use crate::extract::{Json, Path};
+use crate::handlers;
+use crate::models::{DriftRequest, IntrospectRequest};
+use anyhow::{anyhow, bail, Result};
+use axum::extract::State;
+use sqlx::PgPool;
+
+/// Runs the subcommand named by `args` (without the program name). Returns
+/// `Ok(false)` when there is none, so the server should start.
+pub async fn run(pool: &PgPool, args: &[String]) -> Result<bool> {
+    match args.first().map(String::as_str) {
+        Some("introspect") => introspect(pool, &args[1..]).await?,
+        Some("drift") => drift(pool, &args[1..]).await?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+async fn introspect(pool: &PgPool, args: &[String]) -> Result<()> {
+    let mut request = IntrospectRequest {
+        connection_string: String::new(),
+        diagram_id: None,
+        name: None,
+        schemas: Vec::new(),
+        author: None,
+        message: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--diagram" => request.diagram_id = Some(value()?),
+            "--name" => request.name = Some(value()?),
+            "--schema" => request.schemas.push(value()?),
+            "--author" => request.author = Some(value()?),
+            "--message" => request.message = Some(value()?),
+            flag if flag.starts_with("--") => bail!("Unknown option {}", flag),
+            _ if request.connection_string.is_empty() => request.connection_string = arg.clone(),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+    if request.connection_string.is_empty() {
+        bail!("Usage: chartdb-backend introspect <connection-string> [--diagram <id>] [--name <name>] [--schema <schema>]... [--author <author>] [--message <message>]");
+    }
+
+    match handlers::introspect_database(State(pool.clone()), Json(request)).await {
+        Ok((_, Json(response))) => {
+            println!("{}", serde_json::to_string_pretty(&response)?);
+            Ok(())
+        }
+        Err(error) => bail!("{} ({})", error, error.status()),
+    }
+}
+
+/// Prints the drift report and fails when the database has drifted, so that
+/// a scheduled job can alert on the exit status.
+async fn drift(pool: &PgPool, args: &[String]) -> Result<()> {
+    let mut positional = Vec::new();
+    let mut schemas = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schema" => schemas.push(
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{} needs a value", arg))?,
+            ),
+            flag if flag.starts_with("--") => bail!("Unknown option {}", flag),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let [diagram_id, connection_string] = <[String; 2]>::try_from(positional).map_err(|_| {
+        anyhow!(
+            "Usage: chartdb-backend drift <diagram-id> <connection-string> [--schema <schema>]..."
+        )
+    })?;
+    let request = DriftRequest {
+        connection_string,
+        schemas: (!schemas.is_empty()).then_some(schemas),
+    };
+
+    match handlers::check_drift(State(pool.clone()), Path(diagram_id), Json(request)).await {
+        Ok(Json(response)) => {
+            println!("{}", serde_json::to_string_pretty(&response)?);
+            if !response.report.in_sync {
+                bail!("The database has drifted from the diagram");
+            }
+            Ok(())
+        }
+        Err(error) => bail!("{} ({})", error, error.status()),
+    }
+}
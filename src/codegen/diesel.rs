@@ -0,0 +1,243 @@
+//! A Diesel `schema.rs` in the shape `diesel print-schema` writes: a
+//! `table!` per table, custom SQL types for PostgreSQL enums and unknown
+//! types, and `joinable!` for unambiguous foreign keys to primary keys.
+
+use super::{pascal_case, rust_identifier, File, Model, Scalar};
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Backend {
+    Postgres,
+    Mysql,
+    /// SQLite, and the generic SQL types for databases Diesel has no
+    /// backend for.
+    Sqlite,
+}
+
+pub(super) fn generate(model: &Model) -> Vec<File> {
+    let backend = match model.database_type().to_ascii_lowercase().as_str() {
+        "postgresql" | "cockroachdb" | "generic" => Backend::Postgres,
+        "mysql" | "mariadb" => Backend::Mysql,
+        _ => Backend::Sqlite,
+    };
+
+    let mut out = model.header("//");
+
+    // PostgreSQL types Diesel does not know get a marker type each.
+    let mut custom_types: Vec<String> = Vec::new();
+    if backend == Backend::Postgres {
+        for entity in &model.entities {
+            for field in &entity.fields {
+                if matches!(model.scalar(field), Scalar::Enum | Scalar::Other) {
+                    let name = field.type_name.clone();
+                    if !custom_types.contains(&name) {
+                        custom_types.push(name);
+                    }
+                }
+            }
+        }
+    }
+    if !custom_types.is_empty() {
+        out.push_str("\npub mod sql_types {\n");
+        for (i, name) in custom_types.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(
+                "    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]\n",
+            );
+            let _ = writeln!(out, "    #[diesel(postgres_type(name = {:?}))]", name);
+            let _ = writeln!(out, "    pub struct {};", pascal_case(name));
+        }
+        out.push_str("}\n");
+    }
+
+    let names: Vec<String> = model
+        .entities
+        .iter()
+        .map(|e| rust_identifier(&e.name))
+        .collect();
+    for (entity, name) in model.entities.iter().zip(&names) {
+        let used: Vec<&String> = custom_types
+            .iter()
+            .filter(|t| entity.fields.iter().any(|f| &f.type_name == *t))
+            .collect();
+        out.push_str("\ndiesel::table! {\n    use diesel::sql_types::*;\n");
+        for custom_type in &used {
+            let _ = writeln!(
+                out,
+                "    use super::sql_types::{};",
+                pascal_case(custom_type)
+            );
+        }
+        out.push('\n');
+
+        if let Some(comment) = entity.table.comment.as_deref().filter(|c| !c.is_empty()) {
+            for line in comment.lines() {
+                let _ = writeln!(out, "    /// {}", line);
+            }
+        }
+        if name != &entity.table.name {
+            let _ = writeln!(out, "    #[sql_name = {:?}]", entity.table.name);
+        }
+        let schema = match (backend, entity.table.schema.as_deref()) {
+            (Backend::Postgres, Some(schema)) if !schema.is_empty() && schema != "public" => {
+                format!("{}.", schema)
+            }
+            _ => String::new(),
+        };
+        let primary_key: Vec<_> = entity
+            .primary_key()
+            .iter()
+            .map(|f| rust_identifier(&f.name))
+            .collect();
+        let _ = writeln!(
+            out,
+            "    {}{} ({}) {{",
+            schema,
+            name,
+            primary_key.join(", ")
+        );
+        for field in &entity.fields {
+            if let Some(comment) = field.comment.as_deref().filter(|c| !c.is_empty()) {
+                for line in comment.lines() {
+                    let _ = writeln!(out, "        /// {}", line);
+                }
+            }
+            let column = rust_identifier(&field.name);
+            if column != field.name {
+                let _ = writeln!(out, "        #[sql_name = {:?}]", field.name);
+            }
+            let max_length = field
+                .character_maximum_length
+                .as_deref()
+                .filter(|l| l.parse::<u32>().is_ok());
+            if let (Some(length), Scalar::VarChar, Backend::Postgres | Backend::Mysql) =
+                (max_length, model.scalar(field), backend)
+            {
+                let _ = writeln!(out, "        #[max_length = {}]", length);
+            }
+            let _ = writeln!(
+                out,
+                "        {} -> {},",
+                column,
+                sql_type(model, field, backend)
+            );
+        }
+        out.push_str("    }\n}\n");
+    }
+
+    // `print-schema` only joins tables linked by a single foreign key to the
+    // referenced table's primary key.
+    let mut joinables = Vec::new();
+    for relation in &model.relations {
+        let to = &model.entities[relation.to];
+        let references_key = to.primary_key().len() == 1 && relation.to_field.primary_key;
+        if relation.is_self() || model.ambiguous(relation) || !references_key {
+            continue;
+        }
+        joinables.push(format!(
+            "diesel::joinable!({} -> {} ({}));",
+            names[relation.from],
+            names[relation.to],
+            rust_identifier(&relation.from_field.name)
+        ));
+    }
+    if !joinables.is_empty() {
+        out.push('\n');
+        for joinable in joinables {
+            let _ = writeln!(out, "{}", joinable);
+        }
+    }
+
+    if names.len() > 1 {
+        out.push_str("\ndiesel::allow_tables_to_appear_in_same_query!(\n");
+        for name in &names {
+            let _ = writeln!(out, "    {},", name);
+        }
+        out.push_str(");\n");
+    }
+
+    vec![File {
+        path: "schema.rs".to_string(),
+        contents: out,
+    }]
+}
+
+fn sql_type(model: &Model, field: &FieldInfo, backend: Backend) -> String {
+    let scalar = model.scalar(field);
+    let base = match (backend, scalar) {
+        (Backend::Postgres, Scalar::Enum | Scalar::Other) => pascal_case(&field.type_name),
+        (Backend::Postgres, Scalar::SmallInt) => "Int2".to_string(),
+        (Backend::Postgres, Scalar::Int) => "Int4".to_string(),
+        (Backend::Postgres, Scalar::BigInt) => "Int8".to_string(),
+        (Backend::Postgres, Scalar::Float) => "Float4".to_string(),
+        (Backend::Postgres, Scalar::Double) => "Float8".to_string(),
+        (Backend::Postgres, Scalar::VarChar) => {
+            match field.type_name.to_ascii_lowercase().as_str() {
+                "char" | "character" | "bpchar" => "Bpchar".to_string(),
+                _ => "Varchar".to_string(),
+            }
+        }
+        (Backend::Postgres, Scalar::Uuid) => "Uuid".to_string(),
+        (Backend::Postgres, Scalar::TimestampTz) => "Timestamptz".to_string(),
+        (Backend::Postgres, Scalar::Jsonb) => "Jsonb".to_string(),
+        (Backend::Postgres, Scalar::Bytes) => "Bytea".to_string(),
+        (Backend::Mysql, Scalar::Timestamp) => "Datetime".to_string(),
+        (Backend::Mysql, Scalar::Json | Scalar::Jsonb) => "Json".to_string(),
+        (Backend::Mysql, Scalar::Bytes) => "Blob".to_string(),
+        (Backend::Mysql, Scalar::VarChar) => "Varchar".to_string(),
+        (_, Scalar::SmallInt) => "SmallInt".to_string(),
+        (_, Scalar::Int) => "Integer".to_string(),
+        (_, Scalar::BigInt) => "BigInt".to_string(),
+        (_, Scalar::Bool) => "Bool".to_string(),
+        (_, Scalar::Float) => "Float".to_string(),
+        (_, Scalar::Double) => "Double".to_string(),
+        (Backend::Sqlite, Scalar::Decimal) => "Double".to_string(),
+        (_, Scalar::Decimal) => "Numeric".to_string(),
+        (_, Scalar::Date) => "Date".to_string(),
+        (_, Scalar::Time) => "Time".to_string(),
+        (_, Scalar::Timestamp | Scalar::TimestampTz) => "Timestamp".to_string(),
+        (Backend::Postgres, Scalar::Json) => "Json".to_string(),
+        (_, Scalar::Bytes) => "Binary".to_string(),
+        _ => "Text".to_string(),
+    };
+    let base = if field.is_array && backend == Backend::Postgres {
+        format!("Array<Nullable<{}>>", base)
+    } else {
+        base
+    };
+    if field.nullable && !field.primary_key {
+        format!("Nullable<{}>", base)
+    } else {
+        base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::{generate, tests::catalog, Target};
+
+    #[test]
+    fn generates_the_catalog() {
+        let files = generate(&catalog(), Target::Diesel);
+        let file = files.iter().find(|f| f.path == "schema.rs").unwrap();
+        for expected in [
+            "pub struct OrderStatus;",
+            r#"#[sql_name = "Line Items"]"#,
+            r#"#[sql_name = "Type"]"#,
+            "type_ -> Uuid,",
+            "tags -> Nullable<Array<Nullable<Text>>>,",
+            "diesel::joinable!(orders -> users (user_id));",
+            "email -> Nullable<Varchar>,",
+        ] {
+            assert!(
+                file.contents.contains(expected),
+                "{}\n{}",
+                expected,
+                file.contents
+            );
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! ORM model source generated from a diagram: Prisma schemas, Diesel
+//! `table!` macros, SeaORM entities and SQLAlchemy declarative models.
+//!
+//! Each target follows the output of its own schema tool (`prisma db pull`,
+//! `diesel print-schema`, `sea-orm-cli generate entity`, SQLAlchemy 2.0
+//! declarative mapping), so the files drop into a project that would
+//! otherwise generate them from a live database. Views are not generated,
+//! and neither are tables without a primary key, which none of the ORMs but
+//! Prisma can map; they are listed in a comment instead.
+
+mod diesel;
+mod prisma;
+mod seaorm;
+mod sqlalchemy;
+
+use crate::erd;
+use crate::models::{CustomType, Diagram, Table};
+use crate::schema::{self, FieldInfo, IndexInfo};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Prisma,
+    Diesel,
+    SeaOrm,
+    SqlAlchemy,
+}
+
+impl Target {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "prisma" => Some(Target::Prisma),
+            "diesel" => Some(Target::Diesel),
+            "seaorm" | "sea-orm" | "sea_orm" => Some(Target::SeaOrm),
+            "sqlalchemy" => Some(Target::SqlAlchemy),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Target::Prisma => "prisma",
+            Target::Diesel => "diesel",
+            Target::SeaOrm => "seaorm",
+            Target::SqlAlchemy => "sqlalchemy",
+        }
+    }
+}
+
+/// A generated source file, by its path inside the archive.
+pub struct File {
+    pub path: String,
+    pub contents: String,
+}
+
+pub fn generate(diagram: &Diagram, target: Target) -> Vec<File> {
+    let model = Model::new(diagram);
+    match target {
+        Target::Prisma => prisma::generate(&model),
+        Target::Diesel => diesel::generate(&model),
+        Target::SeaOrm => seaorm::generate(&model),
+        Target::SqlAlchemy => sqlalchemy::generate(&model),
+    }
+}
+
+/// Packs generated files into a zip archive.
+pub fn zip(files: &[File]) -> zip::result::ZipResult<Vec<u8>> {
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+    for file in files {
+        archive.start_file(file.path.as_str(), options)?;
+        archive.write_all(file.contents.as_bytes())?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+/// What the targets are generated from: the tables that can be mapped,
+/// the foreign keys between them and the enum types they use.
+struct Model<'a> {
+    diagram: &'a Diagram,
+    entities: Vec<Entity<'a>>,
+    relations: Vec<Relation<'a>>,
+    enums: Vec<Enum>,
+    /// Tables left out, by qualified name.
+    skipped: Vec<String>,
+    /// Several schemas are in use, so names need qualifying.
+    multi_schema: bool,
+}
+
+struct Entity<'a> {
+    table: &'a Table,
+    /// The table name, prefixed with its schema when another schema has a
+    /// table of the same name.
+    name: String,
+    fields: Vec<FieldInfo>,
+    indexes: Vec<IndexInfo>,
+}
+
+impl Entity<'_> {
+    fn primary_key(&self) -> Vec<&FieldInfo> {
+        self.fields.iter().filter(|f| f.primary_key).collect()
+    }
+
+    fn field(&self, id: &str) -> Option<&FieldInfo> {
+        self.fields.iter().find(|f| f.id == id)
+    }
+
+    /// Columns of an index, by name.
+    fn index_columns(&self, index: &IndexInfo) -> Vec<&str> {
+        index
+            .field_ids
+            .iter()
+            .filter_map(|id| self.field(id))
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+
+    /// Indexes other than those the primary key and unique columns already
+    /// create, with their columns.
+    fn secondary_indexes(&self) -> Vec<(&IndexInfo, Vec<&str>)> {
+        let primary_key: Vec<&str> = self.primary_key().iter().map(|f| f.name.as_str()).collect();
+        self.indexes
+            .iter()
+            .map(|index| (index, self.index_columns(index)))
+            .filter(|(index, columns)| {
+                let implied = match columns.as_slice() {
+                    [column] => self.fields.iter().any(|f| f.name == *column && f.unique),
+                    _ => false,
+                } || (columns.len() == primary_key.len()
+                    && columns.iter().all(|c| primary_key.contains(c)));
+                !(columns.is_empty() || index.unique && implied)
+            })
+            .collect()
+    }
+}
+
+/// A foreign key from `from` (the table holding it) to `to`.
+struct Relation<'a> {
+    name: Option<&'a str>,
+    from: usize,
+    from_field: FieldInfo,
+    to: usize,
+    to_field: FieldInfo,
+    /// The diagram says one-to-one and the key column is unique on its
+    /// own, so each row on the other side has at most one match.
+    one_to_one: bool,
+}
+
+impl Relation<'_> {
+    fn is_self(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+struct Enum {
+    name: String,
+    schema: Option<String>,
+    values: Vec<String>,
+}
+
+impl<'a> Model<'a> {
+    fn new(diagram: &'a Diagram) -> Self {
+        let tables = diagram.tables.as_deref().unwrap_or_default();
+        let mut skipped = Vec::new();
+        let mut mapped = Vec::new();
+        for table in tables {
+            let name = schema::qualified_name(table.schema.as_deref(), &table.name);
+            if table.is_view == Some(true) || table.is_materialized_view == Some(true) {
+                skipped.push(format!("{} (view)", name));
+            } else if !schema::fields(table).iter().any(|f| f.primary_key) {
+                skipped.push(format!("{} (no primary key)", name));
+            } else {
+                mapped.push(table);
+            }
+        }
+
+        let entities: Vec<_> = mapped
+            .iter()
+            .map(|table| {
+                let clashes = mapped.iter().filter(|t| t.name == table.name).count() > 1;
+                let name = match (&table.schema, clashes) {
+                    (Some(schema), true) => format!("{}_{}", schema, table.name),
+                    _ => table.name.clone(),
+                };
+                Entity {
+                    table,
+                    name,
+                    fields: schema::fields(table),
+                    indexes: schema::indexes(table),
+                }
+            })
+            .collect();
+
+        let position = |id: &str| entities.iter().position(|e| e.table.id == id);
+        let relations = diagram
+            .relationships
+            .iter()
+            .flatten()
+            .filter_map(|rel| {
+                let (holder, holder_field) = erd::foreign_key_end(rel)?;
+                let (other, other_field) = if holder == rel.source_table_id
+                    && Some(holder_field) == rel.source_field_id.as_deref()
+                {
+                    (&rel.target_table_id, rel.target_field_id.as_deref()?)
+                } else {
+                    (&rel.source_table_id, rel.source_field_id.as_deref()?)
+                };
+                let (from, to) = (position(holder)?, position(other)?);
+                let from_field = entities[from].field(holder_field)?.clone();
+                let unique = from_field.unique
+                    || (from_field.primary_key && entities[from].primary_key().len() == 1);
+                Some(Relation {
+                    name: rel.name.as_deref().filter(|n| !n.is_empty()),
+                    from,
+                    to,
+                    to_field: entities[to].field(other_field)?.clone(),
+                    one_to_one: unique
+                        && rel.source_cardinality.as_deref() != Some("many")
+                        && rel.target_cardinality.as_deref() != Some("many"),
+                    from_field,
+                })
+            })
+            .collect();
+
+        let enums = diagram
+            .custom_types
+            .iter()
+            .flatten()
+            .filter(|t| t.kind.as_deref().unwrap_or("enum") == "enum")
+            .map(|t: &CustomType| Enum {
+                name: t.r#type.clone(),
+                schema: t.schema.clone(),
+                values: schema::enum_values(t),
+            })
+            .collect();
+
+        let mut schemas: Vec<_> = entities
+            .iter()
+            .map(|e| e.table.schema.as_deref().unwrap_or_default())
+            .collect();
+        schemas.sort();
+        schemas.dedup();
+
+        Model {
+            diagram,
+            entities,
+            relations,
+            enums,
+            skipped,
+            multi_schema: schemas.len() > 1,
+        }
+    }
+
+    /// Whether two tables are joined by more than one relation, so that
+    /// each needs a name of its own.
+    fn ambiguous(&self, relation: &Relation) -> bool {
+        relation.is_self()
+            || self
+                .relations
+                .iter()
+                .filter(|r| {
+                    (r.from, r.to) == (relation.from, relation.to)
+                        || (r.from, r.to) == (relation.to, relation.from)
+                })
+                .count()
+                > 1
+    }
+
+    fn enum_of(&self, field: &FieldInfo) -> Option<&Enum> {
+        let name = field.type_name.as_str();
+        self.enums.iter().find(|e| e.name == name)
+    }
+
+    fn scalar(&self, field: &FieldInfo) -> Scalar {
+        if self.enum_of(field).is_some() {
+            return Scalar::Enum;
+        }
+        scalar(&field.type_name)
+    }
+
+    fn database_type(&self) -> &str {
+        &self.diagram.database_type
+    }
+
+    /// The leading comment of every generated file.
+    fn header(&self, comment: &str) -> String {
+        let mut out = format!(
+            "{} Generated by chartdb-backend from diagram \"{}\" (version {}).\n",
+            comment,
+            self.diagram.name,
+            self.diagram.version.unwrap_or_default()
+        );
+        if !self.skipped.is_empty() {
+            out.push_str(&format!("{} Not generated:\n", comment));
+            for name in &self.skipped {
+                out.push_str(&format!("{}   {}\n", comment, name));
+            }
+        }
+        out
+    }
+}
+
+/// The kinds of values the targets tell apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    SmallInt,
+    Int,
+    BigInt,
+    Bool,
+    Float,
+    Double,
+    Decimal,
+    /// Bounded strings (`varchar`, `char`).
+    VarChar,
+    Text,
+    Uuid,
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+    Json,
+    Jsonb,
+    Bytes,
+    Enum,
+    /// Anything else, mapped as text where the target allows.
+    Other,
+}
+
+fn scalar(type_name: &str) -> Scalar {
+    match type_name.to_ascii_lowercase().as_str() {
+        "smallint" | "int2" | "smallserial" | "serial2" | "tinyint" => Scalar::SmallInt,
+        "integer" | "int" | "int4" | "serial" | "serial4" | "mediumint" => Scalar::Int,
+        "bigint" | "int8" | "bigserial" | "serial8" => Scalar::BigInt,
+        "boolean" | "bool" | "bit" => Scalar::Bool,
+        "real" | "float4" => Scalar::Float,
+        "double precision" | "double" | "float8" | "float" => Scalar::Double,
+        "numeric" | "decimal" | "money" | "smallmoney" => Scalar::Decimal,
+        "varchar" | "character varying" | "char" | "character" | "nvarchar" | "nchar"
+        | "bpchar" => Scalar::VarChar,
+        "text" | "citext" | "tinytext" | "mediumtext" | "longtext" | "ntext" | "string"
+        | "clob" => Scalar::Text,
+        "uuid" | "uniqueidentifier" => Scalar::Uuid,
+        "date" => Scalar::Date,
+        "time" | "time without time zone" | "timetz" | "time with time zone" => Scalar::Time,
+        "timestamp"
+        | "timestamp without time zone"
+        | "datetime"
+        | "datetime2"
+        | "smalldatetime" => Scalar::Timestamp,
+        "timestamptz" | "timestamp with time zone" | "datetimeoffset" => Scalar::TimestampTz,
+        "json" => Scalar::Json,
+        "jsonb" => Scalar::Jsonb,
+        "bytea" | "blob" | "tinyblob" | "mediumblob" | "longblob" | "binary" | "varbinary"
+        | "image" => Scalar::Bytes,
+        _ => Scalar::Other,
+    }
+}
+
+/// A column default as the targets can express it.
+#[derive(Debug, PartialEq)]
+enum DefaultValue {
+    Now,
+    Bool(bool),
+    Number(String),
+    String(String),
+    /// Any other SQL expression, kept verbatim.
+    Expression(String),
+}
+
+fn default_value(field: &FieldInfo) -> Option<DefaultValue> {
+    let sql = field.default.as_deref()?.trim();
+    if sql.is_empty() || sql.eq_ignore_ascii_case("null") {
+        return None;
+    }
+    // ChartDB keeps PostgreSQL casts such as `'new'::order_status`.
+    let value = match sql.find("::") {
+        Some(cast) if sql.starts_with('\'') => &sql[..cast],
+        _ => sql,
+    };
+    let lower = value.to_ascii_lowercase();
+    Some(
+        if matches!(
+            lower.as_str(),
+            "now()" | "current_timestamp" | "current_timestamp()"
+        ) {
+            DefaultValue::Now
+        } else if lower == "true" || lower == "false" {
+            DefaultValue::Bool(lower == "true")
+        } else if value.parse::<f64>().is_ok() {
+            DefaultValue::Number(value.to_string())
+        } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            DefaultValue::String(value[1..value.len() - 1].replace("''", "'"))
+        } else {
+            DefaultValue::Expression(sql.to_string())
+        },
+    )
+}
+
+/// A name usable as an identifier in all targets: letters, digits and `_`,
+/// not starting with a digit.
+fn identifier(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && previous_lower {
+                out.push('_');
+            }
+            previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+            out.push(c.to_ascii_lowercase());
+        } else {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            previous_lower = false;
+        }
+    }
+    let out = out.trim_end_matches('_').to_string();
+    identifier(&out)
+}
+
+fn pascal_case(name: &str) -> String {
+    let out: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase());
+            first.into_iter().chain(chars).collect::<String>()
+        })
+        .collect();
+    if out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out
+    } else {
+        format!("_{}", out)
+    }
+}
+
+/// Makes `name` distinct from `taken` by numbering it, and records it.
+fn unique_name(name: String, taken: &mut Vec<String>) -> String {
+    let mut candidate = name.clone();
+    let mut n = 1;
+    while taken.contains(&candidate) {
+        n += 1;
+        candidate = format!("{}{}", name, n);
+    }
+    taken.push(candidate.clone());
+    candidate
+}
+
+/// A double-quoted string literal, escaped the same way in Rust, Python and
+/// Prisma.
+fn quoted(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+    "try", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+/// A snake_case Rust identifier for a column or table, with a trailing `_`
+/// for keywords as `diesel print-schema` does.
+fn rust_identifier(name: &str) -> String {
+    let name = snake_case(name);
+    if RUST_KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fixtures::{column, enumeration, field_mut, key, shop, table};
+
+    /// `shop()` plus a table using an enum, an array and defaults, with
+    /// names that need mapping, and a table that cannot be generated.
+    pub fn catalog() -> Diagram {
+        let mut diagram = shop();
+        diagram.custom_types = Some(vec![enumeration(
+            "c_status",
+            "order status",
+            &["new", "paid"],
+        )]);
+        let tables = diagram.tables.as_mut().unwrap();
+        tables.push(table(
+            "t_totals",
+            "totals",
+            &[column("f_totals_sum", "sum", "numeric")],
+        ));
+        tables.push(table(
+            "t_items",
+            "Line Items",
+            &[
+                key("f_items_type", "Type", "uuid"),
+                column("f_items_status", "status", "order status"),
+                column("f_items_tags", "tags", "text[]"),
+                column("f_items_created", "created", "timestamptz"),
+            ],
+        ));
+        field_mut(&mut diagram, "t_items", "f_items_status").default =
+            Some("'new'::\"order status\"".to_string());
+        field_mut(&mut diagram, "t_items", "f_items_created").default = Some("now()".to_string());
+        diagram
+    }
+
+    fn contents(files: &[File], path: &str) -> String {
+        files
+            .iter()
+            .find(|f| f.path == path)
+            .map(|f| f.contents.clone())
+            .unwrap_or_else(|| panic!("no {}", path))
+    }
+
+    #[test]
+    fn every_file_lists_what_was_not_generated() {
+        let mut diagram = catalog();
+        let mut view = table(
+            "t_view",
+            "paid_orders",
+            &[key("f_view_id", "id", "integer")],
+        );
+        view.is_view = Some(true);
+        diagram.tables.as_mut().unwrap().push(view);
+
+        for target in [
+            Target::Prisma,
+            Target::Diesel,
+            Target::SeaOrm,
+            Target::SqlAlchemy,
+        ] {
+            for file in generate(&diagram, target) {
+                assert!(
+                    file.contents.contains("public.totals (no primary key)\n")
+                        && file.contents.contains("public.paid_orders (view)\n"),
+                    "{}",
+                    file.path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn archives_hold_every_file() {
+        let files = generate(&shop(), Target::SeaOrm);
+        let archive = zip(&files).unwrap();
+        assert!(archive.starts_with(b"PK"));
+        for file in &files {
+            let name = file.path.as_bytes();
+            assert!(
+                archive.windows(name.len()).any(|w| w == name),
+                "{}",
+                file.path
+            );
+        }
+        assert!(contents(&files, "entities/prelude.rs").contains("Orders"));
+    }
+
+    #[test]
+    fn defaults_are_classified() {
+        let default = |sql: &str| {
+            default_value(&FieldInfo {
+                default: Some(sql.to_string()),
+                ..FieldInfo::default()
+            })
+        };
+        assert_eq!(default("now()"), Some(DefaultValue::Now));
+        assert_eq!(default("CURRENT_TIMESTAMP"), Some(DefaultValue::Now));
+        assert_eq!(default("false"), Some(DefaultValue::Bool(false)));
+        assert_eq!(default("-1.5"), Some(DefaultValue::Number("-1.5".into())));
+        assert_eq!(
+            default("'it''s'::text"),
+            Some(DefaultValue::String("it's".into()))
+        );
+        assert_eq!(
+            default("gen_random_uuid()"),
+            Some(DefaultValue::Expression("gen_random_uuid()".into()))
+        );
+        assert_eq!(default("NULL"), None);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(snake_case("OrderItems"), "order_items");
+        assert_eq!(snake_case("Line Items"), "line_items");
+        assert_eq!(snake_case("2fa codes"), "_2fa_codes");
+        assert_eq!(pascal_case("order_items"), "OrderItems");
+        assert_eq!(pascal_case("2fa"), "_2fa");
+        assert_eq!(rust_identifier("Type"), "type_");
+        assert_eq!(identifier("a-b c"), "a_b_c");
+        assert_eq!(quoted("say \"hi\"\n"), r#""say \"hi\"\n""#);
+
+        let mut taken = Vec::new();
+        assert_eq!(unique_name("users".into(), &mut taken), "users");
+        assert_eq!(unique_name("users".into(), &mut taken), "users2");
+    }
+
+    #[test]
+    fn targets_parse() {
+        assert_eq!(Target::parse("Sea-ORM"), Some(Target::SeaOrm));
+        assert_eq!(Target::parse("django"), None);
+        for target in [
+            Target::Prisma,
+            Target::Diesel,
+            Target::SeaOrm,
+            Target::SqlAlchemy,
+        ] {
+            assert_eq!(Target::parse(target.as_str()), Some(target));
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! A Prisma schema in the shape `prisma db pull` writes: models and enums
+//! named after their tables and types, with `@map` where a name is not a
+//! valid Prisma identifier.
+
+use super::{default_value, identifier, quoted, unique_name, DefaultValue, File, Model, Scalar};
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+pub(super) fn generate(model: &Model) -> Vec<File> {
+    let provider = match model.database_type().to_ascii_lowercase().as_str() {
+        "mysql" | "mariadb" => "mysql",
+        "sqlite" => "sqlite",
+        "sql_server" => "sqlserver",
+        "cockroachdb" => "cockroachdb",
+        _ => "postgresql",
+    };
+    let postgres = matches!(provider, "postgresql" | "cockroachdb");
+    let mut schemas: Vec<&str> = model
+        .entities
+        .iter()
+        .filter_map(|e| e.table.schema.as_deref())
+        .collect();
+    schemas.sort();
+    schemas.dedup();
+
+    let mut out = model.header("//");
+    out.push_str("\ngenerator client {\n  provider = \"prisma-client-js\"\n");
+    if model.multi_schema {
+        out.push_str("  previewFeatures = [\"multiSchema\"]\n");
+    }
+    let _ = writeln!(
+        out,
+        "}}\n\ndatasource db {{\n  provider = {}\n  url      = env(\"DATABASE_URL\")",
+        quoted(provider)
+    );
+    if model.multi_schema {
+        let list: Vec<_> = schemas.iter().map(|s| quoted(s)).collect();
+        let _ = writeln!(out, "  schemas  = [{}]", list.join(", "));
+    }
+    out.push_str("}\n");
+
+    // Relation fields, named after the model on the other side; a pair of
+    // tables joined more than once names them after the foreign key column.
+    let mut taken: Vec<Vec<String>> = model
+        .entities
+        .iter()
+        .map(|e| e.fields.iter().map(|f| identifier(&f.name)).collect())
+        .collect();
+    let mut relation_fields: Vec<Vec<[String; 3]>> = vec![Vec::new(); model.entities.len()];
+    for relation in &model.relations {
+        let from = identifier(&model.entities[relation.from].name);
+        let to = identifier(&model.entities[relation.to].name);
+        let column = identifier(&relation.from_field.name);
+        let ambiguous = model.ambiguous(relation);
+        let relation_name = if ambiguous {
+            let name = relation
+                .name
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{}_{}", from, column));
+            format!("{}, ", quoted(&name))
+        } else {
+            String::new()
+        };
+        let (forward, back) = if ambiguous {
+            (format!("{}_{}", to, column), format!("{}_{}", from, column))
+        } else {
+            (to.clone(), from.clone())
+        };
+        let forward = unique_name(forward, &mut taken[relation.from]);
+        let back = unique_name(back, &mut taken[relation.to]);
+
+        let mut attributes = format!(
+            "@relation({}fields: [{}], references: [{}]",
+            relation_name,
+            column,
+            identifier(&relation.to_field.name)
+        );
+        if let Some(name) = relation.name {
+            let _ = write!(attributes, ", map: {}", quoted(name));
+        }
+        attributes.push(')');
+        relation_fields[relation.from].push([
+            forward,
+            format!(
+                "{}{}",
+                to,
+                if relation.from_field.nullable {
+                    "?"
+                } else {
+                    ""
+                }
+            ),
+            attributes,
+        ]);
+
+        let back_type = if relation.one_to_one {
+            format!("{}?", from)
+        } else {
+            format!("{}[]", from)
+        };
+        let back_attributes = if ambiguous {
+            format!("@relation({})", relation_name.trim_end_matches(", "))
+        } else {
+            String::new()
+        };
+        relation_fields[relation.to].push([back, back_type, back_attributes]);
+    }
+
+    for (entity, relations) in model.entities.iter().zip(&relation_fields) {
+        let name = identifier(&entity.name);
+        let _ = writeln!(out, "\nmodel {} {{", name);
+        let mut lines: Vec<[String; 3]> = Vec::new();
+        let primary_key = entity.primary_key();
+        for field in &entity.fields {
+            let mut attributes = Vec::new();
+            if primary_key.len() == 1 && field.primary_key {
+                attributes.push("@id".to_string());
+            }
+            if let Some(default) = default(model, field) {
+                attributes.push(format!("@default({})", default));
+            }
+            if field.unique && !field.primary_key {
+                attributes.push("@unique".to_string());
+            }
+            if identifier(&field.name) != field.name {
+                attributes.push(format!("@map({})", quoted(&field.name)));
+            }
+            if postgres {
+                attributes.extend(native_type(model, field));
+            }
+            lines.push([
+                identifier(&field.name),
+                field_type(model, field),
+                attributes.join(" "),
+            ]);
+        }
+        lines.extend(relations.iter().cloned());
+        let name_width = lines.iter().map(|l| l[0].len()).max().unwrap_or_default();
+        let type_width = lines.iter().map(|l| l[1].len()).max().unwrap_or_default();
+        for [field, type_name, attributes] in &lines {
+            let line = format!(
+                "  {:name_width$} {:type_width$} {}",
+                field,
+                type_name,
+                attributes,
+                name_width = name_width,
+                type_width = type_width
+            );
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+
+        let mut block = Vec::new();
+        if primary_key.len() > 1 {
+            let columns: Vec<_> = primary_key.iter().map(|f| identifier(&f.name)).collect();
+            block.push(format!("@@id([{}])", columns.join(", ")));
+        }
+        for (index, columns) in entity.secondary_indexes() {
+            let columns: Vec<_> = columns.iter().map(|c| identifier(c)).collect();
+            block.push(format!(
+                "@@{}([{}], map: {})",
+                if index.unique { "unique" } else { "index" },
+                columns.join(", "),
+                quoted(&index.name)
+            ));
+        }
+        if name != entity.table.name {
+            block.push(format!("@@map({})", quoted(&entity.table.name)));
+        }
+        if model.multi_schema {
+            if let Some(schema) = &entity.table.schema {
+                block.push(format!("@@schema({})", quoted(schema)));
+            }
+        }
+        if !block.is_empty() {
+            out.push('\n');
+            for line in block {
+                let _ = writeln!(out, "  {}", line);
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    for e in &model.enums {
+        let name = identifier(&e.name);
+        let _ = writeln!(out, "\nenum {} {{", name);
+        for value in &e.values {
+            let variant = identifier(value);
+            if &variant == value {
+                let _ = writeln!(out, "  {}", variant);
+            } else {
+                let _ = writeln!(out, "  {} @map({})", variant, quoted(value));
+            }
+        }
+        if name != e.name {
+            let _ = writeln!(out, "\n  @@map({})", quoted(&e.name));
+        }
+        if model.multi_schema {
+            if let Some(schema) = &e.schema {
+                let _ = writeln!(out, "  @@schema({})", quoted(schema));
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    vec![File {
+        path: "schema.prisma".to_string(),
+        contents: out,
+    }]
+}
+
+fn field_type(model: &Model, field: &FieldInfo) -> String {
+    let base = match model.scalar(field) {
+        Scalar::SmallInt | Scalar::Int => "Int".to_string(),
+        Scalar::BigInt => "BigInt".to_string(),
+        Scalar::Bool => "Boolean".to_string(),
+        Scalar::Float | Scalar::Double => "Float".to_string(),
+        Scalar::Decimal => "Decimal".to_string(),
+        Scalar::VarChar | Scalar::Text | Scalar::Uuid => "String".to_string(),
+        Scalar::Date | Scalar::Time | Scalar::Timestamp | Scalar::TimestampTz => {
+            "DateTime".to_string()
+        }
+        Scalar::Json | Scalar::Jsonb => "Json".to_string(),
+        Scalar::Bytes => "Bytes".to_string(),
+        Scalar::Enum => model
+            .enum_of(field)
+            .map(|e| identifier(&e.name))
+            .unwrap_or_default(),
+        Scalar::Other => format!("Unsupported({})", quoted(&field.element_type_sql())),
+    };
+    if field.is_array {
+        format!("{}[]", base)
+    } else if field.nullable && !field.primary_key {
+        format!("{}?", base)
+    } else {
+        base
+    }
+}
+
+/// The PostgreSQL `@db.` attribute `prisma db pull` adds where the column
+/// type is not the default for its Prisma type.
+fn native_type(model: &Model, field: &FieldInfo) -> Option<String> {
+    let length = field.character_maximum_length.as_deref();
+    let native = match model.scalar(field) {
+        Scalar::SmallInt => "SmallInt".to_string(),
+        Scalar::Float => "Real".to_string(),
+        Scalar::Decimal => match (field.precision, field.scale) {
+            (Some(p), Some(s)) => format!("Decimal({}, {})", p, s),
+            (Some(p), None) => format!("Decimal({}, 0)", p),
+            _ => return None,
+        },
+        Scalar::VarChar => {
+            let kind = match field.type_name.to_ascii_lowercase().as_str() {
+                "char" | "character" | "bpchar" | "nchar" => "Char",
+                _ => "VarChar",
+            };
+            match length {
+                Some(length) => format!("{}({})", kind, length),
+                None => kind.to_string(),
+            }
+        }
+        Scalar::Uuid => "Uuid".to_string(),
+        Scalar::Date => "Date".to_string(),
+        Scalar::Time => "Time(6)".to_string(),
+        Scalar::Timestamp => "Timestamp(6)".to_string(),
+        Scalar::TimestampTz => "Timestamptz(6)".to_string(),
+        Scalar::Json => "Json".to_string(),
+        _ => return None,
+    };
+    Some(format!("@db.{}", native))
+}
+
+fn default(model: &Model, field: &FieldInfo) -> Option<String> {
+    let serial = matches!(
+        field.type_name.to_ascii_lowercase().as_str(),
+        "serial" | "bigserial" | "smallserial" | "serial2" | "serial4" | "serial8"
+    );
+    if field.increment || serial {
+        return Some("autoincrement()".to_string());
+    }
+    Some(match default_value(field)? {
+        DefaultValue::Now => "now()".to_string(),
+        DefaultValue::Bool(value) => value.to_string(),
+        DefaultValue::Number(value) => value,
+        DefaultValue::String(value) if model.enum_of(field).is_some() => identifier(&value),
+        DefaultValue::String(value) => quoted(&value),
+        DefaultValue::Expression(sql) => format!("dbgenerated({})", quoted(&sql)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::{generate, tests::catalog, Target};
+
+    #[test]
+    fn generates_the_catalog() {
+        let files = generate(&catalog(), Target::Prisma);
+        let file = files.iter().find(|f| f.path == "schema.prisma").unwrap();
+        for expected in [
+            "model users {",
+            "  email  String?  @db.VarChar(255)",
+            "  users   users?   @relation(fields: [user_id], references: [id])",
+            r#"@@index([user_id], map: "orders_user_id")"#,
+            "  status  order_status? @default(new)",
+            r#"@@map("Line Items")"#,
+            "enum order_status {",
+            r#"@@map("order status")"#,
+        ] {
+            assert!(
+                file.contents.contains(expected),
+                "{}\n{}",
+                expected,
+                file.contents
+            );
+        }
+    }
+}
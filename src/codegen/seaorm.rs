@@ -0,0 +1,349 @@
+//! SeaORM entities in the shape `sea-orm-cli generate entity` writes: a
+//! module per table with its `Model`, `Relation` and `Related` impls, a
+//! prelude, and active enums for the custom enum types.
+
+use super::{pascal_case, quoted, rust_identifier, unique_name, File, Model, Scalar};
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+pub(super) fn generate(model: &Model) -> Vec<File> {
+    let modules: Vec<String> = model
+        .entities
+        .iter()
+        .map(|e| rust_identifier(&e.name))
+        .collect();
+    let mut files = Vec::new();
+
+    let mut root = model.header("//!");
+    root.push_str("\npub mod prelude;\n\n");
+    for module in &modules {
+        let _ = writeln!(root, "pub mod {};", module);
+    }
+    if !model.enums.is_empty() {
+        root.push_str("pub mod sea_orm_active_enums;\n");
+    }
+    files.push(File {
+        path: "entities/mod.rs".to_string(),
+        contents: root,
+    });
+
+    let mut prelude = model.header("//!");
+    prelude.push('\n');
+    for module in &modules {
+        let _ = writeln!(
+            prelude,
+            "pub use super::{}::Entity as {};",
+            module,
+            pascal_case(module)
+        );
+    }
+    files.push(File {
+        path: "entities/prelude.rs".to_string(),
+        contents: prelude,
+    });
+
+    if !model.enums.is_empty() {
+        let mut out = model.header("//!");
+        out.push_str("\nuse sea_orm::entity::prelude::*;\n");
+        for e in &model.enums {
+            out.push_str("\n#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]\n");
+            let _ = writeln!(
+                out,
+                "#[sea_orm(rs_type = \"String\", db_type = \"Enum\", enum_name = {})]",
+                quoted(&e.name)
+            );
+            let _ = writeln!(out, "pub enum {} {{", pascal_case(&e.name));
+            let mut variants = Vec::new();
+            for value in &e.values {
+                let _ = writeln!(out, "    #[sea_orm(string_value = {})]", quoted(value));
+                let _ = writeln!(
+                    out,
+                    "    {},",
+                    unique_name(pascal_case(value), &mut variants)
+                );
+            }
+            out.push_str("}\n");
+        }
+        files.push(File {
+            path: "entities/sea_orm_active_enums.rs".to_string(),
+            contents: out,
+        });
+    }
+
+    for (index, (entity, module)) in model.entities.iter().zip(&modules).enumerate() {
+        let mut out = model.header("//!");
+        out.push_str("\nuse sea_orm::entity::prelude::*;\n");
+        let mut enums: Vec<String> = entity
+            .fields
+            .iter()
+            .filter_map(|f| model.enum_of(f))
+            .map(|e| pascal_case(&e.name))
+            .collect();
+        enums.sort();
+        enums.dedup();
+        if !enums.is_empty() {
+            let _ = writeln!(
+                out,
+                "use super::sea_orm_active_enums::{};",
+                if enums.len() == 1 {
+                    enums[0].clone()
+                } else {
+                    format!("{{{}}}", enums.join(", "))
+                }
+            );
+        }
+
+        let floats = entity
+            .fields
+            .iter()
+            .any(|f| matches!(model.scalar(f), Scalar::Float | Scalar::Double));
+        let _ = writeln!(
+            out,
+            "\n#[derive(Clone, Debug, PartialEq, DeriveEntityModel{})]",
+            if floats { "" } else { ", Eq" }
+        );
+        match entity.table.schema.as_deref().filter(|s| !s.is_empty()) {
+            Some(schema) => {
+                let _ = writeln!(
+                    out,
+                    "#[sea_orm(schema_name = {}, table_name = {})]",
+                    quoted(schema),
+                    quoted(&entity.table.name)
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    out,
+                    "#[sea_orm(table_name = {})]",
+                    quoted(&entity.table.name)
+                );
+            }
+        }
+        out.push_str("pub struct Model {\n");
+        let composite = entity.primary_key().len() > 1;
+        for field in &entity.fields {
+            if let Some(comment) = field.comment.as_deref().filter(|c| !c.is_empty()) {
+                for line in comment.lines() {
+                    let _ = writeln!(out, "    /// {}", line);
+                }
+            }
+            let name = rust_identifier(&field.name);
+            let mut attributes = Vec::new();
+            if field.primary_key {
+                attributes.push("primary_key".to_string());
+                let integer = matches!(
+                    model.scalar(field),
+                    Scalar::SmallInt | Scalar::Int | Scalar::BigInt
+                );
+                if composite || !integer {
+                    attributes.push("auto_increment = false".to_string());
+                }
+            }
+            if name != field.name {
+                attributes.push(format!("column_name = {}", quoted(&field.name)));
+            }
+            if let Some(column_type) = column_type(model, field) {
+                attributes.push(format!("column_type = {}", quoted(&column_type)));
+            }
+            if field.unique && !field.primary_key {
+                attributes.push("unique".to_string());
+            }
+            if !attributes.is_empty() {
+                let _ = writeln!(out, "    #[sea_orm({})]", attributes.join(", "));
+            }
+            let _ = writeln!(out, "    pub {}: {},", name, rust_type(model, field));
+        }
+        out.push_str("}\n");
+
+        // Variants and the entity each one relates to; `Related` is only
+        // implemented where a single variant leads to an entity.
+        let mut variants: Vec<(String, String, Option<usize>)> = Vec::new();
+        let mut taken = Vec::new();
+        for relation in model.relations.iter().filter(|r| r.from == index) {
+            let (target, variant) = if relation.is_self() {
+                ("Entity".to_string(), "SelfRef".to_string())
+            } else {
+                let target_module = &modules[relation.to];
+                let variant = if model.ambiguous(relation) {
+                    format!(
+                        "{}{}",
+                        pascal_case(target_module),
+                        pascal_case(&rust_identifier(&relation.from_field.name))
+                    )
+                } else {
+                    pascal_case(target_module)
+                };
+                (format!("super::{}::Entity", target_module), variant)
+            };
+            let column = |m: Option<&str>, f: &FieldInfo| {
+                let column = pascal_case(&rust_identifier(&f.name));
+                match m {
+                    Some(m) => format!("super::{}::Column::{}", m, column),
+                    None => format!("Column::{}", column),
+                }
+            };
+            let to_module = (!relation.is_self()).then(|| modules[relation.to].as_str());
+            let attribute = format!(
+                "    #[sea_orm(\n        belongs_to = {},\n        from = {},\n        to = {},\n        on_update = \"NoAction\",\n        on_delete = \"NoAction\"\n    )]",
+                quoted(&target),
+                quoted(&column(None, &relation.from_field)),
+                quoted(&column(to_module, &relation.to_field))
+            );
+            let variant = unique_name(variant, &mut taken);
+            variants.push((
+                format!("{}\n    {},", attribute, variant),
+                variant,
+                (!relation.is_self()).then_some(relation.to),
+            ));
+        }
+        // The other side of a foreign key, unless the two tables are
+        // joined more than once and it would be unclear which key it uses.
+        for relation in model
+            .relations
+            .iter()
+            .filter(|r| r.to == index && !r.is_self() && !model.ambiguous(r))
+        {
+            let source = &modules[relation.from];
+            let kind = if relation.one_to_one {
+                "has_one"
+            } else {
+                "has_many"
+            };
+            let variant = unique_name(pascal_case(source), &mut taken);
+            variants.push((
+                format!(
+                    "    #[sea_orm({} = {})]\n    {},",
+                    kind,
+                    quoted(&format!("super::{}::Entity", source)),
+                    variant
+                ),
+                variant,
+                Some(relation.from),
+            ));
+        }
+
+        out.push_str("\n#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]\n");
+        if variants.is_empty() {
+            out.push_str("pub enum Relation {}\n");
+        } else {
+            out.push_str("pub enum Relation {\n");
+            for (definition, _, _) in &variants {
+                let _ = writeln!(out, "{}", definition);
+            }
+            out.push_str("}\n");
+        }
+
+        for (_, variant, target) in &variants {
+            let Some(target) = target else {
+                continue;
+            };
+            if variants
+                .iter()
+                .filter(|(_, _, t)| t == &Some(*target))
+                .count()
+                > 1
+            {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "\nimpl Related<super::{}::Entity> for Entity {{\n    fn to() -> RelationDef {{\n        Relation::{}.def()\n    }}\n}}",
+                modules[*target], variant
+            );
+        }
+
+        out.push_str("\nimpl ActiveModelBehavior for ActiveModel {}\n");
+        files.push(File {
+            path: format!("entities/{}.rs", module),
+            contents: out,
+        });
+    }
+    files
+}
+
+fn rust_type(model: &Model, field: &FieldInfo) -> String {
+    let base = match model.scalar(field) {
+        Scalar::SmallInt => "i16".to_string(),
+        Scalar::Int => "i32".to_string(),
+        Scalar::BigInt => "i64".to_string(),
+        Scalar::Bool => "bool".to_string(),
+        Scalar::Float => "f32".to_string(),
+        Scalar::Double => "f64".to_string(),
+        Scalar::Decimal => "Decimal".to_string(),
+        Scalar::VarChar | Scalar::Text | Scalar::Other => "String".to_string(),
+        Scalar::Uuid => "Uuid".to_string(),
+        Scalar::Date => "Date".to_string(),
+        Scalar::Time => "Time".to_string(),
+        Scalar::Timestamp => "DateTime".to_string(),
+        Scalar::TimestampTz => "DateTimeWithTimeZone".to_string(),
+        Scalar::Json | Scalar::Jsonb => "Json".to_string(),
+        Scalar::Bytes => "Vec<u8>".to_string(),
+        Scalar::Enum => model
+            .enum_of(field)
+            .map(|e| pascal_case(&e.name))
+            .unwrap_or_default(),
+    };
+    let base = if field.is_array {
+        format!("Vec<{}>", base)
+    } else {
+        base
+    };
+    if field.nullable && !field.primary_key {
+        format!("Option<{}>", base)
+    } else {
+        base
+    }
+}
+
+/// The `column_type` attribute where the Rust type alone does not give the
+/// column type.
+fn column_type(model: &Model, field: &FieldInfo) -> Option<String> {
+    if field.is_array {
+        return None;
+    }
+    match model.scalar(field) {
+        Scalar::VarChar => Some(
+            match field
+                .character_maximum_length
+                .as_deref()
+                .and_then(|l| l.parse::<u32>().ok())
+            {
+                Some(length) => format!("String(StringLen::N({}))", length),
+                None => "String(StringLen::None)".to_string(),
+            },
+        ),
+        Scalar::Text => Some("Text".to_string()),
+        Scalar::Decimal => Some(match (field.precision, field.scale) {
+            (Some(p), s) => format!("Decimal(Some(({}, {})))", p, s.unwrap_or_default()),
+            _ => "Decimal(None)".to_string(),
+        }),
+        Scalar::Jsonb => Some("JsonBinary".to_string()),
+        Scalar::Other => Some(format!("custom({})", quoted(&field.type_name))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::{generate, tests::catalog, Target};
+
+    #[test]
+    fn generates_the_catalog() {
+        let files = generate(&catalog(), Target::SeaOrm);
+        let file = files
+            .iter()
+            .find(|f| f.path == "entities/line_items.rs")
+            .unwrap();
+        for expected in [
+            r#"#[sea_orm(primary_key, auto_increment = false, column_name = "Type")]"#,
+            "pub type_: Uuid,",
+        ] {
+            assert!(
+                file.contents.contains(expected),
+                "{}\n{}",
+                expected,
+                file.contents
+            );
+        }
+    }
+}
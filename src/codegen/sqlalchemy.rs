@@ -0,0 +1,407 @@
+//! SQLAlchemy 2.0 declarative models in one `models.py`, with typed
+//! `Mapped` columns, `relationship()` pairs for each foreign key and a
+//! Python `enum.Enum` per custom enum type.
+
+use super::{identifier, pascal_case, quoted, snake_case, unique_name, File, Model, Scalar};
+use crate::schema::FieldInfo;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield", "metadata", "registry",
+];
+
+/// An attribute name for a column: the column name where it is a valid
+/// Python identifier that does not clash with a keyword or with the
+/// attributes `DeclarativeBase` defines.
+fn attribute(name: &str) -> String {
+    let name = identifier(name);
+    if PYTHON_KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+/// The imports a module ends up needing.
+#[derive(Default)]
+struct Imports {
+    modules: BTreeSet<&'static str>,
+    typing: BTreeSet<&'static str>,
+    sqlalchemy: BTreeSet<&'static str>,
+    postgresql: BTreeSet<&'static str>,
+}
+
+pub(super) fn generate(model: &Model) -> Vec<File> {
+    let mut imports = Imports::default();
+    let classes: Vec<String> = model
+        .entities
+        .iter()
+        .map(|e| pascal_case(&e.name))
+        .collect();
+    let mut body = String::new();
+
+    for e in &model.enums {
+        imports.modules.insert("enum");
+        let _ = writeln!(body, "\n\nclass {}(enum.Enum):", pascal_case(&e.name));
+        if e.values.is_empty() {
+            body.push_str("    pass\n");
+        }
+        let mut members = Vec::new();
+        for value in &e.values {
+            let member = unique_name(attribute(value), &mut members);
+            let _ = writeln!(body, "    {} = {}", member, quoted(value));
+        }
+    }
+
+    // Relationship attributes, named after the class on the other side; a
+    // pair of tables joined more than once names them after the column.
+    let mut taken: Vec<Vec<String>> = model
+        .entities
+        .iter()
+        .map(|e| e.fields.iter().map(|f| attribute(&f.name)).collect())
+        .collect();
+    let mut relationships: Vec<Vec<String>> = vec![Vec::new(); model.entities.len()];
+    for relation in &model.relations {
+        let from = snake_case(&model.entities[relation.from].name);
+        let to = snake_case(&model.entities[relation.to].name);
+        let column = attribute(&relation.from_field.name);
+        let ambiguous = model.ambiguous(relation);
+        let (forward, back) = if ambiguous {
+            (format!("{}_{}", to, column), format!("{}_{}", from, column))
+        } else {
+            (to, from)
+        };
+        let forward = unique_name(forward, &mut taken[relation.from]);
+        let back = unique_name(back, &mut taken[relation.to]);
+
+        let target = &classes[relation.to];
+        let mut arguments = vec![format!("back_populates={}", quoted(&back))];
+        if ambiguous {
+            arguments.push(format!("foreign_keys=[{}]", column));
+        }
+        if relation.is_self() {
+            arguments.push(format!(
+                "remote_side=[{}]",
+                attribute(&relation.to_field.name)
+            ));
+        }
+        let annotation = if relation.from_field.nullable {
+            imports.typing.insert("Optional");
+            format!("Optional[{}]", quoted(target))
+        } else {
+            quoted(target)
+        };
+        relationships[relation.from].push(format!(
+            "{}: Mapped[{}] = relationship({})",
+            forward,
+            annotation,
+            arguments.join(", ")
+        ));
+
+        let source = &classes[relation.from];
+        let mut arguments = vec![format!("back_populates={}", quoted(&forward))];
+        if ambiguous {
+            arguments.push(format!(
+                "foreign_keys={}",
+                quoted(&format!("[{}.{}]", source, column))
+            ));
+        }
+        let annotation = if relation.one_to_one {
+            imports.typing.insert("Optional");
+            format!("Optional[{}]", quoted(source))
+        } else {
+            imports.typing.insert("List");
+            format!("List[{}]", quoted(source))
+        };
+        relationships[relation.to].push(format!(
+            "{}: Mapped[{}] = relationship({})",
+            back,
+            annotation,
+            arguments.join(", ")
+        ));
+    }
+
+    for (index, (entity, class)) in model.entities.iter().zip(&classes).enumerate() {
+        let _ = writeln!(body, "\n\nclass {}(Base):", class);
+        if let Some(comment) = entity.table.comment.as_deref().filter(|c| !c.is_empty()) {
+            let _ = writeln!(
+                body,
+                "    \"\"\"{}\"\"\"\n",
+                comment.replace("\"\"\"", "'''")
+            );
+        }
+        let _ = writeln!(body, "    __tablename__ = {}", quoted(&entity.table.name));
+
+        let mut table_args = Vec::new();
+        for (index, columns) in entity.secondary_indexes() {
+            imports.sqlalchemy.insert("Index");
+            let mut arguments = vec![quoted(&index.name)];
+            arguments.extend(columns.iter().map(|c| quoted(c)));
+            if index.unique {
+                arguments.push("unique=True".to_string());
+            }
+            table_args.push(format!("Index({})", arguments.join(", ")));
+        }
+        let mut options = Vec::new();
+        if let Some(schema) = entity.table.schema.as_deref().filter(|s| !s.is_empty()) {
+            options.push(format!("\"schema\": {}", quoted(schema)));
+        }
+        if let Some(comment) = entity.table.comment.as_deref().filter(|c| !c.is_empty()) {
+            options.push(format!("\"comment\": {}", quoted(comment)));
+        }
+        match (table_args.is_empty(), options.is_empty()) {
+            (true, true) => {}
+            (true, false) => {
+                let _ = writeln!(body, "    __table_args__ = {{{}}}", options.join(", "));
+            }
+            _ => {
+                body.push_str("    __table_args__ = (\n");
+                for argument in &table_args {
+                    let _ = writeln!(body, "        {},", argument);
+                }
+                if !options.is_empty() {
+                    let _ = writeln!(body, "        {{{}}},", options.join(", "));
+                }
+                body.push_str("    )\n");
+            }
+        }
+        body.push('\n');
+
+        for field in &entity.fields {
+            let name = attribute(&field.name);
+            let (python_type, column_type) = types(model, field, &mut imports);
+            let mut arguments = Vec::new();
+            if name != field.name {
+                arguments.push(quoted(&field.name));
+            }
+            arguments.push(column_type);
+            if let Some(relation) = model
+                .relations
+                .iter()
+                .find(|r| r.from == index && r.from_field.id == field.id)
+            {
+                imports.sqlalchemy.insert("ForeignKey");
+                let target = model.entities[relation.to].table;
+                let mut reference = schema_prefix(target.schema.as_deref());
+                reference.push_str(&format!("{}.{}", target.name, relation.to_field.name));
+                let mut foreign_key = vec![quoted(&reference)];
+                if let Some(name) = relation.name {
+                    foreign_key.push(format!("name={}", quoted(name)));
+                }
+                arguments.push(format!("ForeignKey({})", foreign_key.join(", ")));
+            }
+            if field.primary_key {
+                arguments.push("primary_key=True".to_string());
+            }
+            if field.unique && !field.primary_key {
+                arguments.push("unique=True".to_string());
+            }
+            if let Some(default) = field.default.as_deref().filter(|d| !d.trim().is_empty()) {
+                imports.sqlalchemy.insert("text");
+                arguments.push(format!("server_default=text({})", quoted(default)));
+            }
+            if let Some(comment) = field.comment.as_deref().filter(|c| !c.is_empty()) {
+                arguments.push(format!("comment={}", quoted(comment)));
+            }
+            let python_type = if field.nullable && !field.primary_key {
+                imports.typing.insert("Optional");
+                format!("Optional[{}]", python_type)
+            } else {
+                python_type
+            };
+            let _ = writeln!(
+                body,
+                "    {}: Mapped[{}] = mapped_column({})",
+                name,
+                python_type,
+                arguments.join(", ")
+            );
+        }
+        if !relationships[index].is_empty() {
+            body.push('\n');
+            for relationship in &relationships[index] {
+                let _ = writeln!(body, "    {}", relationship);
+            }
+        }
+    }
+
+    let mut out = model.header("#");
+    out.push('\n');
+    for module in &imports.modules {
+        let _ = writeln!(out, "import {}", module);
+    }
+    if !imports.typing.is_empty() {
+        let names: Vec<_> = imports.typing.iter().copied().collect();
+        let _ = writeln!(out, "from typing import {}", names.join(", "));
+    }
+    if !imports.modules.is_empty() || !imports.typing.is_empty() {
+        out.push('\n');
+    }
+    let names: Vec<_> = imports.sqlalchemy.iter().copied().collect();
+    let _ = writeln!(out, "from sqlalchemy import {}", names.join(", "));
+    if !imports.postgresql.is_empty() {
+        let names: Vec<_> = imports.postgresql.iter().copied().collect();
+        let _ = writeln!(
+            out,
+            "from sqlalchemy.dialects.postgresql import {}",
+            names.join(", ")
+        );
+    }
+    out.push_str(
+        "from sqlalchemy.orm import DeclarativeBase, Mapped, mapped_column, relationship\n",
+    );
+    out.push_str("\n\nclass Base(DeclarativeBase):\n    pass\n");
+    out.push_str(&body);
+
+    vec![File {
+        path: "models.py".to_string(),
+        contents: out,
+    }]
+}
+
+fn schema_prefix(schema: Option<&str>) -> String {
+    match schema.filter(|s| !s.is_empty()) {
+        Some(schema) => format!("{}.", schema),
+        None => String::new(),
+    }
+}
+
+/// The Python annotation and SQLAlchemy column type of a field.
+fn types(model: &Model, field: &FieldInfo, imports: &mut Imports) -> (String, String) {
+    let length = field
+        .character_maximum_length
+        .as_deref()
+        .filter(|l| l.parse::<u32>().is_ok());
+    let (python, column, sqlalchemy) = match model.scalar(field) {
+        Scalar::SmallInt => (
+            "int".to_string(),
+            "SmallInteger".to_string(),
+            "SmallInteger",
+        ),
+        Scalar::Int => ("int".to_string(), "Integer".to_string(), "Integer"),
+        Scalar::BigInt => ("int".to_string(), "BigInteger".to_string(), "BigInteger"),
+        Scalar::Bool => ("bool".to_string(), "Boolean".to_string(), "Boolean"),
+        Scalar::Float => ("float".to_string(), "Float".to_string(), "Float"),
+        Scalar::Double => ("float".to_string(), "Double".to_string(), "Double"),
+        Scalar::Decimal => {
+            imports.modules.insert("decimal");
+            let column = match (field.precision, field.scale) {
+                (Some(p), Some(s)) => format!("Numeric({}, {})", p, s),
+                (Some(p), None) => format!("Numeric({})", p),
+                _ => "Numeric".to_string(),
+            };
+            ("decimal.Decimal".to_string(), column, "Numeric")
+        }
+        Scalar::VarChar => {
+            let fixed = matches!(
+                field.type_name.to_ascii_lowercase().as_str(),
+                "char" | "character" | "bpchar" | "nchar"
+            );
+            let name = if fixed { "CHAR" } else { "String" };
+            let column = match length {
+                Some(length) => format!("{}({})", name, length),
+                None => name.to_string(),
+            };
+            ("str".to_string(), column, name)
+        }
+        Scalar::Text => ("str".to_string(), "Text".to_string(), "Text"),
+        Scalar::Uuid => {
+            imports.modules.insert("uuid");
+            ("uuid.UUID".to_string(), "Uuid".to_string(), "Uuid")
+        }
+        Scalar::Date => {
+            imports.modules.insert("datetime");
+            ("datetime.date".to_string(), "Date".to_string(), "Date")
+        }
+        Scalar::Time => {
+            imports.modules.insert("datetime");
+            ("datetime.time".to_string(), "Time".to_string(), "Time")
+        }
+        Scalar::Timestamp => {
+            imports.modules.insert("datetime");
+            (
+                "datetime.datetime".to_string(),
+                "DateTime".to_string(),
+                "DateTime",
+            )
+        }
+        Scalar::TimestampTz => {
+            imports.modules.insert("datetime");
+            (
+                "datetime.datetime".to_string(),
+                "DateTime(timezone=True)".to_string(),
+                "DateTime",
+            )
+        }
+        Scalar::Json => {
+            imports.typing.insert("Any");
+            ("Any".to_string(), "JSON".to_string(), "JSON")
+        }
+        Scalar::Jsonb => {
+            imports.typing.insert("Any");
+            imports.postgresql.insert("JSONB");
+            ("Any".to_string(), "JSONB".to_string(), "")
+        }
+        Scalar::Bytes => (
+            "bytes".to_string(),
+            "LargeBinary".to_string(),
+            "LargeBinary",
+        ),
+        Scalar::Enum => {
+            let name = model
+                .enum_of(field)
+                .map(|e| e.name.as_str())
+                .unwrap_or_default();
+            let class = pascal_case(name);
+            (
+                class.clone(),
+                format!("Enum({}, name={})", class, quoted(name)),
+                "Enum",
+            )
+        }
+        Scalar::Other => {
+            // Types SQLAlchemy has no generic type for are mapped as text.
+            imports.typing.insert("Any");
+            ("Any".to_string(), "Text".to_string(), "Text")
+        }
+    };
+    if !sqlalchemy.is_empty() {
+        imports.sqlalchemy.insert(sqlalchemy);
+    }
+    if field.is_array {
+        imports.typing.insert("List");
+        imports.sqlalchemy.insert("ARRAY");
+        (format!("List[{}]", python), format!("ARRAY({})", column))
+    } else {
+        (python, column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::{generate, tests::catalog, Target};
+
+    #[test]
+    fn generates_the_catalog() {
+        let files = generate(&catalog(), Target::SqlAlchemy);
+        let file = files.iter().find(|f| f.path == "models.py").unwrap();
+        for expected in [
+            "class OrderStatus(enum.Enum):",
+            r#"__tablename__ = "Line Items""#,
+            r#"Enum(OrderStatus, name="order status")"#,
+            r#"server_default=text("now()")"#,
+            r#"mapped_column(Integer, ForeignKey("public.users.id"))"#,
+        ] {
+            assert!(
+                file.contents.contains(expected),
+                "{}\n{}",
+                expected,
+                file.contents
+            );
+        }
+    }
+}
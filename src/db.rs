@@ -0,0 +1,1468 @@
+use crate::lint;
+use crate::models::{
+    lenient, Area, CustomType, DeltaPushRequest, Dependency, Diagram, DiagramPatch, EntityChanges,
+    Note, Relationship, Revision, RevisionSummary, Table,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::types::Json;
+use sqlx::{PgConnection, Row};
+
+/// The child entity kinds stored per diagram, one Postgres table each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    Table,
+    Relationship,
+    Dependency,
+    Area,
+    CustomType,
+    Note,
+}
+
+impl EntityKind {
+    pub fn table_name(self) -> &'static str {
+        match self {
+            EntityKind::Table => "db_tables",
+            EntityKind::Relationship => "db_relationships",
+            EntityKind::Dependency => "db_dependencies",
+            EntityKind::Area => "areas",
+            EntityKind::CustomType => "db_custom_types",
+            EntityKind::Note => "notes",
+        }
+    }
+
+    /// Name recorded in the `deleted_entities` log.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntityKind::Table => "table",
+            EntityKind::Relationship => "relationship",
+            EntityKind::Dependency => "dependency",
+            EntityKind::Area => "area",
+            EntityKind::CustomType => "custom_type",
+            EntityKind::Note => "note",
+        }
+    }
+
+    /// Name used in messages.
+    pub fn label(self) -> &'static str {
+        match self {
+            EntityKind::CustomType => "custom type",
+            kind => kind.as_str(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "table" => Some(EntityKind::Table),
+            "relationship" => Some(EntityKind::Relationship),
+            "dependency" => Some(EntityKind::Dependency),
+            "area" => Some(EntityKind::Area),
+            "custom_type" => Some(EntityKind::CustomType),
+            "note" => Some(EntityKind::Note),
+            _ => None,
+        }
+    }
+}
+
+/// A child entity type: where it is stored, where it sits in a diagram and
+/// in a delta push, so that single entities can be handled generically.
+pub trait Entity: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    const KIND: EntityKind;
+
+    fn from_row(row: &PgRow) -> Self;
+    fn id(&self) -> &str;
+    fn all(diagram: &Diagram) -> &[Self];
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self>;
+}
+
+impl Entity for Table {
+    const KIND: EntityKind = EntityKind::Table;
+
+    fn from_row(row: &PgRow) -> Self {
+        table_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.tables.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.tables
+    }
+}
+
+impl Entity for Relationship {
+    const KIND: EntityKind = EntityKind::Relationship;
+
+    fn from_row(row: &PgRow) -> Self {
+        relationship_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.relationships.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.relationships
+    }
+}
+
+impl Entity for Dependency {
+    const KIND: EntityKind = EntityKind::Dependency;
+
+    fn from_row(row: &PgRow) -> Self {
+        dependency_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.dependencies.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.dependencies
+    }
+}
+
+impl Entity for Area {
+    const KIND: EntityKind = EntityKind::Area;
+
+    fn from_row(row: &PgRow) -> Self {
+        area_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.areas.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.areas
+    }
+}
+
+impl Entity for CustomType {
+    const KIND: EntityKind = EntityKind::CustomType;
+
+    fn from_row(row: &PgRow) -> Self {
+        custom_type_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.custom_types.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.custom_types
+    }
+}
+
+impl Entity for Note {
+    const KIND: EntityKind = EntityKind::Note;
+
+    fn from_row(row: &PgRow) -> Self {
+        note_from_row(row)
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn all(diagram: &Diagram) -> &[Self] {
+        diagram.notes.as_deref().unwrap_or_default()
+    }
+
+    fn changes(delta: &mut DeltaPushRequest) -> &mut EntityChanges<Self> {
+        &mut delta.notes
+    }
+}
+
+/// Position in a diagram's change stream used by incremental pulls.
+#[derive(Debug, Clone, Copy)]
+pub enum Cursor {
+    /// Changes made by pushes after this diagram version.
+    Version(i32),
+    /// Changes made after this point in time. Rows are stamped when their
+    /// push began, not when it committed, so a push still running when the
+    /// cursor was taken can be missed; only `Version` is gapless.
+    Timestamp(chrono::DateTime<chrono::Utc>),
+}
+
+pub fn diagram_from_row(row: &PgRow) -> Diagram {
+    Diagram {
+        id: row.get("id"),
+        name: row.get("name"),
+        database_type: row.get("database_type"),
+        database_edition: row.get("database_edition"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        version: row.get("version"),
+        tables: None,
+        relationships: None,
+        dependencies: None,
+        areas: None,
+        custom_types: None,
+        notes: None,
+    }
+}
+
+pub fn table_from_row(row: &PgRow) -> Table {
+    Table {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        name: row.get("name"),
+        schema: row.get("schema"),
+        x: row.get("x"),
+        y: row.get("y"),
+        width: row.get("width"),
+        color: row.get("color"),
+        comment: row.get("comment"),
+        is_view: row.get("is_view"),
+        is_materialized_view: row.get("is_materialized_view"),
+        order: row.get("order"),
+        fields: json_list(row, "fields").unwrap_or_default(),
+        indexes: json_list(row, "indexes").unwrap_or_default(),
+    }
+}
+
+pub fn relationship_from_row(row: &PgRow) -> Relationship {
+    Relationship {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        name: row.get("name"),
+        source_schema: row.get("source_schema"),
+        source_table_id: row.get("source_table_id"),
+        target_schema: row.get("target_schema"),
+        target_table_id: row.get("target_table_id"),
+        source_field_id: row.get("source_field_id"),
+        target_field_id: row.get("target_field_id"),
+        source_cardinality: row.get("source_cardinality"),
+        target_cardinality: row.get("target_cardinality"),
+    }
+}
+
+pub fn dependency_from_row(row: &PgRow) -> Dependency {
+    Dependency {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        schema: row.get("schema"),
+        table_id: row.get("table_id"),
+        dependent_schema: row.get("dependent_schema"),
+        dependent_table_id: row.get("dependent_table_id"),
+    }
+}
+
+pub fn area_from_row(row: &PgRow) -> Area {
+    Area {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        name: row.get("name"),
+        x: row.get("x"),
+        y: row.get("y"),
+        width: row.get("width"),
+        height: row.get("height"),
+        color: row.get("color"),
+    }
+}
+
+pub fn custom_type_from_row(row: &PgRow) -> CustomType {
+    // `values` is stored the way it was sent, and read like a pushed one
+    let mut stored = serde_json::Map::new();
+    if let Some(values) = row.get::<Option<Value>, _>("values") {
+        stored.insert("values".to_string(), values);
+    }
+    let mut stored = lenient::Reader::new(stored);
+    CustomType {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        schema: row.get("schema"),
+        r#type: row.get("type"),
+        kind: row.get("kind"),
+        values: stored.read("values", lenient::optional_strings).flatten(),
+        fields: json_list(row, "fields"),
+        extra: stored.extra(),
+    }
+}
+
+fn json_column(row: &PgRow, column: &str) -> Value {
+    row.get::<Option<Value>, _>(column).unwrap_or_default()
+}
+
+/// Reads a JSONB array of ChartDB objects as leniently as a pushed diagram,
+/// whose entry types keep what they cannot read. Only a column that is not an
+/// array at all, which the server never writes, is logged and left out.
+fn json_list<T: DeserializeOwned>(row: &PgRow, column: &str) -> Option<Vec<T>> {
+    lenient::optional_list(json_column(row, column)).unwrap_or_else(|e| {
+        let id: String = row.get("id");
+        tracing::warn!("Unreadable {} of {}: {}", column, id, e);
+        None
+    })
+}
+
+pub fn note_from_row(row: &PgRow) -> Note {
+    Note {
+        id: row.get("id"),
+        diagram_id: row.get("diagram_id"),
+        content: row.get("content"),
+        x: row.get("x"),
+        y: row.get("y"),
+        width: row.get("width"),
+        height: row.get("height"),
+        color: row.get("color"),
+    }
+}
+
+/// Fetches the rows of one entity kind for a diagram, optionally limited to
+/// the ones changed after `since`.
+pub async fn fetch_rows(
+    conn: &mut PgConnection,
+    kind: EntityKind,
+    diagram_id: &str,
+    since: Option<Cursor>,
+) -> Result<Vec<PgRow>, sqlx::Error> {
+    let table = kind.table_name();
+    match since {
+        None => {
+            let sql = format!("SELECT * FROM {} WHERE diagram_id = $1", table);
+            sqlx::query(&sql).bind(diagram_id).fetch_all(conn).await
+        }
+        Some(Cursor::Version(version)) => {
+            let sql = format!(
+                "SELECT * FROM {} WHERE diagram_id = $1 AND COALESCE(diagram_version, 0) > $2",
+                table
+            );
+            sqlx::query(&sql)
+                .bind(diagram_id)
+                .bind(version)
+                .fetch_all(conn)
+                .await
+        }
+        Some(Cursor::Timestamp(at)) => {
+            let sql = format!(
+                "SELECT * FROM {} WHERE diagram_id = $1 AND updated_at > $2",
+                table
+            );
+            sqlx::query(&sql)
+                .bind(diagram_id)
+                .bind(at)
+                .fetch_all(conn)
+                .await
+        }
+    }
+}
+
+pub async fn fetch_row(
+    conn: &mut PgConnection,
+    kind: EntityKind,
+    diagram_id: &str,
+    id: &str,
+) -> Result<Option<PgRow>, sqlx::Error> {
+    let sql = format!(
+        "SELECT * FROM {} WHERE diagram_id = $1 AND id = $2",
+        kind.table_name()
+    );
+    sqlx::query(&sql)
+        .bind(diagram_id)
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+}
+
+/// Ids deleted from a diagram after `since`, grouped by entity kind.
+pub async fn fetch_tombstones(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    since: Cursor,
+) -> Result<Vec<(EntityKind, String)>, sqlx::Error> {
+    let rows = match since {
+        Cursor::Version(version) => {
+            sqlx::query(
+                r#"
+                SELECT entity_type, entity_id FROM deleted_entities
+                WHERE diagram_id = $1 AND diagram_version > $2
+                ORDER BY diagram_version, entity_id
+                "#,
+            )
+            .bind(diagram_id)
+            .bind(version)
+            .fetch_all(conn)
+            .await?
+        }
+        Cursor::Timestamp(at) => {
+            sqlx::query(
+                r#"
+                SELECT entity_type, entity_id FROM deleted_entities
+                WHERE diagram_id = $1 AND deleted_at > $2
+                ORDER BY deleted_at, entity_id
+                "#,
+            )
+            .bind(diagram_id)
+            .bind(at)
+            .fetch_all(conn)
+            .await?
+        }
+    };
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let kind = EntityKind::parse(row.get("entity_type"))?;
+            Some((kind, row.get("entity_id")))
+        })
+        .collect())
+}
+
+/// Loads a diagram together with all of its children.
+pub async fn load_diagram(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<Option<Diagram>, sqlx::Error> {
+    let row = sqlx::query("SELECT * FROM diagrams WHERE id = $1")
+        .bind(diagram_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let mut diagram = match row {
+        Some(row) => diagram_from_row(&row),
+        None => return Ok(None),
+    };
+
+    let rows = fetch_rows(conn, EntityKind::Table, diagram_id, None).await?;
+    diagram.tables = Some(rows.iter().map(table_from_row).collect());
+    let rows = fetch_rows(conn, EntityKind::Relationship, diagram_id, None).await?;
+    diagram.relationships = Some(rows.iter().map(relationship_from_row).collect());
+    let rows = fetch_rows(conn, EntityKind::Dependency, diagram_id, None).await?;
+    diagram.dependencies = Some(rows.iter().map(dependency_from_row).collect());
+    let rows = fetch_rows(conn, EntityKind::Area, diagram_id, None).await?;
+    diagram.areas = Some(rows.iter().map(area_from_row).collect());
+    let rows = fetch_rows(conn, EntityKind::CustomType, diagram_id, None).await?;
+    diagram.custom_types = Some(rows.iter().map(custom_type_from_row).collect());
+    let rows = fetch_rows(conn, EntityKind::Note, diagram_id, None).await?;
+    diagram.notes = Some(rows.iter().map(note_from_row).collect());
+
+    Ok(Some(diagram))
+}
+
+/// Reads the current version of a diagram and locks its row until the
+/// surrounding transaction ends, so concurrent writers serialize on it.
+pub async fn lock_diagram_version(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(version, 1) FROM diagrams WHERE id = $1 FOR UPDATE")
+        .bind(diagram_id)
+        .fetch_optional(conn)
+        .await
+}
+
+/// Stores an immutable snapshot of the diagram as it is at `version`. These
+/// back the revision history and serve as merge bases for stale pushes.
+pub async fn save_revision(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    version: i32,
+    author: Option<&str>,
+    message: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let diagram = load_diagram(&mut *conn, diagram_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO diagram_revisions (diagram_id, version, snapshot, author, message)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(diagram_id)
+    .bind(version)
+    .bind(Json(&diagram))
+    .bind(author)
+    .bind(message)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn revision_summary_from_row(row: &PgRow) -> RevisionSummary {
+    RevisionSummary {
+        version: row.get("version"),
+        author: row.get("author"),
+        message: row.get("message"),
+        created_at: row.get("created_at"),
+    }
+}
+
+/// Revision metadata for a diagram, newest first.
+pub async fn list_revisions(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<Vec<RevisionSummary>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT version, author, message, created_at FROM diagram_revisions
+        WHERE diagram_id = $1
+        ORDER BY version DESC
+        "#,
+    )
+    .bind(diagram_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.iter().map(revision_summary_from_row).collect())
+}
+
+pub async fn fetch_revision(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    version: i32,
+) -> Result<Option<Revision>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+        SELECT version, author, message, created_at, snapshot FROM diagram_revisions
+        WHERE diagram_id = $1 AND version = $2
+        "#,
+    )
+    .bind(diagram_id)
+    .bind(version)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(|row| {
+        let Json(diagram) = row.get("snapshot");
+        Revision {
+            summary: revision_summary_from_row(&row),
+            diagram,
+        }
+    }))
+}
+
+pub async fn load_revision(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    version: i32,
+) -> Result<Option<Diagram>, sqlx::Error> {
+    let snapshot: Option<Json<Diagram>> = sqlx::query_scalar(
+        "SELECT snapshot FROM diagram_revisions WHERE diagram_id = $1 AND version = $2",
+    )
+    .bind(diagram_id)
+    .bind(version)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(snapshot.map(|Json(diagram)| diagram))
+}
+
+pub async fn diagram_exists(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM diagrams WHERE id = $1)")
+        .bind(diagram_id)
+        .fetch_one(conn)
+        .await
+}
+
+/// Inserts or updates the diagram row and returns its new version.
+pub async fn upsert_diagram(
+    conn: &mut PgConnection,
+    diagram: &Diagram,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO diagrams (id, name, database_type, database_edition, created_at, updated_at, version)
+        VALUES ($1, $2, $3, $4, $5, NOW(), 1)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            database_type = EXCLUDED.database_type,
+            database_edition = EXCLUDED.database_edition,
+            updated_at = NOW(),
+            version = COALESCE(diagrams.version, 1) + 1
+        RETURNING version
+        "#,
+    )
+    .bind(&diagram.id)
+    .bind(&diagram.name)
+    .bind(&diagram.database_type)
+    .bind(&diagram.database_edition)
+    .bind(diagram.created_at)
+    .fetch_one(conn)
+    .await
+}
+
+/// Bumps the version of an existing diagram, applying any metadata changes
+/// carried by a delta push.
+pub async fn touch_diagram(
+    conn: &mut PgConnection,
+    changes: &DeltaPushRequest,
+) -> Result<i32, sqlx::Error> {
+    update_details(
+        conn,
+        &changes.diagram_id,
+        changes.name.as_deref(),
+        changes.database_type.as_deref(),
+        changes.database_edition.as_ref().map(Option::as_deref),
+    )
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Applies a metadata edit and bumps the diagram's version; `None` if there
+/// is no such diagram.
+pub async fn update_diagram_details(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    patch: &DiagramPatch,
+) -> Result<Option<i32>, sqlx::Error> {
+    update_details(
+        conn,
+        diagram_id,
+        patch.name.as_deref(),
+        patch.database_type.as_deref(),
+        patch.database_edition.as_ref().map(Option::as_deref),
+    )
+    .await
+}
+
+/// Sets the given metadata, leaving out what is `None`, and bumps the
+/// version. `Some(None)` clears the edition.
+async fn update_details(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    name: Option<&str>,
+    database_type: Option<&str>,
+    database_edition: Option<Option<&str>>,
+) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE diagrams SET
+            name = COALESCE($2, name),
+            database_type = COALESCE($3, database_type),
+            database_edition = CASE WHEN $4 THEN $5 ELSE database_edition END,
+            updated_at = NOW(),
+            version = COALESCE(version, 1) + 1
+        WHERE id = $1
+        RETURNING version
+        "#,
+    )
+    .bind(diagram_id)
+    .bind(name)
+    .bind(database_type)
+    .bind(database_edition.is_some())
+    .bind(database_edition.flatten())
+    .fetch_optional(conn)
+    .await
+}
+
+/// Deletes a diagram; its children, revisions, deletion log and lint
+/// settings go with it through `ON DELETE CASCADE`. Returns whether it
+/// existed.
+pub async fn delete_diagram(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM diagrams WHERE id = $1")
+        .bind(diagram_id)
+        .execute(conn)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Child ids of a diagram, grouped by entity kind.
+pub fn child_ids(diagram: &Diagram) -> Vec<(EntityKind, Vec<&str>)> {
+    fn ids<T>(items: &Option<Vec<T>>, id: impl Fn(&T) -> &str) -> Vec<&str> {
+        items.iter().flatten().map(id).collect()
+    }
+
+    vec![
+        (EntityKind::Table, ids(&diagram.tables, |t| &t.id)),
+        (
+            EntityKind::Relationship,
+            ids(&diagram.relationships, |r| &r.id),
+        ),
+        (
+            EntityKind::Dependency,
+            ids(&diagram.dependencies, |d| &d.id),
+        ),
+        (EntityKind::Area, ids(&diagram.areas, |a| &a.id)),
+        (
+            EntityKind::CustomType,
+            ids(&diagram.custom_types, |c| &c.id),
+        ),
+        (EntityKind::Note, ids(&diagram.notes, |n| &n.id)),
+    ]
+}
+
+/// Ids upserted by a delta push, grouped by entity kind.
+pub fn upserted_ids(changes: &DeltaPushRequest) -> Vec<(EntityKind, Vec<&str>)> {
+    fn ids<T>(changes: &EntityChanges<T>, id: impl Fn(&T) -> &str) -> Vec<&str> {
+        changes.upserts.iter().map(id).collect()
+    }
+
+    vec![
+        (EntityKind::Table, ids(&changes.tables, |t| &t.id)),
+        (
+            EntityKind::Relationship,
+            ids(&changes.relationships, |r| &r.id),
+        ),
+        (
+            EntityKind::Dependency,
+            ids(&changes.dependencies, |d| &d.id),
+        ),
+        (EntityKind::Area, ids(&changes.areas, |a| &a.id)),
+        (
+            EntityKind::CustomType,
+            ids(&changes.custom_types, |c| &c.id),
+        ),
+        (EntityKind::Note, ids(&changes.notes, |n| &n.id)),
+    ]
+}
+
+/// Returns the ids that are already used by rows of another diagram.
+///
+/// Entity ids are global primary keys and the upserts below refuse to take
+/// over another diagram's rows, so such ids have to be rejected upfront.
+pub async fn foreign_ids(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    ids: &[(EntityKind, Vec<&str>)],
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut found = Vec::new();
+    for (kind, ids) in ids {
+        if ids.is_empty() {
+            continue;
+        }
+        let sql = format!(
+            "SELECT id FROM {} WHERE id = ANY($1) AND diagram_id <> $2",
+            kind.table_name()
+        );
+        let taken: Vec<String> = sqlx::query_scalar(&sql)
+            .bind(ids)
+            .bind(diagram_id)
+            .fetch_all(&mut *conn)
+            .await?;
+        found.extend(taken);
+    }
+    Ok(found)
+}
+
+/// Replaces the children of a diagram with the ones carried by `diagram`.
+///
+/// Rows whose content is unchanged are left untouched, so their `version`
+/// and `updated_at` only move when the entity actually changed.
+pub async fn replace_children(
+    conn: &mut PgConnection,
+    diagram: &Diagram,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    let id = diagram.id.as_str();
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let dependencies = diagram.dependencies.as_deref().unwrap_or_default();
+    let areas = diagram.areas.as_deref().unwrap_or_default();
+    let custom_types = diagram.custom_types.as_deref().unwrap_or_default();
+    let notes = diagram.notes.as_deref().unwrap_or_default();
+
+    delete_except(
+        conn,
+        EntityKind::Table,
+        id,
+        version,
+        tables.iter().map(|t| &t.id),
+    )
+    .await?;
+    delete_except(
+        conn,
+        EntityKind::Relationship,
+        id,
+        version,
+        relationships.iter().map(|r| &r.id),
+    )
+    .await?;
+    delete_except(
+        conn,
+        EntityKind::Dependency,
+        id,
+        version,
+        dependencies.iter().map(|d| &d.id),
+    )
+    .await?;
+    delete_except(
+        conn,
+        EntityKind::Area,
+        id,
+        version,
+        areas.iter().map(|a| &a.id),
+    )
+    .await?;
+    delete_except(
+        conn,
+        EntityKind::CustomType,
+        id,
+        version,
+        custom_types.iter().map(|c| &c.id),
+    )
+    .await?;
+    delete_except(
+        conn,
+        EntityKind::Note,
+        id,
+        version,
+        notes.iter().map(|n| &n.id),
+    )
+    .await?;
+
+    for table in tables {
+        upsert_table(conn, id, table, version).await?;
+    }
+    for rel in relationships {
+        upsert_relationship(conn, id, rel, version).await?;
+    }
+    for dep in dependencies {
+        upsert_dependency(conn, id, dep, version).await?;
+    }
+    for area in areas {
+        upsert_area(conn, id, area, version).await?;
+    }
+    for ct in custom_types {
+        upsert_custom_type(conn, id, ct, version).await?;
+    }
+    for note in notes {
+        upsert_note(conn, id, note, version).await?;
+    }
+
+    Ok(())
+}
+
+/// Applies the per-entity deletes and upserts of a delta push.
+pub async fn apply_changes(
+    conn: &mut PgConnection,
+    changes: &DeltaPushRequest,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    let id = changes.diagram_id.as_str();
+
+    delete_ids(conn, EntityKind::Table, id, version, &changes.tables).await?;
+    delete_ids(
+        conn,
+        EntityKind::Relationship,
+        id,
+        version,
+        &changes.relationships,
+    )
+    .await?;
+    delete_ids(
+        conn,
+        EntityKind::Dependency,
+        id,
+        version,
+        &changes.dependencies,
+    )
+    .await?;
+    delete_ids(conn, EntityKind::Area, id, version, &changes.areas).await?;
+    delete_ids(
+        conn,
+        EntityKind::CustomType,
+        id,
+        version,
+        &changes.custom_types,
+    )
+    .await?;
+    delete_ids(conn, EntityKind::Note, id, version, &changes.notes).await?;
+
+    for table in &changes.tables.upserts {
+        upsert_table(conn, id, table, version).await?;
+    }
+    for rel in &changes.relationships.upserts {
+        upsert_relationship(conn, id, rel, version).await?;
+    }
+    for dep in &changes.dependencies.upserts {
+        upsert_dependency(conn, id, dep, version).await?;
+    }
+    for area in &changes.areas.upserts {
+        upsert_area(conn, id, area, version).await?;
+    }
+    for ct in &changes.custom_types.upserts {
+        upsert_custom_type(conn, id, ct, version).await?;
+    }
+    for note in &changes.notes.upserts {
+        upsert_note(conn, id, note, version).await?;
+    }
+
+    Ok(())
+}
+
+async fn delete_ids<T>(
+    conn: &mut PgConnection,
+    kind: EntityKind,
+    diagram_id: &str,
+    version: i32,
+    changes: &EntityChanges<T>,
+) -> Result<(), sqlx::Error> {
+    if changes.deletes.is_empty() {
+        return Ok(());
+    }
+
+    let sql = format!(
+        r#"
+        WITH removed AS (
+            DELETE FROM {} WHERE diagram_id = $1 AND id = ANY($2) RETURNING id
+        )
+        INSERT INTO deleted_entities (diagram_id, entity_type, entity_id, diagram_version)
+        SELECT $1, $3, id, $4 FROM removed
+        "#,
+        kind.table_name()
+    );
+    sqlx::query(&sql)
+        .bind(diagram_id)
+        .bind(&changes.deletes)
+        .bind(kind.as_str())
+        .bind(version)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn delete_except<'a>(
+    conn: &mut PgConnection,
+    kind: EntityKind,
+    diagram_id: &str,
+    version: i32,
+    keep: impl Iterator<Item = &'a String>,
+) -> Result<(), sqlx::Error> {
+    let keep: Vec<&str> = keep.map(String::as_str).collect();
+    let sql = format!(
+        r#"
+        WITH removed AS (
+            DELETE FROM {} WHERE diagram_id = $1 AND NOT (id = ANY($2)) RETURNING id
+        )
+        INSERT INTO deleted_entities (diagram_id, entity_type, entity_id, diagram_version)
+        SELECT $1, $3, id, $4 FROM removed
+        "#,
+        kind.table_name()
+    );
+    sqlx::query(&sql)
+        .bind(diagram_id)
+        .bind(&keep)
+        .bind(kind.as_str())
+        .bind(version)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// The upserts below write rows into the diagram they are given, whatever
+// `diagramId` the entity carries. They only touch a row when one of its
+// columns differs, and never move a row that belongs to another diagram.
+// `diagram_version` records the push that last changed the row, for
+// incremental pulls.
+
+pub async fn upsert_table(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    table: &Table,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO db_tables (
+            id, diagram_id, name, schema, x, y, width, color, comment,
+            is_view, is_materialized_view, "order", fields, indexes, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            schema = EXCLUDED.schema,
+            x = EXCLUDED.x,
+            y = EXCLUDED.y,
+            width = EXCLUDED.width,
+            color = EXCLUDED.color,
+            comment = EXCLUDED.comment,
+            is_view = EXCLUDED.is_view,
+            is_materialized_view = EXCLUDED.is_materialized_view,
+            "order" = EXCLUDED."order",
+            fields = EXCLUDED.fields,
+            indexes = EXCLUDED.indexes,
+            updated_at = NOW(),
+            version = COALESCE(db_tables.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE db_tables.diagram_id = EXCLUDED.diagram_id
+          AND (db_tables.name, db_tables.schema, db_tables.x, db_tables.y, db_tables.width,
+               db_tables.color, db_tables.comment, db_tables.is_view,
+               db_tables.is_materialized_view, db_tables."order", db_tables.fields,
+               db_tables.indexes)
+          IS DISTINCT FROM
+              (EXCLUDED.name, EXCLUDED.schema, EXCLUDED.x, EXCLUDED.y, EXCLUDED.width,
+               EXCLUDED.color, EXCLUDED.comment, EXCLUDED.is_view,
+               EXCLUDED.is_materialized_view, EXCLUDED."order", EXCLUDED.fields,
+               EXCLUDED.indexes)
+        "#,
+    )
+    .bind(&table.id)
+    .bind(diagram_id)
+    .bind(&table.name)
+    .bind(&table.schema)
+    .bind(table.x)
+    .bind(table.y)
+    .bind(table.width)
+    .bind(&table.color)
+    .bind(&table.comment)
+    .bind(table.is_view.unwrap_or(false))
+    .bind(table.is_materialized_view.unwrap_or(false))
+    .bind(table.order)
+    .bind(Json(&table.fields))
+    .bind(Json(&table.indexes))
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_relationship(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    rel: &Relationship,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO db_relationships (
+            id, diagram_id, name, source_schema, source_table_id,
+            target_schema, target_table_id, source_field_id, target_field_id,
+            source_cardinality, target_cardinality, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            source_schema = EXCLUDED.source_schema,
+            source_table_id = EXCLUDED.source_table_id,
+            target_schema = EXCLUDED.target_schema,
+            target_table_id = EXCLUDED.target_table_id,
+            source_field_id = EXCLUDED.source_field_id,
+            target_field_id = EXCLUDED.target_field_id,
+            source_cardinality = EXCLUDED.source_cardinality,
+            target_cardinality = EXCLUDED.target_cardinality,
+            updated_at = NOW(),
+            version = COALESCE(db_relationships.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE db_relationships.diagram_id = EXCLUDED.diagram_id
+          AND (db_relationships.name, db_relationships.source_schema,
+               db_relationships.source_table_id, db_relationships.target_schema,
+               db_relationships.target_table_id, db_relationships.source_field_id,
+               db_relationships.target_field_id, db_relationships.source_cardinality,
+               db_relationships.target_cardinality)
+          IS DISTINCT FROM
+              (EXCLUDED.name, EXCLUDED.source_schema, EXCLUDED.source_table_id,
+               EXCLUDED.target_schema, EXCLUDED.target_table_id, EXCLUDED.source_field_id,
+               EXCLUDED.target_field_id, EXCLUDED.source_cardinality,
+               EXCLUDED.target_cardinality)
+        "#,
+    )
+    .bind(&rel.id)
+    .bind(diagram_id)
+    .bind(&rel.name)
+    .bind(&rel.source_schema)
+    .bind(&rel.source_table_id)
+    .bind(&rel.target_schema)
+    .bind(&rel.target_table_id)
+    .bind(&rel.source_field_id)
+    .bind(&rel.target_field_id)
+    .bind(&rel.source_cardinality)
+    .bind(&rel.target_cardinality)
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_dependency(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    dep: &Dependency,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO db_dependencies (
+            id, diagram_id, schema, table_id, dependent_schema, dependent_table_id, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (id) DO UPDATE SET
+            schema = EXCLUDED.schema,
+            table_id = EXCLUDED.table_id,
+            dependent_schema = EXCLUDED.dependent_schema,
+            dependent_table_id = EXCLUDED.dependent_table_id,
+            updated_at = NOW(),
+            version = COALESCE(db_dependencies.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE db_dependencies.diagram_id = EXCLUDED.diagram_id
+          AND (db_dependencies.schema, db_dependencies.table_id,
+               db_dependencies.dependent_schema, db_dependencies.dependent_table_id)
+          IS DISTINCT FROM
+              (EXCLUDED.schema, EXCLUDED.table_id, EXCLUDED.dependent_schema,
+               EXCLUDED.dependent_table_id)
+        "#,
+    )
+    .bind(&dep.id)
+    .bind(diagram_id)
+    .bind(&dep.schema)
+    .bind(&dep.table_id)
+    .bind(&dep.dependent_schema)
+    .bind(&dep.dependent_table_id)
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_area(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    area: &Area,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO areas (
+            id, diagram_id, name, x, y, width, height, color, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            x = EXCLUDED.x,
+            y = EXCLUDED.y,
+            width = EXCLUDED.width,
+            height = EXCLUDED.height,
+            color = EXCLUDED.color,
+            updated_at = NOW(),
+            version = COALESCE(areas.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE areas.diagram_id = EXCLUDED.diagram_id
+          AND (areas.name, areas.x, areas.y, areas.width, areas.height, areas.color)
+          IS DISTINCT FROM
+              (EXCLUDED.name, EXCLUDED.x, EXCLUDED.y, EXCLUDED.width, EXCLUDED.height,
+               EXCLUDED.color)
+        "#,
+    )
+    .bind(&area.id)
+    .bind(diagram_id)
+    .bind(&area.name)
+    .bind(area.x)
+    .bind(area.y)
+    .bind(area.width)
+    .bind(area.height)
+    .bind(&area.color)
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_custom_type(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    ct: &CustomType,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO db_custom_types (
+            id, diagram_id, schema, type, kind, values, fields, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (id) DO UPDATE SET
+            schema = EXCLUDED.schema,
+            type = EXCLUDED.type,
+            kind = EXCLUDED.kind,
+            values = EXCLUDED.values,
+            fields = EXCLUDED.fields,
+            updated_at = NOW(),
+            version = COALESCE(db_custom_types.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE db_custom_types.diagram_id = EXCLUDED.diagram_id
+          AND (db_custom_types.schema, db_custom_types.type, db_custom_types.kind,
+               db_custom_types.values, db_custom_types.fields)
+          IS DISTINCT FROM
+              (EXCLUDED.schema, EXCLUDED.type, EXCLUDED.kind, EXCLUDED.values,
+               EXCLUDED.fields)
+        "#,
+    )
+    .bind(&ct.id)
+    .bind(diagram_id)
+    .bind(&ct.schema)
+    .bind(&ct.r#type)
+    .bind(&ct.kind)
+    .bind(ct.values_json().map(Json))
+    .bind(ct.fields.as_ref().map(Json))
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn upsert_note(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    note: &Note,
+    version: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO notes (
+            id, diagram_id, content, x, y, width, height, color, diagram_version
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (id) DO UPDATE SET
+            content = EXCLUDED.content,
+            x = EXCLUDED.x,
+            y = EXCLUDED.y,
+            width = EXCLUDED.width,
+            height = EXCLUDED.height,
+            color = EXCLUDED.color,
+            updated_at = NOW(),
+            version = COALESCE(notes.version, 1) + 1,
+            diagram_version = EXCLUDED.diagram_version
+        WHERE notes.diagram_id = EXCLUDED.diagram_id
+          AND (notes.content, notes.x, notes.y, notes.width, notes.height, notes.color)
+          IS DISTINCT FROM
+              (EXCLUDED.content, EXCLUDED.x, EXCLUDED.y, EXCLUDED.width, EXCLUDED.height,
+               EXCLUDED.color)
+        "#,
+    )
+    .bind(&note.id)
+    .bind(diagram_id)
+    .bind(&note.content)
+    .bind(note.x)
+    .bind(note.y)
+    .bind(note.width)
+    .bind(note.height)
+    .bind(&note.color)
+    .bind(version)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// The lint rule defaults a diagram has changed.
+pub async fn load_lint_overrides(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+) -> Result<Vec<lint::Override>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT rule, enabled, severity, options FROM diagram_lint_rules WHERE diagram_id = $1",
+    )
+    .bind(diagram_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| lint::Override {
+            rule: row.get("rule"),
+            enabled: row.get("enabled"),
+            severity: row
+                .get::<Option<String>, _>("severity")
+                .as_deref()
+                .and_then(lint::Severity::parse),
+            options: row.get("options"),
+        })
+        .collect())
+}
+
+pub async fn save_lint_override(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    change: &lint::Override,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO diagram_lint_rules (diagram_id, rule, enabled, severity, options)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (diagram_id, rule) DO UPDATE SET
+            enabled = EXCLUDED.enabled,
+            severity = EXCLUDED.severity,
+            options = EXCLUDED.options,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(diagram_id)
+    .bind(&change.rule)
+    .bind(change.enabled)
+    .bind(change.severity.map(lint::Severity::as_str))
+    .bind(&change.options)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_lint_override(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    rule: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM diagram_lint_rules WHERE diagram_id = $1 AND rule = $2")
+        .bind(diagram_id)
+        .bind(rule)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Gives diagram `to` the lint settings of diagram `from`.
+pub async fn copy_lint_overrides(
+    conn: &mut PgConnection,
+    from: &str,
+    to: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO diagram_lint_rules (diagram_id, rule, enabled, severity, options)
+        SELECT $2, rule, enabled, severity, options FROM diagram_lint_rules
+        WHERE diagram_id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{database, fresh, shop};
+    use serde_json::json;
+
+    #[test]
+    fn ids_are_grouped_by_kind() {
+        let mut diagram = shop();
+        diagram.notes = Some(vec![serde_json::from_value(json!({
+            "id": "n_hi",
+            "diagramId": diagram.id,
+            "content": "hi",
+        }))
+        .unwrap()]);
+        let ids = child_ids(&diagram);
+        let kinds: Vec<_> = ids.iter().map(|(kind, _)| kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "table",
+                "relationship",
+                "dependency",
+                "area",
+                "custom_type",
+                "note"
+            ]
+        );
+        assert_eq!(ids[0].1, ["t_users", "t_orders"]);
+        assert_eq!(ids[1].1, ["r_orders_user"]);
+        assert_eq!(ids[5].1, ["n_hi"]);
+
+        // Deleted ids are not upserted, so they are not checked for ownership
+        let changes: DeltaPushRequest = serde_json::from_value(json!({
+            "diagramId": diagram.id,
+            "tables": { "upserts": [diagram.tables.as_ref().unwrap()[0]], "deletes": ["t_orders"] },
+            "notes": { "deletes": ["n_hi"] },
+        }))
+        .unwrap();
+        let ids = upserted_ids(&changes);
+        assert_eq!(ids[0], (EntityKind::Table, vec!["t_users"]));
+        assert!(ids[1..].iter().all(|(_, ids)| ids.is_empty()));
+    }
+
+    #[test]
+    fn tombstone_kinds_read_back() {
+        for (kind, _) in child_ids(&shop()) {
+            assert_eq!(EntityKind::parse(kind.as_str()), Some(kind));
+        }
+        // Rows logged under a kind this build does not know are skipped
+        assert_eq!(EntityKind::parse("view"), None);
+    }
+
+    /// Stores `diagram` the way a full push does.
+    async fn store(conn: &mut PgConnection, diagram: &Diagram) -> i32 {
+        let version = upsert_diagram(conn, diagram).await.unwrap();
+        replace_children(conn, diagram, version).await.unwrap();
+        version
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn odd_enum_labels_are_stored_as_sent() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        let mut diagram = shop();
+        diagram.custom_types = Some(vec![serde_json::from_value(json!({
+            "id": "c_mood",
+            "diagramId": diagram.id,
+            "type": "mood",
+            "kind": "enum",
+            "values": ["happy", 1, null],
+        }))
+        .unwrap()]);
+        let diagram = fresh(&diagram);
+        store(&mut tx, &diagram).await;
+
+        let loaded = load_diagram(&mut tx, &diagram.id).await.unwrap().unwrap();
+        let mood = &loaded.custom_types.as_ref().unwrap()[0];
+        assert_eq!(
+            mood.values,
+            Some(vec!["happy".to_string(), "1".to_string()])
+        );
+        assert_eq!(mood.values_json(), Some(json!(["happy", 1, null])));
+
+        // Pulled and pushed back untouched, they are still there
+        store(&mut tx, &loaded).await;
+        let loaded = load_diagram(&mut tx, &diagram.id).await.unwrap().unwrap();
+        assert_eq!(
+            loaded.custom_types.unwrap()[0].values_json(),
+            Some(json!(["happy", 1, null]))
+        );
+        tx.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn details_are_patched_in_place() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        let mut diagram = fresh(&shop());
+        diagram.database_edition = Some("postgresql_supabase".to_string());
+        assert_eq!(store(&mut tx, &diagram).await, 1);
+
+        let patch: DiagramPatch = serde_json::from_value(json!({ "name": "Store" })).unwrap();
+        let version = update_diagram_details(&mut tx, &diagram.id, &patch).await;
+        assert_eq!(version.unwrap(), Some(2));
+        let loaded = load_diagram(&mut tx, &diagram.id).await.unwrap().unwrap();
+        assert_eq!(loaded.name, "Store");
+        assert_eq!(loaded.database_type, diagram.database_type);
+        assert_eq!(loaded.database_edition, diagram.database_edition);
+
+        let patch = json!({ "diagramId": diagram.id, "databaseEdition": null });
+        let changes: DeltaPushRequest = serde_json::from_value(patch).unwrap();
+        assert_eq!(touch_diagram(&mut tx, &changes).await.unwrap(), 3);
+        let loaded = load_diagram(&mut tx, &diagram.id).await.unwrap().unwrap();
+        assert_eq!(loaded.name, "Store");
+        assert_eq!(loaded.database_edition, None);
+
+        let patch: DiagramPatch = serde_json::from_value(json!({ "name": "Nowhere" })).unwrap();
+        let missing = update_diagram_details(&mut tx, "no_such_diagram", &patch).await;
+        assert_eq!(missing.unwrap(), None);
+        tx.rollback().await.unwrap();
+    }
+}
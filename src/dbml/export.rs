@@ -0,0 +1,259 @@
+//! Rendering a stored diagram as DBML.
+
+use super::{operator, DATABASE_TYPES};
+use crate::models::{Diagram, Table};
+use crate::schema::{self, FieldInfo};
+use std::fmt::Write;
+
+pub fn export(diagram: &Diagram) -> String {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let mut out = String::new();
+
+    let database_type = DATABASE_TYPES
+        .iter()
+        .find(|(chartdb, _)| *chartdb == diagram.database_type)
+        .map_or(diagram.database_type.as_str(), |(_, dbml)| dbml);
+    let _ = writeln!(
+        out,
+        "Project {} {{\n  database_type: {}\n}}",
+        name(&diagram.name),
+        string(database_type)
+    );
+
+    for ct in diagram.custom_types.as_deref().unwrap_or_default() {
+        let qualified = qualified(ct.schema.as_deref(), &ct.r#type);
+        if ct.kind.as_deref() == Some("composite") {
+            // DBML has no composite types; keep them visible at least
+            let fields: Vec<String> = schema::composite_fields(ct)
+                .iter()
+                .map(|f| format!("{} {}", f.field, f.type_name))
+                .collect();
+            let _ = writeln!(
+                out,
+                "\n// Composite type {} ({})",
+                qualified,
+                fields.join(", ")
+            );
+            continue;
+        }
+        let _ = writeln!(out, "\nEnum {} {{", qualified);
+        for value in schema::enum_values(ct) {
+            let _ = writeln!(out, "  {}", name(&value));
+        }
+        out.push_str("}\n");
+    }
+
+    for table in tables {
+        out.push('\n');
+        write_table(&mut out, table);
+    }
+
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    if !relationships.is_empty() {
+        out.push('\n');
+    }
+    for rel in relationships {
+        let source = endpoint(tables, &rel.source_table_id, rel.source_field_id.as_deref());
+        let target = endpoint(tables, &rel.target_table_id, rel.target_field_id.as_deref());
+        let (Some(source), Some(target)) = (source, target) else {
+            continue;
+        };
+        let op = operator(
+            rel.source_cardinality.as_deref() == Some("many"),
+            rel.target_cardinality.as_deref() == Some("many"),
+        );
+        match rel.name.as_deref().filter(|n| !n.is_empty()) {
+            Some(rel_name) => {
+                let _ = writeln!(out, "Ref {}: {} {} {}", name(rel_name), source, op, target);
+            }
+            None => {
+                let _ = writeln!(out, "Ref: {} {} {}", source, op, target);
+            }
+        }
+    }
+
+    for area in diagram.areas.as_deref().unwrap_or_default() {
+        let _ = write!(
+            out,
+            "\nTableGroup {}",
+            name(area.name.as_deref().unwrap_or(&area.id))
+        );
+        if let Some(color) = &area.color {
+            let _ = write!(out, " [color: {}]", color);
+        }
+        out.push_str(" {\n");
+        for table in tables.iter().filter(|t| area.contains(t)) {
+            let _ = writeln!(out, "  {}", qualified(table.schema.as_deref(), &table.name));
+        }
+        out.push_str("}\n");
+    }
+
+    for note in diagram.notes.as_deref().unwrap_or_default() {
+        let _ = writeln!(
+            out,
+            "\nNote {} {{\n  {}\n}}",
+            name(&format!("note_{}", note.id)),
+            string(note.content.as_deref().unwrap_or_default())
+        );
+    }
+
+    out
+}
+
+fn write_table(out: &mut String, table: &Table) {
+    let fields = schema::fields(table);
+    let primary_key: Vec<&FieldInfo> = fields.iter().filter(|f| f.primary_key).collect();
+    // A composite primary key is declared as an index
+    let composite_key = primary_key.len() > 1;
+
+    if table.is_view == Some(true) || table.is_materialized_view == Some(true) {
+        let kind = if table.is_materialized_view == Some(true) {
+            "materialized view"
+        } else {
+            "view"
+        };
+        let _ = writeln!(out, "// {}", kind);
+    }
+    let _ = write!(
+        out,
+        "Table {}",
+        qualified(table.schema.as_deref(), &table.name)
+    );
+    if let Some(color) = &table.color {
+        let _ = write!(out, " [headercolor: {}]", color);
+    }
+    out.push_str(" {\n");
+
+    for field in &fields {
+        let mut settings = Vec::new();
+        let pk = field.primary_key && !composite_key;
+        if pk {
+            settings.push("pk".to_string());
+        }
+        if field.increment {
+            settings.push("increment".to_string());
+        }
+        if !field.nullable && !pk {
+            settings.push("not null".to_string());
+        }
+        if field.unique && !field.primary_key {
+            settings.push("unique".to_string());
+        }
+        if let Some(default) = &field.default {
+            settings.push(format!("default: {}", default_value(default)));
+        }
+        if let Some(comment) = &field.comment {
+            settings.push(format!("note: {}", string(comment)));
+        }
+        let _ = write!(out, "  {} {}", name(&field.name), type_name(field));
+        if !settings.is_empty() {
+            let _ = write!(out, " [{}]", settings.join(", "));
+        }
+        out.push('\n');
+    }
+
+    let indexes = schema::indexes(table);
+    if composite_key || !indexes.is_empty() {
+        out.push_str("\n  Indexes {\n");
+        if composite_key {
+            let columns: Vec<String> = primary_key.iter().map(|f| name(&f.name)).collect();
+            let _ = writeln!(out, "    ({}) [pk]", columns.join(", "));
+        }
+        for index in &indexes {
+            let columns: Vec<String> = index
+                .field_ids
+                .iter()
+                .filter_map(|id| fields.iter().find(|f| &f.id == id))
+                .map(|f| name(&f.name))
+                .collect();
+            if columns.is_empty() {
+                continue;
+            }
+            let mut settings = Vec::new();
+            if index.unique {
+                settings.push("unique".to_string());
+            }
+            if !index.name.is_empty() {
+                settings.push(format!("name: {}", name(&index.name)));
+            }
+            let _ = write!(out, "    ({})", columns.join(", "));
+            if !settings.is_empty() {
+                let _ = write!(out, " [{}]", settings.join(", "));
+            }
+            out.push('\n');
+        }
+        out.push_str("  }\n");
+    }
+
+    if let Some(comment) = &table.comment {
+        let _ = writeln!(out, "\n  Note: {}", string(comment));
+    }
+    out.push_str("}\n");
+}
+
+/// `schema.table.column` for one end of a relationship.
+fn endpoint(tables: &[Table], table_id: &str, field_id: Option<&str>) -> Option<String> {
+    let table = tables.iter().find(|t| t.id == table_id)?;
+    let field = schema::fields(table)
+        .into_iter()
+        .find(|f| Some(f.id.as_str()) == field_id)?;
+    Some(format!(
+        "{}.{}",
+        qualified(table.schema.as_deref(), &table.name),
+        name(&field.name)
+    ))
+}
+
+fn qualified(schema: Option<&str>, object: &str) -> String {
+    match schema {
+        Some(schema) if !schema.is_empty() => format!("{}.{}", name(schema), name(object)),
+        _ => name(object),
+    }
+}
+
+/// A double-quoted DBML identifier.
+fn name(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A DBML string, triple-quoted when it spans several lines.
+fn string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\");
+    if value.contains('\n') {
+        format!("'''{}'''", escaped.replace("'''", "\\'''"))
+    } else {
+        format!("'{}'", escaped.replace('\'', "\\'"))
+    }
+}
+
+/// Field types are bare words unless they contain spaces, such as
+/// `timestamp with time zone`.
+fn type_name(field: &FieldInfo) -> String {
+    let sql = field.type_sql();
+    if sql.contains(char::is_whitespace) || sql.contains('"') {
+        name(&sql)
+    } else {
+        sql
+    }
+}
+
+/// Numbers, booleans, `null` and string literals are written as they are;
+/// anything else is an expression in backticks.
+fn default_value(default: &str) -> String {
+    let value = default.trim();
+    let value = match value.rfind("::") {
+        Some(pos) if value.starts_with('\'') && !value[pos..].contains('\'') => &value[..pos],
+        _ => value,
+    };
+    let is_literal = value.parse::<f64>().is_ok()
+        || matches!(
+            value.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null"
+        )
+        || (value.len() >= 2 && value.starts_with('\'') && value.ends_with('\''));
+    if is_literal {
+        value.to_string()
+    } else {
+        format!("`{}`", value.replace('`', "\\`"))
+    }
+}
@@ -0,0 +1,1073 @@
+//! Parsing DBML into a new diagram.
+//!
+//! Every entity gets a fresh id, and since DBML carries no positions, tables
+//! are laid out with the members of each `TableGroup` kept together inside
+//! the group's area.
+
+use super::DATABASE_TYPES;
+use crate::layout;
+use crate::models::{new_id, CustomType, Diagram, Note, Relationship, Table};
+use crate::schema::{FieldInfo, IndexInfo};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Parses `source` into a diagram named `name` (or after the DBML
+/// `Project`), for `database_type` (or the project's `database_type`).
+pub fn import(
+    source: &str,
+    name: Option<&str>,
+    database_type: Option<&str>,
+) -> Result<Diagram, ParseError> {
+    let tokens = tokenize(source)?;
+    let document = Parser { tokens, pos: 0 }.document()?;
+    build(document, name, database_type)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Identifiers, keywords, numbers and colors.
+    Word(String),
+    /// A double-quoted identifier.
+    Quoted(String),
+    /// A single- or triple-quoted string.
+    Str(String),
+    /// A backtick expression.
+    Expr(String),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "`{}`", w),
+            Token::Quoted(q) => write!(f, "\"{}\"", q),
+            Token::Str(_) => f.write_str("a string"),
+            Token::Expr(_) => f.write_str("an expression"),
+            Token::Punct(p) => write!(f, "`{}`", p),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error(self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+struct Spanned {
+    token: Token,
+    position: Position,
+}
+
+const PUNCTUATION: &[&str] = &[
+    "<>", "{", "}", "[", "]", "(", ")", ":", ",", ".", "<", ">", "-",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Spanned>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+
+    // Advances over `n` characters, keeping track of the position
+    let advance = |i: &mut usize, line: &mut usize, column: &mut usize, n: usize| {
+        for _ in 0..n {
+            if chars.get(*i) == Some(&'\n') {
+                *line += 1;
+                *column = 1;
+            } else {
+                *column += 1;
+            }
+            *i += 1;
+        }
+    };
+    let starts_with = |i: usize, s: &str| {
+        s.chars()
+            .enumerate()
+            .all(|(k, c)| chars.get(i + k) == Some(&c))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = Position { line, column };
+        if c.is_whitespace() {
+            advance(&mut i, &mut line, &mut column, 1);
+            continue;
+        }
+        if starts_with(i, "//") {
+            while i < chars.len() && chars[i] != '\n' {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            continue;
+        }
+        if starts_with(i, "/*") {
+            advance(&mut i, &mut line, &mut column, 2);
+            while i < chars.len() && !starts_with(i, "*/") {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            if i >= chars.len() {
+                return Err(position.error("unterminated comment"));
+            }
+            advance(&mut i, &mut line, &mut column, 2);
+            continue;
+        }
+
+        let token = if starts_with(i, "'''") {
+            advance(&mut i, &mut line, &mut column, 3);
+            let mut text = String::new();
+            loop {
+                if i >= chars.len() {
+                    return Err(position.error("unterminated string"));
+                }
+                if starts_with(i, "'''") {
+                    advance(&mut i, &mut line, &mut column, 3);
+                    break;
+                }
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    text.push(chars[i + 1]);
+                    advance(&mut i, &mut line, &mut column, 2);
+                } else {
+                    text.push(chars[i]);
+                    advance(&mut i, &mut line, &mut column, 1);
+                }
+            }
+            Token::Str(dedent(&text))
+        } else if c == '\'' || c == '"' || c == '`' {
+            advance(&mut i, &mut line, &mut column, 1);
+            let mut text = String::new();
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') if c != '`' => {
+                        return Err(position.error("unterminated string"))
+                    }
+                    None => return Err(position.error("unterminated expression")),
+                    Some(&d) if d == c => {
+                        advance(&mut i, &mut line, &mut column, 1);
+                        break;
+                    }
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(match chars[i + 1] {
+                            'n' => '\n',
+                            't' => '\t',
+                            other => other,
+                        });
+                        advance(&mut i, &mut line, &mut column, 2);
+                    }
+                    Some(&d) => {
+                        text.push(d);
+                        advance(&mut i, &mut line, &mut column, 1);
+                    }
+                }
+            }
+            match c {
+                '\'' => Token::Str(text),
+                '"' => Token::Quoted(text),
+                _ => Token::Expr(text),
+            }
+        } else if let Some(punct) = PUNCTUATION.iter().find(|p| starts_with(i, p)) {
+            advance(&mut i, &mut line, &mut column, punct.len());
+            Token::Punct(punct)
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || (chars[i] == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())))
+            {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            Token::Word(chars[start..i].iter().collect())
+        } else if c.is_alphanumeric() || c == '_' || c == '#' {
+            let start = i;
+            advance(&mut i, &mut line, &mut column, 1);
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            Token::Word(chars[start..i].iter().collect())
+        } else {
+            return Err(position.error(format!("unexpected character `{}`", c)));
+        };
+        tokens.push(Spanned { token, position });
+    }
+    Ok(tokens)
+}
+
+/// Triple-quoted strings are written indented with the surrounding block;
+/// the common indentation and the blank first and last lines are removed.
+fn dedent(text: &str) -> String {
+    let text = text.strip_prefix('\n').unwrap_or(text).trim_end();
+    let indent = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|l| l.get(indent..).unwrap_or(l.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Default)]
+struct Document {
+    project_name: Option<String>,
+    database_type: Option<String>,
+    tables: Vec<ParsedTable>,
+    enums: Vec<ParsedEnum>,
+    refs: Vec<ParsedRef>,
+    groups: Vec<ParsedGroup>,
+    notes: Vec<String>,
+}
+
+struct ParsedTable {
+    schema: Option<String>,
+    name: String,
+    alias: Option<String>,
+    color: Option<String>,
+    note: Option<String>,
+    fields: Vec<ParsedField>,
+    indexes: Vec<ParsedIndex>,
+}
+
+#[derive(Default)]
+struct ParsedField {
+    name: String,
+    type_name: String,
+    args: Vec<String>,
+    array: bool,
+    primary_key: bool,
+    unique: bool,
+    not_null: bool,
+    increment: bool,
+    default: Option<String>,
+    note: Option<String>,
+    /// Inline `ref:` settings, with this field as the left endpoint.
+    refs: Vec<(&'static str, Endpoint)>,
+}
+
+struct ParsedIndex {
+    columns: Vec<String>,
+    unique: bool,
+    primary_key: bool,
+    name: Option<String>,
+    position: Position,
+}
+
+struct ParsedEnum {
+    schema: Option<String>,
+    name: String,
+    values: Vec<String>,
+}
+
+struct Endpoint {
+    /// `[table]` or `[schema, table]`.
+    table: Vec<String>,
+    columns: Vec<String>,
+    position: Position,
+}
+
+struct ParsedRef {
+    name: Option<String>,
+    left: Endpoint,
+    operator: &'static str,
+    right: Endpoint,
+}
+
+struct ParsedGroup {
+    name: String,
+    color: Option<String>,
+    tables: Vec<(Vec<String>, Position)>,
+}
+
+enum SettingValue {
+    Text(Token),
+    Ref(&'static str, Endpoint),
+}
+
+struct Setting {
+    key: String,
+    value: Option<SettingValue>,
+}
+
+impl Setting {
+    /// The setting's value as plain text: the contents of a string or quoted
+    /// name, or the word itself.
+    fn text(&self) -> Option<String> {
+        match &self.value {
+            Some(SettingValue::Text(
+                Token::Word(s) | Token::Quoted(s) | Token::Str(s) | Token::Expr(s),
+            )) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|t| &t.token)
+    }
+
+    fn position(&self) -> Position {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some(t) => t.position,
+            None => Position { line: 1, column: 1 },
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.position().error(message)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|t| t.token.clone())
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn at(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.at(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => self.error(format!("expected `{}`, found {}", punct, token)),
+            None => self.error(format!("expected `{}`, found end of input", punct)),
+        })
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Word(w) | Token::Quoted(w) => Ok(w),
+            other => {
+                self.pos -= 1;
+                Err(self.error(format!("expected a name, found {}", other)))
+            }
+        }
+    }
+
+    /// `name` or `schema.name`, and longer paths for columns.
+    fn path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut parts = vec![self.name()?];
+        while self.at(".") && !matches!(self.peek_at(1), Some(Token::Punct("("))) {
+            self.pos += 1;
+            parts.push(self.name()?);
+        }
+        Ok(parts)
+    }
+
+    /// A table name with an optional schema.
+    fn table_name(&mut self) -> Result<(Option<String>, String), ParseError> {
+        let position = self.position();
+        let mut parts = self.path()?;
+        match parts.len() {
+            1 => Ok((None, parts.remove(0))),
+            2 => {
+                let name = parts.remove(1);
+                Ok((Some(parts.remove(0)), name))
+            }
+            _ => Err(position.error(format!("invalid name `{}`", parts.join(".")))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Str(s) | Token::Quoted(s) => Ok(s),
+            other => {
+                self.pos -= 1;
+                Err(self.error(format!("expected a string, found {}", other)))
+            }
+        }
+    }
+
+    /// `Note: '...'` or `Note { '...' }`, after the `Note` keyword.
+    fn note_body(&mut self) -> Result<String, ParseError> {
+        if self.eat(":") {
+            return self.string();
+        }
+        self.expect("{")?;
+        let note = self.string()?;
+        self.expect("}")?;
+        Ok(note)
+    }
+
+    fn at_note(&self) -> bool {
+        self.at_keyword("note") && matches!(self.peek_at(1), Some(Token::Punct(":" | "{")))
+    }
+
+    fn operator(&mut self) -> Result<&'static str, ParseError> {
+        match self.next()? {
+            Token::Punct(p @ ("<>" | "<" | ">" | "-")) => Ok(p),
+            other => {
+                self.pos -= 1;
+                Err(self.error(format!(
+                    "expected a relationship operator (`<`, `>`, `-` or `<>`), found {}",
+                    other
+                )))
+            }
+        }
+    }
+
+    /// `table.column`, `schema.table.column` or `table.(a, b)`.
+    fn endpoint(&mut self) -> Result<Endpoint, ParseError> {
+        let position = self.position();
+        let mut table = self.path()?;
+        let columns = if self.eat(".") {
+            self.expect("(")?;
+            let mut columns = vec![self.name()?];
+            while self.eat(",") {
+                columns.push(self.name()?);
+            }
+            self.expect(")")?;
+            columns
+        } else if table.len() >= 2 {
+            vec![table.pop().unwrap_or_default()]
+        } else {
+            return Err(position.error("expected `table.column`"));
+        };
+        if table.len() > 2 {
+            return Err(position.error(format!("invalid table name `{}`", table.join("."))));
+        }
+        Ok(Endpoint {
+            table,
+            columns,
+            position,
+        })
+    }
+
+    /// A bracketed settings list, e.g. `[pk, not null, default: 0]`.
+    fn settings(&mut self) -> Result<Vec<Setting>, ParseError> {
+        let mut settings = Vec::new();
+        if !self.eat("[") {
+            return Ok(settings);
+        }
+        if self.eat("]") {
+            return Ok(settings);
+        }
+        loop {
+            let position = self.position();
+            let mut words = Vec::new();
+            while let Some(Token::Word(w)) = self.peek() {
+                words.push(w.to_ascii_lowercase());
+                self.pos += 1;
+            }
+            if words.is_empty() {
+                return Err(self.error("expected a setting"));
+            }
+            let key = words.join(" ");
+            let value = if !self.eat(":") {
+                None
+            } else if key == "ref" {
+                let operator = self.operator()?;
+                Some(SettingValue::Ref(operator, self.endpoint()?))
+            } else if self.eat("-") {
+                match self.next()? {
+                    Token::Word(w) => Some(SettingValue::Text(Token::Word(format!("-{}", w)))),
+                    _ => return Err(position.error("expected a number after `-`")),
+                }
+            } else {
+                match self.next()? {
+                    Token::Punct(p) => {
+                        self.pos -= 1;
+                        return Err(self.error(format!("expected a value, found `{}`", p)));
+                    }
+                    token => Some(SettingValue::Text(token)),
+                }
+            };
+            settings.push(Setting { key, value });
+            if self.eat(",") {
+                continue;
+            }
+            self.expect("]")?;
+            return Ok(settings);
+        }
+    }
+
+    fn document(mut self) -> Result<Document, ParseError> {
+        let mut document = Document::default();
+        while let Some(token) = self.peek() {
+            let keyword = match token {
+                Token::Word(w) => w.to_ascii_lowercase(),
+                other => return Err(self.error(format!("unexpected {}", other))),
+            };
+            match keyword.as_str() {
+                "project" => self.project(&mut document)?,
+                "table" => {
+                    let table = self.table()?;
+                    document.tables.push(table);
+                }
+                "enum" => {
+                    let parsed = self.enumeration()?;
+                    document.enums.push(parsed);
+                }
+                "ref" => self.refs(&mut document.refs)?,
+                "tablegroup" => {
+                    let group = self.table_group()?;
+                    document.groups.push(group);
+                }
+                "note" => {
+                    self.pos += 1;
+                    self.name()?;
+                    self.settings()?;
+                    self.expect("{")?;
+                    let content = self.string()?;
+                    self.expect("}")?;
+                    document.notes.push(content);
+                }
+                "tablepartial" | "records" => {
+                    return Err(self.error(format!("`{}` is not supported", keyword)))
+                }
+                _ => {
+                    let found = self.peek().map(Token::to_string).unwrap_or_default();
+                    return Err(self.error(format!(
+                        "expected Project, Table, Enum, Ref, TableGroup or Note, found {}",
+                        found
+                    )));
+                }
+            }
+        }
+        Ok(document)
+    }
+
+    fn project(&mut self, document: &mut Document) -> Result<(), ParseError> {
+        self.pos += 1;
+        if !self.at("{") {
+            document.project_name = Some(self.name()?);
+        }
+        self.expect("{")?;
+        while !self.eat("}") {
+            if self.at_note() {
+                self.pos += 1;
+                self.note_body()?;
+                continue;
+            }
+            let key = self.name()?;
+            self.expect(":")?;
+            let value = self.next()?;
+            if key.eq_ignore_ascii_case("database_type") {
+                if let Token::Str(s) | Token::Quoted(s) | Token::Word(s) = value {
+                    document.database_type = Some(s);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(&mut self) -> Result<ParsedTable, ParseError> {
+        self.pos += 1;
+        let (schema, name) = self.table_name()?;
+        let alias = if self.at_keyword("as") {
+            self.pos += 1;
+            Some(self.name()?)
+        } else {
+            None
+        };
+        let mut table = ParsedTable {
+            schema,
+            name,
+            alias,
+            color: None,
+            note: None,
+            fields: Vec::new(),
+            indexes: Vec::new(),
+        };
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "headercolor" => table.color = setting.text(),
+                "note" => table.note = setting.text(),
+                _ => {}
+            }
+        }
+
+        self.expect("{")?;
+        while !self.eat("}") {
+            if self.at_note() {
+                self.pos += 1;
+                table.note = Some(self.note_body()?);
+            } else if self.at_keyword("indexes")
+                && matches!(self.peek_at(1), Some(Token::Punct("{")))
+            {
+                self.pos += 2;
+                while !self.eat("}") {
+                    let index = self.index()?;
+                    table.indexes.push(index);
+                }
+            } else {
+                let field = self.field()?;
+                table.fields.push(field);
+            }
+        }
+        Ok(table)
+    }
+
+    fn field(&mut self) -> Result<ParsedField, ParseError> {
+        let mut field = ParsedField {
+            name: self.name()?,
+            type_name: self.path()?.join("."),
+            ..ParsedField::default()
+        };
+        if self.eat("(") {
+            while !self.eat(")") {
+                match self.next()? {
+                    Token::Word(w) | Token::Quoted(w) | Token::Str(w) => field.args.push(w),
+                    Token::Punct(",") => {}
+                    other => {
+                        self.pos -= 1;
+                        return Err(self.error(format!("unexpected {} in type", other)));
+                    }
+                }
+            }
+        }
+        if self.at("[") && matches!(self.peek_at(1), Some(Token::Punct("]"))) {
+            self.pos += 2;
+            field.array = true;
+        }
+
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "pk" | "primary key" => field.primary_key = true,
+                "unique" => field.unique = true,
+                "not null" => field.not_null = true,
+                "null" => field.not_null = false,
+                "increment" => field.increment = true,
+                "note" => field.note = setting.text(),
+                "default" => {
+                    field.default = match setting.value {
+                        Some(SettingValue::Text(Token::Str(s))) => {
+                            Some(format!("'{}'", s.replace('\'', "''")))
+                        }
+                        _ => setting.text(),
+                    }
+                }
+                "ref" => {
+                    if let Some(SettingValue::Ref(operator, endpoint)) = setting.value {
+                        field.refs.push((operator, endpoint));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(field)
+    }
+
+    fn index(&mut self) -> Result<ParsedIndex, ParseError> {
+        let position = self.position();
+        let mut columns = Vec::new();
+        if self.eat("(") {
+            while !self.eat(")") {
+                match self.next()? {
+                    Token::Word(w) | Token::Quoted(w) => columns.push(w),
+                    // Expression parts cannot be represented as fields
+                    Token::Expr(_) | Token::Punct(",") => {}
+                    other => {
+                        self.pos -= 1;
+                        return Err(self.error(format!("unexpected {} in index", other)));
+                    }
+                }
+            }
+        } else if matches!(self.peek(), Some(Token::Expr(_))) {
+            self.pos += 1;
+        } else {
+            columns.push(self.name()?);
+        }
+
+        let mut index = ParsedIndex {
+            columns,
+            unique: false,
+            primary_key: false,
+            name: None,
+            position,
+        };
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "unique" => index.unique = true,
+                "pk" | "primary key" => index.primary_key = true,
+                "name" => index.name = setting.text(),
+                _ => {}
+            }
+        }
+        Ok(index)
+    }
+
+    fn enumeration(&mut self) -> Result<ParsedEnum, ParseError> {
+        self.pos += 1;
+        let (schema, name) = self.table_name()?;
+        self.expect("{")?;
+        let mut values = Vec::new();
+        while !self.eat("}") {
+            values.push(self.name()?);
+            self.settings()?;
+        }
+        Ok(ParsedEnum {
+            schema,
+            name,
+            values,
+        })
+    }
+
+    /// `Ref name: a.x > b.y` or a `Ref { ... }` block of several.
+    fn refs(&mut self, refs: &mut Vec<ParsedRef>) -> Result<(), ParseError> {
+        self.pos += 1;
+        let name = if self.at(":") || self.at("{") {
+            None
+        } else {
+            Some(self.name()?)
+        };
+        let block = !self.eat(":");
+        if block {
+            self.expect("{")?;
+        }
+        loop {
+            let left = self.endpoint()?;
+            let operator = self.operator()?;
+            let right = self.endpoint()?;
+            self.settings()?;
+            refs.push(ParsedRef {
+                name: name.clone(),
+                left,
+                operator,
+                right,
+            });
+            if !block || self.eat("}") {
+                return Ok(());
+            }
+        }
+    }
+
+    fn table_group(&mut self) -> Result<ParsedGroup, ParseError> {
+        self.pos += 1;
+        let mut group = ParsedGroup {
+            name: self.name()?,
+            color: None,
+            tables: Vec::new(),
+        };
+        for setting in self.settings()? {
+            if setting.key == "color" {
+                group.color = setting.text();
+            }
+        }
+        self.expect("{")?;
+        while !self.eat("}") {
+            if self.at_note() {
+                self.pos += 1;
+                self.note_body()?;
+                continue;
+            }
+            let position = self.position();
+            group.tables.push((self.path()?, position));
+        }
+        Ok(group)
+    }
+}
+
+fn build(
+    document: Document,
+    name: Option<&str>,
+    database_type: Option<&str>,
+) -> Result<Diagram, ParseError> {
+    let diagram_id = new_id();
+    let now = chrono::Utc::now();
+    let created_at = now.timestamp_millis();
+    let tables = &document.tables;
+
+    let database_type = match database_type {
+        Some(database_type) => database_type.to_string(),
+        None => document
+            .database_type
+            .as_deref()
+            .and_then(|project_type| {
+                DATABASE_TYPES.iter().find(|(chartdb, dbml)| {
+                    dbml.eq_ignore_ascii_case(project_type)
+                        || chartdb.eq_ignore_ascii_case(project_type)
+                })
+            })
+            .map_or("generic", |(chartdb, _)| chartdb)
+            .to_string(),
+    };
+
+    let find_table = |path: &[String], position: Position| -> Result<usize, ParseError> {
+        let found = match path {
+            [schema, name] => tables
+                .iter()
+                .position(|t| &t.name == name && t.schema.as_deref().unwrap_or("public") == schema),
+            [name] => tables
+                .iter()
+                .position(|t| t.alias.as_ref() == Some(name))
+                .or_else(|| tables.iter().position(|t| &t.name == name)),
+            _ => None,
+        };
+        found.ok_or_else(|| position.error(format!("unknown table `{}`", path.join("."))))
+    };
+
+    let table_ids: Vec<String> = tables.iter().map(|_| new_id()).collect();
+    let field_ids: Vec<Vec<String>> = tables
+        .iter()
+        .map(|t| t.fields.iter().map(|_| new_id()).collect())
+        .collect();
+    let find_field = |table: usize, column: &str, position: Position| {
+        tables[table]
+            .fields
+            .iter()
+            .position(|f| f.name == column)
+            .map(|i| field_ids[table][i].clone())
+            .ok_or_else(|| {
+                position.error(format!(
+                    "unknown column `{}` in table `{}`",
+                    column, tables[table].name
+                ))
+            })
+    };
+
+    // Relationships, from Ref definitions and inline `ref:` settings
+    let mut inline = Vec::new();
+    for (t, table) in tables.iter().enumerate() {
+        for field in &table.fields {
+            for (operator, endpoint) in &field.refs {
+                inline.push((t, field.name.clone(), *operator, endpoint));
+            }
+        }
+    }
+    let mut pairs = Vec::new();
+    for r in &document.refs {
+        if r.left.columns.len() != r.right.columns.len() {
+            return Err(r
+                .right
+                .position
+                .error("both sides of a composite ref need the same number of columns"));
+        }
+        let left = find_table(&r.left.table, r.left.position)?;
+        let right = find_table(&r.right.table, r.right.position)?;
+        for (l, c) in r.left.columns.iter().zip(&r.right.columns) {
+            pairs.push((
+                r.name.clone(),
+                (left, find_field(left, l, r.left.position)?),
+                r.operator,
+                (right, find_field(right, c, r.right.position)?),
+            ));
+        }
+    }
+    for (t, column, operator, endpoint) in inline {
+        let right = find_table(&endpoint.table, endpoint.position)?;
+        let column_id = find_field(right, &endpoint.columns[0], endpoint.position)?;
+        let own = field_ids[t][tables[t]
+            .fields
+            .iter()
+            .position(|f| f.name == column)
+            .unwrap_or(0)]
+        .clone();
+        pairs.push((None, (t, own), operator, (right, column_id)));
+    }
+    let relationships: Vec<Relationship> = pairs
+        .into_iter()
+        .map(
+            |(name, (source, source_field), operator, (target, target_field))| {
+                let (source_many, target_many) = match operator {
+                    ">" => (true, false),
+                    "<" => (false, true),
+                    "<>" => (true, true),
+                    _ => (false, false),
+                };
+                let cardinality = |many: bool| if many { "many" } else { "one" }.to_string();
+                Relationship {
+                    id: new_id(),
+                    diagram_id: diagram_id.clone(),
+                    name,
+                    source_schema: tables[source].schema.clone(),
+                    source_table_id: table_ids[source].clone(),
+                    target_schema: tables[target].schema.clone(),
+                    target_table_id: table_ids[target].clone(),
+                    source_field_id: Some(source_field),
+                    target_field_id: Some(target_field),
+                    source_cardinality: Some(cardinality(source_many)),
+                    target_cardinality: Some(cardinality(target_many)),
+                }
+            },
+        )
+        .collect();
+
+    let mut diagram_tables = Vec::new();
+    for (t, table) in tables.iter().enumerate() {
+        let mut fields: Vec<FieldInfo> = table
+            .fields
+            .iter()
+            .zip(&field_ids[t])
+            .map(|(field, id)| field_info(field, id))
+            .collect();
+
+        let mut indexes = Vec::new();
+        for index in &table.indexes {
+            let mut ids = Vec::new();
+            for column in &index.columns {
+                ids.push(find_field(t, column, index.position)?);
+            }
+            if index.primary_key {
+                for field in fields.iter_mut().filter(|f| ids.contains(&f.id)) {
+                    field.primary_key = true;
+                    field.nullable = false;
+                }
+                continue;
+            }
+            if ids.is_empty() {
+                continue;
+            }
+            indexes.push(IndexInfo {
+                id: new_id(),
+                name: index
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_{}_idx", table.name, index.columns.join("_"))),
+                unique: index.unique,
+                field_ids: ids,
+            });
+        }
+
+        diagram_tables.push(Table {
+            id: table_ids[t].clone(),
+            diagram_id: diagram_id.clone(),
+            name: table.name.clone(),
+            schema: table.schema.clone(),
+            x: None,
+            y: None,
+            width: None,
+            color: table.color.clone(),
+            comment: table.note.clone(),
+            is_view: Some(false),
+            is_materialized_view: Some(false),
+            order: Some(t as i32),
+            fields: fields.iter().map(|f| f.to_field(created_at)).collect(),
+            indexes: indexes.iter().map(|i| i.to_index(created_at)).collect(),
+        });
+    }
+
+    let mut groups = Vec::new();
+    for group in &document.groups {
+        let mut members = Vec::new();
+        for (path, position) in &group.tables {
+            members.push(find_table(path, *position)?);
+        }
+        groups.push(layout::Group {
+            name: group.name.clone(),
+            color: group.color.clone(),
+            tables: members,
+        });
+    }
+    let areas = layout::arrange(&diagram_id, &mut diagram_tables, &groups);
+
+    let custom_types = document
+        .enums
+        .iter()
+        .map(|e| CustomType {
+            id: new_id(),
+            diagram_id: diagram_id.clone(),
+            schema: e.schema.clone(),
+            r#type: e.name.clone(),
+            kind: Some("enum".to_string()),
+            values: Some(e.values.clone()),
+            fields: None,
+            extra: Default::default(),
+        })
+        .collect();
+
+    let notes = document
+        .notes
+        .iter()
+        .enumerate()
+        .map(|(i, content)| Note {
+            id: new_id(),
+            diagram_id: diagram_id.clone(),
+            content: Some(content.clone()),
+            x: Some(layout::SIDE_X),
+            y: Some(i as f64 * 160.0),
+            width: Some(192.0),
+            height: Some(128.0),
+            color: None,
+        })
+        .collect();
+
+    Ok(Diagram {
+        id: diagram_id.clone(),
+        name: name
+            .map(str::to_string)
+            .or(document.project_name)
+            .unwrap_or_else(|| "Imported diagram".to_string()),
+        database_type,
+        database_edition: None,
+        created_at: now,
+        updated_at: now,
+        version: None,
+        tables: Some(diagram_tables),
+        relationships: Some(relationships),
+        dependencies: Some(Vec::new()),
+        areas: Some(areas),
+        custom_types: Some(custom_types),
+        notes: Some(notes),
+    })
+}
+
+fn field_info(field: &ParsedField, id: &str) -> FieldInfo {
+    let mut info = FieldInfo {
+        id: id.to_string(),
+        name: field.name.clone(),
+        primary_key: field.primary_key,
+        unique: field.unique,
+        nullable: !field.not_null && !field.primary_key,
+        increment: field.increment,
+        default: field.default.clone(),
+        comment: field.note.clone(),
+        ..FieldInfo::default()
+    };
+    info.set_type(&field.type_name, &field.args);
+    info.is_array = field.array;
+    info
+}
@@ -0,0 +1,148 @@
+//! DBML, the schema language of dbdiagram.io.
+//!
+//! ChartDB concepts map onto DBML as follows: tables and their fields and
+//! indexes are `Table`s, relationships are `Ref`s with the cardinality as the
+//! operator, enum custom types are `Enum`s, areas are `TableGroup`s and
+//! sticky notes are named `Note`s. Table colors and comments become the
+//! `headercolor` setting and the table `Note`.
+
+mod export;
+mod import;
+
+pub use export::export;
+pub use import::import;
+
+/// ChartDB `databaseType` values and their DBML `Project.database_type`.
+const DATABASE_TYPES: &[(&str, &str)] = &[
+    ("postgresql", "PostgreSQL"),
+    ("mysql", "MySQL"),
+    ("mariadb", "MariaDB"),
+    ("sql_server", "SQL Server"),
+    ("sqlite", "SQLite"),
+    ("oracle", "Oracle"),
+    ("clickhouse", "ClickHouse"),
+    ("cockroachdb", "CockroachDB"),
+];
+
+/// The relationship operator for a source and target cardinality: `>` is
+/// many-to-one, `<` one-to-many, `-` one-to-one and `<>` many-to-many.
+fn operator(source_many: bool, target_many: bool) -> &'static str {
+    match (source_many, target_many) {
+        (true, false) => ">",
+        (false, true) => "<",
+        (true, true) => "<>",
+        (false, false) => "-",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::shop;
+    use crate::schema;
+
+    const SOURCE: &str = r#"
+Project "Shop" {
+  database_type: 'PostgreSQL'
+}
+
+Enum "public"."status" {
+  "new"
+  "paid"
+}
+
+Table "public"."users" [headercolor: #ff9f74] {
+  "id" bigint [pk, increment]
+  "email" varchar(320) [not null, unique]
+  "tags" text[]
+  "created_at" timestamptz [not null, default: `now()`]
+
+  Note: 'People who order'
+}
+
+Table "public"."orders" {
+  "id" integer [pk]
+  "user_id" bigint [not null]
+  "status" status [default: 'new', note: 'Where the order is']
+
+  Indexes {
+    ("user_id", "status") [unique, name: "orders_user_status"]
+  }
+}
+
+Ref: "public"."orders"."user_id" > "public"."users"."id"
+
+TableGroup "Sales" {
+  "public"."users"
+  "public"."orders"
+}
+"#;
+
+    #[test]
+    fn exports_read_back_the_same() {
+        let dbml = export(&shop());
+        assert_eq!(export(&import(&dbml, None, None).unwrap()), dbml);
+
+        let imported = import(SOURCE, None, None).unwrap();
+        assert_eq!(export(&imported), SOURCE.trim_start());
+    }
+
+    #[test]
+    fn imports_tables_types_and_groups() {
+        let diagram = import(SOURCE, None, None).unwrap();
+        assert_eq!(diagram.name, "Shop");
+        assert_eq!(diagram.database_type, "postgresql");
+
+        let tables = diagram.tables.as_deref().unwrap();
+        let users = schema::fields(&tables[0]);
+        let columns: Vec<(&str, String, bool)> = users
+            .iter()
+            .map(|f| (f.name.as_str(), f.type_sql(), f.nullable))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("id", "bigint".to_string(), false),
+                ("email", "varchar(320)".to_string(), false),
+                ("tags", "text[]".to_string(), true),
+                ("created_at", "timestamptz".to_string(), false),
+            ]
+        );
+        assert!(users[0].primary_key && users[0].increment);
+        assert!(users[1].unique);
+        assert_eq!(users[3].default.as_deref(), Some("now()"));
+        assert_eq!(tables[0].comment.as_deref(), Some("People who order"));
+        assert_eq!(tables[0].color.as_deref(), Some("#ff9f74"));
+
+        let indexes = schema::indexes(&tables[1]);
+        assert_eq!(indexes[0].name, "orders_user_status");
+        assert!(indexes[0].unique);
+        let orders = schema::fields(&tables[1]);
+        assert_eq!(indexes[0].field_ids, [&*orders[1].id, &*orders[2].id]);
+        assert_eq!(orders[2].default.as_deref(), Some("'new'"));
+
+        let rel = &diagram.relationships.as_deref().unwrap()[0];
+        assert_eq!(
+            (rel.source_table_id.as_str(), rel.target_table_id.as_str()),
+            (tables[1].id.as_str(), tables[0].id.as_str())
+        );
+        assert_eq!(rel.source_cardinality.as_deref(), Some("many"));
+        assert_eq!(rel.target_cardinality.as_deref(), Some("one"));
+
+        let status = &diagram.custom_types.as_deref().unwrap()[0];
+        assert_eq!(schema::enum_values(status), ["new", "paid"]);
+        assert_eq!(
+            diagram.areas.as_deref().unwrap()[0].name.as_deref(),
+            Some("Sales")
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let error = import("Table users {\n  id int [pk\n}", None, None).unwrap_err();
+        assert_eq!((error.line, error.column), (3, 1));
+
+        let error = import("Ref: a.id > b.id", None, None).unwrap_err();
+        assert_eq!(error.line, 1);
+    }
+}
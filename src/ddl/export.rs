@@ -0,0 +1,421 @@
+//! Full DDL for a stored diagram.
+
+use super::{
+    create_index, create_table, foreign_key, mysql, sqlite, sqlserver, string_literal, Dialect,
+    ForeignKey,
+};
+use crate::diff::{self, CustomTypeDef, TableDef};
+use crate::models::{Dependency, Diagram};
+use std::fmt::{self, Write};
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// Constructs of the diagram the dialect cannot express, one entry each.
+    Unrepresentable {
+        dialect: Dialect,
+        problems: Vec<String>,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Unrepresentable { dialect, problems } => write!(
+                f,
+                "The diagram cannot be exported to {}: {}",
+                dialect.as_str(),
+                problems.join("; ")
+            ),
+        }
+    }
+}
+
+/// Renders `diagram` as a script that creates it from scratch: schemas,
+/// types, tables with their indexes and comments, views, and finally the
+/// foreign keys, so that tables can reference each other in any order.
+pub fn export(diagram: &Diagram, dialect: Dialect) -> Result<String, ExportError> {
+    match dialect {
+        Dialect::PostgreSql => Ok(postgres(diagram)),
+        Dialect::MySql | Dialect::MariaDb => mysql::export(diagram, dialect),
+        Dialect::Sqlite => sqlite::export(diagram),
+        Dialect::SqlServer => sqlserver::export(diagram),
+    }
+}
+
+fn postgres(diagram: &Diagram) -> String {
+    let dialect = Dialect::PostgreSql;
+    let defs = diff::diagram_defs(diagram);
+    let tables = ordered(
+        &defs.tables,
+        diagram.dependencies.as_deref().unwrap_or_default(),
+    );
+    let mut out = header(diagram, dialect);
+
+    let mut schemas: Vec<&str> = tables
+        .iter()
+        .filter_map(|t| t.schema.as_deref())
+        .chain(defs.custom_types.iter().filter_map(|c| c.schema.as_deref()))
+        .filter(|s| !s.is_empty() && *s != "public")
+        .collect();
+    schemas.sort_unstable();
+    schemas.dedup();
+    if !schemas.is_empty() {
+        out.push('\n');
+        for schema in schemas {
+            let _ = writeln!(
+                out,
+                "CREATE SCHEMA IF NOT EXISTS {};",
+                dialect.quote(schema)
+            );
+        }
+    }
+
+    // Enums first: composite types may use them
+    let (enums, composites): (Vec<&CustomTypeDef>, Vec<&CustomTypeDef>) =
+        defs.custom_types.iter().partition(|ct| super::is_enum(ct));
+    for ct in enums.into_iter().chain(composites) {
+        let _ = writeln!(out, "\n{};", super::create_type(dialect, ct));
+    }
+
+    for table in &tables {
+        out.push('\n');
+        if table.is_view || table.is_materialized_view {
+            let _ = writeln!(out, "{};", postgres_view(table));
+        } else {
+            let _ = writeln!(out, "{};", create_table(dialect, table, &[]));
+        }
+
+        let kind = if table.is_materialized_view {
+            "MATERIALIZED VIEW"
+        } else if table.is_view {
+            "VIEW"
+        } else {
+            "TABLE"
+        };
+        if let Some(comment) = &table.comment {
+            let _ = writeln!(
+                out,
+                "COMMENT ON {} {} IS {};",
+                kind,
+                dialect.table_name(table),
+                string_literal(comment)
+            );
+        }
+        for field in &table.fields {
+            if let Some(comment) = &field.comment {
+                let _ = writeln!(
+                    out,
+                    "COMMENT ON COLUMN {}.{} IS {};",
+                    dialect.table_name(table),
+                    dialect.quote(&field.name),
+                    string_literal(comment)
+                );
+            }
+        }
+        if !table.is_view || table.is_materialized_view {
+            for index in &table.indexes {
+                let _ = writeln!(out, "{};", create_index(dialect, table, index));
+            }
+        }
+    }
+
+    let foreign_keys = table_foreign_keys(&defs, &tables);
+    if !foreign_keys.is_empty() {
+        out.push('\n');
+        for fk in foreign_keys {
+            let _ = writeln!(
+                out,
+                "ALTER TABLE {} ADD {};",
+                fk.table_name(dialect),
+                fk.constraint(dialect)
+            );
+        }
+    }
+
+    out
+}
+
+pub(super) fn header(diagram: &Diagram, dialect: Dialect) -> String {
+    let mut out = comment(&format!("{} ({})", diagram.name, diagram.id));
+    out.push('\n');
+    let _ = writeln!(
+        out,
+        "-- {} DDL, diagram version {}",
+        dialect.as_str(),
+        diagram.version.unwrap_or_default()
+    );
+    out
+}
+
+/// `text` as `--` comment lines. Every line break starts a new comment line,
+/// so that text taken from the diagram cannot end the comment and leave the
+/// rest of it to run as SQL.
+pub(super) fn comment(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .split(['\r', '\n'])
+        .map(|line| format!("-- {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The diagram stores a view's columns but not its query, so views are
+/// rendered with a placeholder body that yields the right column types.
+fn postgres_view(table: &TableDef) -> String {
+    let dialect = Dialect::PostgreSql;
+    let columns: Vec<String> = table
+        .fields
+        .iter()
+        .map(|f| format!("NULL::{} AS {}", f.type_sql(), dialect.quote(&f.name)))
+        .collect();
+    let select = if columns.is_empty() {
+        "SELECT".to_string()
+    } else {
+        format!("SELECT\n    {}", columns.join(",\n    "))
+    };
+    if table.is_materialized_view {
+        format!(
+            "-- Placeholder body: the view definition is not stored in the diagram\nCREATE MATERIALIZED VIEW {} AS\n{}\nWITH NO DATA",
+            dialect.table_name(table),
+            select
+        )
+    } else {
+        format!(
+            "-- Placeholder body: the view definition is not stored in the diagram\nCREATE VIEW {} AS\n{}",
+            dialect.table_name(table),
+            select
+        )
+    }
+}
+
+/// Foreign keys between real tables; views cannot take part in one.
+pub(super) fn table_foreign_keys(
+    defs: &diff::DiagramDefs,
+    tables: &[&TableDef],
+) -> Vec<ForeignKey> {
+    let is_table = |schema: &Option<String>, name: &str| {
+        tables.iter().any(|t| {
+            t.name == name
+                && t.schema.as_deref().unwrap_or("") == schema.as_deref().unwrap_or("")
+                && !t.is_view
+                && !t.is_materialized_view
+        })
+    };
+    defs.relationships
+        .iter()
+        .filter_map(foreign_key)
+        .filter(|fk| {
+            is_table(&fk.schema, &fk.table) && is_table(&fk.referenced_schema, &fk.referenced_table)
+        })
+        .collect()
+}
+
+/// Orders tables so that everything a table or view depends on (per the
+/// diagram's dependencies) comes before it. Otherwise the diagram order is
+/// kept; tables caught in a cycle are emitted in diagram order at the end.
+pub(super) fn ordered<'a>(
+    tables: &'a [TableDef],
+    dependencies: &[Dependency],
+) -> Vec<&'a TableDef> {
+    let mut remaining: Vec<&TableDef> = tables.iter().collect();
+    let mut done: Vec<&str> = Vec::new();
+    let mut result = Vec::with_capacity(tables.len());
+
+    let blocked = |table: &TableDef, done: &[&str], remaining: &[&TableDef]| {
+        dependencies.iter().any(|dep| {
+            dep.dependent_table_id == table.id
+                && dep.table_id != table.id
+                && !done.contains(&dep.table_id.as_str())
+                && remaining.iter().any(|t| t.id == dep.table_id)
+        })
+    };
+
+    while !remaining.is_empty() {
+        match remaining
+            .iter()
+            .position(|t| !blocked(t, &done, &remaining))
+        {
+            Some(pos) => {
+                let table = remaining.remove(pos);
+                done.push(&table.id);
+                result.push(table);
+            }
+            None => {
+                result.append(&mut remaining);
+            }
+        }
+    }
+    result
+}
+
+/// A column default with the PostgreSQL spellings ChartDB commonly stores
+/// (`now()`, `'x'::type`, `nextval(...)`) recognised, so that each dialect
+/// can render its own equivalent.
+pub(super) enum DefaultValue<'a> {
+    /// Anything else, with a trailing `::type` cast removed.
+    Value(&'a str),
+    CurrentTimestamp,
+    RandomUuid,
+    /// Drawn from a sequence: an auto-increment column.
+    Sequence,
+}
+
+pub(super) fn classify_default(default: &str) -> DefaultValue<'_> {
+    let mut value = default.trim();
+    if let Some(pos) = value.rfind("::") {
+        if !value[pos..].contains('\'') {
+            value = value[..pos].trim();
+        }
+    }
+    match value.to_ascii_lowercase().as_str() {
+        v if v.starts_with("nextval(") => DefaultValue::Sequence,
+        "now()" | "current_timestamp" | "current_timestamp()" | "localtimestamp" => {
+            DefaultValue::CurrentTimestamp
+        }
+        "gen_random_uuid()" | "uuid_generate_v4()" => DefaultValue::RandomUuid,
+        _ => DefaultValue::Value(value),
+    }
+}
+
+/// PostgreSQL's `serial` pseudo-types, which imply an auto-increment column.
+pub(super) fn is_serial(type_name: &str) -> bool {
+    matches!(
+        type_name.trim().to_ascii_lowercase().as_str(),
+        "serial" | "serial2" | "serial4" | "serial8" | "smallserial" | "bigserial"
+    )
+}
+
+/// The custom type a column type refers to, by plain or qualified name.
+pub(super) fn find_custom_type<'a>(
+    name: &str,
+    custom_types: &'a [CustomTypeDef],
+) -> Option<&'a CustomTypeDef> {
+    custom_types.iter().find(|ct| {
+        ct.name.eq_ignore_ascii_case(name) || ct.qualified_name().eq_ignore_ascii_case(name)
+    })
+}
+
+/// Reports the composite types of a diagram, which only PostgreSQL has.
+pub(super) fn check_composite_types(
+    custom_types: &[CustomTypeDef],
+    dialect: Dialect,
+    problems: &mut Vec<String>,
+) {
+    for ct in custom_types {
+        if !super::is_enum(ct) {
+            problems.push(format!(
+                "composite type {} has no {} equivalent",
+                ct.qualified_name(),
+                dialect.as_str()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{dependency, enumeration, field_mut, shop};
+
+    #[test]
+    fn postgres_export() {
+        assert_eq!(
+            export(&shop(), Dialect::PostgreSql).unwrap(),
+            concat!(
+                "-- Test (d1)\n",
+                "-- postgresql DDL, diagram version 0\n",
+                "\n",
+                "CREATE TABLE \"public\".\"users\" (\n",
+                "    \"id\" integer NOT NULL,\n",
+                "    \"email\" varchar(255),\n",
+                "    CONSTRAINT \"users_pkey\" PRIMARY KEY (\"id\")\n",
+                ");\n",
+                "\n",
+                "CREATE TABLE \"public\".\"orders\" (\n",
+                "    \"id\" integer NOT NULL,\n",
+                "    \"user_id\" integer,\n",
+                "    \"total\" numeric(10,2),\n",
+                "    CONSTRAINT \"orders_pkey\" PRIMARY KEY (\"id\")\n",
+                ");\n",
+                "CREATE INDEX \"orders_user_id\" ON \"public\".\"orders\" (\"user_id\");\n",
+                "\n",
+                "ALTER TABLE \"public\".\"orders\" ADD CONSTRAINT \"fk_orders_user_id\" ",
+                "FOREIGN KEY (\"user_id\") REFERENCES \"public\".\"users\" (\"id\");\n",
+            )
+        );
+    }
+
+    #[test]
+    fn names_cannot_end_the_header_comment() {
+        let mut diagram = shop();
+        diagram.name = "Shop\nDROP TABLE users;\r--\r\nx".to_string();
+
+        for dialect in [
+            Dialect::PostgreSql,
+            Dialect::MySql,
+            Dialect::MariaDb,
+            Dialect::Sqlite,
+            Dialect::SqlServer,
+        ] {
+            let sql = export(&diagram, dialect).unwrap();
+            assert!(
+                sql.starts_with("-- Shop\n-- DROP TABLE users;\n-- --\n-- x (d1)\n"),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let mut diagram = shop();
+        diagram.dependencies = Some(vec![dependency("dep", "t_orders", "t_users")]);
+
+        let sql = export(&diagram, Dialect::PostgreSql).unwrap();
+        let orders = sql.find("CREATE TABLE \"public\".\"orders\"").unwrap();
+        let users = sql.find("CREATE TABLE \"public\".\"users\"").unwrap();
+        assert!(orders < users);
+    }
+
+    #[test]
+    fn enums_are_created_before_their_tables() {
+        let mut diagram = shop();
+        diagram.custom_types = Some(vec![enumeration("c_status", "status", &["new", "paid"])]);
+        let total = field_mut(&mut diagram, "t_orders", "f_orders_total");
+        total.r#type.name = "status".to_string();
+        total.precision = None;
+        total.scale = None;
+
+        let sql = export(&diagram, Dialect::PostgreSql).unwrap();
+        let create = sql
+            .find("CREATE TYPE \"public\".\"status\" AS ENUM ('new', 'paid');\n")
+            .unwrap();
+        assert!(create < sql.find("CREATE TABLE").unwrap());
+        assert!(sql.contains("    \"total\" status,\n"));
+    }
+
+    #[test]
+    fn defaults_are_classified() {
+        assert!(matches!(
+            classify_default("nextval('users_id_seq'::regclass)"),
+            DefaultValue::Sequence
+        ));
+        assert!(matches!(
+            classify_default("CURRENT_TIMESTAMP"),
+            DefaultValue::CurrentTimestamp
+        ));
+        assert!(matches!(
+            classify_default("gen_random_uuid()"),
+            DefaultValue::RandomUuid
+        ));
+        assert!(matches!(
+            classify_default("'new'::status"),
+            DefaultValue::Value("'new'")
+        ));
+        assert!(matches!(
+            classify_default("'a::b'"),
+            DefaultValue::Value("'a::b'")
+        ));
+        assert!(is_serial("BIGSERIAL"));
+        assert!(!is_serial("integer"));
+    }
+}
@@ -0,0 +1,976 @@
+//! Building a new diagram from a DDL script.
+//!
+//! Statements are parsed one at a time, so a script dumped with `pg_dump` or
+//! `mysqldump` can be imported as it is: statements that do not describe the
+//! schema (`SET`, `INSERT`, grants, ...) are ignored and statements that do
+//! not parse are reported as warnings instead of failing the import.
+
+use super::Dialect;
+use crate::layout;
+use crate::models::{
+    new_id, CustomType, CustomTypeField, Dependency, Diagram, Relationship, Table,
+};
+use crate::schema::{FieldInfo, IndexInfo};
+use sqlparser::ast::{
+    visit_relations, AlterColumnOperation, AlterTableOperation, ColumnDef, ColumnOption,
+    CommentDef, CommentObject, CreateIndex, CreateTable, DataType, Expr, GeneratedAs, Ident,
+    ObjectName, Query, SelectItem, SetExpr, Statement, TableConstraint,
+    UserDefinedTypeRepresentation,
+};
+use sqlparser::dialect::{
+    Dialect as SqlDialect, MsSqlDialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect,
+};
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+use std::fmt;
+use std::ops::ControlFlow;
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The script could not be split into statements, e.g. because of an
+    /// unterminated string.
+    Syntax {
+        line: u64,
+        column: u64,
+        message: String,
+    },
+    /// Nothing in the script describes a table, view or type.
+    Empty,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            ImportError::Empty => f.write_str(
+                "The script contains no CREATE TABLE, CREATE VIEW or CREATE TYPE statements",
+            ),
+        }
+    }
+}
+
+pub struct Imported {
+    pub diagram: Diagram,
+    /// Statements that were skipped or only partly understood.
+    pub warnings: Vec<String>,
+}
+
+/// Parses `sql` written for `dialect` into a new diagram named `name`, for
+/// `database_type` (the dialect's own by default).
+pub fn import(
+    sql: &str,
+    dialect: Dialect,
+    name: Option<&str>,
+    database_type: Option<&str>,
+) -> Result<Imported, ImportError> {
+    let parser_dialect: Box<dyn SqlDialect> = match dialect {
+        Dialect::PostgreSql => Box::new(PostgreSqlDialect {}),
+        Dialect::MySql | Dialect::MariaDb => Box::new(MySqlDialect {}),
+        Dialect::Sqlite => Box::new(SQLiteDialect {}),
+        Dialect::SqlServer => Box::new(MsSqlDialect {}),
+    };
+    let sql = without_copy_data(sql);
+    let tokens = Tokenizer::new(parser_dialect.as_ref(), &sql)
+        .tokenize_with_location()
+        .map_err(|e| ImportError::Syntax {
+            line: e.location.line,
+            column: e.location.column,
+            message: e.message,
+        })?;
+
+    let mut builder = Builder::new(dialect);
+    for chunk in statements(tokens) {
+        let line = chunk
+            .iter()
+            .find(|t| !matches!(t.token, Token::Whitespace(_)))
+            .map_or(0, |t| t.span.start.line);
+        match Parser::new(parser_dialect.as_ref())
+            .with_tokens_with_locations(chunk)
+            .parse_statement()
+        {
+            Ok(statement) => builder.statement(statement, line),
+            Err(e) => builder
+                .warnings
+                .push(format!("line {}: statement skipped: {}", line, e)),
+        }
+    }
+
+    if builder.tables.is_empty() && builder.custom_types.is_empty() {
+        return Err(ImportError::Empty);
+    }
+    Ok(builder.finish(name, database_type))
+}
+
+/// Blanks out the `COPY ... FROM stdin` blocks of a `pg_dump` script, whose
+/// rows are data rather than SQL. Lines are kept so that positions in
+/// warnings still match the script.
+fn without_copy_data(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut in_data = false;
+    for line in sql.lines() {
+        if in_data {
+            in_data = line != "\\.";
+        } else {
+            let upper = line.trim_end().to_ascii_uppercase();
+            in_data = upper.starts_with("COPY ") && upper.ends_with("FROM STDIN;");
+            if !in_data {
+                out.push_str(line);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Splits a token stream at the `;` between statements, dropping empty ones.
+fn statements(tokens: Vec<TokenWithSpan>) -> Vec<Vec<TokenWithSpan>> {
+    let mut statements = vec![Vec::new()];
+    for token in tokens {
+        match token.token {
+            Token::SemiColon => statements.push(Vec::new()),
+            Token::EOF => {}
+            _ => statements.last_mut().unwrap_or(&mut Vec::new()).push(token),
+        }
+    }
+    statements.retain(|s| s.iter().any(|t| !matches!(t.token, Token::Whitespace(_))));
+    statements
+}
+
+struct TableDraft {
+    id: String,
+    schema: Option<String>,
+    name: String,
+    fields: Vec<FieldInfo>,
+    indexes: Vec<IndexInfo>,
+    comment: Option<String>,
+    is_view: bool,
+    is_materialized_view: bool,
+    /// Tables and views a view selects from.
+    references: Vec<ObjectName>,
+}
+
+struct ForeignKeyDraft {
+    name: Option<String>,
+    table: usize,
+    columns: Vec<String>,
+    referenced: ObjectName,
+    referenced_columns: Vec<String>,
+    line: u64,
+}
+
+struct Builder {
+    dialect: Dialect,
+    diagram_id: String,
+    tables: Vec<TableDraft>,
+    foreign_keys: Vec<ForeignKeyDraft>,
+    custom_types: Vec<CustomType>,
+    warnings: Vec<String>,
+}
+
+impl Builder {
+    fn new(dialect: Dialect) -> Self {
+        Builder {
+            dialect,
+            diagram_id: new_id(),
+            tables: Vec::new(),
+            foreign_keys: Vec::new(),
+            custom_types: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// PostgreSQL folds unquoted identifiers to lower case.
+    fn ident(&self, ident: &Ident) -> String {
+        if self.dialect == Dialect::PostgreSql && ident.quote_style.is_none() {
+            ident.value.to_lowercase()
+        } else {
+            ident.value.clone()
+        }
+    }
+
+    fn default_schema(&self) -> Option<String> {
+        match self.dialect {
+            Dialect::PostgreSql => Some("public".to_string()),
+            Dialect::SqlServer => Some("dbo".to_string()),
+            _ => None,
+        }
+    }
+
+    /// The schema and name of a possibly qualified object name.
+    fn split_name(&self, name: &ObjectName) -> (Option<String>, String) {
+        let parts: Vec<String> = name.0.iter().map(|i| self.ident(i)).collect();
+        match parts.as_slice() {
+            [.., schema, object] => (Some(schema.clone()), object.clone()),
+            [object] => (self.default_schema(), object.clone()),
+            [] => (None, String::new()),
+        }
+    }
+
+    fn find_table(&self, name: &ObjectName) -> Option<usize> {
+        let (schema, object) = self.split_name(name);
+        self.tables
+            .iter()
+            .position(|t| t.name == object && t.schema == schema)
+            .or_else(|| {
+                // An unqualified reference may point into another schema
+                // on the search path
+                (name.0.len() == 1)
+                    .then(|| self.tables.iter().position(|t| t.name == object))
+                    .flatten()
+            })
+    }
+
+    fn table_or_warn(&mut self, name: &ObjectName, line: u64) -> Option<usize> {
+        let found = self.find_table(name);
+        if found.is_none() {
+            self.warnings
+                .push(format!("line {}: unknown table `{}`", line, name));
+        }
+        found
+    }
+
+    fn statement(&mut self, statement: Statement, line: u64) {
+        match statement {
+            Statement::CreateTable(create) => self.create_table(create, line),
+            Statement::CreateIndex(create) => self.create_index(create, line),
+            Statement::AlterTable {
+                name, operations, ..
+            } => {
+                let Some(table) = self.table_or_warn(&name, line) else {
+                    return;
+                };
+                for operation in operations {
+                    self.alter_table(table, operation, line);
+                }
+            }
+            Statement::CreateType {
+                name,
+                representation,
+            } => self.create_type(&name, representation),
+            Statement::CreateView {
+                name,
+                materialized,
+                columns,
+                query,
+                ..
+            } => {
+                let names = columns.iter().map(|c| self.ident(&c.name)).collect();
+                self.create_view(&name, materialized, names, &query);
+            }
+            Statement::Comment {
+                object_type,
+                object_name,
+                comment,
+                ..
+            } => self.comment(object_type, &object_name, comment, line),
+            _ => {}
+        }
+    }
+
+    fn create_table(&mut self, create: CreateTable, line: u64) {
+        if create.query.is_some() {
+            self.warnings.push(format!(
+                "line {}: CREATE TABLE {} AS SELECT skipped: its columns are not declared",
+                line, create.name
+            ));
+            return;
+        }
+        let (schema, name) = self.split_name(&create.name);
+        let table = self.tables.len();
+        self.tables.push(TableDraft {
+            id: new_id(),
+            schema,
+            name,
+            fields: Vec::new(),
+            indexes: Vec::new(),
+            comment: create.comment.map(|c| match c {
+                CommentDef::WithEq(s)
+                | CommentDef::WithoutEq(s)
+                | CommentDef::AfterColumnDefsWithoutEq(s) => s,
+            }),
+            is_view: false,
+            is_materialized_view: false,
+            references: Vec::new(),
+        });
+        for column in create.columns {
+            self.add_column(table, column, line);
+        }
+        for constraint in create.constraints {
+            self.add_constraint(table, constraint, line);
+        }
+    }
+
+    fn add_column(&mut self, table: usize, column: ColumnDef, line: u64) {
+        let mut field = FieldInfo {
+            id: new_id(),
+            name: self.ident(&column.name),
+            nullable: true,
+            collation: column.collation.map(|c| c.to_string()),
+            ..FieldInfo::default()
+        };
+        set_type(&mut field, &column.data_type);
+        if matches!(
+            field.type_name.as_str(),
+            "serial" | "bigserial" | "smallserial" | "serial2" | "serial4" | "serial8"
+        ) {
+            field.increment = true;
+        }
+
+        for option in column.options {
+            match option.option {
+                ColumnOption::Null => field.nullable = true,
+                ColumnOption::NotNull => field.nullable = false,
+                ColumnOption::Default(expr) => set_default(&mut field, &expr),
+                ColumnOption::Unique { is_primary, .. } => {
+                    if is_primary {
+                        field.primary_key = true;
+                        field.nullable = false;
+                    } else {
+                        field.unique = true;
+                    }
+                }
+                ColumnOption::ForeignKey {
+                    foreign_table,
+                    referred_columns,
+                    ..
+                } => self.foreign_keys.push(ForeignKeyDraft {
+                    name: option.name.as_ref().map(|n| self.ident(n)),
+                    table,
+                    columns: vec![field.name.clone()],
+                    referenced: foreign_table,
+                    referenced_columns: referred_columns.iter().map(|c| self.ident(c)).collect(),
+                    line,
+                }),
+                ColumnOption::Comment(comment) => field.comment = Some(comment),
+                ColumnOption::Generated {
+                    generated_as: GeneratedAs::Always | GeneratedAs::ByDefault,
+                    generation_expr: None,
+                    ..
+                } => field.increment = true,
+                ColumnOption::DialectSpecific(tokens) => {
+                    let auto_increment = tokens.iter().any(|t| {
+                        matches!(t, Token::Word(w)
+                            if w.value.eq_ignore_ascii_case("auto_increment")
+                                || w.value.eq_ignore_ascii_case("autoincrement"))
+                    });
+                    field.increment |= auto_increment;
+                }
+                _ => {}
+            }
+        }
+        self.tables[table].fields.push(field);
+    }
+
+    fn add_constraint(&mut self, table: usize, constraint: TableConstraint, line: u64) {
+        match constraint {
+            TableConstraint::PrimaryKey { columns, .. } => {
+                let columns: Vec<String> = columns.iter().map(|c| self.ident(c)).collect();
+                for field in &mut self.tables[table].fields {
+                    if columns.contains(&field.name) {
+                        field.primary_key = true;
+                        field.nullable = false;
+                    }
+                }
+            }
+            TableConstraint::Unique {
+                name,
+                index_name,
+                columns,
+                ..
+            } => {
+                let columns: Vec<String> = columns.iter().map(|c| self.ident(c)).collect();
+                // MySQL's `UNIQUE KEY name (...)` names the index, not a constraint
+                let name = name.or(index_name);
+                if let ([column], None) = (columns.as_slice(), &name) {
+                    if let Some(field) = self.tables[table]
+                        .fields
+                        .iter_mut()
+                        .find(|f| &f.name == column)
+                    {
+                        field.unique = true;
+                        return;
+                    }
+                }
+                let name = name.map(|n| self.ident(&n));
+                self.add_index(table, name, &columns, true, line);
+            }
+            TableConstraint::ForeignKey {
+                name,
+                columns,
+                foreign_table,
+                referred_columns,
+                ..
+            } => self.foreign_keys.push(ForeignKeyDraft {
+                name: name.map(|n| self.ident(&n)),
+                table,
+                columns: columns.iter().map(|c| self.ident(c)).collect(),
+                referenced: foreign_table,
+                referenced_columns: referred_columns.iter().map(|c| self.ident(c)).collect(),
+                line,
+            }),
+            TableConstraint::Index { name, columns, .. }
+            | TableConstraint::FulltextOrSpatial {
+                opt_index_name: name,
+                columns,
+                ..
+            } => {
+                let columns: Vec<String> = columns.iter().map(|c| self.ident(c)).collect();
+                let name = name.map(|n| self.ident(&n));
+                self.add_index(table, name, &columns, false, line);
+            }
+            TableConstraint::Check { .. } => {}
+        }
+    }
+
+    fn add_index(
+        &mut self,
+        table: usize,
+        name: Option<String>,
+        columns: &[String],
+        unique: bool,
+        line: u64,
+    ) {
+        let draft = &self.tables[table];
+        let mut field_ids = Vec::new();
+        for column in columns {
+            match draft.fields.iter().find(|f| &f.name == column) {
+                Some(field) => field_ids.push(field.id.clone()),
+                None => {
+                    self.warnings.push(format!(
+                        "line {}: index on unknown column `{}` of `{}` skipped",
+                        line, column, draft.name
+                    ));
+                    return;
+                }
+            }
+        }
+        let name = name.unwrap_or_else(|| {
+            let suffix = if unique { "key" } else { "idx" };
+            format!("{}_{}_{}", draft.name, columns.join("_"), suffix)
+        });
+        self.tables[table].indexes.push(IndexInfo {
+            id: new_id(),
+            name,
+            unique,
+            field_ids,
+        });
+    }
+
+    fn create_index(&mut self, create: CreateIndex, line: u64) {
+        let Some(table) = self.table_or_warn(&create.table_name, line) else {
+            return;
+        };
+        let mut columns = Vec::new();
+        for column in &create.columns {
+            match &column.expr {
+                Expr::Identifier(ident) => columns.push(self.ident(ident)),
+                Expr::CompoundIdentifier(parts) => {
+                    columns.extend(parts.last().map(|i| self.ident(i)))
+                }
+                other => {
+                    self.warnings.push(format!(
+                        "line {}: expression index on `{}` skipped",
+                        line, other
+                    ));
+                    return;
+                }
+            }
+        }
+        let name = create
+            .name
+            .as_ref()
+            .and_then(|n| n.0.last())
+            .map(|n| self.ident(n));
+        self.add_index(table, name, &columns, create.unique, line);
+    }
+
+    fn alter_table(&mut self, table: usize, operation: AlterTableOperation, line: u64) {
+        match operation {
+            AlterTableOperation::AddConstraint(constraint) => {
+                self.add_constraint(table, constraint, line)
+            }
+            AlterTableOperation::AddColumn { column_def, .. } => {
+                self.add_column(table, column_def, line)
+            }
+            AlterTableOperation::AlterColumn { column_name, op } => {
+                let column = self.ident(&column_name);
+                let Some(field) = self.tables[table]
+                    .fields
+                    .iter_mut()
+                    .find(|f| f.name == column)
+                else {
+                    return;
+                };
+                match op {
+                    AlterColumnOperation::SetNotNull => field.nullable = false,
+                    AlterColumnOperation::DropNotNull => field.nullable = true,
+                    AlterColumnOperation::SetDefault { value } => set_default(field, &value),
+                    AlterColumnOperation::DropDefault => field.default = None,
+                    AlterColumnOperation::SetDataType { data_type, .. } => {
+                        set_type(field, &data_type)
+                    }
+                    AlterColumnOperation::AddGenerated { .. } => field.increment = true,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn create_type(&mut self, name: &ObjectName, representation: UserDefinedTypeRepresentation) {
+        let (schema, type_name) = self.split_name(name);
+        let (kind, values, fields) = match representation {
+            UserDefinedTypeRepresentation::Enum { labels } => {
+                let labels: Vec<String> = labels.into_iter().map(|l| l.value).collect();
+                ("enum", Some(labels), None)
+            }
+            UserDefinedTypeRepresentation::Composite { attributes } => {
+                let fields: Vec<_> = attributes
+                    .iter()
+                    .map(|a| {
+                        let mut field = FieldInfo::default();
+                        set_type(&mut field, &a.data_type);
+                        CustomTypeField {
+                            field: self.ident(&a.name),
+                            r#type: field.type_sql(),
+                            extra: Default::default(),
+                            raw: None,
+                        }
+                    })
+                    .collect();
+                ("composite", None, Some(fields))
+            }
+        };
+        self.custom_types.push(CustomType {
+            id: new_id(),
+            diagram_id: self.diagram_id.clone(),
+            schema,
+            r#type: type_name,
+            kind: Some(kind.to_string()),
+            values,
+            fields,
+            extra: Default::default(),
+        });
+    }
+
+    /// Views get the columns they select. Types are taken from the columns of
+    /// the tables they come from, or from casts; others are `unknown`.
+    fn create_view(
+        &mut self,
+        name: &ObjectName,
+        materialized: bool,
+        names: Vec<String>,
+        query: &Query,
+    ) {
+        let mut references = Vec::new();
+        let _ = visit_relations(query, |relation| {
+            references.push(relation.clone());
+            ControlFlow::<()>::Continue(())
+        });
+        let sources: Vec<usize> = references
+            .iter()
+            .filter_map(|r| self.find_table(r))
+            .collect();
+        let source_field = |builder: &Builder, column: &str| {
+            sources
+                .iter()
+                .flat_map(|t| &builder.tables[*t].fields)
+                .find(|f| f.name == column)
+                .cloned()
+        };
+
+        let mut fields: Vec<FieldInfo> = Vec::new();
+        for item in projection(&query.body) {
+            match item {
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                    for t in &sources {
+                        fields.extend(self.tables[*t].fields.iter().cloned());
+                    }
+                }
+                SelectItem::UnnamedExpr(expr) => {
+                    let column = match expr {
+                        Expr::Identifier(ident) => Some(self.ident(ident)),
+                        Expr::CompoundIdentifier(parts) => parts.last().map(|i| self.ident(i)),
+                        _ => None,
+                    };
+                    let field = column
+                        .as_deref()
+                        .and_then(|c| source_field(self, c))
+                        .unwrap_or_else(|| expression_field(column.unwrap_or_default(), expr));
+                    fields.push(field);
+                }
+                SelectItem::ExprWithAlias { expr, alias } => {
+                    let column = match expr {
+                        Expr::Identifier(ident) => Some(self.ident(ident)),
+                        Expr::CompoundIdentifier(parts) => parts.last().map(|i| self.ident(i)),
+                        _ => None,
+                    };
+                    let mut field = column
+                        .as_deref()
+                        .and_then(|c| source_field(self, c))
+                        .unwrap_or_else(|| expression_field(String::new(), expr));
+                    field.name = self.ident(alias);
+                    fields.push(field);
+                }
+            }
+        }
+        for field in &mut fields {
+            field.id = new_id();
+            field.primary_key = false;
+            field.unique = false;
+            field.increment = false;
+            field.nullable = true;
+            field.default = None;
+            field.comment = None;
+        }
+        for (field, name) in fields.iter_mut().zip(names) {
+            field.name = name;
+        }
+
+        let (schema, view_name) = self.split_name(name);
+        self.tables.push(TableDraft {
+            id: new_id(),
+            schema,
+            name: view_name,
+            fields,
+            indexes: Vec::new(),
+            comment: None,
+            is_view: true,
+            is_materialized_view: materialized,
+            references,
+        });
+    }
+
+    fn comment(
+        &mut self,
+        object_type: CommentObject,
+        object_name: &ObjectName,
+        comment: Option<String>,
+        line: u64,
+    ) {
+        match object_type {
+            CommentObject::Table => {
+                if let Some(table) = self.table_or_warn(object_name, line) {
+                    self.tables[table].comment = comment;
+                }
+            }
+            CommentObject::Column => {
+                let Some((column, table_name)) = object_name.0.split_last() else {
+                    return;
+                };
+                let column = self.ident(column);
+                let table_name = ObjectName(table_name.to_vec());
+                let Some(table) = self.table_or_warn(&table_name, line) else {
+                    return;
+                };
+                if let Some(field) = self.tables[table]
+                    .fields
+                    .iter_mut()
+                    .find(|f| f.name == column)
+                {
+                    field.comment = comment;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self, name: Option<&str>, database_type: Option<&str>) -> Imported {
+        let diagram_id = self.diagram_id.clone();
+        let created_at = chrono::Utc::now();
+
+        let mut relationships = Vec::new();
+        for fk in std::mem::take(&mut self.foreign_keys) {
+            let Some(referenced) = self.table_or_warn(&fk.referenced, fk.line) else {
+                continue;
+            };
+            let source = &self.tables[fk.table];
+            let target = &self.tables[referenced];
+            let referenced_columns = if fk.referenced_columns.is_empty() {
+                target
+                    .fields
+                    .iter()
+                    .filter(|f| f.primary_key)
+                    .map(|f| f.name.clone())
+                    .collect()
+            } else {
+                fk.referenced_columns.clone()
+            };
+            // A foreign key on a unique column makes the relationship one-to-one
+            let one_to_one = match fk.columns.as_slice() {
+                [column] => source
+                    .fields
+                    .iter()
+                    .any(|f| &f.name == column && (f.unique || f.primary_key)),
+                _ => false,
+            };
+            for (column, referenced_column) in fk.columns.iter().zip(&referenced_columns) {
+                let source_field = source.fields.iter().find(|f| &f.name == column);
+                let target_field = target.fields.iter().find(|f| &f.name == referenced_column);
+                let (Some(source_field), Some(target_field)) = (source_field, target_field) else {
+                    self.warnings.push(format!(
+                        "line {}: foreign key {}.{} -> {}.{} references an unknown column",
+                        fk.line, source.name, column, target.name, referenced_column
+                    ));
+                    continue;
+                };
+                relationships.push(Relationship {
+                    id: new_id(),
+                    diagram_id: diagram_id.clone(),
+                    name: fk.name.clone(),
+                    source_schema: source.schema.clone(),
+                    source_table_id: source.id.clone(),
+                    target_schema: target.schema.clone(),
+                    target_table_id: target.id.clone(),
+                    source_field_id: Some(source_field.id.clone()),
+                    target_field_id: Some(target_field.id.clone()),
+                    source_cardinality: Some(if one_to_one { "one" } else { "many" }.to_string()),
+                    target_cardinality: Some("one".to_string()),
+                });
+            }
+        }
+
+        let mut dependencies = Vec::new();
+        for view in self.tables.iter().filter(|t| t.is_view) {
+            let mut seen = Vec::new();
+            for reference in &view.references {
+                let Some(t) = self.find_table(reference) else {
+                    continue;
+                };
+                let table = &self.tables[t];
+                if table.id == view.id || seen.contains(&t) {
+                    continue;
+                }
+                seen.push(t);
+                dependencies.push(Dependency {
+                    id: new_id(),
+                    diagram_id: diagram_id.clone(),
+                    schema: table.schema.clone(),
+                    table_id: table.id.clone(),
+                    dependent_schema: view.schema.clone(),
+                    dependent_table_id: view.id.clone(),
+                });
+            }
+        }
+
+        let timestamp = created_at.timestamp_millis();
+        let mut tables: Vec<Table> = self
+            .tables
+            .iter()
+            .enumerate()
+            .map(|(order, draft)| Table {
+                id: draft.id.clone(),
+                diagram_id: diagram_id.clone(),
+                name: draft.name.clone(),
+                schema: draft.schema.clone(),
+                x: None,
+                y: None,
+                width: None,
+                color: None,
+                comment: draft.comment.clone(),
+                is_view: Some(draft.is_view),
+                is_materialized_view: Some(draft.is_materialized_view),
+                order: Some(order as i32),
+                fields: draft.fields.iter().map(|f| f.to_field(timestamp)).collect(),
+                indexes: draft
+                    .indexes
+                    .iter()
+                    .map(|i| i.to_index(timestamp))
+                    .collect(),
+            })
+            .collect();
+        let areas = layout::arrange(&diagram_id, &mut tables, &[]);
+
+        let diagram = Diagram {
+            id: diagram_id,
+            name: name.unwrap_or("Imported schema").to_string(),
+            database_type: database_type.unwrap_or(self.dialect.as_str()).to_string(),
+            database_edition: None,
+            created_at,
+            updated_at: created_at,
+            version: None,
+            tables: Some(tables),
+            relationships: Some(relationships),
+            dependencies: Some(dependencies),
+            areas: Some(areas),
+            custom_types: Some(self.custom_types),
+            notes: Some(Vec::new()),
+        };
+        Imported {
+            diagram,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// The select list of a query; for set operations, that of the first part.
+fn projection(body: &SetExpr) -> &[SelectItem] {
+    match body {
+        SetExpr::Select(select) => &select.projection,
+        SetExpr::Query(query) => projection(&query.body),
+        SetExpr::SetOperation { left, .. } => projection(left),
+        _ => &[],
+    }
+}
+
+/// A view column computed by an expression, typed by its cast if it has one.
+fn expression_field(name: String, expr: &Expr) -> FieldInfo {
+    let mut field = FieldInfo {
+        name,
+        type_name: "unknown".to_string(),
+        ..FieldInfo::default()
+    };
+    if let Expr::Cast { data_type, .. } = expr {
+        set_type(&mut field, data_type);
+    }
+    field
+}
+
+fn set_type(field: &mut FieldInfo, data_type: &DataType) {
+    field.set_type_sql(&data_type.to_string());
+}
+
+/// Records a column default. A sequence default (how `pg_dump` writes
+/// `serial` columns) marks the column as auto-incrementing.
+fn set_default(field: &mut FieldInfo, expr: &Expr) {
+    let default = expr.to_string();
+    if default.to_ascii_lowercase().starts_with("nextval(") {
+        field.increment = true;
+    }
+    field.default = Some(default);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff;
+    use crate::fixtures::shop;
+    use crate::schema;
+
+    const PG_DUMP: &str = r#"
+SET statement_timeout = 0;
+SELECT pg_catalog.set_config('search_path', '', false);
+
+CREATE TYPE public.status AS ENUM ('new', 'paid');
+
+CREATE TABLE public.users (
+    id bigserial PRIMARY KEY,
+    email character varying(320) NOT NULL UNIQUE,
+    created_at timestamp with time zone DEFAULT now() NOT NULL
+);
+COMMENT ON TABLE public.users IS 'People who order';
+
+CREATE TABLE public.orders (
+    id integer NOT NULL,
+    user_id bigint REFERENCES public.users (id),
+    status public.status DEFAULT 'new'::public.status,
+    lines jsonb
+);
+ALTER TABLE ONLY public.orders ADD CONSTRAINT orders_pkey PRIMARY KEY (id);
+CREATE UNIQUE INDEX orders_user_status ON public.orders USING btree (user_id, status);
+
+CREATE VIEW public.paid_orders AS SELECT id, user_id FROM public.orders WHERE status = 'paid';
+
+COPY public.users (id, email, created_at) FROM stdin;
+1	a@example.com	2024-01-01
+\.
+
+GRANT SELECT ON public.orders TO reporting;
+CREATE STATISTICS nonsense ON (((;
+"#;
+
+    fn table<'a>(diagram: &'a Diagram, name: &str) -> &'a Table {
+        diagram
+            .tables
+            .iter()
+            .flatten()
+            .find(|t| t.name == name)
+            .unwrap_or_else(|| panic!("no table {}", name))
+    }
+
+    #[test]
+    fn exported_ddl_reads_back_as_the_same_diagram() {
+        let sql = crate::ddl::export::export(&shop(), Dialect::PostgreSql).unwrap();
+        let imported = import(&sql, Dialect::PostgreSql, None, None).unwrap();
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+
+        let diff = diff::compare(&shop(), &imported.diagram);
+        assert!(diff.tables_added.is_empty() && diff.tables_removed.is_empty());
+        assert!(diff.tables_changed.is_empty(), "{:#?}", diff.tables_changed);
+        // The foreign key's constraint name becomes the relationship's
+        assert_eq!(diff.relationships_changed.len(), 1);
+        assert_eq!(diff.relationships_changed[0].changed, ["name"]);
+    }
+
+    #[test]
+    fn reads_a_pg_dump() {
+        let imported = import(PG_DUMP, Dialect::PostgreSql, Some("Dump"), None).unwrap();
+        let diagram = &imported.diagram;
+        assert_eq!(diagram.name, "Dump");
+        assert_eq!(diagram.database_type, "postgresql");
+
+        let users = table(diagram, "users");
+        assert_eq!(users.comment.as_deref(), Some("People who order"));
+        let fields = schema::fields(users);
+        assert!(fields[0].primary_key && fields[0].increment);
+        assert_eq!(fields[1].type_sql(), "character varying(320)");
+        assert!(fields[1].unique && !fields[1].nullable);
+        assert_eq!(fields[2].default.as_deref(), Some("now()"));
+
+        let orders = table(diagram, "orders");
+        let fields = schema::fields(orders);
+        assert!(fields[0].primary_key);
+        let indexes = schema::indexes(orders);
+        assert_eq!(indexes[0].name, "orders_user_status");
+        assert!(indexes[0].unique);
+        assert_eq!(indexes[0].field_ids, [&*fields[1].id, &*fields[2].id]);
+
+        let rel = &diagram.relationships.as_deref().unwrap()[0];
+        assert_eq!(rel.source_table_id, orders.id);
+        assert_eq!(rel.target_table_id, users.id);
+
+        let status = &diagram.custom_types.as_deref().unwrap()[0];
+        assert_eq!(schema::enum_values(status), ["new", "paid"]);
+
+        let view = table(diagram, "paid_orders");
+        assert_eq!(view.is_view, Some(true));
+        let dependency = &diagram.dependencies.as_deref().unwrap()[0];
+        assert_eq!(
+            (&dependency.table_id, &dependency.dependent_table_id),
+            (&orders.id, &view.id)
+        );
+
+        assert_eq!(imported.warnings.len(), 1, "{:?}", imported.warnings);
+    }
+
+    #[test]
+    fn reads_mysql() {
+        let sql = "CREATE TABLE `users` (\n\
+            `id` int NOT NULL AUTO_INCREMENT,\n\
+            `role` enum('admin','guest') DEFAULT 'guest',\n\
+            PRIMARY KEY (`id`)\n\
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;";
+        let imported = import(sql, Dialect::MySql, None, None).unwrap();
+        assert_eq!(imported.diagram.database_type, "mysql");
+        let fields = schema::fields(table(&imported.diagram, "users"));
+        assert!(fields[0].primary_key && fields[0].increment);
+        assert_eq!(fields[1].default.as_deref(), Some("'guest'"));
+    }
+
+    #[test]
+    fn scripts_without_a_schema_are_rejected() {
+        let error = import("SELECT 1;", Dialect::PostgreSql, None, None).err();
+        assert!(matches!(error, Some(ImportError::Empty)));
+
+        let error = import(
+            "CREATE TABLE t (\n  a text DEFAULT 'x\n);",
+            Dialect::Sqlite,
+            None,
+            None,
+        );
+        assert!(matches!(error, Err(ImportError::Syntax { line: 2, .. })));
+    }
+}
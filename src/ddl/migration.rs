@@ -0,0 +1,1106 @@
+//! Migration scripts turning one diagram state into another.
+//!
+//! Statements are ordered so each one is valid when it runs: foreign keys and
+//! indexes that go away are dropped first, new types and tables are created
+//! before the tables that change, and new foreign keys come last. SQLite
+//! tables that cannot be altered in place are rebuilt. Changes the dialect
+//! cannot express at all are left in the script as `--` comments.
+
+use super::{
+    column_definition, create_index, create_table, create_type, default_constraint_name,
+    drop_index, export, foreign_key, increment_clause, is_enum, primary_key_clause,
+    primary_key_name, sqlite, string_literal, unique_constraint_name, Dialect, ForeignKey,
+};
+use crate::diff::{self, CustomTypeDef, CustomTypeDiff, FieldDiff, TableDef, TableDiff};
+use crate::models::Diagram;
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+/// Renders the statements that turn `from` into `to`. The rollback script is
+/// the same function with the arguments swapped.
+pub fn generate(from: &Diagram, to: &Diagram, dialect: Dialect) -> String {
+    let diff = diff::compare(from, to);
+    let target = diff::diagram_defs(to);
+    let mut script = Script {
+        dialect,
+        sql: String::new(),
+        foreign_keys: target
+            .relationships
+            .iter()
+            .filter_map(foreign_key)
+            .collect(),
+        custom_types: target.custom_types,
+        rebuilt: Vec::new(),
+    };
+    if diff.is_empty() {
+        script.note("No structural changes.");
+        return script.sql;
+    }
+
+    let dropped_fks: Vec<ForeignKey> = diff
+        .relationships_removed
+        .iter()
+        .chain(diff.relationships_changed.iter().map(|r| &r.before))
+        .filter_map(foreign_key)
+        .collect();
+    let mut added_fks: Vec<ForeignKey> = diff
+        .relationships_added
+        .iter()
+        .chain(diff.relationships_changed.iter().map(|r| &r.after))
+        .filter_map(foreign_key)
+        .collect();
+
+    // A primary key cannot be dropped while foreign keys in other tables
+    // reference it: those that stay are dropped first and recreated once the
+    // key has changed. SQLite rebuilds the table instead, which keeps them.
+    if dialect != Dialect::Sqlite {
+        let rekeyed: Vec<&TableDef> = diff
+            .tables_changed
+            .iter()
+            .filter(|t| primary_key_changed(t))
+            .map(|t| &t.before)
+            .collect();
+        let touched = |id: &str| {
+            diff.relationships_removed.iter().any(|r| r.id == id)
+                || diff.relationships_changed.iter().any(|r| r.before.id == id)
+        };
+        let referencing = diff::diagram_defs(from)
+            .relationships
+            .iter()
+            .filter(|r| !touched(&r.id))
+            .filter_map(foreign_key)
+            .filter(|fk| rekeyed.iter().any(|t| references(fk, t)))
+            .collect::<Vec<_>>();
+        for fk in referencing {
+            script.drop_foreign_key(&fk);
+            added_fks.push(fk);
+        }
+    }
+
+    // SQLite can neither add nor drop a foreign key on an existing table;
+    // tables whose foreign keys change are rebuilt instead.
+    let mut rebuilds: Vec<&str> = Vec::new();
+    if dialect == Dialect::Sqlite {
+        for fk in dropped_fks.iter().chain(&added_fks) {
+            let existing = !diff.tables_added.iter().any(|t| fk_on(fk, t))
+                && !diff.tables_removed.iter().any(|t| fk_on(fk, t));
+            if existing && !rebuilds.contains(&fk.table.as_str()) {
+                rebuilds.push(&fk.table);
+            }
+        }
+        // Enum columns are CHECK constraints, which cannot be altered either
+        for ct in diff.custom_types_changed.iter().map(|c| &c.after) {
+            if !is_enum(ct) {
+                continue;
+            }
+            for table in &target.tables {
+                let uses_type = table.fields.iter().any(|f| {
+                    f.type_name.eq_ignore_ascii_case(&ct.name)
+                        || f.type_name.eq_ignore_ascii_case(&ct.qualified_name())
+                });
+                if uses_type && !rebuilds.contains(&table.name.as_str()) {
+                    rebuilds.push(&table.name);
+                }
+            }
+        }
+    } else {
+        for fk in &dropped_fks {
+            script.drop_foreign_key(fk);
+        }
+    }
+
+    for table in &diff.tables_changed {
+        let dropped = table
+            .indexes_removed
+            .iter()
+            .chain(table.indexes_changed.iter().map(|i| &i.before));
+        for index in dropped {
+            script.statement(drop_index(dialect, &table.before, index));
+        }
+    }
+
+    for ct in &diff.custom_types_added {
+        script.create_type(ct);
+    }
+    for ct in &diff.custom_types_changed {
+        script.alter_type(ct);
+    }
+
+    for table in &diff.tables_added {
+        script.create_table(table);
+    }
+
+    for table in &diff.tables_changed {
+        let rebuild = rebuilds.contains(&table.after.name.as_str());
+        script.alter_table(table, rebuild);
+    }
+    for name in rebuilds {
+        if !script.rebuilt.iter().any(|t| t == name) {
+            if let Some(table) = target.tables.iter().find(|t| t.name == name) {
+                script.rebuild_sqlite_table(table, table);
+            }
+        }
+    }
+
+    for table in &diff.tables_changed {
+        if script.rebuilt.contains(&table.after.name) {
+            continue;
+        }
+        let created = table
+            .indexes_added
+            .iter()
+            .chain(table.indexes_changed.iter().map(|i| &i.after));
+        for index in created {
+            script.statement(create_index(dialect, &table.after, index));
+        }
+    }
+
+    for table in &diff.tables_removed {
+        script.drop_table(table);
+    }
+    for ct in &diff.custom_types_removed {
+        script.drop_type(ct);
+    }
+
+    // SQLite foreign keys were declared by CREATE TABLE or a rebuild
+    if dialect != Dialect::Sqlite {
+        for fk in &added_fks {
+            script.add_foreign_key(fk);
+        }
+    }
+
+    script.sql
+}
+
+fn fk_on(fk: &ForeignKey, table: &TableDef) -> bool {
+    fk.table == table.name
+        && fk.schema.as_deref().unwrap_or("") == table.schema.as_deref().unwrap_or("")
+}
+
+fn references(fk: &ForeignKey, table: &TableDef) -> bool {
+    fk.referenced_table == table.name
+        && fk.referenced_schema.as_deref().unwrap_or("") == table.schema.as_deref().unwrap_or("")
+}
+
+fn primary_key_changed(diff: &TableDiff) -> bool {
+    let key = |table: &TableDef| -> Vec<String> {
+        table
+            .fields
+            .iter()
+            .filter(|f| f.primary_key)
+            .map(|f| f.id.clone())
+            .collect()
+    };
+    key(&diff.before) != key(&diff.after)
+}
+
+struct Script {
+    dialect: Dialect,
+    sql: String,
+    /// Foreign keys and custom types of the target state, for SQLite tables
+    /// that are created or rebuilt with everything declared inline.
+    foreign_keys: Vec<ForeignKey>,
+    custom_types: Vec<CustomTypeDef>,
+    /// Tables rebuilt from scratch, which already have all their indexes.
+    rebuilt: Vec<String>,
+}
+
+impl Script {
+    fn statement(&mut self, sql: impl AsRef<str>) {
+        let _ = writeln!(self.sql, "{};", sql.as_ref());
+    }
+
+    fn note(&mut self, text: impl AsRef<str>) {
+        let _ = writeln!(self.sql, "{}", export::comment(text.as_ref()));
+    }
+
+    fn quote(&self, ident: &str) -> String {
+        self.dialect.quote(ident)
+    }
+
+    fn create_table(&mut self, table: &TableDef) {
+        let dialect = self.dialect;
+        if table.is_view || table.is_materialized_view {
+            self.note(format!(
+                "View {} added; its definition is not stored in the diagram",
+                table.qualified_name()
+            ));
+            return;
+        }
+
+        if dialect == Dialect::Sqlite {
+            let sql = self.sqlite_create_table(table, &table.name);
+            self.statement(sql);
+        } else {
+            self.statement(create_table(dialect, table, &[]));
+        }
+        if dialect == Dialect::PostgreSql {
+            if let Some(comment) = &table.comment {
+                self.statement(format!(
+                    "COMMENT ON TABLE {} IS {}",
+                    dialect.table_name(table),
+                    string_literal(comment)
+                ));
+            }
+            for field in &table.fields {
+                self.comment_on_column(table, field);
+            }
+        }
+        for index in &table.indexes {
+            self.statement(create_index(dialect, table, index));
+        }
+    }
+
+    fn drop_table(&mut self, table: &TableDef) {
+        let name = self.dialect.table_name(table);
+        if table.is_materialized_view && self.dialect == Dialect::PostgreSql {
+            self.statement(format!("DROP MATERIALIZED VIEW {}", name));
+        } else if table.is_view || table.is_materialized_view {
+            self.statement(format!("DROP VIEW {}", name));
+        } else {
+            self.statement(format!("DROP TABLE {}", name));
+        }
+    }
+
+    /// `rebuild` forces SQLite to rebuild the table, e.g. because its foreign
+    /// keys changed.
+    fn alter_table(&mut self, diff: &TableDiff, rebuild: bool) {
+        let dialect = self.dialect;
+        let (before, after) = (&diff.before, &diff.after);
+        if before.is_view || after.is_view {
+            self.note(format!(
+                "View {} changed; views are not migrated",
+                after.qualified_name()
+            ));
+            return;
+        }
+
+        let pk_changed = primary_key_changed(diff);
+
+        if dialect == Dialect::Sqlite {
+            self.alter_sqlite_table(diff, pk_changed || rebuild);
+            return;
+        }
+
+        // Constraints that go away are dropped under the old names, before
+        // anything is renamed.
+        if pk_changed && before.fields.iter().any(|f| f.primary_key) {
+            self.drop_primary_key(before);
+        }
+        for field in &diff.fields_changed {
+            if field.before.unique && !field.after.unique && !field.before.primary_key {
+                self.drop_unique(before, &field.before);
+            }
+        }
+        if dialect == Dialect::SqlServer {
+            // SQL Server refuses to drop columns that still carry constraints
+            for field in &diff.fields_removed {
+                if field.default.is_some() {
+                    self.drop_constraint(
+                        before,
+                        &default_constraint_name(&before.name, &field.name),
+                    );
+                }
+                if field.unique && !field.primary_key {
+                    self.drop_unique(before, field);
+                }
+            }
+        }
+
+        if before.name != after.name || before.schema != after.schema {
+            self.rename_table(diff, pk_changed);
+        }
+        for field in &diff.fields_changed {
+            if field.changed.contains(&"name") {
+                self.rename_column(after, field);
+            }
+        }
+
+        let table = dialect.table_name(after);
+        for field in &diff.fields_added {
+            let keyword = if dialect == Dialect::SqlServer {
+                "ADD"
+            } else {
+                "ADD COLUMN"
+            };
+            self.statement(format!(
+                "ALTER TABLE {} {} {}",
+                table,
+                keyword,
+                column_definition(dialect, after, field)
+            ));
+            if dialect == Dialect::PostgreSql {
+                self.comment_on_column(after, field);
+            }
+        }
+        for field in &diff.fields_changed {
+            self.alter_column(after, field);
+        }
+        for field in &diff.fields_removed {
+            self.statement(format!(
+                "ALTER TABLE {} DROP COLUMN {}",
+                table,
+                self.quote(&field.name)
+            ));
+        }
+
+        for field in &diff.fields_changed {
+            if field.after.unique && !field.before.unique && !field.after.primary_key {
+                self.add_unique(after, &field.after);
+            }
+        }
+        if pk_changed {
+            if let Some(clause) = primary_key_clause(dialect, after) {
+                self.statement(format!("ALTER TABLE {} ADD {}", table, clause));
+            }
+        }
+
+        if let Some(comment) = &diff.comment {
+            match dialect {
+                Dialect::PostgreSql => self.statement(format!(
+                    "COMMENT ON TABLE {} IS {}",
+                    table,
+                    comment
+                        .to
+                        .as_deref()
+                        .map(string_literal)
+                        .unwrap_or_else(|| "NULL".to_string())
+                )),
+                Dialect::MySql | Dialect::MariaDb => self.statement(format!(
+                    "ALTER TABLE {} COMMENT = {}",
+                    table,
+                    string_literal(comment.to.as_deref().unwrap_or(""))
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    /// SQLite's ALTER TABLE only renames tables and columns and adds or drops
+    /// plain columns; anything else rebuilds the table.
+    fn alter_sqlite_table(&mut self, diff: &TableDiff, rebuild: bool) {
+        let (before, after) = (&diff.before, &diff.after);
+        let needs_rebuild = rebuild
+            || diff
+                .fields_changed
+                .iter()
+                .any(|f| f.changed.iter().any(|c| *c != "name" && *c != "comment"))
+            || diff.fields_added.iter().any(|f| f.primary_key || f.unique);
+        if needs_rebuild {
+            self.rebuild_sqlite_table(before, after);
+            return;
+        }
+
+        if before.name != after.name {
+            self.statement(format!(
+                "ALTER TABLE {} RENAME TO {}",
+                self.dialect.table_name(before),
+                self.quote(&after.name)
+            ));
+        }
+        let table = self.dialect.table_name(after);
+        for field in &diff.fields_changed {
+            if field.changed.contains(&"name") {
+                self.statement(format!(
+                    "ALTER TABLE {} RENAME COLUMN {} TO {}",
+                    table,
+                    self.quote(&field.before.name),
+                    self.quote(&field.after.name)
+                ));
+            }
+        }
+        for field in &diff.fields_added {
+            self.statement(format!(
+                "ALTER TABLE {} ADD COLUMN {}",
+                table,
+                column_definition(self.dialect, after, field)
+            ));
+        }
+        for field in &diff.fields_removed {
+            self.statement(format!(
+                "ALTER TABLE {} DROP COLUMN {}",
+                table,
+                self.quote(&field.name)
+            ));
+        }
+    }
+
+    /// The rebuild procedure from the SQLite documentation: create the new
+    /// table under a temporary name, copy the surviving columns over, drop
+    /// the old table and rename the new one into place.
+    fn rebuild_sqlite_table(&mut self, before: &TableDef, after: &TableDef) {
+        let dialect = self.dialect;
+        let temp = format!("_new_{}", after.name);
+        let (columns, sources): (Vec<String>, Vec<String>) = after
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let old = before.fields.iter().find(|f| f.id == field.id)?;
+                Some((self.quote(&field.name), self.quote(&old.name)))
+            })
+            .unzip();
+
+        self.statement("PRAGMA foreign_keys = OFF");
+        let sql = self.sqlite_create_table(after, &temp);
+        self.statement(sql);
+        if !columns.is_empty() {
+            self.statement(format!(
+                "INSERT INTO {} ({}) SELECT {} FROM {}",
+                self.quote(&temp),
+                columns.join(", "),
+                sources.join(", "),
+                dialect.table_name(before)
+            ));
+        }
+        self.statement(format!("DROP TABLE {}", dialect.table_name(before)));
+        self.statement(format!(
+            "ALTER TABLE {} RENAME TO {}",
+            self.quote(&temp),
+            self.quote(&after.name)
+        ));
+        for index in &after.indexes {
+            self.statement(create_index(dialect, after, index));
+        }
+        self.statement("PRAGMA foreign_keys = ON");
+        self.rebuilt.push(after.name.clone());
+    }
+
+    /// SQLite `CREATE TABLE` for `table` under `name`, with the target
+    /// state's foreign keys declared inline.
+    fn sqlite_create_table(&mut self, table: &TableDef, name: &str) -> String {
+        let foreign_keys: Vec<&ForeignKey> = self
+            .foreign_keys
+            .iter()
+            .filter(|fk| fk_on(fk, table))
+            .collect();
+        let mut renamed = table.clone();
+        renamed.name = name.to_string();
+        let mut problems = Vec::new();
+        let sql = sqlite::create_table(&renamed, &self.custom_types, &foreign_keys, &mut problems);
+        for problem in problems {
+            self.note(problem);
+        }
+        sql
+    }
+
+    fn rename_table(&mut self, diff: &TableDiff, pk_changed: bool) {
+        let dialect = self.dialect;
+        let (before, after) = (&diff.before, &diff.after);
+        match dialect {
+            Dialect::PostgreSql => {
+                let mut current = dialect.table_name(before);
+                if before.schema != after.schema {
+                    self.statement(format!(
+                        "ALTER TABLE {} SET SCHEMA {}",
+                        current,
+                        self.quote(after.schema.as_deref().unwrap_or("public"))
+                    ));
+                    current = dialect.qualified(after.schema.as_deref(), &before.name);
+                }
+                if before.name != after.name {
+                    self.statement(format!(
+                        "ALTER TABLE {} RENAME TO {}",
+                        current,
+                        self.quote(&after.name)
+                    ));
+                }
+            }
+            Dialect::MySql | Dialect::MariaDb => self.statement(format!(
+                "RENAME TABLE {} TO {}",
+                dialect.table_name(before),
+                dialect.table_name(after)
+            )),
+            Dialect::SqlServer => {
+                let mut current = dialect.table_name(before);
+                if before.schema != after.schema {
+                    self.statement(format!(
+                        "ALTER SCHEMA {} TRANSFER {}",
+                        self.quote(after.schema.as_deref().unwrap_or("dbo")),
+                        current
+                    ));
+                    current = dialect.qualified(after.schema.as_deref(), &before.name);
+                }
+                if before.name != after.name {
+                    self.statement(format!(
+                        "EXEC sp_rename {}, {}",
+                        string_literal(&current),
+                        string_literal(&after.name)
+                    ));
+                }
+            }
+            Dialect::Sqlite => {}
+        }
+
+        if before.name == after.name || dialect.is_mysql_family() {
+            return;
+        }
+        // Keep the constraint names derived from the table name in step with it
+        let mut renames = Vec::new();
+        if !pk_changed && before.fields.iter().any(|f| f.primary_key) {
+            renames.push((
+                primary_key_name(&before.name),
+                primary_key_name(&after.name),
+            ));
+        }
+        for field in &before.fields {
+            if field.unique && !field.primary_key && still_unique(diff, field) {
+                renames.push((
+                    unique_constraint_name(&before.name, &field.name),
+                    unique_constraint_name(&after.name, &field.name),
+                ));
+            }
+            if dialect == Dialect::SqlServer && field.default.is_some() && still_exists(diff, field)
+            {
+                renames.push((
+                    default_constraint_name(&before.name, &field.name),
+                    default_constraint_name(&after.name, &field.name),
+                ));
+            }
+        }
+        for (from, to) in renames {
+            self.rename_constraint(after, &from, &to);
+        }
+    }
+
+    fn rename_column(&mut self, table: &TableDef, field: &FieldDiff) {
+        let dialect = self.dialect;
+        let (old, new) = (&field.before.name, &field.after.name);
+        match dialect {
+            Dialect::SqlServer => {
+                let path = format!("{}.{}", dialect.table_name(table), self.quote(old));
+                self.statement(format!(
+                    "EXEC sp_rename {}, {}, 'COLUMN'",
+                    string_literal(&path),
+                    string_literal(new)
+                ));
+            }
+            _ => self.statement(format!(
+                "ALTER TABLE {} RENAME COLUMN {} TO {}",
+                dialect.table_name(table),
+                self.quote(old),
+                self.quote(new)
+            )),
+        }
+
+        if dialect.is_mysql_family() {
+            return;
+        }
+        if field.before.unique && field.after.unique && !field.after.primary_key {
+            self.rename_constraint(
+                table,
+                &unique_constraint_name(&table.name, old),
+                &unique_constraint_name(&table.name, new),
+            );
+        }
+        if dialect == Dialect::SqlServer && field.before.default.is_some() {
+            self.rename_constraint(
+                table,
+                &default_constraint_name(&table.name, old),
+                &default_constraint_name(&table.name, new),
+            );
+        }
+    }
+
+    fn alter_column(&mut self, table: &TableDef, field: &FieldDiff) {
+        let dialect = self.dialect;
+        let name = dialect.table_name(table);
+        let column = self.quote(&field.after.name);
+        let changed = |property: &str| field.changed.contains(&property);
+
+        match dialect {
+            Dialect::PostgreSql => {
+                if changed("type") || changed("collation") {
+                    let collation = field
+                        .after
+                        .collation
+                        .as_deref()
+                        .map(|c| format!(" COLLATE {}", self.quote(c)))
+                        .unwrap_or_default();
+                    self.statement(format!(
+                        "ALTER TABLE {} ALTER COLUMN {} TYPE {}{}",
+                        name,
+                        column,
+                        field.after.type_sql(),
+                        collation
+                    ));
+                }
+                if changed("nullable") && !field.after.primary_key {
+                    let action = if field.after.nullable { "DROP" } else { "SET" };
+                    self.statement(format!(
+                        "ALTER TABLE {} ALTER COLUMN {} {} NOT NULL",
+                        name, column, action
+                    ));
+                }
+                if changed("increment") {
+                    if increment_clause(dialect, &field.after).is_some() {
+                        self.statement(format!(
+                            "ALTER TABLE {} ALTER COLUMN {} ADD GENERATED BY DEFAULT AS IDENTITY",
+                            name, column
+                        ));
+                    } else if !field.after.increment {
+                        self.statement(format!(
+                            "ALTER TABLE {} ALTER COLUMN {} DROP IDENTITY IF EXISTS",
+                            name, column
+                        ));
+                    }
+                }
+                if changed("default") {
+                    match &field.after.default {
+                        Some(default) => self.statement(format!(
+                            "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {}",
+                            name, column, default
+                        )),
+                        None => self.statement(format!(
+                            "ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT",
+                            name, column
+                        )),
+                    }
+                }
+                if changed("comment") {
+                    self.statement(format!(
+                        "COMMENT ON COLUMN {}.{} IS {}",
+                        name,
+                        column,
+                        field
+                            .after
+                            .comment
+                            .as_deref()
+                            .map(string_literal)
+                            .unwrap_or_else(|| "NULL".to_string())
+                    ));
+                }
+            }
+            Dialect::MySql | Dialect::MariaDb => {
+                let redefine = [
+                    "type",
+                    "nullable",
+                    "increment",
+                    "default",
+                    "collation",
+                    "comment",
+                ]
+                .iter()
+                .any(|p| changed(p));
+                if redefine {
+                    self.statement(format!(
+                        "ALTER TABLE {} MODIFY COLUMN {}",
+                        name,
+                        column_definition(
+                            dialect,
+                            table,
+                            &FieldInfo {
+                                unique: false,
+                                ..field.after.clone()
+                            }
+                        )
+                    ));
+                }
+            }
+            Dialect::SqlServer => {
+                if changed("type") || changed("nullable") || changed("collation") {
+                    let collation = field
+                        .after
+                        .collation
+                        .as_deref()
+                        .map(|c| format!(" COLLATE {}", c))
+                        .unwrap_or_default();
+                    let null = if field.after.nullable && !field.after.primary_key {
+                        "NULL"
+                    } else {
+                        "NOT NULL"
+                    };
+                    self.statement(format!(
+                        "ALTER TABLE {} ALTER COLUMN {} {}{} {}",
+                        name,
+                        column,
+                        field.after.type_sql(),
+                        collation,
+                        null
+                    ));
+                }
+                if changed("increment") {
+                    self.note(format!(
+                        "{}.{}: SQL Server cannot add or remove IDENTITY on an existing column; recreate it",
+                        table.name, field.after.name
+                    ));
+                }
+                if changed("default") {
+                    let constraint = default_constraint_name(&table.name, &field.after.name);
+                    // Renames have already moved the old constraint to the new names
+                    if field.before.default.is_some() {
+                        self.drop_constraint(table, &constraint);
+                    }
+                    if let Some(default) = &field.after.default {
+                        self.statement(format!(
+                            "ALTER TABLE {} ADD CONSTRAINT {} DEFAULT {} FOR {}",
+                            name,
+                            self.quote(&constraint),
+                            default,
+                            column
+                        ));
+                    }
+                }
+            }
+            Dialect::Sqlite => {}
+        }
+    }
+
+    fn drop_primary_key(&mut self, table: &TableDef) {
+        match self.dialect {
+            Dialect::MySql | Dialect::MariaDb => self.statement(format!(
+                "ALTER TABLE {} DROP PRIMARY KEY",
+                self.dialect.table_name(table)
+            )),
+            _ => self.drop_constraint(table, &primary_key_name(&table.name)),
+        }
+    }
+
+    fn drop_unique(&mut self, table: &TableDef, field: &FieldInfo) {
+        match self.dialect {
+            // MySQL names the index behind an inline UNIQUE after the column
+            Dialect::MySql | Dialect::MariaDb => self.statement(format!(
+                "ALTER TABLE {} DROP INDEX {}",
+                self.dialect.table_name(table),
+                self.quote(&field.name)
+            )),
+            _ => self.drop_constraint(table, &unique_constraint_name(&table.name, &field.name)),
+        }
+    }
+
+    fn add_unique(&mut self, table: &TableDef, field: &FieldInfo) {
+        let name = match self.dialect {
+            Dialect::MySql | Dialect::MariaDb => field.name.clone(),
+            _ => unique_constraint_name(&table.name, &field.name),
+        };
+        self.statement(format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} UNIQUE ({})",
+            self.dialect.table_name(table),
+            self.quote(&name),
+            self.quote(&field.name)
+        ));
+    }
+
+    fn drop_constraint(&mut self, table: &TableDef, name: &str) {
+        self.statement(format!(
+            "ALTER TABLE {} DROP CONSTRAINT {}",
+            self.dialect.table_name(table),
+            self.quote(name)
+        ));
+    }
+
+    fn rename_constraint(&mut self, table: &TableDef, from: &str, to: &str) {
+        match self.dialect {
+            Dialect::PostgreSql => self.statement(format!(
+                "ALTER TABLE {} RENAME CONSTRAINT {} TO {}",
+                self.dialect.table_name(table),
+                self.quote(from),
+                self.quote(to)
+            )),
+            Dialect::SqlServer => {
+                let object = self.dialect.qualified(table.schema.as_deref(), from);
+                self.statement(format!(
+                    "EXEC sp_rename {}, {}, 'OBJECT'",
+                    string_literal(&object),
+                    string_literal(to)
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn comment_on_column(&mut self, table: &TableDef, field: &FieldInfo) {
+        if let Some(comment) = &field.comment {
+            self.statement(format!(
+                "COMMENT ON COLUMN {}.{} IS {}",
+                self.dialect.table_name(table),
+                self.quote(&field.name),
+                string_literal(comment)
+            ));
+        }
+    }
+
+    fn create_type(&mut self, ct: &CustomTypeDef) {
+        let dialect = self.dialect;
+        if dialect == Dialect::Sqlite && is_enum(ct) {
+            // Declared as a CHECK constraint on each column using it
+            return;
+        }
+        if dialect != Dialect::PostgreSql {
+            self.note(format!(
+                "Type {} has no CREATE TYPE equivalent in {}; columns using it are left as declared",
+                ct.qualified_name(),
+                dialect.as_str()
+            ));
+            return;
+        }
+
+        self.statement(create_type(dialect, ct));
+    }
+
+    fn alter_type(&mut self, diff: &CustomTypeDiff) {
+        let dialect = self.dialect;
+        let (before, after) = (&diff.before, &diff.after);
+        if dialect == Dialect::Sqlite && is_enum(after) {
+            // The tables using it are rebuilt with the new CHECK constraint
+            return;
+        }
+        if dialect != Dialect::PostgreSql {
+            self.note(format!(
+                "Type {} changed; {} has no ALTER TYPE equivalent",
+                after.qualified_name(),
+                dialect.as_str()
+            ));
+            return;
+        }
+
+        if before.name != after.name {
+            self.statement(format!(
+                "ALTER TYPE {} RENAME TO {}",
+                dialect.qualified(before.schema.as_deref(), &before.name),
+                self.quote(&after.name)
+            ));
+        }
+        let name = dialect.qualified(after.schema.as_deref(), &after.name);
+        for value in &diff.values_added {
+            self.statement(format!(
+                "ALTER TYPE {} ADD VALUE IF NOT EXISTS {}",
+                name,
+                string_literal(value)
+            ));
+        }
+        if !diff.values_removed.is_empty() {
+            self.note(format!(
+                "PostgreSQL cannot remove enum values ({}) from {}; recreate the type",
+                diff.values_removed.join(", "),
+                after.qualified_name()
+            ));
+        }
+        for field in &diff.fields_removed {
+            self.statement(format!(
+                "ALTER TYPE {} DROP ATTRIBUTE {}",
+                name,
+                self.quote(&field.field)
+            ));
+        }
+        for field in &diff.fields_added {
+            self.statement(format!(
+                "ALTER TYPE {} ADD ATTRIBUTE {} {}",
+                name,
+                self.quote(&field.field),
+                field.type_name
+            ));
+        }
+    }
+
+    fn drop_type(&mut self, ct: &CustomTypeDef) {
+        if self.dialect == Dialect::PostgreSql {
+            self.statement(format!(
+                "DROP TYPE {}",
+                self.dialect.qualified(ct.schema.as_deref(), &ct.name)
+            ));
+        }
+    }
+
+    fn add_foreign_key(&mut self, fk: &ForeignKey) {
+        self.statement(format!(
+            "ALTER TABLE {} ADD {}",
+            fk.table_name(self.dialect),
+            fk.constraint(self.dialect)
+        ));
+    }
+
+    fn drop_foreign_key(&mut self, fk: &ForeignKey) {
+        let keyword = if self.dialect.is_mysql_family() {
+            "FOREIGN KEY"
+        } else {
+            "CONSTRAINT"
+        };
+        self.statement(format!(
+            "ALTER TABLE {} DROP {} {}",
+            fk.table_name(self.dialect),
+            keyword,
+            self.quote(&fk.name)
+        ));
+    }
+}
+
+fn still_unique(diff: &TableDiff, field: &FieldInfo) -> bool {
+    diff.after
+        .fields
+        .iter()
+        .any(|f| f.id == field.id && f.unique && !f.primary_key)
+}
+
+fn still_exists(diff: &TableDiff, field: &FieldInfo) -> bool {
+    diff.after.fields.iter().any(|f| f.id == field.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, field_mut, shop, table};
+
+    fn position(script: &str, statement: &str) -> usize {
+        script
+            .find(statement)
+            .unwrap_or_else(|| panic!("`{}` not in:\n{}", statement, script))
+    }
+
+    #[test]
+    fn notes_cannot_end_their_comment() {
+        let mut before = shop();
+        before.relationships = None;
+        before.tables.as_mut().unwrap()[0].is_view = Some(true);
+        let mut after = before.clone();
+        after.tables.as_mut().unwrap()[0].name = "users\nDROP TABLE orders;".to_string();
+
+        let script = generate(&before, &after, Dialect::PostgreSql);
+        assert!(script.contains(concat!(
+            "-- View public.users\n",
+            "-- DROP TABLE orders; changed; views are not migrated\n"
+        )));
+        assert!(!script.lines().any(|line| line.starts_with("DROP")));
+    }
+
+    #[test]
+    fn changing_a_referenced_primary_key_recreates_the_foreign_keys() {
+        let before = shop();
+        let mut after = shop();
+        // `users` gets a new key column
+        let tables = after.tables.as_mut().unwrap();
+        tables[0] = table(
+            "t_users",
+            "users",
+            &[
+                column("f_users_id", "id", "integer"),
+                column("f_users_email", "email", "varchar(255)"),
+            ],
+        );
+        let email = field_mut(&mut after, "t_users", "f_users_email");
+        email.primary_key = true;
+        email.nullable = false;
+
+        let script = generate(&before, &after, Dialect::PostgreSql);
+        let drop_fk = position(
+            &script,
+            r#"ALTER TABLE "public"."orders" DROP CONSTRAINT "fk_orders_user_id""#,
+        );
+        let drop_pk = position(&script, r#"DROP CONSTRAINT "users_pkey""#);
+        let add_pk = position(
+            &script,
+            r#"ADD CONSTRAINT "users_pkey" PRIMARY KEY ("email")"#,
+        );
+        let add_fk = position(
+            &script,
+            r#"ALTER TABLE "public"."orders" ADD CONSTRAINT "fk_orders_user_id""#,
+        );
+        assert!(
+            drop_fk < drop_pk && drop_pk < add_pk && add_pk < add_fk,
+            "{}",
+            script
+        );
+    }
+
+    #[test]
+    fn unrelated_changes_leave_foreign_keys_alone() {
+        let before = shop();
+        let mut after = shop();
+        field_mut(&mut after, "t_users", "f_users_email").nullable = false;
+
+        let script = generate(&before, &after, Dialect::PostgreSql);
+        assert_eq!(
+            script,
+            "ALTER TABLE \"public\".\"users\" ALTER COLUMN \"email\" SET NOT NULL;\n"
+        );
+    }
+
+    #[test]
+    fn new_tables_come_before_their_foreign_keys() {
+        let before = shop();
+        let mut after = shop();
+        after.tables.as_mut().unwrap().push(table(
+            "t_items",
+            "items",
+            &[column("f_items_order", "order_id", "integer")],
+        ));
+        after
+            .relationships
+            .as_mut()
+            .unwrap()
+            .push(crate::fixtures::relationship(
+                "r_items_order",
+                ("t_items", "f_items_order"),
+                ("t_orders", "f_orders_id"),
+            ));
+
+        let script = generate(&before, &after, Dialect::PostgreSql);
+        let create = position(&script, r#"CREATE TABLE "public"."items""#);
+        let fk = position(&script, r#"ADD CONSTRAINT "fk_items_order_id""#);
+        assert!(create < fk, "{}", script);
+    }
+
+    #[test]
+    fn rollback_drops_what_was_added() {
+        let before = shop();
+        let mut after = shop();
+        after.tables.as_mut().unwrap()[0]
+            .fields
+            .push(column("f_users_name", "name", "text").to_field(0));
+
+        let forward = generate(&before, &after, Dialect::PostgreSql);
+        let rollback = generate(&after, &before, Dialect::PostgreSql);
+        assert_eq!(
+            forward,
+            "ALTER TABLE \"public\".\"users\" ADD COLUMN \"name\" text;\n"
+        );
+        assert_eq!(
+            rollback,
+            "ALTER TABLE \"public\".\"users\" DROP COLUMN \"name\";\n"
+        );
+    }
+
+    #[test]
+    fn turning_on_increment_alters_the_column() {
+        let mut after = shop();
+        field_mut(&mut after, "t_orders", "f_orders_id").increment = Some(true);
+
+        let postgres = generate(&shop(), &after, Dialect::PostgreSql);
+        position(
+            &postgres,
+            r#"ALTER TABLE "public"."orders" ALTER COLUMN "id" ADD GENERATED BY DEFAULT AS IDENTITY;"#,
+        );
+        let mysql = generate(&shop(), &after, Dialect::MySql);
+        position(
+            &mysql,
+            "ALTER TABLE `public`.`orders` MODIFY COLUMN `id` integer NOT NULL AUTO_INCREMENT;",
+        );
+        let rollback = generate(&after, &shop(), Dialect::PostgreSql);
+        position(
+            &rollback,
+            r#"ALTER TABLE "public"."orders" ALTER COLUMN "id" DROP IDENTITY IF EXISTS;"#,
+        );
+    }
+
+    #[test]
+    fn no_changes() {
+        assert_eq!(
+            generate(&shop(), &shop(), Dialect::PostgreSql),
+            "-- No structural changes.\n"
+        );
+    }
+
+    #[test]
+    fn sqlite_rebuilds_tables_it_cannot_alter() {
+        let before = shop();
+        let mut after = shop();
+        field_mut(&mut after, "t_users", "f_users_email").unique = true;
+
+        let script = generate(&before, &after, Dialect::Sqlite);
+        assert!(script.contains("PRAGMA foreign_keys = OFF"), "{}", script);
+        assert!(
+            script.contains(r#"ALTER TABLE "_new_users" RENAME TO "users""#),
+            "{}",
+            script
+        );
+    }
+}
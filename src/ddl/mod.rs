@@ -0,0 +1,333 @@
+//! SQL generation for the database engines ChartDB diagrams target.
+
+pub mod export;
+pub mod import;
+pub mod migration;
+mod mysql;
+mod sqlite;
+mod sqlserver;
+
+use crate::diff::{CustomTypeDef, IndexDef, RelationshipDef, TableDef};
+use crate::schema::FieldInfo;
+use export::is_serial;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    PostgreSql,
+    MySql,
+    MariaDb,
+    Sqlite,
+    #[serde(rename = "sql_server")]
+    SqlServer,
+}
+
+impl Dialect {
+    /// Maps a ChartDB `databaseType` (or a `dialect` query value) to a dialect.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "postgresql" | "postgres" | "cockroachdb" | "generic" => Some(Dialect::PostgreSql),
+            "mysql" => Some(Dialect::MySql),
+            "mariadb" => Some(Dialect::MariaDb),
+            "sqlite" => Some(Dialect::Sqlite),
+            "sql_server" | "sqlserver" | "mssql" => Some(Dialect::SqlServer),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dialect::PostgreSql => "postgresql",
+            Dialect::MySql => "mysql",
+            Dialect::MariaDb => "mariadb",
+            Dialect::Sqlite => "sqlite",
+            Dialect::SqlServer => "sql_server",
+        }
+    }
+
+    pub fn is_mysql_family(self) -> bool {
+        matches!(self, Dialect::MySql | Dialect::MariaDb)
+    }
+
+    pub fn quote(self, ident: &str) -> String {
+        match self {
+            Dialect::PostgreSql | Dialect::Sqlite => format!("\"{}\"", ident.replace('"', "\"\"")),
+            Dialect::MySql | Dialect::MariaDb => format!("`{}`", ident.replace('`', "``")),
+            Dialect::SqlServer => format!("[{}]", ident.replace(']', "]]")),
+        }
+    }
+
+    /// A quoted, schema-qualified object name. SQLite has no schemas, so the
+    /// schema is dropped there.
+    pub fn qualified(self, schema: Option<&str>, name: &str) -> String {
+        match schema {
+            Some(schema) if !schema.is_empty() && self != Dialect::Sqlite => {
+                format!("{}.{}", self.quote(schema), self.quote(name))
+            }
+            _ => self.quote(name),
+        }
+    }
+
+    pub fn table_name(self, table: &TableDef) -> String {
+        self.qualified(table.schema.as_deref(), &table.name)
+    }
+}
+
+pub fn string_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Name of the primary key constraint the generated DDL gives `table`.
+/// PostgreSQL's own default is used there so that existing databases match.
+pub fn primary_key_name(table: &str) -> String {
+    format!("{}_pkey", table)
+}
+
+/// Name of the unique constraint created for a column flagged `unique`.
+pub fn unique_constraint_name(table: &str, column: &str) -> String {
+    format!("{}_{}_key", table, column)
+}
+
+/// Name of the SQL Server default constraint for a column.
+pub fn default_constraint_name(table: &str, column: &str) -> String {
+    format!("DF_{}_{}", table, column)
+}
+
+/// A column definition as used in `CREATE TABLE` and `ADD COLUMN`. Primary
+/// keys are declared at table level and comments separately.
+pub fn column_definition(dialect: Dialect, table: &TableDef, field: &FieldInfo) -> String {
+    let mut sql = format!("{} {}", dialect.quote(&field.name), field.type_sql());
+    if let Some(collation) = &field.collation {
+        match dialect {
+            Dialect::PostgreSql => sql.push_str(&format!(" COLLATE {}", dialect.quote(collation))),
+            _ => sql.push_str(&format!(" COLLATE {}", collation)),
+        }
+    }
+    if !field.nullable || field.primary_key {
+        sql.push_str(" NOT NULL");
+    }
+    if let Some(increment) = increment_clause(dialect, field) {
+        sql.push_str(increment);
+    }
+    if let Some(default) = &field.default {
+        if dialect == Dialect::SqlServer {
+            sql.push_str(&format!(
+                " CONSTRAINT {}",
+                dialect.quote(&default_constraint_name(&table.name, &field.name))
+            ));
+        }
+        sql.push_str(&format!(" DEFAULT {}", default));
+    }
+    if field.unique && !field.primary_key {
+        if dialect == Dialect::SqlServer {
+            sql.push_str(&format!(
+                " CONSTRAINT {}",
+                dialect.quote(&unique_constraint_name(&table.name, &field.name))
+            ));
+        }
+        sql.push_str(" UNIQUE");
+    }
+    if dialect.is_mysql_family() {
+        if let Some(comment) = &field.comment {
+            sql.push_str(&format!(" COMMENT {}", string_literal(comment)));
+        }
+    }
+    sql
+}
+
+/// How an auto-increment column is declared, if the dialect can declare it
+/// in a column definition and the type or default does not already imply it.
+fn increment_clause(dialect: Dialect, field: &FieldInfo) -> Option<&'static str> {
+    if !field.increment {
+        return None;
+    }
+    match dialect {
+        Dialect::PostgreSql if !is_serial(&field.type_name) && field.default.is_none() => {
+            Some(" GENERATED BY DEFAULT AS IDENTITY")
+        }
+        Dialect::MySql | Dialect::MariaDb => Some(" AUTO_INCREMENT"),
+        Dialect::SqlServer => Some(" IDENTITY(1,1)"),
+        _ => None,
+    }
+}
+
+pub fn primary_key_columns(table: &TableDef) -> Vec<&str> {
+    table
+        .fields
+        .iter()
+        .filter(|f| f.primary_key)
+        .map(|f| f.name.as_str())
+        .collect()
+}
+
+pub fn column_list(dialect: Dialect, columns: &[impl AsRef<str>]) -> String {
+    columns
+        .iter()
+        .map(|c| dialect.quote(c.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The `PRIMARY KEY (...)` table constraint, named where the dialect needs a
+/// stable name to drop it later.
+pub fn primary_key_clause(dialect: Dialect, table: &TableDef) -> Option<String> {
+    let columns = primary_key_columns(table);
+    if columns.is_empty() {
+        return None;
+    }
+    let constraint = match dialect {
+        Dialect::PostgreSql | Dialect::SqlServer => format!(
+            "CONSTRAINT {} ",
+            dialect.quote(&primary_key_name(&table.name))
+        ),
+        _ => String::new(),
+    };
+    Some(format!(
+        "{}PRIMARY KEY ({})",
+        constraint,
+        column_list(dialect, &columns)
+    ))
+}
+
+/// `CREATE TABLE` with columns and primary key. `extra` holds further table
+/// constraints, such as foreign keys SQLite can only declare inline.
+pub fn create_table(dialect: Dialect, table: &TableDef, extra: &[String]) -> String {
+    let mut lines: Vec<String> = table
+        .fields
+        .iter()
+        .map(|field| column_definition(dialect, table, field))
+        .collect();
+    lines.extend(primary_key_clause(dialect, table));
+    lines.extend(extra.iter().cloned());
+
+    let mut sql = format!(
+        "CREATE TABLE {} (\n    {}\n)",
+        dialect.table_name(table),
+        lines.join(",\n    ")
+    );
+    if dialect.is_mysql_family() {
+        if let Some(comment) = &table.comment {
+            sql.push_str(&format!(" COMMENT = {}", string_literal(comment)));
+        }
+    }
+    sql
+}
+
+pub fn create_index(dialect: Dialect, table: &TableDef, index: &IndexDef) -> String {
+    format!(
+        "CREATE {}INDEX {} ON {} ({})",
+        if index.unique { "UNIQUE " } else { "" },
+        dialect.quote(&index.name),
+        dialect.table_name(table),
+        column_list(dialect, &index.columns)
+    )
+}
+
+pub fn drop_index(dialect: Dialect, table: &TableDef, index: &IndexDef) -> String {
+    match dialect {
+        Dialect::PostgreSql => format!(
+            "DROP INDEX {}",
+            dialect.qualified(table.schema.as_deref(), &index.name)
+        ),
+        Dialect::Sqlite => format!("DROP INDEX {}", dialect.quote(&index.name)),
+        Dialect::MySql | Dialect::MariaDb | Dialect::SqlServer => format!(
+            "DROP INDEX {} ON {}",
+            dialect.quote(&index.name),
+            dialect.table_name(table)
+        ),
+    }
+}
+
+pub fn is_enum(ct: &CustomTypeDef) -> bool {
+    ct.kind.as_deref() == Some("enum") || (ct.kind.is_none() && !ct.values.is_empty())
+}
+
+/// PostgreSQL `CREATE TYPE` for an enum or composite type.
+pub fn create_type(dialect: Dialect, ct: &CustomTypeDef) -> String {
+    let name = dialect.qualified(ct.schema.as_deref(), &ct.name);
+    if is_enum(ct) {
+        let values: Vec<String> = ct.values.iter().map(|v| string_literal(v)).collect();
+        format!("CREATE TYPE {} AS ENUM ({})", name, values.join(", "))
+    } else {
+        let fields: Vec<String> = ct
+            .fields
+            .iter()
+            .map(|f| format!("{} {}", dialect.quote(&f.field), f.type_name))
+            .collect();
+        format!("CREATE TYPE {} AS ({})", name, fields.join(", "))
+    }
+}
+
+/// A relationship as a foreign key: the `many` side (or the source of a
+/// one-to-one) holds the column referencing the other side. Many-to-many
+/// relationships have no single foreign key and yield `None`.
+pub struct ForeignKey {
+    pub name: String,
+    pub schema: Option<String>,
+    pub table: String,
+    pub column: String,
+    pub referenced_schema: Option<String>,
+    pub referenced_table: String,
+    pub referenced_column: String,
+}
+
+pub fn foreign_key(rel: &RelationshipDef) -> Option<ForeignKey> {
+    let source_field = rel.source_field.clone()?;
+    let target_field = rel.target_field.clone()?;
+    let source_many = rel.source_cardinality.as_deref() == Some("many");
+    let target_many = rel.target_cardinality.as_deref() == Some("many");
+
+    let (schema, table, column, referenced_schema, referenced_table, referenced_column) =
+        match (source_many, target_many) {
+            (true, true) => return None,
+            (false, true) => (
+                rel.target_schema.clone(),
+                rel.target_table.clone(),
+                target_field,
+                rel.source_schema.clone(),
+                rel.source_table.clone(),
+                source_field,
+            ),
+            _ => (
+                rel.source_schema.clone(),
+                rel.source_table.clone(),
+                source_field,
+                rel.target_schema.clone(),
+                rel.target_table.clone(),
+                target_field,
+            ),
+        };
+
+    let name = match &rel.name {
+        Some(name) if !name.is_empty() => name.clone(),
+        _ => format!("fk_{}_{}", table, column),
+    };
+
+    Some(ForeignKey {
+        name,
+        schema,
+        table,
+        column,
+        referenced_schema,
+        referenced_table,
+        referenced_column,
+    })
+}
+
+impl ForeignKey {
+    pub fn table_name(&self, dialect: Dialect) -> String {
+        dialect.qualified(self.schema.as_deref(), &self.table)
+    }
+
+    /// The `CONSTRAINT ... FOREIGN KEY ... REFERENCES ...` clause.
+    pub fn constraint(&self, dialect: Dialect) -> String {
+        format!(
+            "CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+            dialect.quote(&self.name),
+            dialect.quote(&self.column),
+            dialect.qualified(self.referenced_schema.as_deref(), &self.referenced_table),
+            dialect.quote(&self.referenced_column)
+        )
+    }
+}
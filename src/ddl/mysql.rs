@@ -0,0 +1,540 @@
+//! MySQL and MariaDB rendering of the DDL export.
+//!
+//! Diagrams drawn for PostgreSQL can be exported too: types are mapped to
+//! their MySQL counterparts and custom enums become inline `ENUM(...)`
+//! columns. Anything without a faithful equivalent is collected and reported
+//! instead of being silently dropped.
+
+use super::export::{
+    check_composite_types, classify_default, find_custom_type, header, ordered, table_foreign_keys,
+    DefaultValue, ExportError,
+};
+use super::{string_literal, Dialect};
+use crate::diff::{self, CustomTypeDef, TableDef};
+use crate::models::Diagram;
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+/// What the target server supports, from the diagram's `databaseEdition`.
+struct Target {
+    dialect: Dialect,
+    /// MySQL 5.7: no expression defaults, and no defaults at all on
+    /// TEXT/BLOB/JSON columns.
+    legacy: bool,
+    /// Whether the diagram was drawn for this dialect, in which case its
+    /// collations are meaningful here.
+    native: bool,
+}
+
+impl Target {
+    fn collation(&self) -> &'static str {
+        match (self.dialect, self.legacy) {
+            (Dialect::MariaDb, _) => "utf8mb4_unicode_ci",
+            (_, true) => "utf8mb4_general_ci",
+            _ => "utf8mb4_0900_ai_ci",
+        }
+    }
+}
+
+struct Column {
+    definition: String,
+    /// TEXT/BLOB columns need a prefix length when indexed.
+    is_blob: bool,
+    /// JSON columns cannot be indexed at all.
+    is_json: bool,
+    auto_increment: bool,
+}
+
+pub(super) fn export(diagram: &Diagram, dialect: Dialect) -> Result<String, ExportError> {
+    let native = Dialect::parse(&diagram.database_type) == Some(dialect);
+    let target = Target {
+        dialect,
+        legacy: native
+            && dialect == Dialect::MySql
+            && diagram.database_edition.as_deref() == Some("mysql_5_7"),
+        native,
+    };
+    let defs = diff::diagram_defs(diagram);
+    let tables = ordered(
+        &defs.tables,
+        diagram.dependencies.as_deref().unwrap_or_default(),
+    );
+    let mut problems = Vec::new();
+
+    check_composite_types(&defs.custom_types, dialect, &mut problems);
+
+    // A MySQL schema is a database. A diagram with a single schema is
+    // exported unqualified so it can be loaded into any database.
+    let mut schemas: Vec<&str> = tables
+        .iter()
+        .filter_map(|t| t.schema.as_deref())
+        .filter(|s| !s.is_empty())
+        .collect();
+    schemas.sort_unstable();
+    schemas.dedup();
+    let qualify = schemas.len() > 1;
+    let name = |schema: Option<&str>, table: &str| {
+        if qualify {
+            dialect.qualified(schema, table)
+        } else {
+            dialect.quote(table)
+        }
+    };
+
+    let mut out = header(diagram, dialect);
+    if qualify {
+        out.push('\n');
+        for schema in &schemas {
+            let _ = writeln!(
+                out,
+                "CREATE DATABASE IF NOT EXISTS {};",
+                dialect.quote(schema)
+            );
+        }
+    }
+
+    for table in &tables {
+        let table_name = name(table.schema.as_deref(), &table.name);
+        out.push('\n');
+
+        if table.is_materialized_view {
+            problems.push(format!(
+                "materialized view {} has no {} equivalent",
+                table.qualified_name(),
+                dialect.as_str()
+            ));
+            continue;
+        }
+        if table.is_view {
+            let columns: Vec<String> = table
+                .fields
+                .iter()
+                .map(|f| format!("NULL AS {}", dialect.quote(&f.name)))
+                .collect();
+            let _ = writeln!(
+                out,
+                "-- Placeholder body: the view definition is not stored in the diagram\nCREATE VIEW {} AS\nSELECT\n    {};",
+                table_name,
+                columns.join(",\n    ")
+            );
+            continue;
+        }
+
+        let columns: Vec<Column> = table
+            .fields
+            .iter()
+            .map(|field| column(&target, table, field, &defs.custom_types, &mut problems))
+            .collect();
+
+        let mut lines: Vec<String> = columns.iter().map(|c| c.definition.clone()).collect();
+        // The columns of each key, to check AUTO_INCREMENT against
+        let mut keys: Vec<Vec<String>> = Vec::new();
+        let mut key_parts = |columns_of_key: &[String], what: &str| -> String {
+            let parts: Vec<String> = columns_of_key
+                .iter()
+                .map(|name| {
+                    let column = table
+                        .fields
+                        .iter()
+                        .zip(&columns)
+                        .find(|(f, _)| &f.name == name);
+                    match column {
+                        Some((_, c)) if c.is_json => {
+                            problems.push(format!(
+                                "{} of {}: JSON column `{}` cannot be indexed in {}",
+                                what,
+                                table.qualified_name(),
+                                name,
+                                dialect.as_str()
+                            ));
+                            dialect.quote(name)
+                        }
+                        Some((_, c)) if c.is_blob => format!("{}(255)", dialect.quote(name)),
+                        _ => dialect.quote(name),
+                    }
+                })
+                .collect();
+            keys.push(columns_of_key.to_vec());
+            parts.join(", ")
+        };
+        let primary_key: Vec<String> = super::primary_key_columns(table)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if !primary_key.is_empty() {
+            let parts = key_parts(&primary_key, "primary key");
+            lines.push(format!("PRIMARY KEY ({})", parts));
+        }
+        for field in table.fields.iter().filter(|f| f.unique && !f.primary_key) {
+            let parts = key_parts(std::slice::from_ref(&field.name), "unique column");
+            lines.push(format!(
+                "UNIQUE KEY {} ({})",
+                dialect.quote(&field.name),
+                parts
+            ));
+        }
+        for index in &table.indexes {
+            let parts = key_parts(&index.columns, &format!("index {}", index.name));
+            lines.push(format!(
+                "{}KEY {} ({})",
+                if index.unique { "UNIQUE " } else { "" },
+                dialect.quote(&index.name),
+                parts
+            ));
+        }
+
+        // InnoDB allows one AUTO_INCREMENT column, which has to lead a key
+        let auto_increment: Vec<&str> = table
+            .fields
+            .iter()
+            .zip(&columns)
+            .filter(|(_, c)| c.auto_increment)
+            .map(|(f, _)| f.name.as_str())
+            .collect();
+        if auto_increment.len() > 1 {
+            problems.push(format!(
+                "table {}: {} has a single AUTO_INCREMENT column, not {}",
+                table.qualified_name(),
+                dialect.as_str(),
+                auto_increment.join(", ")
+            ));
+        } else if let Some(name) = auto_increment.first() {
+            if !keys
+                .iter()
+                .any(|key| key.first().map(String::as_str) == Some(name))
+            {
+                problems.push(format!(
+                    "column {}.{}: an AUTO_INCREMENT column must be the first column of a key",
+                    table.qualified_name(),
+                    name
+                ));
+            }
+        }
+
+        let _ = write!(
+            out,
+            "CREATE TABLE {} (\n    {}\n) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE={}",
+            table_name,
+            lines.join(",\n    "),
+            target.collation()
+        );
+        if let Some(comment) = &table.comment {
+            let _ = write!(out, " COMMENT={}", string_literal(comment));
+        }
+        out.push_str(";\n");
+    }
+
+    let foreign_keys = table_foreign_keys(&defs, &tables);
+    if !foreign_keys.is_empty() {
+        out.push('\n');
+        // Constraint names are unique per database in MySQL, not per table
+        let mut used: Vec<String> = Vec::new();
+        for fk in foreign_keys {
+            let mut constraint = fk.name.clone();
+            if used.contains(&constraint) {
+                constraint = format!("{}_{}", fk.table, fk.name);
+            }
+            let mut n = 2;
+            while used.contains(&constraint) {
+                constraint = format!("{}_{}_{}", fk.table, fk.name, n);
+                n += 1;
+            }
+            used.push(constraint.clone());
+            let _ = writeln!(
+                out,
+                "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({});",
+                name(fk.schema.as_deref(), &fk.table),
+                dialect.quote(&constraint),
+                dialect.quote(&fk.column),
+                name(fk.referenced_schema.as_deref(), &fk.referenced_table),
+                dialect.quote(&fk.referenced_column)
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(out)
+    } else {
+        Err(ExportError::Unrepresentable { dialect, problems })
+    }
+}
+
+fn column(
+    target: &Target,
+    table: &TableDef,
+    field: &FieldInfo,
+    custom_types: &[CustomTypeDef],
+    problems: &mut Vec<String>,
+) -> Column {
+    let dialect = target.dialect;
+    let location = format!("column {}.{}", table.qualified_name(), field.name);
+    let mapped = map_type(field, custom_types);
+    let (sql_type, mut auto_increment) = match mapped {
+        Ok(mapped) => mapped,
+        Err(reason) => {
+            problems.push(format!("{}: {}", location, reason));
+            (field.type_sql(), false)
+        }
+    };
+    auto_increment |= field.increment;
+    let is_blob = is_blob(&sql_type);
+
+    let mut sql = format!("{} {}", dialect.quote(&field.name), sql_type);
+    if target.native {
+        if let Some(collation) = &field.collation {
+            let _ = write!(sql, " COLLATE {}", collation);
+        }
+    }
+    if !field.nullable || field.primary_key {
+        sql.push_str(" NOT NULL");
+    }
+
+    if let Some(default) = &field.default {
+        let value = match classify_default(default) {
+            DefaultValue::Sequence => None,
+            DefaultValue::CurrentTimestamp => Some("CURRENT_TIMESTAMP"),
+            DefaultValue::RandomUuid if target.legacy => {
+                problems.push(format!(
+                    "{}: default `{}` has no {} 5.7 equivalent",
+                    location,
+                    default,
+                    dialect.as_str()
+                ));
+                None
+            }
+            DefaultValue::RandomUuid => Some("uuid()"),
+            DefaultValue::Value(value) => Some(value),
+        };
+        match value {
+            None => auto_increment = true,
+            Some(value) => {
+                let expression = value.contains('(') && !value.starts_with('\'');
+                if (is_blob || expression) && target.legacy {
+                    problems.push(format!(
+                        "{}: default `{}` needs MySQL 8.0.13 or later",
+                        location, default
+                    ));
+                } else if is_blob || expression {
+                    let _ = write!(sql, " DEFAULT ({})", value);
+                } else {
+                    let _ = write!(sql, " DEFAULT {}", value);
+                }
+            }
+        }
+    }
+    if auto_increment {
+        sql.push_str(" AUTO_INCREMENT");
+    }
+    if let Some(comment) = &field.comment {
+        let _ = write!(sql, " COMMENT {}", string_literal(comment));
+    }
+
+    Column {
+        definition: sql,
+        is_blob: is_blob && !sql_type.eq_ignore_ascii_case("json"),
+        is_json: sql_type.eq_ignore_ascii_case("json"),
+        auto_increment,
+    }
+}
+
+/// Maps a field's type to MySQL, returning the SQL type and whether the
+/// source type implied auto-increment (PostgreSQL `serial`).
+fn map_type(field: &FieldInfo, custom_types: &[CustomTypeDef]) -> Result<(String, bool), String> {
+    let name = field.type_name.trim().to_ascii_lowercase();
+    if field.is_array {
+        return Err(format!(
+            "array type `{}` has no MySQL equivalent",
+            field.type_sql()
+        ));
+    }
+
+    let length = field.character_maximum_length.as_deref();
+    let mapped = match name.as_str() {
+        "serial" | "serial4" => return Ok(("int".to_string(), true)),
+        "bigserial" | "serial8" => return Ok(("bigint".to_string(), true)),
+        "smallserial" | "serial2" => return Ok(("smallint".to_string(), true)),
+        "integer" | "int4" => "int".to_string(),
+        "int8" => "bigint".to_string(),
+        "int2" => "smallint".to_string(),
+        "boolean" | "bool" => "tinyint(1)".to_string(),
+        "character varying" | "varchar" => format!("varchar({})", length.unwrap_or("255")),
+        "character" | "bpchar" => format!("char({})", length.unwrap_or("1")),
+        "citext" | "name" => "text".to_string(),
+        "uuid" => "char(36)".to_string(),
+        "jsonb" => "json".to_string(),
+        "bytea" => "longblob".to_string(),
+        "numeric" | "decimal" => match (field.precision, field.scale) {
+            (Some(p), Some(s)) => format!("decimal({},{})", p, s),
+            (Some(p), None) => format!("decimal({})", p),
+            _ => "decimal(65,30)".to_string(),
+        },
+        "real" | "float4" => "float".to_string(),
+        "double precision" | "float8" => "double".to_string(),
+        "money" => "decimal(19,4)".to_string(),
+        "timestamp"
+        | "timestamp without time zone"
+        | "timestamptz"
+        | "timestamp with time zone" => "datetime".to_string(),
+        "time without time zone" | "timetz" | "time with time zone" => "time".to_string(),
+        "interval" | "tsvector" | "tsquery" | "xml" | "inet" | "cidr" | "macaddr" | "macaddr8"
+        | "hstore" | "ltree" | "point" | "line" | "lseg" | "box" | "path" | "polygon"
+        | "circle" | "int4range" | "int8range" | "numrange" | "tsrange" | "tstzrange"
+        | "daterange" | "oid" => {
+            return Err(format!(
+                "type `{}` has no MySQL equivalent",
+                field.type_name
+            ))
+        }
+        _ => match find_custom_type(&name, custom_types) {
+            Some(ct) if super::is_enum(ct) => {
+                let values: Vec<String> = ct.values.iter().map(|v| string_literal(v)).collect();
+                format!("enum({})", values.join(", "))
+            }
+            Some(ct) => {
+                return Err(format!(
+                    "composite type {} cannot be used as a MySQL column type",
+                    ct.qualified_name()
+                ))
+            }
+            None => field.type_sql(),
+        },
+    };
+    Ok((mapped, false))
+}
+
+fn is_blob(sql_type: &str) -> bool {
+    let base = sql_type.split('(').next().unwrap_or_default();
+    base.ends_with("text") || base.ends_with("blob") || base == "json"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, diagram, field_mut, indexed, key, relationship, shop, table};
+
+    fn rejected(diagram: &Diagram) -> Vec<String> {
+        match export(diagram, Dialect::MySql) {
+            Ok(sql) => panic!("exported:\n{}", sql),
+            Err(ExportError::Unrepresentable { problems, .. }) => problems,
+        }
+    }
+
+    #[test]
+    fn exports_a_shop() {
+        let sql = export(&shop(), Dialect::MySql).unwrap();
+        assert!(sql.contains("`total` decimal(10,2)"), "{}", sql);
+        assert!(sql.contains("PRIMARY KEY (`id`)"), "{}", sql);
+        assert!(sql.contains("KEY `orders_user_id` (`user_id`)"), "{}", sql);
+        assert!(
+            sql.contains(
+                "ALTER TABLE `orders` ADD CONSTRAINT `fk_orders_user_id` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`);"
+            ),
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn indexed_text_columns_get_a_prefix_length() {
+        let mut shop = shop();
+        field_mut(&mut shop, "t_users", "f_users_email").unique = true;
+        field_mut(&mut shop, "t_users", "f_users_email").r#type.name = "text".to_string();
+        field_mut(&mut shop, "t_users", "f_users_email").character_maximum_length = None;
+        let sql = export(&shop, Dialect::MySql).unwrap();
+        assert!(sql.contains("UNIQUE KEY `email` (`email`(255))"), "{}", sql);
+    }
+
+    #[test]
+    fn indexed_json_columns_are_reported() {
+        let diagram = diagram(
+            vec![indexed(
+                table(
+                    "t1",
+                    "events",
+                    &[key("f1", "id", "integer"), column("f2", "payload", "jsonb")],
+                ),
+                &[("i1", "events_payload", false, &["f2"])],
+            )],
+            Vec::new(),
+        );
+        let problems = rejected(&diagram);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(
+            problems[0].contains("JSON column `payload`"),
+            "{:?}",
+            problems
+        );
+    }
+
+    #[test]
+    fn auto_increment_must_be_a_single_key_column() {
+        let mut serials = shop();
+        field_mut(&mut serials, "t_orders", "f_orders_id").increment = Some(true);
+        field_mut(&mut serials, "t_orders", "f_orders_total").increment = Some(true);
+        let problems = rejected(&serials);
+        assert!(
+            problems[0].contains("single AUTO_INCREMENT column, not id, total"),
+            "{:?}",
+            problems
+        );
+
+        let mut unkeyed = shop();
+        field_mut(&mut unkeyed, "t_users", "f_users_email")
+            .r#type
+            .name = "serial".to_string();
+        let problems = rejected(&unkeyed);
+        assert!(
+            problems[0].contains("users.email: an AUTO_INCREMENT column must be the first"),
+            "{:?}",
+            problems
+        );
+
+        let mut keyed = shop();
+        field_mut(&mut keyed, "t_orders", "f_orders_user").increment = Some(true);
+        assert!(export(&keyed, Dialect::MySql).is_ok());
+    }
+
+    #[test]
+    fn foreign_key_names_are_unique_across_tables() {
+        let mut shop = shop();
+        let tables = shop.tables.as_mut().unwrap();
+        tables.push(table(
+            "t_reviews",
+            "reviews",
+            &[column("f_reviews_user", "user_id", "integer")],
+        ));
+        let relationships = shop.relationships.as_mut().unwrap();
+        relationships[0].name = Some("user_fk".to_string());
+        let mut review = relationship(
+            "r_reviews_user",
+            ("t_reviews", "f_reviews_user"),
+            ("t_users", "f_users_id"),
+        );
+        review.name = Some("user_fk".to_string());
+        relationships.push(review);
+
+        let sql = export(&shop, Dialect::MySql).unwrap();
+        assert!(
+            sql.contains("ADD CONSTRAINT `user_fk` FOREIGN KEY"),
+            "{}",
+            sql
+        );
+        assert!(
+            sql.contains("ADD CONSTRAINT `reviews_user_fk` FOREIGN KEY"),
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn arrays_have_no_equivalent() {
+        let mut shop = shop();
+        field_mut(&mut shop, "t_users", "f_users_email").is_array = Some(true);
+        let problems = rejected(&shop);
+        assert!(
+            problems[0].contains("array type `varchar(255)[]`"),
+            "{:?}",
+            problems
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! SQLite rendering of the DDL export.
+//!
+//! SQLite has no schemas, cannot add constraints to an existing table, and
+//! stores values by type affinity rather than declared type. Columns are
+//! therefore declared with their affinity (`INTEGER`, `TEXT`, `REAL`,
+//! `NUMERIC`, `BLOB`), enums become `CHECK` constraints and foreign keys are
+//! declared inline in `CREATE TABLE`.
+
+use super::export::{
+    self, check_composite_types, classify_default, find_custom_type, header, is_serial, ordered,
+    table_foreign_keys, DefaultValue, ExportError,
+};
+use super::{column_list, create_index, string_literal, Dialect, ForeignKey};
+use crate::diff::{self, CustomTypeDef, TableDef};
+use crate::models::Diagram;
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+const DIALECT: Dialect = Dialect::Sqlite;
+
+pub(super) fn export(diagram: &Diagram) -> Result<String, ExportError> {
+    let defs = diff::diagram_defs(diagram);
+    let tables = ordered(
+        &defs.tables,
+        diagram.dependencies.as_deref().unwrap_or_default(),
+    );
+    let foreign_keys = table_foreign_keys(&defs, &tables);
+    let mut problems = Vec::new();
+    check_composite_types(&defs.custom_types, DIALECT, &mut problems);
+    let mut out = header(diagram, DIALECT);
+
+    for table in &tables {
+        out.push('\n');
+        if let Some(comment) = &table.comment {
+            let _ = writeln!(out, "{}", export::comment(comment));
+        }
+
+        if table.is_materialized_view {
+            problems.push(format!(
+                "materialized view {} has no sqlite equivalent",
+                table.qualified_name()
+            ));
+            continue;
+        }
+        if table.is_view {
+            let columns: Vec<String> = table
+                .fields
+                .iter()
+                .map(|f| format!("NULL AS {}", DIALECT.quote(&f.name)))
+                .collect();
+            let _ = writeln!(
+                out,
+                "-- Placeholder body: the view definition is not stored in the diagram\nCREATE VIEW {} AS\nSELECT\n    {};",
+                DIALECT.table_name(table),
+                columns.join(",\n    ")
+            );
+            continue;
+        }
+
+        let fks: Vec<&ForeignKey> = foreign_keys
+            .iter()
+            .filter(|fk| fk.table == table.name)
+            .collect();
+        let _ = writeln!(
+            out,
+            "{};",
+            create_table(table, &defs.custom_types, &fks, &mut problems)
+        );
+        for index in &table.indexes {
+            let _ = writeln!(out, "{};", create_index(DIALECT, table, index));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(out)
+    } else {
+        Err(ExportError::Unrepresentable {
+            dialect: DIALECT,
+            problems,
+        })
+    }
+}
+
+/// `CREATE TABLE` with affinity types and inline foreign keys. Also used by
+/// migrations to rebuild a table.
+pub(super) fn create_table(
+    table: &TableDef,
+    custom_types: &[CustomTypeDef],
+    foreign_keys: &[&ForeignKey],
+    problems: &mut Vec<String>,
+) -> String {
+    let primary_key = super::primary_key_columns(table);
+    // Only a lone INTEGER PRIMARY KEY can be AUTOINCREMENT, and it has to be
+    // declared on the column itself.
+    let mut inline_key = false;
+
+    let mut lines = Vec::new();
+    for field in &table.fields {
+        let location = format!("column {}.{}", table.name, field.name);
+        let (affinity, check) = match affinity(field, custom_types) {
+            Ok(mapped) => mapped,
+            Err(reason) => {
+                problems.push(format!("{}: {}", location, reason));
+                ("TEXT", None)
+            }
+        };
+
+        if let Some(comment) = &field.comment {
+            lines.push(export::comment(&comment.replace(['\r', '\n'], " ")));
+        }
+        let mut sql = format!("{} {}", DIALECT.quote(&field.name), affinity);
+
+        let mut auto_increment = field.increment || is_serial(&field.type_name);
+        let default = field.default.as_deref().map(classify_default);
+        if matches!(default, Some(DefaultValue::Sequence)) {
+            auto_increment = true;
+        }
+        if auto_increment {
+            if primary_key.len() == 1 && field.primary_key && affinity == "INTEGER" {
+                sql.push_str(" PRIMARY KEY AUTOINCREMENT");
+                inline_key = true;
+            } else {
+                problems.push(format!(
+                    "{}: auto-increment needs a single-column INTEGER primary key in SQLite",
+                    location
+                ));
+            }
+        }
+        if let Some(collation) = &field.collation {
+            if matches!(
+                collation.to_ascii_uppercase().as_str(),
+                "BINARY" | "NOCASE" | "RTRIM"
+            ) {
+                let _ = write!(sql, " COLLATE {}", collation.to_ascii_uppercase());
+            }
+        }
+        if !field.nullable || field.primary_key {
+            sql.push_str(" NOT NULL");
+        }
+        match default {
+            Some(DefaultValue::CurrentTimestamp) => sql.push_str(" DEFAULT CURRENT_TIMESTAMP"),
+            Some(DefaultValue::RandomUuid) => sql.push_str(" DEFAULT (lower(hex(randomblob(16))))"),
+            Some(DefaultValue::Value(value)) => {
+                // Expressions have to be parenthesised in SQLite
+                if value.contains('(') && !value.starts_with('\'') {
+                    let _ = write!(sql, " DEFAULT ({})", value);
+                } else {
+                    let _ = write!(sql, " DEFAULT {}", value);
+                }
+            }
+            Some(DefaultValue::Sequence) | None => {}
+        }
+        if field.unique && !field.primary_key {
+            sql.push_str(" UNIQUE");
+        }
+        if let Some(check) = check {
+            let _ = write!(
+                sql,
+                " CHECK ({} IN ({}))",
+                DIALECT.quote(&field.name),
+                check
+            );
+        }
+        lines.push(sql);
+    }
+
+    if !primary_key.is_empty() && !inline_key {
+        lines.push(format!(
+            "PRIMARY KEY ({})",
+            column_list(DIALECT, &primary_key)
+        ));
+    }
+    for fk in foreign_keys {
+        lines.push(fk.constraint(DIALECT));
+    }
+
+    // Comment lines must not get a trailing comma
+    let mut body = String::new();
+    let count = lines.iter().filter(|l| !l.starts_with("--")).count();
+    let mut written = 0;
+    for line in &lines {
+        body.push_str("    ");
+        body.push_str(line);
+        if !line.starts_with("--") {
+            written += 1;
+            if written < count {
+                body.push(',');
+            }
+        }
+        body.push('\n');
+    }
+
+    format!("CREATE TABLE {} (\n{})", DIALECT.table_name(table), body)
+}
+
+/// The affinity a field is declared with, following SQLite's own rules for
+/// declared types, plus the value list for enum columns.
+fn affinity(
+    field: &FieldInfo,
+    custom_types: &[CustomTypeDef],
+) -> Result<(&'static str, Option<String>), String> {
+    let name = field.type_name.trim().to_ascii_lowercase();
+    if field.is_array {
+        return Err(format!(
+            "array type `{}` has no SQLite equivalent",
+            field.type_sql()
+        ));
+    }
+    if let Some(ct) = find_custom_type(&name, custom_types) {
+        if super::is_enum(ct) {
+            let values: Vec<String> = ct.values.iter().map(|v| string_literal(v)).collect();
+            return Ok(("TEXT", Some(values.join(", "))));
+        }
+        return Err(format!(
+            "composite type {} has no SQLite equivalent",
+            ct.qualified_name()
+        ));
+    }
+
+    let affinity = match name.as_str() {
+        "boolean" | "bool" | "bit" => "INTEGER",
+        "uuid"
+        | "json"
+        | "jsonb"
+        | "xml"
+        | "date"
+        | "time"
+        | "timetz"
+        | "timestamp"
+        | "timestamptz"
+        | "timestamp with time zone"
+        | "timestamp without time zone"
+        | "time with time zone"
+        | "time without time zone"
+        | "datetime"
+        | "datetime2"
+        | "datetimeoffset"
+        | "interval"
+        | "inet"
+        | "cidr"
+        | "macaddr" => "TEXT",
+        "bytea" | "" => "BLOB",
+        n if n.contains("int") || is_serial(n) => "INTEGER",
+        n if n.contains("char") || n.contains("clob") || n.contains("text") => "TEXT",
+        n if n.contains("blob") || n.contains("binary") => "BLOB",
+        n if n.contains("real") || n.contains("floa") || n.contains("doub") => "REAL",
+        _ => "NUMERIC",
+    };
+    Ok((affinity, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{enumeration, field_mut, shop};
+
+    #[test]
+    fn foreign_keys_are_declared_inline() {
+        let sql = export(&shop()).unwrap();
+        assert!(sql.contains(concat!(
+            "CREATE TABLE \"orders\" (\n",
+            "    \"id\" INTEGER NOT NULL,\n",
+            "    \"user_id\" INTEGER,\n",
+            "    \"total\" NUMERIC,\n",
+            "    PRIMARY KEY (\"id\"),\n",
+            "    CONSTRAINT \"fk_orders_user_id\" FOREIGN KEY (\"user_id\") REFERENCES \"users\" (\"id\")\n",
+            ");\n",
+            "CREATE INDEX \"orders_user_id\" ON \"orders\" (\"user_id\");\n",
+        )));
+        assert!(!sql.contains("ALTER TABLE"));
+    }
+
+    #[test]
+    fn serial_keys_autoincrement() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_users", "f_users_id").r#type.name = "serial".to_string();
+        field_mut(&mut diagram, "t_users", "f_users_email").comments =
+            Some("login\nname".to_string());
+
+        let sql = export(&diagram).unwrap();
+        assert!(sql.contains(concat!(
+            "CREATE TABLE \"users\" (\n",
+            "    \"id\" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,\n",
+            "    -- login name\n",
+            "    \"email\" TEXT\n",
+            ");\n",
+        )));
+    }
+
+    #[test]
+    fn enums_become_checks() {
+        let mut diagram = shop();
+        diagram.custom_types = Some(vec![enumeration("c_status", "status", &["new", "it's"])]);
+        let total = field_mut(&mut diagram, "t_orders", "f_orders_total");
+        total.r#type.name = "status".to_string();
+        total.precision = None;
+        total.scale = None;
+
+        let sql = export(&diagram).unwrap();
+        assert!(sql.contains("    \"total\" TEXT CHECK (\"total\" IN ('new', 'it''s')),\n"));
+    }
+
+    #[test]
+    fn auto_increment_needs_an_integer_key() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_users", "f_users_email").increment = Some(true);
+
+        let err = export(&diagram).unwrap_err();
+        assert!(err.to_string().contains(
+            "column users.email: auto-increment needs a single-column INTEGER primary key in SQLite"
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+//! SQL Server rendering of the DDL export.
+//!
+//! Objects are bracket-quoted and schema-qualified (`public` maps to `dbo`
+//! for diagrams drawn for other databases), auto-increment columns become
+//! `IDENTITY(1,1)`, and character types from other databases are mapped to
+//! their Unicode `NVARCHAR`/`NCHAR` forms. Comments are stored as
+//! `MS_Description` extended properties. Statements that must start a batch
+//! are separated with `GO`.
+
+use super::export::{
+    check_composite_types, classify_default, find_custom_type, header, is_serial, ordered,
+    table_foreign_keys, DefaultValue, ExportError,
+};
+use super::{
+    column_list, create_index, default_constraint_name, primary_key_name, string_literal,
+    unique_constraint_name, Dialect,
+};
+use crate::diff::{self, CustomTypeDef, TableDef};
+use crate::models::Diagram;
+use crate::schema::FieldInfo;
+use std::fmt::Write;
+
+const DIALECT: Dialect = Dialect::SqlServer;
+
+pub(super) fn export(diagram: &Diagram) -> Result<String, ExportError> {
+    let native = Dialect::parse(&diagram.database_type) == Some(DIALECT);
+    let defs = diff::diagram_defs(diagram);
+    let mut tables: Vec<TableDef> = ordered(
+        &defs.tables,
+        diagram.dependencies.as_deref().unwrap_or_default(),
+    )
+    .into_iter()
+    .cloned()
+    .collect();
+    if !native {
+        for table in &mut tables {
+            table.schema = Some(native_schema(table.schema.as_deref()));
+        }
+    }
+    let mut problems = Vec::new();
+    check_composite_types(&defs.custom_types, DIALECT, &mut problems);
+    let mut out = header(diagram, DIALECT);
+
+    let mut schemas: Vec<&str> = tables
+        .iter()
+        .filter_map(|t| t.schema.as_deref())
+        .filter(|s| !s.is_empty() && *s != "dbo")
+        .collect();
+    schemas.sort_unstable();
+    schemas.dedup();
+    if !schemas.is_empty() {
+        out.push('\n');
+        for schema in schemas {
+            // CREATE SCHEMA has to be alone in its batch
+            let _ = writeln!(
+                out,
+                "IF SCHEMA_ID({}) IS NULL EXEC({});",
+                unicode_literal(schema),
+                unicode_literal(&format!("CREATE SCHEMA {}", DIALECT.quote(schema)))
+            );
+        }
+    }
+
+    for table in &tables {
+        out.push('\n');
+        if table.is_materialized_view {
+            problems.push(format!(
+                "materialized view {} has no sql_server equivalent",
+                table.qualified_name()
+            ));
+            continue;
+        }
+
+        if table.is_view {
+            let columns: Vec<String> = table
+                .fields
+                .iter()
+                .map(|f| {
+                    let sql_type = map_type(f, &defs.custom_types, native)
+                        .map(|(t, _)| t)
+                        .unwrap_or_else(|_| "NVARCHAR(MAX)".to_string());
+                    format!("CAST(NULL AS {}) AS {}", sql_type, DIALECT.quote(&f.name))
+                })
+                .collect();
+            let _ = writeln!(
+                out,
+                "GO\n-- Placeholder body: the view definition is not stored in the diagram\nCREATE VIEW {} AS\nSELECT\n    {};\nGO",
+                DIALECT.table_name(table),
+                columns.join(",\n    ")
+            );
+        } else {
+            let mut lines: Vec<String> = table
+                .fields
+                .iter()
+                .map(|field| column(table, field, &defs.custom_types, native, &mut problems))
+                .collect();
+            let primary_key = super::primary_key_columns(table);
+            if !primary_key.is_empty() {
+                lines.push(format!(
+                    "CONSTRAINT {} PRIMARY KEY ({})",
+                    DIALECT.quote(&primary_key_name(&table.name)),
+                    column_list(DIALECT, &primary_key)
+                ));
+            }
+            let _ = writeln!(
+                out,
+                "CREATE TABLE {} (\n    {}\n);",
+                DIALECT.table_name(table),
+                lines.join(",\n    ")
+            );
+            for index in &table.indexes {
+                let _ = writeln!(out, "{};", create_index(DIALECT, table, index));
+            }
+        }
+
+        let kind = if table.is_view { "VIEW" } else { "TABLE" };
+        if let Some(comment) = &table.comment {
+            let _ = writeln!(out, "{};", describe(table, kind, None, comment));
+        }
+        for field in &table.fields {
+            if let Some(comment) = &field.comment {
+                let _ = writeln!(
+                    out,
+                    "{};",
+                    describe(table, kind, Some(&field.name), comment)
+                );
+            }
+        }
+    }
+
+    // Matched against the diagram's own schemas, before any `dbo` mapping
+    let foreign_keys = table_foreign_keys(&defs, &defs.tables.iter().collect::<Vec<_>>());
+    if !foreign_keys.is_empty() {
+        out.push('\n');
+        for mut fk in foreign_keys {
+            if !native {
+                fk.schema = Some(native_schema(fk.schema.as_deref()));
+                fk.referenced_schema = Some(native_schema(fk.referenced_schema.as_deref()));
+            }
+            let _ = writeln!(
+                out,
+                "ALTER TABLE {} ADD {};",
+                fk.table_name(DIALECT),
+                fk.constraint(DIALECT)
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(out)
+    } else {
+        Err(ExportError::Unrepresentable {
+            dialect: DIALECT,
+            problems,
+        })
+    }
+}
+
+/// The schema a diagram from another database lands in: its default
+/// schema (or none) becomes `dbo`.
+fn native_schema(schema: Option<&str>) -> String {
+    match schema {
+        None | Some("") | Some("public") => "dbo".to_string(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn unicode_literal(value: &str) -> String {
+    format!("N{}", string_literal(value))
+}
+
+/// `sp_addextendedproperty` call storing a table, view or column comment.
+fn describe(table: &TableDef, kind: &str, column: Option<&str>, comment: &str) -> String {
+    let mut sql = format!(
+        "EXEC sp_addextendedproperty @name = N'MS_Description', @value = {}, @level0type = N'SCHEMA', @level0name = {}, @level1type = N'{}', @level1name = {}",
+        unicode_literal(comment),
+        unicode_literal(table.schema.as_deref().unwrap_or("dbo")),
+        kind,
+        unicode_literal(&table.name)
+    );
+    if let Some(column) = column {
+        let _ = write!(
+            sql,
+            ", @level2type = N'COLUMN', @level2name = {}",
+            unicode_literal(column)
+        );
+    }
+    sql
+}
+
+fn column(
+    table: &TableDef,
+    field: &FieldInfo,
+    custom_types: &[CustomTypeDef],
+    native: bool,
+    problems: &mut Vec<String>,
+) -> String {
+    let location = format!("column {}.{}", table.qualified_name(), field.name);
+    let (sql_type, check) = match map_type(field, custom_types, native) {
+        Ok(mapped) => mapped,
+        Err(reason) => {
+            problems.push(format!("{}: {}", location, reason));
+            (field.type_sql(), None)
+        }
+    };
+
+    let mut sql = format!("{} {}", DIALECT.quote(&field.name), sql_type);
+    let default = field.default.as_deref().map(classify_default);
+    let identity = field.increment
+        || is_serial(&field.type_name)
+        || matches!(default, Some(DefaultValue::Sequence));
+    if identity {
+        sql.push_str(" IDENTITY(1,1)");
+    }
+    if native {
+        if let Some(collation) = &field.collation {
+            let _ = write!(sql, " COLLATE {}", collation);
+        }
+    }
+    sql.push_str(if field.nullable && !field.primary_key {
+        " NULL"
+    } else {
+        " NOT NULL"
+    });
+
+    let value = match default {
+        Some(DefaultValue::CurrentTimestamp) => Some(
+            if sql_type.eq_ignore_ascii_case("datetimeoffset") {
+                "SYSDATETIMEOFFSET()"
+            } else {
+                "SYSDATETIME()"
+            }
+            .to_string(),
+        ),
+        Some(DefaultValue::RandomUuid) => Some("NEWID()".to_string()),
+        Some(DefaultValue::Value(value)) => Some(match value.to_ascii_lowercase().as_str() {
+            "true" => "1".to_string(),
+            "false" => "0".to_string(),
+            _ => value.to_string(),
+        }),
+        Some(DefaultValue::Sequence) | None => None,
+    };
+    if let Some(value) = value {
+        let _ = write!(
+            sql,
+            " CONSTRAINT {} DEFAULT {}",
+            DIALECT.quote(&default_constraint_name(&table.name, &field.name)),
+            value
+        );
+    }
+    if field.unique && !field.primary_key {
+        let _ = write!(
+            sql,
+            " CONSTRAINT {} UNIQUE",
+            DIALECT.quote(&unique_constraint_name(&table.name, &field.name))
+        );
+    }
+    if let Some(values) = check {
+        let _ = write!(
+            sql,
+            " CHECK ({} IN ({}))",
+            DIALECT.quote(&field.name),
+            values
+        );
+    }
+    sql
+}
+
+/// Maps a field's type to SQL Server, plus the value list of enum columns
+/// (enforced with a `CHECK` constraint). Types of diagrams drawn for SQL
+/// Server are kept as declared.
+fn map_type(
+    field: &FieldInfo,
+    custom_types: &[CustomTypeDef],
+    native: bool,
+) -> Result<(String, Option<String>), String> {
+    let name = field.type_name.trim().to_ascii_lowercase();
+    if field.is_array {
+        return Err(format!(
+            "array type `{}` has no SQL Server equivalent",
+            field.type_sql()
+        ));
+    }
+    if let Some(ct) = find_custom_type(&name, custom_types) {
+        if super::is_enum(ct) {
+            let values: Vec<String> = ct.values.iter().map(|v| unicode_literal(v)).collect();
+            let width = ct
+                .values
+                .iter()
+                .map(|v| v.chars().count())
+                .max()
+                .unwrap_or(1)
+                .max(1);
+            return Ok((format!("NVARCHAR({})", width), Some(values.join(", "))));
+        }
+        return Err(format!(
+            "composite type {} has no SQL Server equivalent",
+            ct.qualified_name()
+        ));
+    }
+    if native {
+        return Ok((field.type_sql(), None));
+    }
+
+    let length = field
+        .character_maximum_length
+        .as_deref()
+        .filter(|l| l.parse::<u32>().map(|n| n <= 4000).unwrap_or(false));
+    let mapped = match name.as_str() {
+        "serial" | "serial4" | "integer" | "int4" | "int" => "INT".to_string(),
+        "bigserial" | "serial8" | "bigint" | "int8" => "BIGINT".to_string(),
+        "smallserial" | "serial2" | "smallint" | "int2" => "SMALLINT".to_string(),
+        "tinyint" => "TINYINT".to_string(),
+        "boolean" | "bool" => "BIT".to_string(),
+        "character varying" | "varchar" | "nvarchar" => {
+            format!("NVARCHAR({})", length.unwrap_or("MAX"))
+        }
+        "character" | "char" | "bpchar" | "nchar" => format!("NCHAR({})", length.unwrap_or("1")),
+        "text" | "citext" | "name" | "mediumtext" | "longtext" | "tinytext" | "json" | "jsonb" => {
+            "NVARCHAR(MAX)".to_string()
+        }
+        "xml" => "XML".to_string(),
+        "uuid" => "UNIQUEIDENTIFIER".to_string(),
+        "bytea" | "blob" | "longblob" | "mediumblob" => "VARBINARY(MAX)".to_string(),
+        "numeric" | "decimal" => match (field.precision, field.scale) {
+            (Some(p), Some(s)) => format!("DECIMAL({},{})", p, s),
+            (Some(p), None) => format!("DECIMAL({})", p),
+            _ => "DECIMAL(38,10)".to_string(),
+        },
+        "real" | "float4" => "REAL".to_string(),
+        "double precision" | "float8" | "double" | "float" => "FLOAT".to_string(),
+        "money" => "MONEY".to_string(),
+        "timestamp" | "timestamp without time zone" | "datetime" => "DATETIME2".to_string(),
+        "timestamptz" | "timestamp with time zone" => "DATETIMEOFFSET".to_string(),
+        "date" => "DATE".to_string(),
+        "time" | "time without time zone" | "timetz" | "time with time zone" => "TIME".to_string(),
+        "interval" | "tsvector" | "tsquery" | "inet" | "cidr" | "macaddr" | "macaddr8"
+        | "hstore" | "ltree" | "point" | "line" | "lseg" | "box" | "path" | "polygon"
+        | "circle" | "int4range" | "int8range" | "numrange" | "tsrange" | "tstzrange"
+        | "daterange" | "oid" | "enum" | "set" => {
+            return Err(format!(
+                "type `{}` has no SQL Server equivalent",
+                field.type_name
+            ))
+        }
+        _ => field.type_sql(),
+    };
+    Ok((mapped, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::shop;
+
+    #[test]
+    fn public_tables_move_to_dbo_with_their_foreign_keys() {
+        let sql = export(&shop()).unwrap();
+        assert!(sql.contains("CREATE TABLE [dbo].[orders] (\n"));
+        assert!(sql.contains("    [email] NVARCHAR(255) NULL,\n"));
+        assert!(sql.contains(concat!(
+            "ALTER TABLE [dbo].[orders] ADD CONSTRAINT [fk_orders_user_id] ",
+            "FOREIGN KEY ([user_id]) REFERENCES [dbo].[users] ([id]);\n"
+        )));
+    }
+
+    #[test]
+    fn native_diagrams_keep_their_schemas() {
+        let mut diagram = shop();
+        diagram.database_type = "sql_server".to_string();
+        for table in diagram.tables.as_mut().unwrap() {
+            table.schema = Some("sales".to_string());
+        }
+        for rel in diagram.relationships.as_mut().unwrap() {
+            rel.source_schema = Some("sales".to_string());
+            rel.target_schema = Some("sales".to_string());
+        }
+
+        let sql = export(&diagram).unwrap();
+        assert!(sql.contains("CREATE TABLE [sales].[users] (\n"));
+        assert!(sql.contains("REFERENCES [sales].[users] ([id]);\n"));
+        assert!(!sql.contains("[dbo]"));
+    }
+}
@@ -0,0 +1,568 @@
+//! Data dictionary documents for a diagram, as Markdown or as a standalone
+//! HTML page: a section per schema with its tables, their fields, indexes,
+//! relationships and the notes placed on them, and its custom types.
+
+use crate::erd;
+use crate::layout::{self, TABLE_WIDTH};
+use crate::models::{Diagram, Note, Relationship, Table};
+use crate::schema::{self, FieldInfo};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A piece of inline text.
+enum Span {
+    Text(String),
+    Code(String),
+    /// A link to the section of a table.
+    Link(String, String),
+}
+
+type Line = Vec<Span>;
+
+enum Block {
+    Heading {
+        level: usize,
+        text: Line,
+        anchor: Option<String>,
+    },
+    Paragraph(Line),
+    List(Vec<Line>),
+    Table {
+        columns: &'static [&'static str],
+        rows: Vec<Vec<Line>>,
+    },
+}
+
+fn text(value: impl Into<String>) -> Span {
+    Span::Text(value.into())
+}
+
+fn code(value: impl Into<String>) -> Span {
+    Span::Code(value.into())
+}
+
+pub fn markdown(diagram: &Diagram) -> String {
+    let mut out = String::new();
+    for block in document(diagram) {
+        match block {
+            Block::Heading {
+                level,
+                text,
+                anchor,
+            } => {
+                if let Some(anchor) = anchor {
+                    let _ = writeln!(out, "<a id=\"{}\"></a>\n", anchor);
+                }
+                let _ = writeln!(out, "{} {}\n", "#".repeat(level), markdown_line(&text));
+            }
+            Block::Paragraph(line) => {
+                let _ = writeln!(out, "{}\n", markdown_line(&line));
+            }
+            Block::List(items) => {
+                for item in items {
+                    let _ = writeln!(out, "- {}", markdown_line(&item));
+                }
+                out.push('\n');
+            }
+            Block::Table { columns, rows } => {
+                let _ = writeln!(out, "| {} |", columns.join(" | "));
+                let _ = writeln!(out, "|{}", " --- |".repeat(columns.len()));
+                for row in rows {
+                    let cells: Vec<_> = row
+                        .iter()
+                        .map(|cell| markdown_line(cell).replace('|', "\\|"))
+                        .collect();
+                    let _ = writeln!(out, "| {} |", cells.join(" | "));
+                }
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+pub fn html(diagram: &Diagram) -> String {
+    let mut out =
+        String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(out, "<title>{}</title>", escape(&diagram.name));
+    out.push_str(STYLE);
+    out.push_str("</head>\n<body>\n");
+    for block in document(diagram) {
+        match block {
+            Block::Heading {
+                level,
+                text,
+                anchor,
+            } => {
+                let id = anchor
+                    .map(|a| format!(" id=\"{}\"", escape(&a)))
+                    .unwrap_or_default();
+                let _ = writeln!(out, "<h{l}{}>{}</h{l}>", id, html_line(&text), l = level);
+            }
+            Block::Paragraph(line) => {
+                let _ = writeln!(out, "<p>{}</p>", html_line(&line));
+            }
+            Block::List(items) => {
+                out.push_str("<ul>\n");
+                for item in items {
+                    let _ = writeln!(out, "<li>{}</li>", html_line(&item));
+                }
+                out.push_str("</ul>\n");
+            }
+            Block::Table { columns, rows } => {
+                out.push_str("<table>\n<thead><tr>");
+                for column in columns {
+                    let _ = write!(out, "<th>{}</th>", escape(column));
+                }
+                out.push_str("</tr></thead>\n<tbody>\n");
+                for row in rows {
+                    out.push_str("<tr>");
+                    for cell in row {
+                        let _ = write!(out, "<td>{}</td>", html_line(&cell));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</tbody>\n</table>\n");
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+const STYLE: &str = "<style>
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #1f2937; max-width: 72rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }
+h2 { border-bottom: 1px solid #e5e7eb; padding-bottom: .3rem; margin-top: 2.5rem; }
+h3 { margin-top: 2rem; }
+table { border-collapse: collapse; margin: .5rem 0 1rem; }
+th, td { border: 1px solid #e5e7eb; padding: .3rem .6rem; text-align: left; vertical-align: top; }
+th { background: #f9fafb; }
+code { background: #f3f4f6; padding: .1rem .3rem; border-radius: 3px; font-size: .9em; }
+a { color: #2563eb; text-decoration: none; }
+</style>
+";
+
+/// The document shared by both formats.
+fn document(diagram: &Diagram) -> Vec<Block> {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let dependencies = diagram.dependencies.as_deref().unwrap_or_default();
+    let custom_types = diagram.custom_types.as_deref().unwrap_or_default();
+    let notes = diagram.notes.as_deref().unwrap_or_default();
+
+    let mut schemas: BTreeMap<&str, Vec<&Table>> = BTreeMap::new();
+    for table in tables {
+        schemas
+            .entry(table.schema.as_deref().unwrap_or_default())
+            .or_default()
+            .push(table);
+    }
+    for custom_type in custom_types {
+        schemas
+            .entry(custom_type.schema.as_deref().unwrap_or_default())
+            .or_default();
+    }
+    for members in schemas.values_mut() {
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    let link = |table_id: &str| match tables.iter().find(|t| t.id == table_id) {
+        Some(table) => Span::Link(display_name(table), anchor(table)),
+        None => code(table_id),
+    };
+    let field_name = |table_id: &str, field_id: Option<&str>| {
+        tables
+            .iter()
+            .find(|t| t.id == table_id)
+            .and_then(|t| {
+                schema::fields(t)
+                    .into_iter()
+                    .find(|f| Some(f.id.as_str()) == field_id)
+            })
+            .map(|f| f.name)
+            .unwrap_or_default()
+    };
+    let foreign_keys = erd::foreign_key_fields(relationships);
+
+    let mut blocks = vec![Block::Heading {
+        level: 1,
+        text: vec![text(&diagram.name)],
+        anchor: None,
+    }];
+    let mut summary = vec![
+        text("Database: "),
+        code(&diagram.database_type),
+        text(format!(
+            ". {}, {}",
+            count(tables.len(), "table"),
+            count(relationships.len(), "relationship")
+        )),
+    ];
+    if let Some(version) = diagram.version {
+        summary.push(text(format!(", version {}", version)));
+    }
+    summary.push(text(format!(
+        ", last updated {}.",
+        diagram.updated_at.format("%Y-%m-%d %H:%M UTC")
+    )));
+    blocks.push(Block::Paragraph(summary));
+
+    for (schema_name, members) in &schemas {
+        blocks.push(Block::Heading {
+            level: 2,
+            text: if schema_name.is_empty() {
+                vec![text("Default schema")]
+            } else {
+                vec![text("Schema "), code(*schema_name)]
+            },
+            anchor: None,
+        });
+        if !members.is_empty() {
+            blocks.push(Block::List(
+                members.iter().map(|t| vec![link(&t.id)]).collect(),
+            ));
+        }
+
+        for table in members {
+            let kind = match (table.is_view, table.is_materialized_view) {
+                (_, Some(true)) => " (materialized view)",
+                (Some(true), _) => " (view)",
+                _ => "",
+            };
+            blocks.push(Block::Heading {
+                level: 3,
+                text: vec![code(display_name(table)), text(kind)],
+                anchor: Some(anchor(table)),
+            });
+            if let Some(comment) = table.comment.as_deref().filter(|c| !c.is_empty()) {
+                blocks.push(Block::Paragraph(vec![text(comment)]));
+            }
+
+            let fields = schema::fields(table);
+            blocks.push(Block::Table {
+                columns: &["Field", "Type", "Nullable", "Default", "Keys", "Comment"],
+                rows: fields
+                    .iter()
+                    .map(|field| {
+                        let keys = erd::key_markers(table, field, &foreign_keys);
+                        vec![
+                            vec![code(&field.name)],
+                            vec![code(field.type_sql())],
+                            vec![text(if field.nullable { "yes" } else { "no" })],
+                            default_value(field),
+                            vec![text(keys.join(", "))],
+                            vec![text(field.comment.clone().unwrap_or_default())],
+                        ]
+                    })
+                    .collect(),
+            });
+
+            let indexes = schema::indexes(table);
+            if !indexes.is_empty() {
+                blocks.push(Block::Paragraph(vec![text("Indexes:")]));
+                blocks.push(Block::Table {
+                    columns: &["Index", "Fields", "Unique"],
+                    rows: indexes
+                        .iter()
+                        .map(|index| {
+                            let columns = index
+                                .field_ids
+                                .iter()
+                                .map(|id| {
+                                    fields
+                                        .iter()
+                                        .find(|f| &f.id == id)
+                                        .map_or(id.as_str(), |f| f.name.as_str())
+                                })
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            vec![
+                                vec![code(&index.name)],
+                                vec![text(columns)],
+                                vec![text(if index.unique { "yes" } else { "no" })],
+                            ]
+                        })
+                        .collect(),
+                });
+            }
+
+            let mut outgoing = Vec::new();
+            let mut incoming = Vec::new();
+            for rel in relationships {
+                let (holder, other) = ends(rel);
+                // `own` is this table's end of the relationship.
+                let describe = |own: End, other: End, arrow: &str| {
+                    let mut line = vec![
+                        code(field_name(own.0, own.1)),
+                        text(arrow),
+                        link(other.0),
+                        text("."),
+                        code(field_name(other.0, other.1)),
+                    ];
+                    line.push(text(format!(" ({})", cardinality(rel, holder.0))));
+                    if let Some(name) = rel.name.as_deref().filter(|n| !n.is_empty()) {
+                        line.push(text(" "));
+                        line.push(code(name));
+                    }
+                    line
+                };
+                if holder.0 == table.id {
+                    outgoing.push(describe(holder, other, " → "));
+                }
+                if other.0 == table.id {
+                    incoming.push(describe(other, holder, " ← "));
+                }
+            }
+            if !outgoing.is_empty() {
+                blocks.push(Block::Paragraph(vec![text("References:")]));
+                blocks.push(Block::List(outgoing));
+            }
+            if !incoming.is_empty() {
+                blocks.push(Block::Paragraph(vec![text("Referenced by:")]));
+                blocks.push(Block::List(incoming));
+            }
+
+            let sources: Vec<_> = dependencies
+                .iter()
+                .filter(|d| d.dependent_table_id == table.id)
+                .map(|d| vec![link(&d.table_id)])
+                .collect();
+            if !sources.is_empty() {
+                blocks.push(Block::Paragraph(vec![text("Depends on:")]));
+                blocks.push(Block::List(sources));
+            }
+
+            let attached: Vec<_> = notes
+                .iter()
+                .filter(|n| attached_to(n, tables).is_some_and(|t| t.id == table.id))
+                .filter_map(|n| n.content.as_deref().filter(|c| !c.trim().is_empty()))
+                .map(|c| vec![text(c)])
+                .collect();
+            if !attached.is_empty() {
+                blocks.push(Block::Paragraph(vec![text("Notes:")]));
+                blocks.push(Block::List(attached));
+            }
+        }
+
+        let types: Vec<_> = custom_types
+            .iter()
+            .filter(|t| t.schema.as_deref().unwrap_or_default() == *schema_name)
+            .collect();
+        if !types.is_empty() {
+            blocks.push(Block::Heading {
+                level: 3,
+                text: vec![text("Types")],
+                anchor: None,
+            });
+            blocks.push(Block::Table {
+                columns: &["Type", "Kind", "Definition"],
+                rows: types
+                    .iter()
+                    .map(|t| {
+                        let kind = t.kind.as_deref().unwrap_or("enum");
+                        let definition = if kind == "composite" {
+                            schema::composite_fields(t)
+                                .iter()
+                                .map(|f| format!("{} {}", f.field, f.type_name))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        } else {
+                            schema::enum_values(t).join(", ")
+                        };
+                        vec![
+                            vec![code(&t.r#type)],
+                            vec![text(kind)],
+                            vec![text(definition)],
+                        ]
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    let loose: Vec<_> = notes
+        .iter()
+        .filter(|n| attached_to(n, tables).is_none())
+        .filter_map(|n| n.content.as_deref().filter(|c| !c.trim().is_empty()))
+        .map(|c| vec![text(c)])
+        .collect();
+    if !loose.is_empty() {
+        blocks.push(Block::Heading {
+            level: 2,
+            text: vec![text("Notes")],
+            anchor: None,
+        });
+        blocks.push(Block::List(loose));
+    }
+    blocks
+}
+
+fn count(n: usize, noun: &str) -> String {
+    format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" })
+}
+
+fn display_name(table: &Table) -> String {
+    schema::qualified_name(table.schema.as_deref(), &table.name)
+}
+
+fn anchor(table: &Table) -> String {
+    let slug: String = display_name(table)
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("table-{}", slug)
+}
+
+fn default_value(field: &FieldInfo) -> Line {
+    match (&field.default, field.increment) {
+        (Some(default), _) if !default.is_empty() => vec![code(default)],
+        (_, true) => vec![text("auto increment")],
+        _ => Vec::new(),
+    }
+}
+
+/// One end of a relationship, as (table id, field id).
+type End<'a> = (&'a str, Option<&'a str>);
+
+/// The end holding the foreign key and the end it references. Many-to-many
+/// relationships are described from their source.
+fn ends(rel: &Relationship) -> (End<'_>, End<'_>) {
+    let source = (rel.source_table_id.as_str(), rel.source_field_id.as_deref());
+    let target = (rel.target_table_id.as_str(), rel.target_field_id.as_deref());
+    match erd::foreign_key_end(rel) {
+        Some((table_id, field_id)) if (table_id, Some(field_id)) == target => (target, source),
+        _ => (source, target),
+    }
+}
+
+/// The cardinality seen from the end holding the foreign key, such as
+/// `many to one`.
+fn cardinality(rel: &Relationship, holder: &str) -> String {
+    let name = |c: Option<&str>| match c {
+        Some("many") => "many",
+        _ => "one",
+    };
+    let source = name(rel.source_cardinality.as_deref());
+    let target = name(rel.target_cardinality.as_deref());
+    if holder == rel.source_table_id {
+        format!("{} to {}", source, target)
+    } else {
+        format!("{} to {}", target, source)
+    }
+}
+
+/// The table a note is placed on: the first one its rectangle overlaps.
+/// ChartDB notes are free-standing, so this is all that ties them to a table.
+fn attached_to<'a>(note: &Note, tables: &'a [Table]) -> Option<&'a Table> {
+    let (Some(x), Some(y)) = (note.x, note.y) else {
+        return None;
+    };
+    let (right, bottom) = (
+        x + note.width.unwrap_or_default(),
+        y + note.height.unwrap_or_default(),
+    );
+    tables.iter().find(|t| {
+        let (Some(tx), Some(ty)) = (t.x, t.y) else {
+            return false;
+        };
+        let (t_right, t_bottom) = (tx + t.width.unwrap_or(TABLE_WIDTH), ty + layout::height(t));
+        x < t_right && tx < right && y < t_bottom && ty < bottom
+    })
+}
+
+fn markdown_line(line: &Line) -> String {
+    line.iter()
+        .map(|span| match span {
+            Span::Text(value) => markdown_text(value),
+            Span::Code(value) => markdown_code(value),
+            Span::Link(value, anchor) => format!("[{}](#{})", markdown_text(value), anchor),
+        })
+        .collect()
+}
+
+/// Markdown text on one line, with the characters that would start
+/// formatting escaped. Underscores inside words are left alone, as GitHub
+/// does not read them as emphasis.
+fn markdown_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '*' | '[' | ']' | '<' | '>' | '`' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\r' => {}
+            '\n' => out.push_str("<br>"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn markdown_code(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.contains('`') {
+        format!("`` {} ``", value)
+    } else {
+        format!("`{}`", value)
+    }
+}
+
+fn html_line(line: &Line) -> String {
+    line.iter()
+        .map(|span| match span {
+            Span::Text(value) => escape(value).replace('\n', "<br>"),
+            Span::Code(value) => format!("<code>{}</code>", escape(value)),
+            Span::Link(value, anchor) => {
+                format!("<a href=\"#{}\">{}</a>", escape(anchor), escape(value))
+            }
+        })
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{field_mut, shop};
+
+    #[test]
+    fn markdown_documents_fields_indexes_and_references() {
+        let doc = markdown(&shop());
+        assert!(doc.starts_with("# Test\n\nDatabase: `postgresql`. 2 tables, 1 relationship,"));
+        assert!(doc.contains("- [public.orders](#table-public-orders)\n"));
+        assert!(doc.contains("| `user_id` | `integer` | yes |  | FK |  |\n"));
+        assert!(doc.contains("| `orders_user_id` | user_id | no |\n"));
+        assert!(
+            doc.contains("- `user_id` → [public.users](#table-public-users).`id` (many to one)\n")
+        );
+        assert!(doc
+            .contains("- `id` ← [public.orders](#table-public-orders).`user_id` (many to one)\n"));
+    }
+
+    #[test]
+    fn comments_are_escaped() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_users", "f_users_email").comments =
+            Some("login | <b>contact</b>".to_string());
+
+        assert!(markdown(&diagram).contains(r"login \| \<b\>contact\</b\>"));
+        assert!(html(&diagram).contains("<td>login | &lt;b&gt;contact&lt;/b&gt;</td>"));
+    }
+
+    #[test]
+    fn html_links_tables_by_anchor() {
+        let doc = html(&shop());
+        assert!(doc.contains(r#"<h3 id="table-public-users"><code>public.users</code></h3>"#));
+        assert!(doc.contains(r##"<a href="#table-public-users">public.users</a>.<code>id</code>"##));
+        assert!(doc.ends_with("</body>\n</html>\n"));
+    }
+}
@@ -0,0 +1,823 @@
+//! Structural comparison of two diagrams.
+//!
+//! Entities are paired by id first, so renames within one diagram's history
+//! are recognised, and then by name, so two unrelated diagrams (whose ids
+//! never match) can still be compared.
+
+use crate::models::{CustomType, Diagram, Relationship, Table};
+use crate::schema::{self, CompositeField, FieldInfo, IndexInfo};
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDef {
+    pub id: String,
+    pub schema: Option<String>,
+    pub name: String,
+    pub is_view: bool,
+    pub is_materialized_view: bool,
+    pub comment: Option<String>,
+    pub fields: Vec<FieldInfo>,
+    pub indexes: Vec<IndexDef>,
+}
+
+impl TableDef {
+    pub fn qualified_name(&self) -> String {
+        schema::qualified_name(self.schema.as_deref(), &self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexDef {
+    pub id: String,
+    pub name: String,
+    pub unique: bool,
+    /// Names of the indexed fields, in index order.
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipDef {
+    pub id: String,
+    pub name: Option<String>,
+    pub source_schema: Option<String>,
+    pub source_table: String,
+    pub source_field: Option<String>,
+    pub target_schema: Option<String>,
+    pub target_table: String,
+    pub target_field: Option<String>,
+    pub source_cardinality: Option<String>,
+    pub target_cardinality: Option<String>,
+}
+
+impl RelationshipDef {
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!(
+                "{}.{} -> {}.{}",
+                schema::qualified_name(self.source_schema.as_deref(), &self.source_table),
+                self.source_field.as_deref().unwrap_or("?"),
+                schema::qualified_name(self.target_schema.as_deref(), &self.target_table),
+                self.target_field.as_deref().unwrap_or("?"),
+            ),
+        }
+    }
+
+    fn cardinality(&self) -> String {
+        format!(
+            "{}:{}",
+            self.source_cardinality.as_deref().unwrap_or("?"),
+            self.target_cardinality.as_deref().unwrap_or("?")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomTypeDef {
+    pub id: String,
+    pub schema: Option<String>,
+    pub name: String,
+    pub kind: Option<String>,
+    pub values: Vec<String>,
+    pub fields: Vec<CompositeField>,
+}
+
+impl CustomTypeDef {
+    pub fn qualified_name(&self) -> String {
+        schema::qualified_name(self.schema.as_deref(), &self.name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+    pub before: FieldInfo,
+    pub after: FieldInfo,
+    /// Names of the properties that differ, e.g. `type`, `nullable`.
+    pub changed: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexDiff {
+    pub before: IndexDef,
+    pub after: IndexDef,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDiff {
+    pub id: String,
+    pub schema: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<Change<Option<String>>>,
+    pub fields_added: Vec<FieldInfo>,
+    pub fields_removed: Vec<FieldInfo>,
+    pub fields_changed: Vec<FieldDiff>,
+    pub indexes_added: Vec<IndexDef>,
+    pub indexes_removed: Vec<IndexDef>,
+    pub indexes_changed: Vec<IndexDiff>,
+    /// Both full definitions, for consumers that need more than the changes
+    /// (e.g. the primary key columns when generating migrations).
+    #[serde(skip)]
+    pub before: TableDef,
+    #[serde(skip)]
+    pub after: TableDef,
+}
+
+impl TableDiff {
+    fn is_empty(&self) -> bool {
+        self.renamed_from.is_none()
+            && self.comment.is_none()
+            && self.fields_added.is_empty()
+            && self.fields_removed.is_empty()
+            && self.fields_changed.is_empty()
+            && self.indexes_added.is_empty()
+            && self.indexes_removed.is_empty()
+            && self.indexes_changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelationshipDiff {
+    pub before: RelationshipDef,
+    pub after: RelationshipDef,
+    pub changed: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomTypeDiff {
+    pub before: CustomTypeDef,
+    pub after: CustomTypeDef,
+    pub values_added: Vec<String>,
+    pub values_removed: Vec<String>,
+    pub fields_added: Vec<CompositeField>,
+    pub fields_removed: Vec<CompositeField>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagramDiff {
+    pub tables_added: Vec<TableDef>,
+    pub tables_removed: Vec<TableDef>,
+    pub tables_changed: Vec<TableDiff>,
+    pub relationships_added: Vec<RelationshipDef>,
+    pub relationships_removed: Vec<RelationshipDef>,
+    pub relationships_changed: Vec<RelationshipDiff>,
+    pub custom_types_added: Vec<CustomTypeDef>,
+    pub custom_types_removed: Vec<CustomTypeDef>,
+    pub custom_types_changed: Vec<CustomTypeDiff>,
+}
+
+impl DiagramDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tables_added.is_empty()
+            && self.tables_removed.is_empty()
+            && self.tables_changed.is_empty()
+            && self.relationships_added.is_empty()
+            && self.relationships_removed.is_empty()
+            && self.relationships_changed.is_empty()
+            && self.custom_types_added.is_empty()
+            && self.custom_types_removed.is_empty()
+            && self.custom_types_changed.is_empty()
+    }
+}
+
+pub fn table_def(table: &Table) -> TableDef {
+    let fields = schema::fields(table);
+    let indexes = schema::indexes(table)
+        .into_iter()
+        .map(|index| index_def(&index, &fields))
+        .collect();
+    TableDef {
+        id: table.id.clone(),
+        schema: table.schema.clone(),
+        name: table.name.clone(),
+        is_view: table.is_view.unwrap_or(false),
+        is_materialized_view: table.is_materialized_view.unwrap_or(false),
+        comment: table.comment.clone().filter(|c| !c.is_empty()),
+        fields,
+        indexes,
+    }
+}
+
+fn index_def(index: &IndexInfo, fields: &[FieldInfo]) -> IndexDef {
+    IndexDef {
+        id: index.id.clone(),
+        name: index.name.clone(),
+        unique: index.unique,
+        columns: index
+            .field_ids
+            .iter()
+            .map(|id| {
+                fields
+                    .iter()
+                    .find(|f| &f.id == id)
+                    .map(|f| f.name.clone())
+                    .unwrap_or_else(|| id.clone())
+            })
+            .collect(),
+    }
+}
+
+pub fn relationship_def(rel: &Relationship, tables: &[TableDef]) -> RelationshipDef {
+    let resolve = |table_id: &str, field_id: Option<&String>| {
+        let table = tables.iter().find(|t| t.id == table_id);
+        let table_name = table
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| table_id.to_string());
+        let field_name = field_id.map(|field_id| {
+            table
+                .and_then(|t| t.fields.iter().find(|f| &f.id == field_id))
+                .map(|f| f.name.clone())
+                .unwrap_or_else(|| field_id.clone())
+        });
+        let schema = table.and_then(|t| t.schema.clone());
+        (schema, table_name, field_name)
+    };
+
+    let (source_schema, source_table, source_field) =
+        resolve(&rel.source_table_id, rel.source_field_id.as_ref());
+    let (target_schema, target_table, target_field) =
+        resolve(&rel.target_table_id, rel.target_field_id.as_ref());
+
+    RelationshipDef {
+        id: rel.id.clone(),
+        name: rel.name.clone(),
+        source_schema: rel.source_schema.clone().or(source_schema),
+        source_table,
+        source_field,
+        target_schema: rel.target_schema.clone().or(target_schema),
+        target_table,
+        target_field,
+        source_cardinality: rel.source_cardinality.clone(),
+        target_cardinality: rel.target_cardinality.clone(),
+    }
+}
+
+pub fn custom_type_def(ct: &CustomType) -> CustomTypeDef {
+    CustomTypeDef {
+        id: ct.id.clone(),
+        schema: ct.schema.clone(),
+        name: ct.r#type.clone(),
+        kind: ct.kind.clone(),
+        values: schema::enum_values(ct),
+        fields: schema::composite_fields(ct),
+    }
+}
+
+/// Resolved definitions of everything in a diagram that `compare` looks at.
+pub struct DiagramDefs {
+    pub tables: Vec<TableDef>,
+    pub relationships: Vec<RelationshipDef>,
+    pub custom_types: Vec<CustomTypeDef>,
+}
+
+pub fn diagram_defs(diagram: &Diagram) -> DiagramDefs {
+    let tables: Vec<TableDef> = diagram.tables.iter().flatten().map(table_def).collect();
+    let relationships = diagram
+        .relationships
+        .iter()
+        .flatten()
+        .map(|rel| relationship_def(rel, &tables))
+        .collect();
+    let custom_types = diagram
+        .custom_types
+        .iter()
+        .flatten()
+        .map(custom_type_def)
+        .collect();
+    DiagramDefs {
+        tables,
+        relationships,
+        custom_types,
+    }
+}
+
+/// Computes what changed going from `old` to `new`.
+pub fn compare(old: &Diagram, new: &Diagram) -> DiagramDiff {
+    let old = diagram_defs(old);
+    let new = diagram_defs(new);
+    let mut diff = DiagramDiff::default();
+
+    let tables = pair_up(&old.tables, &new.tables, |t| &t.id, |t| t.qualified_name());
+    diff.tables_removed = tables.removed.into_iter().cloned().collect();
+    diff.tables_added = tables.added.into_iter().cloned().collect();
+    for (before, after) in tables.matched {
+        let table_diff = compare_tables(before, after);
+        if !table_diff.is_empty() {
+            diff.tables_changed.push(table_diff);
+        }
+    }
+
+    let relationships = pair_up(
+        &old.relationships,
+        &new.relationships,
+        |r| &r.id,
+        |r| {
+            format!(
+                "{:?}.{}.{:?}->{:?}.{}.{:?}",
+                r.source_schema,
+                r.source_table,
+                r.source_field,
+                r.target_schema,
+                r.target_table,
+                r.target_field
+            )
+        },
+    );
+    diff.relationships_removed = relationships.removed.into_iter().cloned().collect();
+    diff.relationships_added = relationships.added.into_iter().cloned().collect();
+    for (before, after) in relationships.matched {
+        let mut changed = Vec::new();
+        if before.name != after.name {
+            changed.push("name");
+        }
+        if before.source_schema != after.source_schema
+            || before.source_table != after.source_table
+            || before.source_field != after.source_field
+        {
+            changed.push("source");
+        }
+        if before.target_schema != after.target_schema
+            || before.target_table != after.target_table
+            || before.target_field != after.target_field
+        {
+            changed.push("target");
+        }
+        if before.cardinality() != after.cardinality() {
+            changed.push("cardinality");
+        }
+        if !changed.is_empty() {
+            diff.relationships_changed.push(RelationshipDiff {
+                before: before.clone(),
+                after: after.clone(),
+                changed,
+            });
+        }
+    }
+
+    let custom_types = pair_up(
+        &old.custom_types,
+        &new.custom_types,
+        |c| &c.id,
+        |c| c.qualified_name(),
+    );
+    diff.custom_types_removed = custom_types.removed.into_iter().cloned().collect();
+    diff.custom_types_added = custom_types.added.into_iter().cloned().collect();
+    for (before, after) in custom_types.matched {
+        if before == after {
+            continue;
+        }
+        diff.custom_types_changed.push(CustomTypeDiff {
+            before: before.clone(),
+            after: after.clone(),
+            values_added: missing_from(&after.values, &before.values),
+            values_removed: missing_from(&before.values, &after.values),
+            fields_added: missing_from(&after.fields, &before.fields),
+            fields_removed: missing_from(&before.fields, &after.fields),
+        });
+    }
+
+    diff
+}
+
+fn compare_tables(before: &TableDef, after: &TableDef) -> TableDiff {
+    let mut diff = TableDiff {
+        id: after.id.clone(),
+        schema: after.schema.clone(),
+        name: after.name.clone(),
+        renamed_from: None,
+        comment: None,
+        fields_added: Vec::new(),
+        fields_removed: Vec::new(),
+        fields_changed: Vec::new(),
+        indexes_added: Vec::new(),
+        indexes_removed: Vec::new(),
+        indexes_changed: Vec::new(),
+        before: before.clone(),
+        after: after.clone(),
+    };
+
+    if before.qualified_name() != after.qualified_name() {
+        diff.renamed_from = Some(before.qualified_name());
+    }
+    if before.comment != after.comment {
+        diff.comment = Some(Change {
+            from: before.comment.clone(),
+            to: after.comment.clone(),
+        });
+    }
+
+    let fields = pair_up(&before.fields, &after.fields, |f| &f.id, |f| f.name.clone());
+    diff.fields_removed = fields.removed.into_iter().cloned().collect();
+    diff.fields_added = fields.added.into_iter().cloned().collect();
+    for (old, new) in fields.matched {
+        let changed = field_changes(old, new);
+        if !changed.is_empty() {
+            diff.fields_changed.push(FieldDiff {
+                before: old.clone(),
+                after: new.clone(),
+                changed,
+            });
+        }
+    }
+
+    let indexes = pair_up(
+        &before.indexes,
+        &after.indexes,
+        |i| &i.id,
+        |i| i.name.clone(),
+    );
+    diff.indexes_removed = indexes.removed.into_iter().cloned().collect();
+    diff.indexes_added = indexes.added.into_iter().cloned().collect();
+    for (old, new) in indexes.matched {
+        if old.name != new.name || old.unique != new.unique || old.columns != new.columns {
+            diff.indexes_changed.push(IndexDiff {
+                before: old.clone(),
+                after: new.clone(),
+            });
+        }
+    }
+
+    diff
+}
+
+fn field_changes(old: &FieldInfo, new: &FieldInfo) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.name != new.name {
+        changed.push("name");
+    }
+    if old.type_sql() != new.type_sql() {
+        changed.push("type");
+    }
+    if old.nullable != new.nullable {
+        changed.push("nullable");
+    }
+    if old.primary_key != new.primary_key {
+        changed.push("primaryKey");
+    }
+    if old.unique != new.unique {
+        changed.push("unique");
+    }
+    if old.increment != new.increment {
+        changed.push("increment");
+    }
+    if old.default != new.default {
+        changed.push("default");
+    }
+    if old.collation != new.collation {
+        changed.push("collation");
+    }
+    if old.comment != new.comment {
+        changed.push("comment");
+    }
+    changed
+}
+
+fn missing_from<T: Clone + PartialEq>(items: &[T], other: &[T]) -> Vec<T> {
+    items
+        .iter()
+        .filter(|item| !other.contains(item))
+        .cloned()
+        .collect()
+}
+
+struct Pairing<'a, T> {
+    matched: Vec<(&'a T, &'a T)>,
+    removed: Vec<&'a T>,
+    added: Vec<&'a T>,
+}
+
+/// Pairs items of two lists by id, then pairs the leftovers by `key`.
+fn pair_up<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    id: impl Fn(&T) -> &str,
+    key: impl Fn(&T) -> String,
+) -> Pairing<'a, T> {
+    let mut matched = Vec::new();
+    let mut old_left: Vec<&T> = Vec::new();
+    let mut new_left: Vec<&T> = new.iter().collect();
+
+    for item in old {
+        match new_left.iter().position(|n| id(n) == id(item)) {
+            Some(pos) => matched.push((item, new_left.remove(pos))),
+            None => old_left.push(item),
+        }
+    }
+
+    let mut removed = Vec::new();
+    for item in old_left {
+        match new_left.iter().position(|n| key(n) == key(item)) {
+            Some(pos) => matched.push((item, new_left.remove(pos))),
+            None => removed.push(item),
+        }
+    }
+
+    Pairing {
+        matched,
+        removed,
+        added: new_left,
+    }
+}
+
+/// Renders a diff as a plain-text report meant for code review.
+pub fn to_text(diff: &DiagramDiff) -> String {
+    let mut out = String::new();
+    if diff.is_empty() {
+        out.push_str("No structural changes.\n");
+        return out;
+    }
+
+    for table in &diff.tables_added {
+        let kind = if table.is_view { "view" } else { "table" };
+        let _ = writeln!(out, "+ {} {}", kind, table.qualified_name());
+        for field in &table.fields {
+            let _ = writeln!(out, "    + {}", describe_field(field));
+        }
+        for index in &table.indexes {
+            let _ = writeln!(out, "    + {}", describe_index(index));
+        }
+    }
+    for table in &diff.tables_removed {
+        let kind = if table.is_view { "view" } else { "table" };
+        let _ = writeln!(out, "- {} {}", kind, table.qualified_name());
+    }
+
+    for table in &diff.tables_changed {
+        let name = schema::qualified_name(table.schema.as_deref(), &table.name);
+        let _ = writeln!(out, "~ table {}", name);
+        if let Some(from) = &table.renamed_from {
+            let _ = writeln!(out, "    renamed from {}", from);
+        }
+        if let Some(comment) = &table.comment {
+            let _ = writeln!(
+                out,
+                "    comment: {} -> {}",
+                show(&comment.from),
+                show(&comment.to)
+            );
+        }
+        for field in &table.fields_added {
+            let _ = writeln!(out, "    + {}", describe_field(field));
+        }
+        for field in &table.fields_removed {
+            let _ = writeln!(out, "    - column {}", field.name);
+        }
+        for field in &table.fields_changed {
+            let _ = writeln!(out, "    ~ column {}", field.after.name);
+            for property in &field.changed {
+                let (from, to) = field_property(&field.before, &field.after, property);
+                let _ = writeln!(out, "        {}: {} -> {}", property, from, to);
+            }
+        }
+        for index in &table.indexes_added {
+            let _ = writeln!(out, "    + {}", describe_index(index));
+        }
+        for index in &table.indexes_removed {
+            let _ = writeln!(out, "    - index {}", index.name);
+        }
+        for index in &table.indexes_changed {
+            let _ = writeln!(
+                out,
+                "    ~ {} -> {}",
+                describe_index(&index.before),
+                describe_index(&index.after)
+            );
+        }
+    }
+
+    for rel in &diff.relationships_added {
+        let _ = writeln!(
+            out,
+            "+ relationship {} ({})",
+            rel.label(),
+            rel.cardinality()
+        );
+    }
+    for rel in &diff.relationships_removed {
+        let _ = writeln!(out, "- relationship {}", rel.label());
+    }
+    for rel in &diff.relationships_changed {
+        let _ = writeln!(out, "~ relationship {}", rel.after.label());
+        if rel.changed.contains(&"cardinality") {
+            let _ = writeln!(
+                out,
+                "    cardinality: {} -> {}",
+                rel.before.cardinality(),
+                rel.after.cardinality()
+            );
+        }
+        for property in rel.changed.iter().filter(|p| **p != "cardinality") {
+            let _ = writeln!(out, "    {} changed", property);
+        }
+    }
+
+    for ct in &diff.custom_types_added {
+        let _ = writeln!(out, "+ type {}{}", ct.qualified_name(), describe_type(ct));
+    }
+    for ct in &diff.custom_types_removed {
+        let _ = writeln!(out, "- type {}", ct.qualified_name());
+    }
+    for ct in &diff.custom_types_changed {
+        let _ = writeln!(out, "~ type {}", ct.after.qualified_name());
+        if ct.before.qualified_name() != ct.after.qualified_name() {
+            let _ = writeln!(out, "    renamed from {}", ct.before.qualified_name());
+        }
+        for value in &ct.values_added {
+            let _ = writeln!(out, "    + value '{}'", value);
+        }
+        for value in &ct.values_removed {
+            let _ = writeln!(out, "    - value '{}'", value);
+        }
+        for field in &ct.fields_added {
+            let _ = writeln!(out, "    + field {} {}", field.field, field.type_name);
+        }
+        for field in &ct.fields_removed {
+            let _ = writeln!(out, "    - field {}", field.field);
+        }
+    }
+
+    out
+}
+
+fn describe_field(field: &FieldInfo) -> String {
+    let mut s = format!("column {} {}", field.name, field.type_sql());
+    if field.primary_key {
+        s.push_str(" PRIMARY KEY");
+    } else if !field.nullable {
+        s.push_str(" NOT NULL");
+    }
+    if field.unique && !field.primary_key {
+        s.push_str(" UNIQUE");
+    }
+    if field.increment {
+        s.push_str(" INCREMENT");
+    }
+    if let Some(default) = &field.default {
+        let _ = write!(s, " DEFAULT {}", default);
+    }
+    s
+}
+
+fn describe_index(index: &IndexDef) -> String {
+    format!(
+        "{}index {} ({})",
+        if index.unique { "unique " } else { "" },
+        index.name,
+        index.columns.join(", ")
+    )
+}
+
+fn describe_type(ct: &CustomTypeDef) -> String {
+    if !ct.values.is_empty() {
+        format!(" enum ({})", ct.values.join(", "))
+    } else if !ct.fields.is_empty() {
+        let fields: Vec<String> = ct
+            .fields
+            .iter()
+            .map(|f| format!("{} {}", f.field, f.type_name))
+            .collect();
+        format!(" composite ({})", fields.join(", "))
+    } else {
+        String::new()
+    }
+}
+
+fn field_property(before: &FieldInfo, after: &FieldInfo, property: &str) -> (String, String) {
+    let pick = |f: &FieldInfo| match property {
+        "name" => f.name.clone(),
+        "type" => f.type_sql(),
+        "nullable" => f.nullable.to_string(),
+        "primaryKey" => f.primary_key.to_string(),
+        "unique" => f.unique.to_string(),
+        "increment" => f.increment.to_string(),
+        "default" => show(&f.default),
+        "collation" => show(&f.collation),
+        "comment" => show(&f.comment),
+        _ => String::new(),
+    };
+    (pick(before), pick(after))
+}
+
+fn show(value: &Option<String>) -> String {
+    match value {
+        Some(v) => format!("'{}'", v),
+        None => "(none)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, field_mut, shop, table};
+
+    #[test]
+    fn identical_diagrams_have_no_diff() {
+        let diff = compare(&shop(), &shop());
+        assert!(diff.is_empty());
+        assert_eq!(to_text(&diff), "No structural changes.\n");
+    }
+
+    #[test]
+    fn field_properties_are_compared() {
+        let mut after = shop();
+        let id = field_mut(&mut after, "t_orders", "f_orders_id");
+        id.increment = Some(true);
+        let total = field_mut(&mut after, "t_orders", "f_orders_total");
+        total.r#type.name = "decimal".to_string();
+        total.nullable = false;
+
+        let diff = compare(&shop(), &after);
+        assert_eq!(diff.tables_changed.len(), 1);
+        let changes: Vec<(&str, &[&str])> = diff.tables_changed[0]
+            .fields_changed
+            .iter()
+            .map(|f| (f.after.name.as_str(), f.changed.as_slice()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("id", &["increment"][..]),
+                ("total", &["type", "nullable"][..])
+            ]
+        );
+        assert!(to_text(&diff).contains("increment: false -> true"));
+    }
+
+    #[test]
+    fn moving_a_relationship_end_to_another_schema_changes_it() {
+        let mut after = shop();
+        after.relationships.as_mut().unwrap()[0].target_schema = Some("auth".to_string());
+
+        let diff = compare(&shop(), &after);
+        assert!(diff.relationships_added.is_empty());
+        assert!(diff.relationships_removed.is_empty());
+        assert_eq!(diff.relationships_changed.len(), 1);
+        assert_eq!(diff.relationships_changed[0].changed, ["target"]);
+    }
+
+    #[test]
+    fn relationships_are_paired_by_their_ends_including_schemas() {
+        let before = shop();
+        let mut same_ends = shop();
+        same_ends.relationships.as_mut().unwrap()[0].id = "r_new".to_string();
+        assert!(compare(&before, &same_ends).is_empty());
+
+        let mut other_schema = same_ends.clone();
+        other_schema.relationships.as_mut().unwrap()[0].source_schema = Some("sales".to_string());
+        let diff = compare(&before, &other_schema);
+        assert_eq!(diff.relationships_removed.len(), 1);
+        assert_eq!(diff.relationships_added.len(), 1);
+    }
+
+    #[test]
+    fn renamed_tables_and_fields_are_matched_by_id() {
+        let mut after = shop();
+        let tables = after.tables.as_mut().unwrap();
+        tables[0] = table(
+            "t_users",
+            "customers",
+            &[
+                column("f_users_id", "id", "integer"),
+                column("f_users_email", "mail", "varchar(255)"),
+            ],
+        );
+        tables[0].fields[0] = shop().tables.unwrap()[0].fields[0].clone();
+
+        let diff = compare(&shop(), &after);
+        assert!(diff.tables_added.is_empty() && diff.tables_removed.is_empty());
+        let users = &diff.tables_changed[0];
+        assert_eq!(users.renamed_from.as_deref(), Some("public.users"));
+        assert_eq!(users.fields_changed.len(), 1);
+        assert_eq!(users.fields_changed[0].changed, ["name"]);
+    }
+
+    #[test]
+    fn dropped_tables_are_removed_with_their_relationships() {
+        let mut after = shop();
+        after.tables.as_mut().unwrap().remove(0);
+        after.relationships = Some(Vec::new());
+
+        let diff = compare(&shop(), &after);
+        assert_eq!(diff.tables_removed[0].name, "users");
+        assert_eq!(diff.relationships_removed[0].id, "r_orders_user");
+        let text = to_text(&diff);
+        assert!(text.contains("- table public.users"), "{}", text);
+    }
+}
@@ -0,0 +1,467 @@
+//! Schema drift: how a live database differs from the diagram that designs it.
+//!
+//! The database is introspected into a diagram of its own and compared with
+//! the stored one by name, since the ids on the two sides are unrelated.
+//! Column types are compared after folding PostgreSQL's aliases (`int` and
+//! `integer`, `varchar` and `character varying`, ...). Views are only checked
+//! for existence, as a diagram does not know how their columns are computed.
+
+use crate::ddl;
+use crate::diff::{self, IndexDef, TableDef};
+use crate::models::Diagram;
+use crate::schema::{self, same_name, schema_or_default};
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReport {
+    pub in_sync: bool,
+    /// Tables and views of the diagram that the database lacks.
+    pub missing_tables: Vec<String>,
+    /// Tables and views of the database that the diagram lacks.
+    pub extra_tables: Vec<String>,
+    pub missing_columns: Vec<ColumnRef>,
+    pub extra_columns: Vec<ColumnRef>,
+    pub type_mismatches: Vec<Mismatch<String>>,
+    /// `expected`/`actual` say whether the column is nullable.
+    pub nullability_mismatches: Vec<Mismatch<bool>>,
+    pub primary_key_mismatches: Vec<KeyMismatch>,
+    /// Foreign keys implied by the diagram's relationships that the database
+    /// does not enforce.
+    pub missing_foreign_keys: Vec<ForeignKeyRef>,
+    pub extra_foreign_keys: Vec<ForeignKeyRef>,
+    pub missing_indexes: Vec<IndexRef>,
+    pub extra_indexes: Vec<IndexRef>,
+    /// Indexes with the same name on both sides but different columns or
+    /// uniqueness.
+    pub changed_indexes: Vec<IndexChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnRef {
+    pub table: String,
+    pub column: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mismatch<T> {
+    pub table: String,
+    pub column: String,
+    pub expected: T,
+    pub actual: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyMismatch {
+    pub table: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForeignKeyRef {
+    /// The diagram relationship the foreign key comes from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relationship_id: Option<String>,
+    pub name: String,
+    pub table: String,
+    pub column: String,
+    pub referenced_table: String,
+    pub referenced_column: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexRef {
+    pub table: String,
+    /// `None` for a column's unique flag, which has no index of its own.
+    pub name: Option<String>,
+    pub unique: bool,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexChange {
+    pub expected: IndexRef,
+    pub actual: IndexRef,
+}
+
+/// Compares the designed `diagram` with `live`, the introspected database.
+pub fn check(diagram: &Diagram, live: &Diagram) -> DriftReport {
+    let designed = diff::diagram_defs(diagram);
+    let actual = diff::diagram_defs(live);
+    let mut report = DriftReport::default();
+
+    for table in &designed.tables {
+        let Some(live_table) = actual.tables.iter().find(|t| same_table(t, table)) else {
+            report.missing_tables.push(table_name(table));
+            continue;
+        };
+        if !(table.is_view || live_table.is_view) {
+            compare_tables(&mut report, table, live_table);
+        }
+    }
+    for live_table in &actual.tables {
+        if !designed.tables.iter().any(|t| same_table(t, live_table)) {
+            report.extra_tables.push(table_name(live_table));
+        }
+    }
+
+    let designed_keys: Vec<_> = designed
+        .relationships
+        .iter()
+        .filter_map(|rel| Some((rel, ddl::foreign_key(rel)?)))
+        .collect();
+    let live_keys: Vec<_> = actual
+        .relationships
+        .iter()
+        .filter_map(ddl::foreign_key)
+        .collect();
+    for (rel, fk) in &designed_keys {
+        // A missing table or column is reported on its own
+        let present = |schema: &Option<String>, table: &str, column: &str| {
+            actual.tables.iter().any(|t| {
+                same_name(&t.schema, &t.name, schema, table)
+                    && t.fields.iter().any(|f| f.name == column)
+            })
+        };
+        if !present(&fk.schema, &fk.table, &fk.column)
+            || !present(
+                &fk.referenced_schema,
+                &fk.referenced_table,
+                &fk.referenced_column,
+            )
+        {
+            continue;
+        }
+        if !live_keys.iter().any(|live| same_key(live, fk)) {
+            report
+                .missing_foreign_keys
+                .push(foreign_key_ref(fk, Some(rel.id.clone())));
+        }
+    }
+    for fk in &live_keys {
+        if !designed_keys
+            .iter()
+            .any(|(_, designed)| same_key(designed, fk))
+        {
+            report.extra_foreign_keys.push(foreign_key_ref(fk, None));
+        }
+    }
+
+    report.in_sync = report.missing_tables.is_empty()
+        && report.extra_tables.is_empty()
+        && report.missing_columns.is_empty()
+        && report.extra_columns.is_empty()
+        && report.type_mismatches.is_empty()
+        && report.nullability_mismatches.is_empty()
+        && report.primary_key_mismatches.is_empty()
+        && report.missing_foreign_keys.is_empty()
+        && report.extra_foreign_keys.is_empty()
+        && report.missing_indexes.is_empty()
+        && report.extra_indexes.is_empty()
+        && report.changed_indexes.is_empty();
+    report
+}
+
+/// The schemas a diagram's tables live in, which bound the introspection so
+/// that unrelated schemas are not reported as extra tables.
+pub fn schemas(diagram: &Diagram) -> Vec<String> {
+    let mut schemas: Vec<String> = diagram
+        .tables
+        .iter()
+        .flatten()
+        .map(|t| schema_or_default(&t.schema).to_string())
+        .collect();
+    schemas.sort();
+    schemas.dedup();
+    schemas
+}
+
+fn compare_tables(report: &mut DriftReport, table: &TableDef, live: &TableDef) {
+    let name = table_name(table);
+    for field in &table.fields {
+        let Some(live_field) = live.fields.iter().find(|f| f.name == field.name) else {
+            report.missing_columns.push(ColumnRef {
+                table: name.clone(),
+                column: field.name.clone(),
+                type_name: field.type_sql(),
+            });
+            continue;
+        };
+        if canonical_type(&field.type_sql()) != canonical_type(&live_field.type_sql()) {
+            report.type_mismatches.push(Mismatch {
+                table: name.clone(),
+                column: field.name.clone(),
+                expected: field.type_sql(),
+                actual: live_field.type_sql(),
+            });
+        }
+        // Primary key columns are implicitly NOT NULL
+        let nullable = field.nullable && !field.primary_key;
+        if nullable != live_field.nullable {
+            report.nullability_mismatches.push(Mismatch {
+                table: name.clone(),
+                column: field.name.clone(),
+                expected: nullable,
+                actual: live_field.nullable,
+            });
+        }
+    }
+    for live_field in &live.fields {
+        if !table.fields.iter().any(|f| f.name == live_field.name) {
+            report.extra_columns.push(ColumnRef {
+                table: name.clone(),
+                column: live_field.name.clone(),
+                type_name: live_field.type_sql(),
+            });
+        }
+    }
+
+    let expected_key = ddl::primary_key_columns(table);
+    let actual_key = ddl::primary_key_columns(live);
+    if expected_key != actual_key {
+        report.primary_key_mismatches.push(KeyMismatch {
+            table: name.clone(),
+            expected: expected_key.iter().map(|c| c.to_string()).collect(),
+            actual: actual_key.iter().map(|c| c.to_string()).collect(),
+        });
+    }
+
+    compare_indexes(report, index_refs(&name, table), index_refs(&name, live));
+}
+
+/// Pairs indexes by name, then by columns and uniqueness, which is how the
+/// unique flag of a column meets the unique index that enforces it.
+fn compare_indexes(report: &mut DriftReport, expected: Vec<IndexRef>, mut actual: Vec<IndexRef>) {
+    let mut unmatched = Vec::new();
+    for index in expected {
+        let by_name = index
+            .name
+            .as_ref()
+            .and_then(|name| actual.iter().position(|a| a.name.as_ref() == Some(name)));
+        match by_name {
+            Some(pos) => {
+                let live = actual.remove(pos);
+                if live.columns != index.columns || live.unique != index.unique {
+                    report.changed_indexes.push(IndexChange {
+                        expected: index,
+                        actual: live,
+                    });
+                }
+            }
+            None => unmatched.push(index),
+        }
+    }
+    for index in unmatched {
+        match actual
+            .iter()
+            .position(|a| a.columns == index.columns && a.unique == index.unique)
+        {
+            Some(pos) => {
+                actual.remove(pos);
+            }
+            None => report.missing_indexes.push(index),
+        }
+    }
+    report.extra_indexes.extend(actual);
+}
+
+fn index_refs(table_name: &str, table: &TableDef) -> Vec<IndexRef> {
+    let unique_fields = table
+        .fields
+        .iter()
+        .filter(|f| f.unique && !f.primary_key)
+        .map(|f| IndexRef {
+            table: table_name.to_string(),
+            name: None,
+            unique: true,
+            columns: vec![f.name.clone()],
+        });
+    let indexes = table.indexes.iter().map(|index: &IndexDef| IndexRef {
+        table: table_name.to_string(),
+        name: Some(index.name.clone()).filter(|n| !n.is_empty()),
+        unique: index.unique,
+        columns: index.columns.clone(),
+    });
+    unique_fields.chain(indexes).collect()
+}
+
+fn foreign_key_ref(fk: &ddl::ForeignKey, relationship_id: Option<String>) -> ForeignKeyRef {
+    ForeignKeyRef {
+        relationship_id,
+        name: fk.name.clone(),
+        table: schema::qualified_name(Some(schema_or_default(&fk.schema)), &fk.table),
+        column: fk.column.clone(),
+        referenced_table: schema::qualified_name(
+            Some(schema_or_default(&fk.referenced_schema)),
+            &fk.referenced_table,
+        ),
+        referenced_column: fk.referenced_column.clone(),
+    }
+}
+
+fn same_key(a: &ddl::ForeignKey, b: &ddl::ForeignKey) -> bool {
+    same_name(&a.schema, &a.table, &b.schema, &b.table)
+        && a.column == b.column
+        && same_name(
+            &a.referenced_schema,
+            &a.referenced_table,
+            &b.referenced_schema,
+            &b.referenced_table,
+        )
+        && a.referenced_column == b.referenced_column
+}
+
+fn same_table(a: &TableDef, b: &TableDef) -> bool {
+    same_name(&a.schema, &a.name, &b.schema, &b.name)
+}
+
+fn table_name(table: &TableDef) -> String {
+    schema::qualified_name(Some(schema_or_default(&table.schema)), &table.name)
+}
+
+/// A PostgreSQL type in the spelling `format_type` uses, so that aliases
+/// compare equal: `int4` and `serial` are `integer`, `varchar(10)` is
+/// `character varying(10)`, `timestamptz` is `timestamp with time zone`.
+pub fn canonical_type(sql: &str) -> String {
+    let sql = sql.trim().to_ascii_lowercase().replace('"', "");
+    if let Some(element) = sql.strip_suffix("[]") {
+        return format!("{}[]", canonical_type(element));
+    }
+    let (base, args) = match (sql.find('('), sql.find(')')) {
+        (Some(open), Some(close)) if open < close => (
+            format!("{} {}", &sql[..open], &sql[close + 1..]),
+            Some(sql[open + 1..close].replace(' ', "")),
+        ),
+        _ => (sql.clone(), None),
+    };
+    let base = base.split_whitespace().collect::<Vec<_>>().join(" ");
+    let base = base.strip_prefix("public.").unwrap_or(&base);
+    let base = match base {
+        "int" | "int4" | "serial" | "serial4" => "integer",
+        "int8" | "bigserial" | "serial8" => "bigint",
+        "int2" | "smallserial" | "serial2" => "smallint",
+        "bool" => "boolean",
+        "float8" | "float" => "double precision",
+        "float4" => "real",
+        "decimal" => "numeric",
+        "varchar" => "character varying",
+        "char" | "bpchar" => "character",
+        "varbit" => "bit varying",
+        "timestamp" => "timestamp without time zone",
+        "timestamptz" => "timestamp with time zone",
+        "time" => "time without time zone",
+        "timetz" => "time with time zone",
+        other => other,
+    };
+    let args = match (base, args) {
+        ("character", None) => Some("1".to_string()),
+        (_, args) => args,
+    };
+    match args {
+        // The precision of time types goes before the time zone
+        Some(args) if base.starts_with("time") => match base.split_once(' ') {
+            Some((head, tail)) => format!("{}({}) {}", head, args, tail),
+            None => format!("{}({})", base, args),
+        },
+        Some(args) => format!("{}({})", base, args),
+        None => base.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, field_mut, indexed, shop, table};
+
+    #[test]
+    fn aliases_fold_to_one_spelling() {
+        let cases = [
+            ("int4", "integer"),
+            ("SERIAL", "integer"),
+            ("bigserial", "bigint"),
+            ("varchar(255)", "character varying(255)"),
+            ("character varying( 255 )", "character varying(255)"),
+            ("decimal(10, 2)", "numeric(10,2)"),
+            ("bpchar", "character(1)"),
+            ("timestamptz", "timestamp with time zone"),
+            ("timestamp(3) with time zone", "timestamp(3) with time zone"),
+            ("timestamptz(3)", "timestamp(3) with time zone"),
+            ("int[]", "integer[]"),
+            ("\"public\".\"status\"", "status"),
+        ];
+        for (sql, canonical) in cases {
+            assert_eq!(canonical_type(sql), canonical, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn a_matching_database_is_in_sync() {
+        let mut live = shop();
+        field_mut(&mut live, "t_users", "f_users_id").r#type.name = "int4".to_string();
+        let report = check(&shop(), &live);
+        assert!(report.in_sync, "{:?}", report);
+    }
+
+    #[test]
+    fn differences_are_reported_by_name() {
+        let mut live = shop();
+        let tables = live.tables.as_mut().unwrap();
+        // Ids are unrelated on the two sides
+        for table in tables.iter_mut() {
+            table.id = format!("live_{}", table.id);
+        }
+        tables[1] = indexed(
+            table(
+                "live_t_orders",
+                "orders",
+                &[
+                    column("f_orders_id", "id", "integer"),
+                    column("f_orders_user", "user_id", "integer"),
+                    column("f_orders_note", "note", "text"),
+                ],
+            ),
+            &[("i_orders_note", "orders_note", true, &["f_orders_note"])],
+        );
+        tables.push(table("live_t_audit", "audit", &[]));
+        live.relationships = Some(Vec::new());
+
+        let report = check(&shop(), &live);
+        assert!(!report.in_sync);
+        assert_eq!(report.extra_tables, ["public.audit"]);
+        let columns = |refs: &[ColumnRef]| -> Vec<String> {
+            refs.iter()
+                .map(|c| format!("{}.{}", c.table, c.column))
+                .collect()
+        };
+        assert_eq!(columns(&report.missing_columns), ["public.orders.total"]);
+        assert_eq!(columns(&report.extra_columns), ["public.orders.note"]);
+        assert_eq!(report.nullability_mismatches[0].column, "id");
+        assert_eq!(report.primary_key_mismatches[0].expected, ["id"]);
+        assert!(report.primary_key_mismatches[0].actual.is_empty());
+        assert_eq!(
+            report.missing_indexes[0].name.as_deref(),
+            Some("orders_user_id")
+        );
+        assert_eq!(report.extra_indexes[0].name.as_deref(), Some("orders_note"));
+        assert_eq!(
+            report.missing_foreign_keys[0].relationship_id.as_deref(),
+            Some("r_orders_user")
+        );
+    }
+
+    #[test]
+    fn type_changes_are_reported() {
+        let mut live = shop();
+        field_mut(&mut live, "t_users", "f_users_id").r#type.name = "int8".to_string();
+        let report = check(&shop(), &live);
+        let mismatch = &report.type_mismatches[0];
+        assert_eq!(
+            (mismatch.expected.as_str(), mismatch.actual.as_str()),
+            ("integer", "int8")
+        );
+    }
+}
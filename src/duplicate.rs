@@ -0,0 +1,138 @@
+//! Deep copies of diagrams.
+
+use crate::models::{new_id, Diagram};
+use std::collections::HashMap;
+
+/// Copies `diagram` under a new id and `name`. Every child, field and index
+/// gets a fresh id, and the references between them (relationship ends,
+/// dependency tables, index `fieldIds`) are pointed at the copies. A
+/// reference to something the diagram does not contain is kept as it is.
+pub fn duplicate(diagram: &Diagram, name: &str) -> Diagram {
+    let now = chrono::Utc::now();
+    let mut copy = diagram.clone();
+    copy.id = new_id();
+    copy.name = name.to_string();
+    copy.created_at = now;
+    copy.updated_at = now;
+    copy.version = None;
+
+    let mut table_ids = HashMap::new();
+    let mut field_ids = HashMap::new();
+    for table in copy.tables.iter_mut().flatten() {
+        table.diagram_id = copy.id.clone();
+        table_ids.insert(std::mem::replace(&mut table.id, new_id()), table.id.clone());
+        // Field ids are only looked up through their table
+        let fields: HashMap<String, String> = table
+            .fields
+            .iter_mut()
+            .map(|field| (std::mem::replace(&mut field.id, new_id()), field.id.clone()))
+            .collect();
+        for index in &mut table.indexes {
+            index.id = new_id();
+            for id in &mut index.field_ids {
+                remap(id, &fields);
+            }
+        }
+        field_ids.insert(table.id.clone(), fields);
+    }
+
+    for rel in copy.relationships.iter_mut().flatten() {
+        rel.id = new_id();
+        rel.diagram_id = copy.id.clone();
+        remap(&mut rel.source_table_id, &table_ids);
+        remap(&mut rel.target_table_id, &table_ids);
+        let ends = [
+            (&rel.source_table_id, &mut rel.source_field_id),
+            (&rel.target_table_id, &mut rel.target_field_id),
+        ];
+        for (table_id, field_id) in ends {
+            if let (Some(fields), Some(field_id)) = (field_ids.get(table_id), field_id) {
+                remap(field_id, fields);
+            }
+        }
+    }
+    for dep in copy.dependencies.iter_mut().flatten() {
+        dep.id = new_id();
+        dep.diagram_id = copy.id.clone();
+        remap(&mut dep.table_id, &table_ids);
+        remap(&mut dep.dependent_table_id, &table_ids);
+    }
+    for area in copy.areas.iter_mut().flatten() {
+        area.id = new_id();
+        area.diagram_id = copy.id.clone();
+    }
+    for ct in copy.custom_types.iter_mut().flatten() {
+        ct.id = new_id();
+        ct.diagram_id = copy.id.clone();
+    }
+    for note in copy.notes.iter_mut().flatten() {
+        note.id = new_id();
+        note.diagram_id = copy.id.clone();
+    }
+    copy
+}
+
+fn remap(id: &mut String, ids: &HashMap<String, String>) {
+    if let Some(new) = ids.get(id.as_str()) {
+        *id = new.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{dependency, shop, table};
+    use crate::{diff, validate};
+
+    #[test]
+    fn copies_get_new_ids_and_keep_their_references() {
+        let mut original = shop();
+        let tables = original.tables.as_mut().unwrap();
+        tables.push(table("t_totals", "totals", &[]));
+        original.dependencies = Some(vec![dependency("d_totals", "t_orders", "t_totals")]);
+
+        let copy = duplicate(&original, "Copy");
+        assert_eq!(copy.name, "Copy");
+        assert_ne!(copy.id, original.id);
+        assert!(validate::diagram(&copy).is_empty());
+
+        let ids = |diagram: &Diagram| -> Vec<String> {
+            let tables = diagram.tables.iter().flatten();
+            tables
+                .clone()
+                .map(|t| t.id.clone())
+                .chain(
+                    tables
+                        .clone()
+                        .flat_map(|t| t.fields.iter().map(|f| f.id.clone())),
+                )
+                .chain(tables.flat_map(|t| t.indexes.iter().map(|i| i.id.clone())))
+                .chain(diagram.relationships.iter().flatten().map(|r| r.id.clone()))
+                .chain(diagram.dependencies.iter().flatten().map(|d| d.id.clone()))
+                .collect()
+        };
+        let (before, after) = (ids(&original), ids(&copy));
+        assert_eq!(before.len(), after.len());
+        assert!(after.iter().all(|id| !before.contains(id)));
+
+        // Same structure, down to which fields the index and relationship use
+        let mut renamed = copy.clone();
+        renamed.name = original.name.clone();
+        let diff = diff::compare(&original, &renamed);
+        assert!(diff.tables_added.is_empty() && diff.tables_removed.is_empty());
+        assert!(diff.tables_changed.is_empty());
+        assert!(diff.relationships_changed.is_empty());
+        let orders = &copy.tables.as_deref().unwrap()[1];
+        assert_eq!(orders.indexes[0].field_ids, [orders.fields[1].id.clone()]);
+    }
+
+    #[test]
+    fn references_to_missing_entities_are_kept() {
+        let mut original = shop();
+        original.tables.as_mut().unwrap()[1].indexes[0]
+            .field_ids
+            .push("f_gone".to_string());
+        let copy = duplicate(&original, "Copy");
+        assert_eq!(copy.tables.unwrap()[1].indexes[0].field_ids[1], "f_gone");
+    }
+}
@@ -0,0 +1,236 @@
+//! Graphviz DOT graphs with a record-like node per table.
+
+use super::{area_of, color, foreign_key_fields, key_markers, multiplicities, Multiplicity};
+use crate::models::{Diagram, Table};
+use crate::schema;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Renders each table as an HTML-like node with a row per column, headed in
+/// the table's color. Tables inside an area are drawn in a cluster for it,
+/// views with a dashed border. Relationships connect the columns they join,
+/// with crow's foot arrows. With `keys_only`, columns other than primary
+/// keys, foreign keys and unique columns are left out.
+pub fn dot(diagram: &Diagram, keys_only: bool) -> String {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let areas = diagram.areas.as_deref().unwrap_or_default();
+    let foreign_keys = foreign_key_fields(relationships);
+    let mut ports = HashSet::new();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph {} {{", quote(&diagram.name));
+    out.push_str("  graph [rankdir=LR, fontname=\"Helvetica\"];\n");
+    out.push_str("  node [shape=plaintext, fontname=\"Helvetica\", fontsize=10];\n");
+    out.push_str("  edge [fontname=\"Helvetica\", fontsize=9, dir=both];\n");
+
+    let mut node = |out: &mut String, table: &Table, indent: &str| {
+        let is_view = table.is_view == Some(true) || table.is_materialized_view == Some(true);
+        let name = html(&schema::qualified_name(
+            table.schema.as_deref(),
+            &table.name,
+        ));
+        let header = if is_view {
+            format!("<I>{}</I>", name)
+        } else {
+            format!("<B>{}</B>", name)
+        };
+        let _ = write!(
+            out,
+            "{}{} [label=<<TABLE BORDER=\"1\" CELLBORDER=\"0\" CELLSPACING=\"0\" CELLPADDING=\"4\"{}>",
+            indent,
+            quote(&table.id),
+            if is_view { " STYLE=\"dashed\"" } else { "" }
+        );
+        let _ = write!(out, "<TR><TD COLSPAN=\"3\"");
+        if let Some(color) = color(table.color.as_deref()) {
+            let _ = write!(out, " BGCOLOR=\"{}\"", html(&color));
+        }
+        let _ = write!(out, ">{}</TD></TR>", header);
+
+        for field in schema::fields(table) {
+            let markers = key_markers(table, &field, &foreign_keys);
+            if keys_only && markers.is_empty() {
+                continue;
+            }
+            ports.insert((table.id.clone(), field.id.clone()));
+            let name = if field.primary_key {
+                format!("<U>{}</U>", html(&field.name))
+            } else {
+                html(&field.name)
+            };
+            let _ = write!(
+                out,
+                "<TR><TD ALIGN=\"LEFT\" PORT=\"{}\">{}</TD><TD ALIGN=\"LEFT\">{}{}</TD><TD ALIGN=\"LEFT\">{}</TD></TR>",
+                html(&field.id),
+                name,
+                html(&field.type_sql()),
+                if field.nullable { "" } else { " NOT NULL" },
+                markers.join(" ")
+            );
+        }
+        out.push_str("</TABLE>>];\n");
+    };
+
+    for area in areas {
+        let members: Vec<_> = tables
+            .iter()
+            .filter(|t| area_of(t, areas).is_some_and(|a| a.id == area.id))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            "  subgraph {} {{",
+            quote(&format!("cluster_{}", area.id))
+        );
+        let _ = writeln!(
+            out,
+            "    label={};",
+            quote(area.name.as_deref().unwrap_or_default())
+        );
+        match color(area.color.as_deref()) {
+            // Seven-character hex colors get an alpha channel so the
+            // tables stay readable on top of the fill.
+            Some(color) if color.len() == 7 && color.starts_with('#') => {
+                let _ = writeln!(
+                    out,
+                    "    style=\"rounded,filled\"; color={}; fillcolor={};",
+                    quote(&color),
+                    quote(&format!("{}33", color))
+                );
+            }
+            Some(color) => {
+                let _ = writeln!(out, "    style=rounded; color={};", quote(&color));
+            }
+            None => out.push_str("    style=rounded;\n"),
+        }
+        for table in members {
+            node(&mut out, table, "    ");
+        }
+        out.push_str("  }\n");
+    }
+    for table in tables.iter().filter(|t| area_of(t, areas).is_none()) {
+        node(&mut out, table, "  ");
+    }
+
+    let names: HashSet<&str> = tables.iter().map(|t| t.id.as_str()).collect();
+    for rel in relationships {
+        if !names.contains(rel.source_table_id.as_str())
+            || !names.contains(rel.target_table_id.as_str())
+        {
+            continue;
+        }
+        let endpoint = |table_id: &str, field_id: Option<&str>| match field_id {
+            Some(field_id) if ports.contains(&(table_id.to_string(), field_id.to_string())) => {
+                format!("{}:{}", quote(table_id), quote(field_id))
+            }
+            _ => quote(table_id),
+        };
+        let arrow = |end| match end {
+            Multiplicity::One => "teetee",
+            Multiplicity::ZeroOrOne => "teeodot",
+            Multiplicity::Many => "crowodot",
+        };
+        let (source_end, target_end) = multiplicities(rel, tables);
+        let _ = write!(
+            out,
+            "  {} -> {} [arrowtail={}, arrowhead={}",
+            endpoint(&rel.source_table_id, rel.source_field_id.as_deref()),
+            endpoint(&rel.target_table_id, rel.target_field_id.as_deref()),
+            arrow(source_end),
+            arrow(target_end)
+        );
+        if let Some(name) = rel.name.as_deref().filter(|n| !n.is_empty()) {
+            let _ = write!(out, ", label={}", quote(name));
+        }
+        out.push_str("];\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A DOT double-quoted string.
+fn quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace(['\r', '\n'], " ")
+    )
+}
+
+/// Text inside an HTML-like label.
+fn html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, column, shop, table};
+
+    #[test]
+    fn relationships_join_the_columns_they_use() {
+        let graph = dot(&shop(), false);
+        assert!(graph.starts_with("digraph \"Test\" {\n"));
+        assert!(graph.contains(
+            "<TR><TD ALIGN=\"LEFT\" PORT=\"f_users_id\"><U>id</U></TD><TD ALIGN=\"LEFT\">integer NOT NULL</TD><TD ALIGN=\"LEFT\">PK</TD></TR>"
+        ));
+        assert!(graph.contains(
+            "  \"t_orders\":\"f_orders_user\" -> \"t_users\":\"f_users_id\" [arrowtail=crowodot, arrowhead=teeodot];\n"
+        ));
+        assert!(graph.ends_with("}\n"));
+    }
+
+    #[test]
+    fn edges_to_left_out_columns_attach_to_the_table() {
+        let mut diagram = shop();
+        // Relate orders to the email column, which `keys_only` leaves out
+        let rel = &mut diagram.relationships.as_mut().unwrap()[0];
+        rel.target_field_id = Some("f_users_email".to_string());
+        let graph = dot(&diagram, true);
+        assert!(!graph.contains("PORT=\"f_users_email\""));
+        assert!(graph.contains("\"t_orders\":\"f_orders_user\" -> \"t_users\" ["));
+    }
+
+    #[test]
+    fn areas_become_clusters() {
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap()[1].x = Some(500.0);
+        let mut people = area("a_people", "People", -10.0, -10.0);
+        people.color = Some("#b0b0b0".to_string());
+        diagram.areas = Some(vec![people]);
+
+        let graph = dot(&diagram, false);
+        assert!(graph.contains(concat!(
+            "  subgraph \"cluster_a_people\" {\n",
+            "    label=\"People\";\n",
+            "    style=\"rounded,filled\"; color=\"#b0b0b0\"; fillcolor=\"#b0b0b033\";\n",
+            "    \"t_users\" [label=<",
+        )));
+        assert!(graph.contains("\n  \"t_orders\" [label=<"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let diagram = crate::fixtures::diagram(
+            vec![table(
+                "t\"1",
+                "a<b>",
+                &[column("f&1", "x & y", "varchar(10)")],
+            )],
+            Vec::new(),
+        );
+        let graph = dot(&diagram, false);
+        assert!(graph.contains("  \"t\\\"1\" [label=<"));
+        assert!(graph.contains("<B>public.a&lt;b&gt;</B>"));
+        assert!(graph.contains("PORT=\"f&amp;1\">x &amp; y</TD>"));
+    }
+}
@@ -0,0 +1,185 @@
+//! Mermaid `erDiagram` syntax, which GitHub and GitLab render in Markdown.
+
+use super::{foreign_key_fields, key_markers, multiplicities, Multiplicity};
+use crate::models::{Diagram, Table};
+use crate::schema;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Renders the tables with their columns and the relationships between
+/// them. With `keys_only`, columns other than primary keys, foreign keys and
+/// unique columns are left out.
+pub fn mermaid(diagram: &Diagram, keys_only: bool) -> String {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let names = entity_names(tables);
+    let foreign_keys = foreign_key_fields(relationships);
+
+    let mut out = String::from("erDiagram\n");
+    for table in tables {
+        let _ = writeln!(out, "    {} {{", names[table.id.as_str()]);
+        for field in schema::fields(table) {
+            let keys = key_markers(table, &field, &foreign_keys);
+            if keys_only && keys.is_empty() {
+                continue;
+            }
+            let _ = write!(
+                out,
+                "        {} {}",
+                word(&field.type_sql()),
+                word(&field.name)
+            );
+            if !keys.is_empty() {
+                let _ = write!(out, " {}", keys.join(","));
+            }
+            if let Some(comment) = field.comment.as_deref().filter(|c| !c.is_empty()) {
+                let _ = write!(out, " \"{}\"", text(comment));
+            }
+            out.push('\n');
+        }
+        out.push_str("    }\n");
+    }
+
+    for rel in relationships {
+        let (Some(source), Some(target)) = (
+            names.get(rel.source_table_id.as_str()),
+            names.get(rel.target_table_id.as_str()),
+        ) else {
+            continue;
+        };
+        let (source_end, target_end) = multiplicities(rel, tables);
+        let source_end = match source_end {
+            Multiplicity::One => "||",
+            Multiplicity::ZeroOrOne => "|o",
+            Multiplicity::Many => "}o",
+        };
+        let target_end = match target_end {
+            Multiplicity::One => "||",
+            Multiplicity::ZeroOrOne => "o|",
+            Multiplicity::Many => "o{",
+        };
+        let _ = writeln!(
+            out,
+            "    {} {}--{} {} : \"{}\"",
+            source,
+            source_end,
+            target_end,
+            target,
+            text(rel.name.as_deref().unwrap_or_default())
+        );
+    }
+    out
+}
+
+/// Entity names by table id: the table name, qualified with its schema when
+/// several schemas use the same name.
+fn entity_names(tables: &[Table]) -> HashMap<&str, String> {
+    tables
+        .iter()
+        .map(|table| {
+            let clashes = tables
+                .iter()
+                .filter(|t| word(&t.name) == word(&table.name))
+                .count()
+                > 1;
+            let name = match (&table.schema, clashes) {
+                (Some(schema), true) => word(&format!("{}_{}", schema, table.name)),
+                _ => word(&table.name),
+            };
+            (table.id.as_str(), name)
+        })
+        .collect()
+}
+
+/// Mermaid names and attribute types allow letters, digits, `_`, `-`,
+/// parentheses and brackets; anything else becomes `_` (or `-` between type
+/// arguments).
+fn word(value: &str) -> String {
+    let word: String = value
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || "_-()[]".contains(c) => c,
+            ',' => '-',
+            _ => '_',
+        })
+        .collect();
+    if word.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        word
+    } else {
+        format!("_{}", word)
+    }
+}
+
+/// Quoted Mermaid text cannot contain double quotes or line breaks.
+fn text(value: &str) -> String {
+    value.replace('"', "'").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, field_mut, key, shop, table};
+
+    #[test]
+    fn renders_tables_and_relationships() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_users", "f_users_email").comments =
+            Some("Say \"hi\"".to_string());
+        assert_eq!(
+            mermaid(&diagram, false),
+            concat!(
+                "erDiagram\n",
+                "    users {\n",
+                "        integer id PK\n",
+                "        varchar(255) email \"Say 'hi'\"\n",
+                "    }\n",
+                "    orders {\n",
+                "        integer id PK\n",
+                "        integer user_id FK\n",
+                "        numeric(10-2) total\n",
+                "    }\n",
+                "    orders }o--o| users : \"\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn keys_only_leaves_out_plain_columns() {
+        let keys = mermaid(&shop(), true);
+        assert!(keys.contains("integer user_id FK"));
+        assert!(!keys.contains("email") && !keys.contains("total"));
+    }
+
+    #[test]
+    fn required_foreign_keys_make_the_target_mandatory() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_orders", "f_orders_user").nullable = false;
+        assert!(mermaid(&diagram, false).contains("orders }o--|| users"));
+    }
+
+    #[test]
+    fn names_are_made_valid_and_unique() {
+        let mut sales = table(
+            "t_sales_users",
+            "users",
+            &[
+                key("f_id", "id", "integer"),
+                column("f_name", "full name", "text"),
+            ],
+        );
+        sales.schema = Some("sales".to_string());
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap().push(sales);
+        diagram
+            .tables
+            .as_mut()
+            .unwrap()
+            .push(table("t_2fa", "2fa", &[]));
+
+        let erd = mermaid(&diagram, false);
+        assert!(erd.contains("    public_users {"));
+        assert!(erd.contains("    sales_users {"));
+        assert!(erd.contains("        text full_name\n"));
+        assert!(erd.contains("    _2fa {"));
+    }
+}
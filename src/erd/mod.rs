@@ -0,0 +1,224 @@
+//! Entity-relationship diagrams in text formats that documentation tools
+//! render: Mermaid `erDiagram`, PlantUML and Graphviz DOT; and as pictures of
+//! the canvas in SVG and PNG.
+//!
+//! All formats can be limited to one schema or to the tables inside one area.
+
+mod dot;
+mod mermaid;
+mod plantuml;
+mod svg;
+
+pub use dot::dot;
+pub use mermaid::mermaid;
+pub use plantuml::plantuml;
+pub use svg::{png, svg, RasterError};
+
+use crate::models::{Area, Diagram, Relationship, Table};
+use crate::schema::{self, FieldInfo};
+
+/// Which part of a diagram to draw.
+#[derive(Debug, Default)]
+pub struct Scope<'a> {
+    pub schema: Option<&'a str>,
+    /// Area id or name.
+    pub area: Option<&'a str>,
+}
+
+/// The tables in `scope` and the relationships between them. Fails with the
+/// requested area when the diagram has no such area.
+pub fn select(diagram: &Diagram, scope: &Scope) -> Result<Diagram, String> {
+    let area = match scope.area {
+        Some(wanted) => Some(
+            diagram
+                .areas
+                .iter()
+                .flatten()
+                .find(|a| a.id == wanted || a.name.as_deref() == Some(wanted))
+                .ok_or_else(|| wanted.to_string())?,
+        ),
+        None => None,
+    };
+
+    let tables: Vec<_> = diagram
+        .tables
+        .iter()
+        .flatten()
+        .filter(|t| scope.schema.is_none_or(|s| t.schema.as_deref() == Some(s)))
+        .filter(|t| area.is_none_or(|a| a.contains(t)))
+        .cloned()
+        .collect();
+    let included = |id: &str| tables.iter().any(|t| t.id == id);
+    let relationships = diagram
+        .relationships
+        .iter()
+        .flatten()
+        .filter(|r| included(&r.source_table_id) && included(&r.target_table_id))
+        .cloned()
+        .collect();
+    let dependencies = diagram
+        .dependencies
+        .iter()
+        .flatten()
+        .filter(|d| included(&d.table_id) && included(&d.dependent_table_id))
+        .cloned()
+        .collect();
+    let areas = diagram
+        .areas
+        .iter()
+        .flatten()
+        .filter(|a| area.is_none_or(|selected| selected.id == a.id))
+        .cloned()
+        .collect();
+
+    Ok(Diagram {
+        tables: Some(tables),
+        relationships: Some(relationships),
+        dependencies: Some(dependencies),
+        areas: Some(areas),
+        ..diagram.clone()
+    })
+}
+
+/// The table and field holding the foreign key of a relationship, following
+/// the convention of SQL export: the `many` side, or the source of a
+/// one-to-one. Many-to-many relationships have none.
+pub fn foreign_key_end(rel: &Relationship) -> Option<(&str, &str)> {
+    let source_many = rel.source_cardinality.as_deref() == Some("many");
+    let target_many = rel.target_cardinality.as_deref() == Some("many");
+    match (source_many, target_many) {
+        (true, true) => None,
+        (false, true) => Some((&rel.target_table_id, rel.target_field_id.as_deref()?)),
+        _ => Some((&rel.source_table_id, rel.source_field_id.as_deref()?)),
+    }
+}
+
+/// The columns of a table holding foreign keys, as (table id, field id).
+pub fn foreign_key_fields(relationships: &[Relationship]) -> Vec<(&str, &str)> {
+    relationships.iter().filter_map(foreign_key_end).collect()
+}
+
+/// `PK`, `FK` and `UK` markers for a column.
+pub fn key_markers(
+    table: &Table,
+    field: &FieldInfo,
+    foreign_keys: &[(&str, &str)],
+) -> Vec<&'static str> {
+    let mut keys = Vec::new();
+    if field.primary_key {
+        keys.push("PK");
+    }
+    if foreign_keys.contains(&(table.id.as_str(), field.id.as_str())) {
+        keys.push("FK");
+    }
+    if field.unique && !field.primary_key {
+        keys.push("UK");
+    }
+    keys
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Multiplicity {
+    One,
+    ZeroOrOne,
+    Many,
+}
+
+/// The multiplicity of the source and target ends of a relationship. The
+/// end a nullable foreign key points at is optional.
+fn multiplicities(rel: &Relationship, tables: &[Table]) -> (Multiplicity, Multiplicity) {
+    let end = |cardinality: Option<&str>| match cardinality {
+        Some("many") => Multiplicity::Many,
+        _ => Multiplicity::One,
+    };
+    let mut source = end(rel.source_cardinality.as_deref());
+    let mut target = end(rel.target_cardinality.as_deref());
+
+    let nullable_key = foreign_key_end(rel).and_then(|(table_id, field_id)| {
+        let table = tables.iter().find(|t| t.id == table_id)?;
+        let field = schema::fields(table)
+            .into_iter()
+            .find(|f| f.id == field_id)?;
+        (field.nullable && !field.primary_key).then_some(table_id)
+    });
+    match nullable_key {
+        Some(table_id) if table_id == rel.source_table_id && target == Multiplicity::One => {
+            target = Multiplicity::ZeroOrOne
+        }
+        Some(_) if source == Multiplicity::One => source = Multiplicity::ZeroOrOne,
+        _ => {}
+    }
+    (source, target)
+}
+
+/// The first area a table sits in.
+fn area_of<'a>(table: &Table, areas: &'a [Area]) -> Option<&'a Area> {
+    areas.iter().find(|a| a.contains(table))
+}
+
+/// A stored color as `#rrggbb`, for colors saved without the `#`.
+fn color(value: Option<&str>) -> Option<String> {
+    let value = value?.trim();
+    if value.is_empty() {
+        None
+    } else if value.starts_with('#') || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(value.to_string())
+    } else {
+        Some(format!("#{}", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, key, shop, table};
+
+    fn table_names(diagram: &Diagram) -> Vec<&str> {
+        diagram
+            .tables
+            .iter()
+            .flatten()
+            .map(|t| t.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn selects_a_schema() {
+        let mut diagram = shop();
+        let mut ledger = table("t_ledger", "ledger", &[key("f_ledger_id", "id", "integer")]);
+        ledger.schema = Some("billing".to_string());
+        diagram.tables.as_mut().unwrap().push(ledger);
+
+        let scope = Scope {
+            schema: Some("billing"),
+            ..Scope::default()
+        };
+        let selected = select(&diagram, &scope).unwrap();
+        assert_eq!(table_names(&selected), ["ledger"]);
+        assert!(selected.relationships.unwrap().is_empty());
+    }
+
+    #[test]
+    fn selects_the_tables_inside_an_area() {
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap()[1].x = Some(500.0);
+        diagram.areas = Some(vec![area("a_people", "People", -10.0, -10.0)]);
+
+        for wanted in ["a_people", "People"] {
+            let scope = Scope {
+                area: Some(wanted),
+                ..Scope::default()
+            };
+            let selected = select(&diagram, &scope).unwrap();
+            assert_eq!(table_names(&selected), ["users"]);
+            // Relationships leaving the area are left out
+            assert!(selected.relationships.unwrap().is_empty());
+        }
+
+        let scope = Scope {
+            area: Some("Nowhere"),
+            ..Scope::default()
+        };
+        assert_eq!(select(&diagram, &scope).err().as_deref(), Some("Nowhere"));
+    }
+}
@@ -0,0 +1,245 @@
+//! PlantUML entity diagrams in information engineering notation.
+
+use super::{area_of, color, foreign_key_fields, key_markers, multiplicities, Multiplicity};
+use crate::models::{Diagram, Table};
+use crate::schema;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Renders the tables as entities, grouped into a package per area and
+/// filled with their color. Views are drawn with a dashed border and a
+/// `<<view>>` stereotype. With `keys_only`, columns other than primary keys,
+/// foreign keys and unique columns are left out.
+pub fn plantuml(diagram: &Diagram, keys_only: bool) -> String {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let areas = diagram.areas.as_deref().unwrap_or_default();
+    let aliases = aliases(tables);
+    let foreign_keys = foreign_key_fields(relationships);
+
+    let mut out = String::from("@startuml\n");
+    let _ = writeln!(out, "title {}", text(&diagram.name));
+    out.push_str("hide circle\nskinparam linetype ortho\n\n");
+
+    let entity = |out: &mut String, table: &Table, indent: &str| {
+        let mut style = color(table.color.as_deref()).unwrap_or_default();
+        let stereotype = match (table.is_view, table.is_materialized_view) {
+            (_, Some(true)) => " <<materialized view>>",
+            (Some(true), _) => " <<view>>",
+            _ => "",
+        };
+        if !stereotype.is_empty() {
+            style.push_str(if style.is_empty() {
+                "#line.dashed"
+            } else {
+                ";line.dashed"
+            });
+        }
+        let _ = write!(
+            out,
+            "{}entity \"{}\" as {}{}",
+            indent,
+            text(&schema::qualified_name(
+                table.schema.as_deref(),
+                &table.name
+            )),
+            aliases[table.id.as_str()],
+            stereotype
+        );
+        if !style.is_empty() {
+            let _ = write!(out, " {}", style);
+        }
+        out.push_str(" {\n");
+
+        let columns: Vec<_> = schema::fields(table)
+            .into_iter()
+            .map(|field| {
+                let markers = key_markers(table, &field, &foreign_keys);
+                (field, markers)
+            })
+            .filter(|(_, markers)| !keys_only || !markers.is_empty())
+            .collect();
+        let (keys, others): (Vec<_>, Vec<_>) = columns.iter().partition(|(f, _)| f.primary_key);
+        for (i, (field, markers)) in keys.iter().chain(&others).enumerate() {
+            if i == keys.len() && !keys.is_empty() {
+                let _ = writeln!(out, "{}  --", indent);
+            }
+            let _ = write!(
+                out,
+                "{}  {}{} : {}",
+                indent,
+                if field.nullable { "" } else { "* " },
+                text(&field.name),
+                text(&field.type_sql())
+            );
+            for marker in markers {
+                let _ = write!(out, " <<{}>>", marker);
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}}}", indent);
+    };
+
+    for area in areas {
+        let members: Vec<_> = tables
+            .iter()
+            .filter(|t| area_of(t, areas).is_some_and(|a| a.id == area.id))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        let _ = write!(
+            out,
+            "package \"{}\"",
+            text(area.name.as_deref().unwrap_or_default())
+        );
+        if let Some(color) = color(area.color.as_deref()) {
+            let _ = write!(out, " {}", color);
+        }
+        out.push_str(" {\n");
+        for table in members {
+            entity(&mut out, table, "  ");
+        }
+        out.push_str("}\n");
+    }
+    for table in tables.iter().filter(|t| area_of(t, areas).is_none()) {
+        entity(&mut out, table, "");
+    }
+
+    out.push('\n');
+    for rel in relationships {
+        let (Some(source), Some(target)) = (
+            aliases.get(rel.source_table_id.as_str()),
+            aliases.get(rel.target_table_id.as_str()),
+        ) else {
+            continue;
+        };
+        let (source_end, target_end) = multiplicities(rel, tables);
+        let source_end = match source_end {
+            Multiplicity::One => "||",
+            Multiplicity::ZeroOrOne => "|o",
+            Multiplicity::Many => "}o",
+        };
+        let target_end = match target_end {
+            Multiplicity::One => "||",
+            Multiplicity::ZeroOrOne => "o|",
+            Multiplicity::Many => "o{",
+        };
+        let _ = write!(out, "{} {}--{} {}", source, source_end, target_end, target);
+        if let Some(name) = rel.name.as_deref().filter(|n| !n.is_empty()) {
+            let _ = write!(out, " : {}", text(name));
+        }
+        out.push('\n');
+    }
+    out.push_str("@enduml\n");
+    out
+}
+
+/// Entity aliases by table id: the qualified name with everything but
+/// letters, digits and `_` replaced, numbered when two tables collide.
+fn aliases(tables: &[Table]) -> HashMap<&str, String> {
+    let mut taken = Vec::new();
+    tables
+        .iter()
+        .map(|table| {
+            let base: String = schema::qualified_name(table.schema.as_deref(), &table.name)
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let mut alias = base.clone();
+            let mut n = 1;
+            while taken.contains(&alias) {
+                n += 1;
+                alias = format!("{}_{}", base, n);
+            }
+            taken.push(alias.clone());
+            (table.id.as_str(), alias)
+        })
+        .collect()
+}
+
+/// PlantUML text runs to the end of the line and must not contain quotes
+/// where it is quoted.
+fn text(value: &str) -> String {
+    value.replace('"', "'").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, key, shop, table};
+
+    #[test]
+    fn renders_entities_and_relationships() {
+        assert_eq!(
+            plantuml(&shop(), false),
+            concat!(
+                "@startuml\n",
+                "title Test\n",
+                "hide circle\n",
+                "skinparam linetype ortho\n",
+                "\n",
+                "entity \"public.users\" as public_users {\n",
+                "  * id : integer <<PK>>\n",
+                "  --\n",
+                "  email : varchar(255)\n",
+                "}\n",
+                "entity \"public.orders\" as public_orders {\n",
+                "  * id : integer <<PK>>\n",
+                "  --\n",
+                "  user_id : integer <<FK>>\n",
+                "  total : numeric(10,2)\n",
+                "}\n",
+                "\n",
+                "public_orders }o--o| public_users\n",
+                "@enduml\n",
+            )
+        );
+    }
+
+    #[test]
+    fn areas_become_packages_and_views_are_dashed() {
+        let mut diagram = shop();
+        let tables = diagram.tables.as_mut().unwrap();
+        tables[1].x = Some(500.0);
+        tables[1].color = Some("#ff9f74".to_string());
+        let mut view = table("t_totals", "totals", &[]);
+        view.x = Some(500.0);
+        view.is_view = Some(true);
+        tables.push(view);
+        let mut people = area("a_people", "People", -10.0, -10.0);
+        people.color = Some("#b0b0b0".to_string());
+        diagram.areas = Some(vec![people]);
+
+        let uml = plantuml(&diagram, true);
+        assert!(uml.contains(concat!(
+            "package \"People\" #b0b0b0 {\n",
+            "  entity \"public.users\" as public_users {\n",
+            "    * id : integer <<PK>>\n",
+            "  }\n",
+            "}\n",
+        )));
+        assert!(uml.contains("entity \"public.orders\" as public_orders #ff9f74 {\n"));
+        assert!(uml.contains("entity \"public.totals\" as public_totals <<view>> #line.dashed {\n"));
+    }
+
+    #[test]
+    fn aliases_are_unique() {
+        let diagram = crate::fixtures::diagram(
+            vec![
+                table("t_a", "user-accounts", &[key("f_a", "id", "integer")]),
+                table("t_b", "user_accounts", &[key("f_b", "id", "integer")]),
+            ],
+            Vec::new(),
+        );
+        let uml = plantuml(&diagram, false);
+        assert!(uml.contains("as public_user_accounts {"));
+        assert!(uml.contains("as public_user_accounts_2 {"));
+    }
+}
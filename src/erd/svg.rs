@@ -0,0 +1,463 @@
+//! SVG pictures of the canvas, drawn as positioned in ChartDB, and their PNG
+//! rasterization.
+
+use super::{color, foreign_key_fields, key_markers, multiplicities, Multiplicity};
+use crate::layout::{self, FIELD_HEIGHT, HEADER_HEIGHT, TABLE_WIDTH};
+use crate::models::{Diagram, Table};
+use crate::schema;
+use resvg::{tiny_skia, usvg};
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+const MARGIN: f64 = 40.0;
+const FONT: &str = "DejaVu Sans, Helvetica, Arial, sans-serif";
+const FONT_SIZE: f64 = 13.0;
+/// Rough advance of a character, for cutting text to fit.
+const CHAR_WIDTH: f64 = FONT_SIZE * 0.55;
+/// Offset from the middle of a line to its text baseline.
+const BASELINE: f64 = 4.5;
+const DEFAULT_TABLE_COLOR: &str = "#b0b7c3";
+const DEFAULT_AREA_COLOR: &str = "#94a3b8";
+const DEFAULT_NOTE_COLOR: &str = "#ffe374";
+/// Largest side of a PNG, in pixels.
+const MAX_PNG_SIDE: f64 = 16384.0;
+
+#[derive(Debug)]
+pub enum RasterError {
+    /// The picture at the requested scale exceeds the size limit.
+    TooLarge,
+    Svg(String),
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Rect {
+    fn of_table(table: &Table) -> Rect {
+        Rect {
+            x: table.x.unwrap_or_default(),
+            y: table.y.unwrap_or_default(),
+            width: table.width.unwrap_or(TABLE_WIDTH),
+            height: layout::height(table),
+        }
+    }
+
+    fn center_x(&self) -> f64 {
+        self.x + self.width / 2.0
+    }
+}
+
+/// Draws areas, relationship lines, tables with their fields and notes at
+/// their stored positions and sizes, on a canvas just large enough for all
+/// of them.
+pub fn svg(diagram: &Diagram) -> String {
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let relationships = diagram.relationships.as_deref().unwrap_or_default();
+    let areas = diagram.areas.as_deref().unwrap_or_default();
+    let notes = diagram.notes.as_deref().unwrap_or_default();
+
+    let rect = |x: Option<f64>, y: Option<f64>, width: Option<f64>, height: Option<f64>| Rect {
+        x: x.unwrap_or_default(),
+        y: y.unwrap_or_default(),
+        width: width.unwrap_or_default(),
+        height: height.unwrap_or_default(),
+    };
+    let area_rects: Vec<_> = areas
+        .iter()
+        .map(|a| rect(a.x, a.y, a.width, a.height))
+        .collect();
+    let note_rects: Vec<_> = notes
+        .iter()
+        .map(|n| rect(n.x, n.y, n.width, n.height))
+        .collect();
+    let table_rects: Vec<_> = tables.iter().map(Rect::of_table).collect();
+
+    let all = || table_rects.iter().chain(&area_rects).chain(&note_rects);
+    let (left, top, right, bottom) = all()
+        .fold(None, |bounds, r| {
+            let (l, t, r2, b) = bounds.unwrap_or((r.x, r.y, r.x + r.width, r.y + r.height));
+            Some((
+                f64::min(l, r.x),
+                f64::min(t, r.y),
+                f64::max(r2, r.x + r.width),
+                f64::max(b, r.y + r.height),
+            ))
+        })
+        .unwrap_or((0.0, 0.0, 0.0, 0.0));
+    let (left, top) = (left - MARGIN, top - MARGIN);
+    let (width, height) = (right - left + MARGIN, bottom - top + MARGIN);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{l} {t} {w} {h}" font-family="{font}" font-size="{size}">"#,
+        w = width,
+        h = height,
+        l = left,
+        t = top,
+        font = FONT,
+        size = FONT_SIZE
+    );
+    let _ = writeln!(out, "<title>{}</title>", xml(&diagram.name));
+    let _ = writeln!(
+        out,
+        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#f8fafc"/>"##,
+        left, top, width, height
+    );
+
+    for (area, r) in areas.iter().zip(&area_rects) {
+        let color = color(area.color.as_deref()).unwrap_or_else(|| DEFAULT_AREA_COLOR.into());
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="8" fill="{c}" fill-opacity="0.12" stroke="{c}" stroke-width="2"/>"#,
+            r.x,
+            r.y,
+            r.width,
+            r.height,
+            c = xml(&color)
+        );
+        let _ = writeln!(
+            out,
+            r##"<text x="{}" y="{}" font-weight="bold" fill="#334155">{}</text>"##,
+            r.x + 12.0,
+            r.y + 22.0,
+            xml(&fit(
+                area.name.as_deref().unwrap_or_default(),
+                r.width - 24.0
+            ))
+        );
+    }
+
+    for rel in relationships {
+        let end = |table_id: &str, field_id: Option<&str>| {
+            let index = tables.iter().position(|t| t.id == table_id)?;
+            let table = &tables[index];
+            let row = field_id.and_then(|id| schema::fields(table).iter().position(|f| f.id == id));
+            let y = match row {
+                Some(row) => {
+                    table_rects[index].y + HEADER_HEIGHT + FIELD_HEIGHT * (row as f64 + 0.5)
+                }
+                None => table_rects[index].y + HEADER_HEIGHT / 2.0,
+            };
+            Some((table_rects[index], y))
+        };
+        let (Some((source, sy)), Some((target, ty))) = (
+            end(&rel.source_table_id, rel.source_field_id.as_deref()),
+            end(&rel.target_table_id, rel.target_field_id.as_deref()),
+        ) else {
+            continue;
+        };
+
+        // Lines leave from the facing sides of the two tables; a table
+        // referencing itself gets a loop on its right.
+        let self_reference = rel.source_table_id == rel.target_table_id;
+        let rightwards = self_reference || source.center_x() <= target.center_x();
+        let (sx, sdir) = if rightwards {
+            (source.x + source.width, 1.0)
+        } else {
+            (source.x, -1.0)
+        };
+        let (tx, tdir) = if self_reference || !rightwards {
+            (target.x + target.width, 1.0)
+        } else {
+            (target.x, -1.0)
+        };
+        let bend = f64::max(40.0, (tx - sx).abs() / 2.0);
+        let _ = writeln!(
+            out,
+            r##"<path d="M {} {} C {} {}, {} {}, {} {}" fill="none" stroke="#64748b" stroke-width="1.5"/>"##,
+            sx,
+            sy,
+            sx + sdir * bend,
+            sy,
+            tx + tdir * bend,
+            ty,
+            tx,
+            ty
+        );
+
+        let label = |m| match m {
+            Multiplicity::One => "1",
+            Multiplicity::ZeroOrOne => "0..1",
+            Multiplicity::Many => "N",
+        };
+        let (source_end, target_end) = multiplicities(rel, tables);
+        for (x, y, dir, m) in [(sx, sy, sdir, source_end), (tx, ty, tdir, target_end)] {
+            let _ = writeln!(
+                out,
+                r##"<text x="{}" y="{}" font-size="11" fill="#475569" text-anchor="{}">{}</text>"##,
+                x + dir * 6.0,
+                y - 5.0,
+                if dir > 0.0 { "start" } else { "end" },
+                label(m)
+            );
+        }
+    }
+
+    let foreign_keys = foreign_key_fields(relationships);
+    for (table, r) in tables.iter().zip(&table_rects) {
+        let color = color(table.color.as_deref()).unwrap_or_else(|| DEFAULT_TABLE_COLOR.into());
+        let is_view = table.is_view == Some(true) || table.is_materialized_view == Some(true);
+        let _ = writeln!(out, "<g>");
+        let _ = writeln!(
+            out,
+            r##"<rect x="{}" y="{}" width="{}" height="{}" rx="6" fill="#ffffff" stroke="#cbd5e1"{}/>"##,
+            r.x,
+            r.y,
+            r.width,
+            r.height,
+            if is_view {
+                r#" stroke-dasharray="6 4""#
+            } else {
+                ""
+            }
+        );
+        let _ = writeln!(
+            out,
+            r#"<path d="M {x} {y2} v -2 a 6 6 0 0 1 6 -6 h {w} a 6 6 0 0 1 6 6 v 2 z" fill="{c}"/>"#,
+            x = r.x,
+            y2 = r.y + 8.0,
+            w = r.width - 12.0,
+            c = xml(&color)
+        );
+        let name = schema::qualified_name(table.schema.as_deref(), &table.name);
+        let _ = writeln!(
+            out,
+            r##"<text x="{}" y="{}" font-weight="bold" fill="#0f172a"{}>{}</text>"##,
+            r.x + 12.0,
+            r.y + 8.0 + (HEADER_HEIGHT - 8.0) / 2.0 + BASELINE,
+            if is_view {
+                r#" font-style="italic""#
+            } else {
+                ""
+            },
+            xml(&fit(&name, r.width - 24.0))
+        );
+
+        for (row, field) in schema::fields(table).iter().enumerate() {
+            let y = r.y + HEADER_HEIGHT + FIELD_HEIGHT * row as f64;
+            let baseline = y + FIELD_HEIGHT / 2.0 + BASELINE;
+            let _ = writeln!(
+                out,
+                r##"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="#e2e8f0"/>"##,
+                r.x,
+                r.x + r.width,
+                y = y
+            );
+            let markers = key_markers(table, field, &foreign_keys).join(" ");
+            let type_sql = format!(
+                "{}{}",
+                field.type_sql(),
+                if field.nullable { "?" } else { "" }
+            );
+            let type_width = f64::min(
+                (type_sql.chars().count() + markers.len() + 1) as f64 * CHAR_WIDTH,
+                r.width / 2.0,
+            );
+            let _ = writeln!(
+                out,
+                r##"<text x="{}" y="{}" fill="#1e293b"{}>{}</text>"##,
+                r.x + 12.0,
+                baseline,
+                if field.primary_key {
+                    r#" font-weight="bold""#
+                } else {
+                    ""
+                },
+                xml(&fit(&field.name, r.width - 36.0 - type_width))
+            );
+            let _ = writeln!(
+                out,
+                r##"<text x="{}" y="{}" fill="#64748b" text-anchor="end">{}{}</text>"##,
+                r.x + r.width - 12.0,
+                baseline,
+                xml(&fit(&type_sql, r.width / 2.0)),
+                if markers.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        r##" <tspan fill="#b45309" font-size="10">{}</tspan>"##,
+                        markers
+                    )
+                }
+            );
+        }
+        let _ = writeln!(out, "</g>");
+    }
+
+    for (note, r) in notes.iter().zip(&note_rects) {
+        let color = color(note.color.as_deref()).unwrap_or_else(|| DEFAULT_NOTE_COLOR.into());
+        let _ = writeln!(
+            out,
+            r##"<rect x="{}" y="{}" width="{}" height="{}" rx="4" fill="{}" stroke="#000000" stroke-opacity="0.15"/>"##,
+            r.x,
+            r.y,
+            r.width,
+            r.height,
+            xml(&color)
+        );
+        let columns = ((r.width - 24.0) / CHAR_WIDTH).max(1.0) as usize;
+        let rows = ((r.height - 16.0) / (FONT_SIZE * 1.4)).max(0.0) as usize;
+        let lines = wrap(note.content.as_deref().unwrap_or_default(), columns);
+        for (i, line) in lines.iter().take(rows).enumerate() {
+            let _ = writeln!(
+                out,
+                r##"<text x="{}" y="{}" fill="#1f2937">{}</text>"##,
+                r.x + 12.0,
+                r.y + 8.0 + FONT_SIZE * 1.4 * (i as f64 + 1.0),
+                xml(line)
+            );
+        }
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Rasterizes an SVG from [`svg`], `scale` pixels per canvas unit.
+pub fn png(svg: &str, scale: f32) -> Result<Vec<u8>, RasterError> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    let fontdb = FONTS.get_or_init(|| {
+        let mut fontdb = usvg::fontdb::Database::new();
+        fontdb.load_system_fonts();
+        Arc::new(fontdb)
+    });
+    let options = usvg::Options {
+        fontdb: fontdb.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| RasterError::Svg(e.to_string()))?;
+
+    let size = tree.size();
+    let (width, height) = (size.width() * scale, size.height() * scale);
+    if f64::from(width.max(height)) > MAX_PNG_SIDE {
+        return Err(RasterError::TooLarge);
+    }
+    let mut pixmap = tiny_skia::Pixmap::new(width.ceil() as u32, height.ceil() as u32)
+        .ok_or(RasterError::TooLarge)?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .encode_png()
+        .map_err(|e| RasterError::Svg(e.to_string()))
+}
+
+/// Cuts `text` to what fits in `width`, ending it with an ellipsis.
+fn fit(text: &str, width: f64) -> String {
+    let max = (width / CHAR_WIDTH).max(1.0) as usize;
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+/// Breaks note text into lines of at most `columns` characters, at spaces
+/// where possible.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word.to_string();
+            while word.chars().count() > columns {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let rest = word.split_off(
+                    word.char_indices()
+                        .nth(columns)
+                        .map_or(word.len(), |(i, _)| i),
+                );
+                lines.push(std::mem::replace(&mut word, rest));
+            }
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > columns {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, shop};
+
+    fn placed() -> Diagram {
+        let mut diagram = shop();
+        diagram.name = "Shop & co".to_string();
+        let tables = diagram.tables.as_mut().unwrap();
+        tables[1].x = Some(400.0);
+        tables[1].y = Some(100.0);
+        diagram
+    }
+
+    #[test]
+    fn the_canvas_fits_everything_with_a_margin() {
+        let mut diagram = placed();
+        diagram.areas = Some(vec![area("a_people", "People", -60.0, -20.0)]);
+        let picture = svg(&diagram);
+
+        let orders = Rect::of_table(&diagram.tables.as_deref().unwrap()[1]);
+        let (left, top) = (-60.0 - MARGIN, -20.0 - MARGIN);
+        let width = orders.x + orders.width + MARGIN - left;
+        let height = orders.y + orders.height + MARGIN - top;
+        assert!(picture.starts_with(&format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{l} {t} {w} {h}""#,
+            w = width,
+            h = height,
+            l = left,
+            t = top
+        )));
+        assert!(picture.contains("<title>Shop &amp; co</title>"));
+        assert!(picture.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn renders_to_png() {
+        let bytes = png(&svg(&placed()), 1.0).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(matches!(
+            png(&svg(&placed()), 1000.0),
+            Err(RasterError::TooLarge)
+        ));
+        assert!(matches!(png("<svg", 1.0), Err(RasterError::Svg(_))));
+    }
+
+    #[test]
+    fn text_is_cut_to_fit() {
+        assert_eq!(fit("orders", 100.0), "orders");
+        let cut = fit("a_very_long_column_name", 10.0 * CHAR_WIDTH);
+        assert_eq!(cut, "a_very_lo…");
+    }
+
+    #[test]
+    fn notes_wrap_at_spaces() {
+        assert_eq!(wrap("one two three\nfour", 8), ["one two", "three", "four"]);
+        assert_eq!(wrap("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+    }
+}
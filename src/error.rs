@@ -0,0 +1,475 @@
+//! The error type of the HTTP handlers.
+//!
+//! Every error response carries a stable, machine-readable `code` next to the
+//! human-readable `error` message, and `details` for errors with structured
+//! data. Database and other internal failures are logged in full but reported
+//! to clients only by what was being done; constraint violations are told
+//! apart so that clients can react to them.
+
+use crate::merge::MergeConflict;
+use crate::models::ErrorResponse;
+use crate::validate::Problem;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+use sqlx::error::ErrorKind;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AppError {
+    /// A request body, query string or path that does not parse, as told by
+    /// the extractor that rejected it.
+    InvalidRequest {
+        status: StatusCode,
+        message: String,
+    },
+    /// A missing, malformed or unknown request parameter.
+    BadRequest(String),
+    /// A DBML or SQL script that does not parse.
+    InvalidSource {
+        message: String,
+        line: u64,
+        column: u64,
+    },
+    DiagramNotFound,
+    RevisionNotFound {
+        diagram_id: String,
+        version: i32,
+    },
+    /// Something inside a diagram, e.g. an area, that does not exist.
+    NotFound(String),
+    /// A push against a version the server cannot merge from.
+    VersionConflict {
+        base: i32,
+        current: i32,
+        reason: &'static str,
+    },
+    /// A stale push whose changes overlap with edits made on the server.
+    MergeConflict {
+        base: i32,
+        current: i32,
+        conflicts: Vec<MergeConflict>,
+    },
+    /// Entity ids that already belong to another diagram.
+    IdsTaken(Vec<String>),
+    /// An entity created with the id of one the diagram already has.
+    AlreadyExists(String),
+    /// Dangling references in a diagram that was about to be stored.
+    ValidationFailed(Vec<Problem>),
+    /// A well-formed request for something the diagram cannot provide, like
+    /// SQL for a dialect that cannot express it.
+    Unsupported {
+        message: String,
+        details: Option<Value>,
+    },
+    /// The database named in an introspection or drift request could not be
+    /// reached.
+    ConnectionFailed(sqlx::Error),
+    /// That database was reached but failed to answer.
+    UpstreamFailed(sqlx::Error),
+    Database {
+        context: &'static str,
+        source: sqlx::Error,
+    },
+    Internal {
+        context: &'static str,
+        source: String,
+    },
+}
+
+/// Maps a database error to [`AppError::Database`], keeping what was being
+/// done as the message clients see.
+pub fn db_error(context: &'static str) -> impl Fn(sqlx::Error) -> AppError {
+    move |source| AppError::Database { context, source }
+}
+
+/// How a database error is reported.
+enum DbFailure {
+    Unavailable,
+    Unique,
+    ForeignKey,
+    NotNull,
+    Check,
+    InvalidValue,
+    Other,
+}
+
+fn classify(error: &sqlx::Error) -> DbFailure {
+    match error {
+        sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_) => DbFailure::Unavailable,
+        sqlx::Error::Database(e) => match e.kind() {
+            ErrorKind::UniqueViolation => DbFailure::Unique,
+            ErrorKind::ForeignKeyViolation => DbFailure::ForeignKey,
+            ErrorKind::NotNullViolation => DbFailure::NotNull,
+            ErrorKind::CheckViolation => DbFailure::Check,
+            _ => {
+                let code = e.code().unwrap_or_default();
+                // Class 22 is bad data, e.g. a value too long for its column;
+                // 08, 53300 and 57P0x mean the server is going away or full
+                if code.starts_with("22") {
+                    DbFailure::InvalidValue
+                } else if code.starts_with("08") || code == "53300" || code.starts_with("57P0") {
+                    DbFailure::Unavailable
+                } else {
+                    DbFailure::Other
+                }
+            }
+        },
+        _ => DbFailure::Other,
+    }
+}
+
+impl AppError {
+    pub fn internal(context: &'static str, source: impl fmt::Display) -> Self {
+        AppError::Internal {
+            context,
+            source: source.to_string(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::InvalidRequest { status, .. } => *status,
+            AppError::BadRequest(_)
+            | AppError::InvalidSource { .. }
+            | AppError::ConnectionFailed(_) => StatusCode::BAD_REQUEST,
+            AppError::DiagramNotFound
+            | AppError::RevisionNotFound { .. }
+            | AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::VersionConflict { .. }
+            | AppError::MergeConflict { .. }
+            | AppError::IdsTaken(_)
+            | AppError::AlreadyExists(_) => StatusCode::CONFLICT,
+            AppError::ValidationFailed(_) | AppError::Unsupported { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::UpstreamFailed(_) => StatusCode::BAD_GATEWAY,
+            AppError::Database { source, .. } => match classify(source) {
+                DbFailure::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+                DbFailure::Unique | DbFailure::ForeignKey => StatusCode::CONFLICT,
+                DbFailure::NotNull | DbFailure::Check | DbFailure::InvalidValue => {
+                    StatusCode::BAD_REQUEST
+                }
+                DbFailure::Other => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InvalidRequest { .. } => "invalid_request",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InvalidSource { .. } => "parse_failed",
+            AppError::DiagramNotFound => "diagram_not_found",
+            AppError::RevisionNotFound { .. } => "revision_not_found",
+            AppError::NotFound(_) => "not_found",
+            AppError::VersionConflict { .. } => "version_conflict",
+            AppError::MergeConflict { .. } => "merge_conflict",
+            AppError::IdsTaken(_) => "id_conflict",
+            AppError::AlreadyExists(_) => "already_exists",
+            AppError::ValidationFailed(_) => "validation_failed",
+            AppError::Unsupported { .. } => "unsupported",
+            AppError::ConnectionFailed(_) => "connection_failed",
+            AppError::UpstreamFailed(_) => "upstream_failed",
+            AppError::Database { source, .. } => match classify(source) {
+                DbFailure::Unavailable => "db_unavailable",
+                DbFailure::Unique => "unique_violation",
+                DbFailure::ForeignKey => "foreign_key_violation",
+                DbFailure::NotNull => "not_null_violation",
+                DbFailure::Check => "check_violation",
+                DbFailure::InvalidValue => "invalid_value",
+                DbFailure::Other => "internal_error",
+            },
+            AppError::Internal { .. } => "internal_error",
+        }
+    }
+
+    /// The message clients see; unlike [`fmt::Display`] it leaves out the
+    /// cause of database and internal errors.
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Database { context, source } => {
+                let reason = match classify(source) {
+                    DbFailure::Unavailable => "the database is unavailable",
+                    DbFailure::Unique => "a row with the same key already exists",
+                    DbFailure::ForeignKey => "a referenced row does not exist",
+                    DbFailure::NotNull => "a required value is missing",
+                    DbFailure::Check => "a value is not allowed",
+                    DbFailure::InvalidValue => "a value is invalid for its column",
+                    DbFailure::Other => return context.to_string(),
+                };
+                format!("{}: {}", context, reason)
+            }
+            AppError::Internal { context, .. } => context.to_string(),
+            // Driver messages can carry hosts, user names and the like
+            AppError::ConnectionFailed(_) => "Could not connect to the database".to_string(),
+            AppError::UpstreamFailed(_) => "Could not read the database schema".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            AppError::InvalidSource { line, column, .. } => {
+                Some(json!({ "line": line, "column": column }))
+            }
+            AppError::VersionConflict { current, .. } => Some(json!({ "currentVersion": current })),
+            AppError::MergeConflict {
+                current, conflicts, ..
+            } => Some(json!({ "currentVersion": current, "conflicts": conflicts })),
+            AppError::IdsTaken(ids) => Some(json!({ "ids": ids })),
+            AppError::ValidationFailed(problems) => Some(json!({ "problems": problems })),
+            AppError::Unsupported { details, .. } => details.clone(),
+            AppError::ConnectionFailed(source) | AppError::UpstreamFailed(source) => {
+                upstream_details(source)
+            }
+            AppError::Database {
+                source: sqlx::Error::Database(e),
+                ..
+            } => e.constraint().map(|c| json!({ "constraint": c })),
+            _ => None,
+        }
+    }
+}
+
+/// What can be told about a failure of a database a request named: its
+/// SQLSTATE, or the kind of failure, but none of the driver's text.
+fn upstream_details(error: &sqlx::Error) -> Option<Value> {
+    let reason = match error {
+        sqlx::Error::Database(e) => return e.code().map(|code| json!({ "sqlstate": code })),
+        sqlx::Error::Configuration(_) => "invalid_connection_string",
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => "unreachable",
+        sqlx::Error::Tls(_) => "tls_failed",
+        sqlx::Error::Protocol(_) => "protocol_error",
+        _ => return None,
+    };
+    Some(json!({ "reason": reason }))
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::InvalidRequest { message, .. }
+            | AppError::BadRequest(message)
+            | AppError::NotFound(message)
+            | AppError::AlreadyExists(message)
+            | AppError::Unsupported { message, .. } => f.write_str(message),
+            AppError::InvalidSource { message, .. } => f.write_str(message),
+            AppError::DiagramNotFound => f.write_str("Diagram not found"),
+            AppError::RevisionNotFound {
+                diagram_id,
+                version,
+            } => write!(
+                f,
+                "Revision {} of diagram {} not found",
+                version, diagram_id
+            ),
+            AppError::VersionConflict {
+                base,
+                current,
+                reason,
+            } => write!(
+                f,
+                "Version conflict: pushed against version {} but server is at version {}; {}",
+                base, current, reason
+            ),
+            AppError::MergeConflict {
+                base, conflicts, ..
+            } => write!(
+                f,
+                "Merge conflict: {} change(s) overlap with edits made since version {}",
+                conflicts.len(),
+                base
+            ),
+            AppError::IdsTaken(ids) => write!(
+                f,
+                "Entity ids already used by another diagram: {}",
+                ids.join(", ")
+            ),
+            AppError::ValidationFailed(problems) => write!(
+                f,
+                "Diagram failed validation with {} problem(s)",
+                problems.len()
+            ),
+            AppError::ConnectionFailed(source) => {
+                write!(f, "Could not connect to the database: {}", source)
+            }
+            AppError::UpstreamFailed(source) => {
+                write!(f, "Could not read the database schema: {}", source)
+            }
+            AppError::Database { context, source } => write!(f, "{}: {}", context, source),
+            AppError::Internal { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("{}", self);
+        } else if let AppError::Database { .. } | AppError::ConnectionFailed(_) = self {
+            tracing::warn!("{}", self);
+        }
+        let body = ErrorResponse {
+            code: self.code(),
+            error: self.message(),
+            details: self.details(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::error::Error as StdError;
+
+    /// A database error carrying only a SQLSTATE, classified the way the
+    /// Postgres driver does.
+    #[derive(Debug)]
+    struct Sqlstate(&'static str);
+
+    impl fmt::Display for Sqlstate {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "failed with {} at host db.internal", self.0)
+        }
+    }
+
+    impl StdError for Sqlstate {}
+
+    impl sqlx::error::DatabaseError for Sqlstate {
+        fn message(&self) -> &str {
+            "failed"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.0.starts_with("23").then_some("tables_pkey")
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.0 {
+                "23505" => ErrorKind::UniqueViolation,
+                "23503" => ErrorKind::ForeignKeyViolation,
+                "23502" => ErrorKind::NotNullViolation,
+                "23514" => ErrorKind::CheckViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    fn database(source: sqlx::Error) -> AppError {
+        db_error("Failed to save diagram")(source)
+    }
+
+    fn failing_with(code: &'static str) -> AppError {
+        database(sqlx::Error::Database(Box::new(Sqlstate(code))))
+    }
+
+    fn reported(error: &AppError) -> (StatusCode, &'static str) {
+        (error.status(), error.code())
+    }
+
+    #[test]
+    fn constraint_violations_are_told_apart() {
+        let unique = failing_with("23505");
+        assert_eq!(
+            reported(&unique),
+            (StatusCode::CONFLICT, "unique_violation")
+        );
+        assert_eq!(
+            unique.message(),
+            "Failed to save diagram: a row with the same key already exists"
+        );
+        assert_eq!(
+            unique.details(),
+            Some(json!({ "constraint": "tables_pkey" }))
+        );
+
+        let cases = [
+            ("23503", StatusCode::CONFLICT, "foreign_key_violation"),
+            ("23502", StatusCode::BAD_REQUEST, "not_null_violation"),
+            ("23514", StatusCode::BAD_REQUEST, "check_violation"),
+            ("22001", StatusCode::BAD_REQUEST, "invalid_value"),
+            ("08006", StatusCode::SERVICE_UNAVAILABLE, "db_unavailable"),
+            ("57P01", StatusCode::SERVICE_UNAVAILABLE, "db_unavailable"),
+        ];
+        for (code, status, name) in cases {
+            assert_eq!(reported(&failing_with(code)), (status, name), "{}", code);
+        }
+    }
+
+    #[test]
+    fn other_database_failures_are_internal() {
+        for error in [failing_with("42P01"), database(sqlx::Error::RowNotFound)] {
+            assert_eq!(
+                reported(&error),
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+            );
+            // Only what was being done reaches the client
+            assert_eq!(error.message(), "Failed to save diagram");
+        }
+        assert_eq!(
+            reported(&database(sqlx::Error::PoolTimedOut)),
+            (StatusCode::SERVICE_UNAVAILABLE, "db_unavailable")
+        );
+    }
+
+    #[test]
+    fn missing_things_are_not_found() {
+        let missing = [
+            AppError::DiagramNotFound,
+            AppError::RevisionNotFound {
+                diagram_id: "d1".to_string(),
+                version: 3,
+            },
+            AppError::NotFound("Area a1 not found".to_string()),
+        ];
+        let codes: Vec<_> = missing.iter().map(reported).collect();
+        assert_eq!(
+            codes,
+            [
+                (StatusCode::NOT_FOUND, "diagram_not_found"),
+                (StatusCode::NOT_FOUND, "revision_not_found"),
+                (StatusCode::NOT_FOUND, "not_found"),
+            ]
+        );
+        assert_eq!(missing[1].message(), "Revision 3 of diagram d1 not found");
+    }
+
+    #[test]
+    fn upstream_failures_hide_the_driver_message() {
+        let error = AppError::ConnectionFailed(sqlx::Error::Database(Box::new(Sqlstate("28P01"))));
+        assert_eq!(
+            reported(&error),
+            (StatusCode::BAD_REQUEST, "connection_failed")
+        );
+        assert_eq!(error.message(), "Could not connect to the database");
+        assert_eq!(error.details(), Some(json!({ "sqlstate": "28P01" })));
+    }
+}
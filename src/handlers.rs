@@ -1,621 +1,2030 @@
-use crate::models::{Diagram, ErrorResponse, PushRequest, PushResponse};
-use axum::{extract::Path, extract::State, http::StatusCode, response::Json};
-use sqlx::{PgPool, Row};
+use crate::db::{self, Cursor, Entity, EntityKind};
+use crate::ddl::import::ImportError;
+use crate::ddl::{self, export, migration, Dialect};
+use crate::error::{db_error, AppError};
+use crate::extract::{Json, OptionalJson, Path, Query};
+use crate::introspect;
+use crate::models::{
+    new_id, ChangesQuery, ChangesResponse, DbmlImportRequest, DeltaPushRequest, Diagram,
+    DiagramPatch, DictionaryQuery, DiffQuery, DiffResponse, DiffSide, DriftRequest, DriftResponse,
+    DuplicateRequest, EntityChanges, EntityQuery, EntityResponse, ErdQuery, ExportQuery,
+    IntrospectRequest, LintResponse, LintRulesRequest, LintRulesResponse, MigrationQuery,
+    MigrationResponse, OrmQuery, PushRequest, PushResponse, RenderQuery, RestoreRequest, Revision,
+    RevisionSummary, SqlImportRequest, SqlImportResponse,
+};
+use crate::{codegen, dbml, dictionary, diff, drift, duplicate, erd, lint, merge, validate};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sqlx::{PgConnection, PgPool};
 
-pub async fn push_diagram(
-    State(pool): State<PgPool>,
-    Json(payload): Json<PushRequest>,
-) -> Result<Json<PushResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let diagram = payload.diagram;
+async fn check_foreign_ids(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    ids: &[(EntityKind, Vec<&str>)],
+) -> Result<(), AppError> {
+    let taken = db::foreign_ids(conn, diagram_id, ids)
+        .await
+        .map_err(db_error("Failed to check entity ids"))?;
+    if !taken.is_empty() {
+        return Err(AppError::IdsTaken(taken));
+    }
+    Ok(())
+}
+
+/// Rejects a change that would leave dangling references behind, before
+/// anything is written. Problems `before` already has are not the change's
+/// doing and do not block it.
+fn check_integrity(before: Option<&Diagram>, after: &Diagram) -> Result<(), AppError> {
+    let problems = validate::introduced(before, after);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::ValidationFailed(problems))
+}
 
-    let mut tx = pool
-        .begin()
+/// [`check_integrity`] against the stored copy of the diagram, which is only
+/// loaded if there are problems to compare.
+async fn check_stored_integrity(
+    conn: &mut PgConnection,
+    diagram: &Diagram,
+) -> Result<(), AppError> {
+    if validate::diagram(diagram).is_empty() {
+        return Ok(());
+    }
+    let stored = db::load_diagram(conn, &diagram.id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Database error: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to load diagram"))?;
+    check_integrity(stored.as_ref(), diagram)
+}
 
-    // Upsert diagram
-    sqlx::query(
-        r#"
-        INSERT INTO diagrams (id, name, database_type, database_edition, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, NOW())
-        ON CONFLICT (id) DO UPDATE SET
-            name = EXCLUDED.name,
-            database_type = EXCLUDED.database_type,
-            database_edition = EXCLUDED.database_edition,
-            updated_at = NOW()
-        "#,
-    )
-    .bind(&diagram.id)
-    .bind(&diagram.name)
-    .bind(&diagram.database_type)
-    .bind(&diagram.database_edition)
-    .bind(diagram.created_at)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("Failed to insert diagram: {}", e),
-            }),
-        )
-    })?;
+/// The version a push was made against when the server copy has moved on
+/// since; `None` when it is current or the client gave none.
+fn stale_base(base: Option<i32>, current: i32) -> Option<i32> {
+    base.filter(|&base| base != current)
+}
 
-    // Delete old data
-    sqlx::query("DELETE FROM db_tables WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+/// Loads the revision a stale push was made against, to use as the common
+/// ancestor for merging.
+async fn load_base_revision(
+    conn: &mut PgConnection,
+    diagram_id: &str,
+    base: i32,
+    current: i32,
+) -> Result<Diagram, AppError> {
+    db::load_revision(conn, diagram_id, base)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete tables: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to load base revision"))?
+        .ok_or(AppError::VersionConflict {
+            base,
+            current,
+            reason: "that revision is not available to merge against",
+        })
+}
 
-    sqlx::query("DELETE FROM db_relationships WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+/// Merges the client's copy of a diagram with the changes made on the server
+/// since `base`, failing with the list of conflicts if they overlap.
+async fn merge_with_server(
+    conn: &mut PgConnection,
+    base: &Diagram,
+    client: &Diagram,
+    current: i32,
+) -> Result<Diagram, AppError> {
+    let server = db::load_diagram(conn, &client.id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete relationships: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to load diagram"))?
+        .ok_or(AppError::DiagramNotFound)?;
 
-    sqlx::query("DELETE FROM db_dependencies WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+    let outcome = merge::three_way(base, &server, client)
+        .map_err(|e| AppError::internal("Failed to merge diagram", e))?;
+
+    if !outcome.conflicts.is_empty() {
+        return Err(AppError::MergeConflict {
+            base: base.version.unwrap_or_default(),
+            current,
+            conflicts: outcome.conflicts,
+        });
+    }
+
+    Ok(outcome.diagram)
+}
+
+/// Writes a full diagram and records the resulting revision: the storage
+/// path of full pushes and imports.
+async fn store_diagram(
+    conn: &mut PgConnection,
+    diagram: &Diagram,
+    author: Option<&str>,
+    message: Option<&str>,
+) -> Result<i32, AppError> {
+    check_stored_integrity(conn, diagram).await?;
+    save_diagram(conn, diagram, author, message).await
+}
+
+/// [`store_diagram`] without the integrity check, for copies of a stored
+/// diagram, which can only bring along the problems it already has.
+async fn save_diagram(
+    conn: &mut PgConnection,
+    diagram: &Diagram,
+    author: Option<&str>,
+    message: Option<&str>,
+) -> Result<i32, AppError> {
+    check_foreign_ids(conn, &diagram.id, &db::child_ids(diagram)).await?;
+
+    let version = db::upsert_diagram(conn, diagram)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete dependencies: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to insert diagram"))?;
 
-    sqlx::query("DELETE FROM areas WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+    db::replace_children(conn, diagram, version)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete areas: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to store diagram contents"))?;
 
-    sqlx::query("DELETE FROM db_custom_types WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+    db::save_revision(conn, &diagram.id, version, author, message)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete custom types: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to save revision"))?;
 
-    sqlx::query("DELETE FROM notes WHERE diagram_id = $1")
-        .bind(&diagram.id)
-        .execute(&mut *tx)
+    Ok(version)
+}
+
+/// Applies a delta to the current state of a diagram in place, after
+/// checking what it would break. Returns the new version.
+async fn write_changes(
+    conn: &mut PgConnection,
+    current: &Diagram,
+    changes: &DeltaPushRequest,
+) -> Result<i32, AppError> {
+    check_integrity(Some(current), &merge::apply_delta(current, changes))?;
+    check_foreign_ids(conn, &changes.diagram_id, &db::upserted_ids(changes)).await?;
+    let version = db::touch_diagram(conn, changes)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to delete notes: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to update diagram"))?;
+    db::apply_changes(conn, changes, version)
+        .await
+        .map_err(db_error("Failed to apply changes"))?;
+    Ok(version)
+}
 
-    // Insert tables
-    if let Some(tables) = diagram.tables {
-        for table in tables {
-            sqlx::query(
-                r#"
-                INSERT INTO db_tables (
-                    id, diagram_id, name, schema, x, y, width, color, comment,
-                    is_view, is_materialized_view, "order", fields, indexes
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-                "#,
-            )
-            .bind(&table.id)
-            .bind(&table.diagram_id)
-            .bind(&table.name)
-            .bind(&table.schema)
-            .bind(table.x)
-            .bind(table.y)
-            .bind(table.width)
-            .bind(&table.color)
-            .bind(&table.comment)
-            .bind(table.is_view.unwrap_or(false))
-            .bind(table.is_materialized_view.unwrap_or(false))
-            .bind(table.order)
-            .bind(&table.fields)
-            .bind(&table.indexes)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert table: {}", e),
-                    }),
-                )
-            })?;
-        }
-    }
+pub async fn push_diagram(
+    State(pool): State<PgPool>,
+    Json(payload): Json<PushRequest>,
+) -> Result<Json<PushResponse>, AppError> {
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
 
-    // Insert relationships
-    if let Some(relationships) = diagram.relationships {
-        for rel in relationships {
-            sqlx::query(
-                r#"
-                INSERT INTO db_relationships (
-                    id, diagram_id, name, source_schema, source_table_id,
-                    target_schema, target_table_id, source_field_id, target_field_id,
-                    source_cardinality, target_cardinality
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
-                "#,
-            )
-            .bind(&rel.id)
-            .bind(&rel.diagram_id)
-            .bind(&rel.name)
-            .bind(&rel.source_schema)
-            .bind(&rel.source_table_id)
-            .bind(&rel.target_schema)
-            .bind(&rel.target_table_id)
-            .bind(&rel.source_field_id)
-            .bind(&rel.target_field_id)
-            .bind(&rel.source_cardinality)
-            .bind(&rel.target_cardinality)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert relationship: {}", e),
-                    }),
-                )
-            })?;
-        }
-    }
+    let current_version = db::lock_diagram_version(&mut tx, &payload.diagram.id)
+        .await
+        .map_err(db_error("Failed to read diagram version"))?;
 
-    // Insert dependencies
-    if let Some(dependencies) = diagram.dependencies {
-        for dep in dependencies {
-            sqlx::query(
-                r#"
-                INSERT INTO db_dependencies (
-                    id, diagram_id, schema, table_id, dependent_schema, dependent_table_id
-                ) VALUES ($1, $2, $3, $4, $5, $6)
-                "#,
-            )
-            .bind(&dep.id)
-            .bind(&dep.diagram_id)
-            .bind(&dep.schema)
-            .bind(&dep.table_id)
-            .bind(&dep.dependent_schema)
-            .bind(&dep.dependent_table_id)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert dependency: {}", e),
-                    }),
-                )
-            })?;
+    let stale = current_version
+        .and_then(|current| Some((stale_base(payload.base_version, current)?, current)));
+    let (diagram, merged) = match stale {
+        Some((base, current)) => {
+            let base = load_base_revision(&mut tx, &payload.diagram.id, base, current).await?;
+            let diagram = merge_with_server(&mut tx, &base, &payload.diagram, current).await?;
+            (diagram, true)
         }
-    }
+        None => (payload.diagram, false),
+    };
 
-    // Insert areas
-    if let Some(areas) = diagram.areas {
-        for area in areas {
-            sqlx::query(
-                r#"
-                INSERT INTO areas (
-                    id, diagram_id, name, x, y, width, height, color
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-                "#,
-            )
-            .bind(&area.id)
-            .bind(&area.diagram_id)
-            .bind(&area.name)
-            .bind(area.x)
-            .bind(area.y)
-            .bind(area.width)
-            .bind(area.height)
-            .bind(&area.color)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert area: {}", e),
-                    }),
-                )
-            })?;
-        }
-    }
+    let version = store_diagram(
+        &mut tx,
+        &diagram,
+        payload.author.as_deref(),
+        payload.message.as_deref(),
+    )
+    .await?;
 
-    // Insert custom types
-    if let Some(custom_types) = diagram.custom_types {
-        for ct in custom_types {
-            sqlx::query(
-                r#"
-                INSERT INTO db_custom_types (
-                    id, diagram_id, schema, type, kind, values, fields
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7)
-                "#,
-            )
-            .bind(&ct.id)
-            .bind(&ct.diagram_id)
-            .bind(&ct.schema)
-            .bind(&ct.r#type)
-            .bind(&ct.kind)
-            .bind(&ct.values)
-            .bind(&ct.fields)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert custom type: {}", e),
-                    }),
-                )
-            })?;
-        }
-    }
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
 
-    // Insert notes
-    if let Some(notes) = diagram.notes {
-        for note in notes {
-            sqlx::query(
-                r#"
-                INSERT INTO notes (
-                    id, diagram_id, content, x, y, width, height, color
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-                "#,
-            )
-            .bind(&note.id)
-            .bind(&note.diagram_id)
-            .bind(&note.content)
-            .bind(note.x)
-            .bind(note.y)
-            .bind(note.width)
-            .bind(note.height)
-            .bind(&note.color)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: format!("Failed to insert note: {}", e),
-                    }),
-                )
-            })?;
+    Ok(Json(PushResponse {
+        success: true,
+        diagram_id: diagram.id,
+        version,
+        merged,
+    }))
+}
+
+pub async fn push_changes(
+    State(pool): State<PgPool>,
+    Json(payload): Json<DeltaPushRequest>,
+) -> Result<Json<PushResponse>, AppError> {
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+
+    let current_version = db::lock_diagram_version(&mut tx, &payload.diagram_id)
+        .await
+        .map_err(db_error("Failed to read diagram version"))?
+        .ok_or(AppError::DiagramNotFound)?;
+
+    let (version, merged) = match stale_base(payload.base_version, current_version) {
+        Some(base) => {
+            // Replay the delta on the revision it was made against, then merge
+            // the result like a stale full push
+            let base =
+                load_base_revision(&mut tx, &payload.diagram_id, base, current_version).await?;
+            let client = merge::apply_delta(&base, &payload);
+            let diagram = merge_with_server(&mut tx, &base, &client, current_version).await?;
+            check_stored_integrity(&mut tx, &diagram).await?;
+            check_foreign_ids(&mut tx, &diagram.id, &db::child_ids(&diagram)).await?;
+
+            let version = db::upsert_diagram(&mut tx, &diagram)
+                .await
+                .map_err(db_error("Failed to update diagram"))?;
+            db::replace_children(&mut tx, &diagram, version)
+                .await
+                .map_err(db_error("Failed to store diagram contents"))?;
+            (version, true)
         }
-    }
+        None => {
+            let current = load_diagram_at(&mut tx, &payload.diagram_id, None).await?;
+            (write_changes(&mut tx, &current, &payload).await?, false)
+        }
+    };
+
+    db::save_revision(
+        &mut tx,
+        &payload.diagram_id,
+        version,
+        payload.author.as_deref(),
+        payload.message.as_deref(),
+    )
+    .await
+    .map_err(db_error("Failed to save revision"))?;
 
     tx.commit()
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to commit transaction: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to commit transaction"))?;
 
     Ok(Json(PushResponse {
         success: true,
-        diagram_id: diagram.id,
+        diagram_id: payload.diagram_id,
+        version,
+        merged,
     }))
 }
 
 pub async fn pull_diagram(
     State(pool): State<PgPool>,
     Path(id): Path<String>,
-) -> Result<Json<Diagram>, (StatusCode, Json<ErrorResponse>)> {
-    // Get diagram (ID is now TEXT, not UUID)
-    let diagram_row = sqlx::query("SELECT * FROM diagrams WHERE id = $1")
-        .bind(&id)
-        .fetch_optional(&pool)
+) -> Result<Json<Diagram>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    match db::load_diagram(&mut conn, &id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Database error: {}", e),
-                }),
+        .map_err(db_error("Failed to fetch diagram"))?
+    {
+        Some(diagram) => Ok(Json(diagram)),
+        None => Err(AppError::DiagramNotFound),
+    }
+}
+
+fn parse_cursor(since: Option<&str>) -> Result<Cursor, AppError> {
+    let since = match since {
+        Some(since) => since,
+        None => return Ok(Cursor::Version(0)),
+    };
+    if let Ok(version) = since.parse::<i32>() {
+        return Ok(Cursor::Version(version));
+    }
+    chrono::DateTime::parse_from_rfc3339(since)
+        .map(|at| Cursor::Timestamp(at.with_timezone(&chrono::Utc)))
+        .map_err(|_| {
+            AppError::BadRequest(
+                "`since` must be a diagram version or an RFC 3339 timestamp".to_string(),
             )
-        })?;
+        })
+}
 
-    let diagram_row = match diagram_row {
-        Some(row) => row,
-        None => {
-            return Err((
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "Diagram not found".to_string(),
-                }),
-            ))
-        }
+pub async fn pull_changes(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ChangesResponse>, AppError> {
+    let since = parse_cursor(query.since.as_deref())?;
+
+    // Read everything from one snapshot so the returned version matches the rows
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error("Database error"))?;
+
+    let diagram_row = sqlx::query("SELECT * FROM diagrams WHERE id = $1")
+        .bind(&id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error("Database error"))?;
+    let diagram = match diagram_row {
+        Some(row) => db::diagram_from_row(&row),
+        None => return Err(AppError::DiagramNotFound),
     };
 
-    let mut diagram = Diagram {
-        id: diagram_row.get("id"),
-        name: diagram_row.get("name"),
-        database_type: diagram_row.get("database_type"),
-        database_edition: diagram_row.get("database_edition"),
-        created_at: diagram_row.get("created_at"),
-        updated_at: diagram_row.get("updated_at"),
-        tables: None,
-        relationships: None,
-        dependencies: None,
-        areas: None,
-        custom_types: None,
-        notes: None,
+    let mut changes = ChangesResponse {
+        diagram_id: diagram.id,
+        version: diagram.version.unwrap_or(1),
+        name: diagram.name,
+        database_type: diagram.database_type,
+        database_edition: diagram.database_edition,
+        updated_at: diagram.updated_at,
+        tables: EntityChanges::default(),
+        relationships: EntityChanges::default(),
+        dependencies: EntityChanges::default(),
+        areas: EntityChanges::default(),
+        custom_types: EntityChanges::default(),
+        notes: EntityChanges::default(),
     };
 
-    // Get tables
-    let table_rows = sqlx::query("SELECT * FROM db_tables WHERE diagram_id = $1")
-        .bind(&id)
-        .fetch_all(&pool)
+    let rows = db::fetch_rows(&mut tx, EntityKind::Table, &id, Some(since))
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch tables: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to fetch tables"))?;
+    changes.tables.upserts = rows.iter().map(db::table_from_row).collect();
 
-    use crate::models::Table;
-    let tables: Vec<Table> = table_rows
-        .into_iter()
-        .map(|row| Table {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            name: row.get("name"),
-            schema: row.get("schema"),
-            x: row.get("x"),
-            y: row.get("y"),
-            width: row.get("width"),
-            color: row.get("color"),
-            comment: row.get("comment"),
-            is_view: row.get("is_view"),
-            is_materialized_view: row.get("is_materialized_view"),
-            order: row.get("order"),
-            fields: row.get("fields"),
-            indexes: row.get("indexes"),
-        })
-        .collect();
+    let rows = db::fetch_rows(&mut tx, EntityKind::Relationship, &id, Some(since))
+        .await
+        .map_err(db_error("Failed to fetch relationships"))?;
+    changes.relationships.upserts = rows.iter().map(db::relationship_from_row).collect();
 
-    diagram.tables = Some(tables);
+    let rows = db::fetch_rows(&mut tx, EntityKind::Dependency, &id, Some(since))
+        .await
+        .map_err(db_error("Failed to fetch dependencies"))?;
+    changes.dependencies.upserts = rows.iter().map(db::dependency_from_row).collect();
 
-    // Get relationships
-    let rel_rows = sqlx::query("SELECT * FROM db_relationships WHERE diagram_id = $1")
-        .bind(&id)
-        .fetch_all(&pool)
+    let rows = db::fetch_rows(&mut tx, EntityKind::Area, &id, Some(since))
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch relationships: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to fetch areas"))?;
+    changes.areas.upserts = rows.iter().map(db::area_from_row).collect();
 
-    use crate::models::Relationship;
-    let relationships: Vec<Relationship> = rel_rows
-        .into_iter()
-        .map(|row| Relationship {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            name: row.get("name"),
-            source_schema: row.get("source_schema"),
-            source_table_id: row.get("source_table_id"),
-            target_schema: row.get("target_schema"),
-            target_table_id: row.get("target_table_id"),
-            source_field_id: row.get("source_field_id"),
-            target_field_id: row.get("target_field_id"),
-            source_cardinality: row.get("source_cardinality"),
-            target_cardinality: row.get("target_cardinality"),
-        })
-        .collect();
+    let rows = db::fetch_rows(&mut tx, EntityKind::CustomType, &id, Some(since))
+        .await
+        .map_err(db_error("Failed to fetch custom types"))?;
+    changes.custom_types.upserts = rows.iter().map(db::custom_type_from_row).collect();
 
-    diagram.relationships = Some(relationships);
+    let rows = db::fetch_rows(&mut tx, EntityKind::Note, &id, Some(since))
+        .await
+        .map_err(db_error("Failed to fetch notes"))?;
+    changes.notes.upserts = rows.iter().map(db::note_from_row).collect();
 
-    // Get dependencies
-    let dep_rows = sqlx::query("SELECT * FROM db_dependencies WHERE diagram_id = $1")
-        .bind(&id)
-        .fetch_all(&pool)
+    let tombstones = db::fetch_tombstones(&mut tx, &id, since)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch dependencies: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Failed to fetch deleted entities"))?;
 
-    use crate::models::Dependency;
-    let dependencies: Vec<Dependency> = dep_rows
-        .into_iter()
-        .map(|row| Dependency {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            schema: row.get("schema"),
-            table_id: row.get("table_id"),
-            dependent_schema: row.get("dependent_schema"),
-            dependent_table_id: row.get("dependent_table_id"),
-        })
-        .collect();
+    for (kind, entity_id) in tombstones {
+        // An id that was deleted and later recreated shows up as an upsert only
+        let recreated = match kind {
+            EntityKind::Table => changes.tables.upserts.iter().any(|e| e.id == entity_id),
+            EntityKind::Relationship => changes
+                .relationships
+                .upserts
+                .iter()
+                .any(|e| e.id == entity_id),
+            EntityKind::Dependency => changes
+                .dependencies
+                .upserts
+                .iter()
+                .any(|e| e.id == entity_id),
+            EntityKind::Area => changes.areas.upserts.iter().any(|e| e.id == entity_id),
+            EntityKind::CustomType => changes
+                .custom_types
+                .upserts
+                .iter()
+                .any(|e| e.id == entity_id),
+            EntityKind::Note => changes.notes.upserts.iter().any(|e| e.id == entity_id),
+        };
+        if recreated {
+            continue;
+        }
+        let deletes = match kind {
+            EntityKind::Table => &mut changes.tables.deletes,
+            EntityKind::Relationship => &mut changes.relationships.deletes,
+            EntityKind::Dependency => &mut changes.dependencies.deletes,
+            EntityKind::Area => &mut changes.areas.deletes,
+            EntityKind::CustomType => &mut changes.custom_types.deletes,
+            EntityKind::Note => &mut changes.notes.deletes,
+        };
+        if !deletes.contains(&entity_id) {
+            deletes.push(entity_id);
+        }
+    }
+
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
 
-    diagram.dependencies = Some(dependencies);
+    Ok(Json(changes))
+}
 
-    // Get areas
-    let area_rows = sqlx::query("SELECT * FROM areas WHERE diagram_id = $1")
-        .bind(&id)
+pub async fn list_diagrams(State(pool): State<PgPool>) -> Result<Json<Vec<Diagram>>, AppError> {
+    let diagram_rows = sqlx::query("SELECT * FROM diagrams ORDER BY updated_at DESC")
         .fetch_all(&pool)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch areas: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Database error"))?;
 
-    use crate::models::Area;
-    let areas: Vec<Area> = area_rows
-        .into_iter()
-        .map(|row| Area {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            name: row.get("name"),
-            x: row.get("x"),
-            y: row.get("y"),
-            width: row.get("width"),
-            height: row.get("height"),
-            color: row.get("color"),
-        })
-        .collect();
+    // Children are left out of the list view
+    let diagrams: Vec<Diagram> = diagram_rows.iter().map(db::diagram_from_row).collect();
 
-    diagram.areas = Some(areas);
+    Ok(Json(diagrams))
+}
 
-    // Get custom types
-    let ct_rows = sqlx::query("SELECT * FROM db_custom_types WHERE diagram_id = $1")
-        .bind(&id)
-        .fetch_all(&pool)
+pub async fn list_revisions(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<RevisionSummary>>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    let exists = db::diagram_exists(&mut conn, &id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch custom types: {}", e),
-                }),
-            )
-        })?;
+        .map_err(db_error("Database error"))?;
+    if !exists {
+        return Err(AppError::DiagramNotFound);
+    }
+
+    let revisions = db::list_revisions(&mut conn, &id)
+        .await
+        .map_err(db_error("Failed to fetch revisions"))?;
+
+    Ok(Json(revisions))
+}
+
+pub async fn get_revision(
+    State(pool): State<PgPool>,
+    Path((id, version)): Path<(String, i32)>,
+) -> Result<Json<Revision>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
 
-    use crate::models::CustomType;
-    let custom_types: Vec<CustomType> = ct_rows
-        .into_iter()
-        .map(|row| CustomType {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            schema: row.get("schema"),
-            r#type: row.get("type"),
-            kind: row.get("kind"),
-            values: row.get("values"),
-            fields: row.get("fields"),
+    db::fetch_revision(&mut conn, &id, version)
+        .await
+        .map_err(db_error("Failed to fetch revision"))?
+        .map(Json)
+        .ok_or(AppError::RevisionNotFound {
+            diagram_id: id.clone(),
+            version,
         })
-        .collect();
+}
 
-    diagram.custom_types = Some(custom_types);
+/// Makes the state of an old revision the current state of the diagram, as a
+/// new revision on top of the history.
+pub async fn restore_revision(
+    State(pool): State<PgPool>,
+    Path((id, version)): Path<(String, i32)>,
+    OptionalJson(payload): OptionalJson<RestoreRequest>,
+) -> Result<Json<PushResponse>, AppError> {
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
 
-    // Get notes
-    let note_rows = sqlx::query("SELECT * FROM notes WHERE diagram_id = $1")
-        .bind(&id)
-        .fetch_all(&pool)
+    db::lock_diagram_version(&mut tx, &id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to fetch notes: {}", e),
-                }),
-            )
+        .map_err(db_error("Failed to read diagram version"))?
+        .ok_or(AppError::DiagramNotFound)?;
+
+    let diagram = db::load_revision(&mut tx, &id, version)
+        .await
+        .map_err(db_error("Failed to load revision"))?
+        .ok_or(AppError::RevisionNotFound {
+            diagram_id: id.clone(),
+            version,
         })?;
 
-    use crate::models::Note;
-    let notes: Vec<Note> = note_rows
-        .into_iter()
-        .map(|row| Note {
-            id: row.get("id"),
-            diagram_id: row.get("diagram_id"),
-            content: row.get("content"),
-            x: row.get("x"),
-            y: row.get("y"),
-            width: row.get("width"),
-            height: row.get("height"),
-            color: row.get("color"),
-        })
-        .collect();
+    check_foreign_ids(&mut tx, &diagram.id, &db::child_ids(&diagram)).await?;
+
+    let new_version = db::upsert_diagram(&mut tx, &diagram)
+        .await
+        .map_err(db_error("Failed to update diagram"))?;
+
+    db::replace_children(&mut tx, &diagram, new_version)
+        .await
+        .map_err(db_error("Failed to store diagram contents"))?;
+
+    let message = payload
+        .message
+        .unwrap_or_else(|| format!("Restored revision {}", version));
+    db::save_revision(
+        &mut tx,
+        &id,
+        new_version,
+        payload.author.as_deref(),
+        Some(&message),
+    )
+    .await
+    .map_err(db_error("Failed to save revision"))?;
 
-    diagram.notes = Some(notes);
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
 
-    Ok(Json(diagram))
+    Ok(Json(PushResponse {
+        success: true,
+        diagram_id: id,
+        version: new_version,
+        merged: false,
+    }))
 }
 
-pub async fn list_diagrams(
+/// Deletes a diagram with everything that belongs to it, history included.
+pub async fn delete_diagram(
     State(pool): State<PgPool>,
-) -> Result<Json<Vec<Diagram>>, (StatusCode, Json<ErrorResponse>)> {
-    let diagram_rows = sqlx::query("SELECT * FROM diagrams ORDER BY updated_at DESC")
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Database error: {}", e),
-                }),
-            )
-        })?;
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
 
-    let diagrams: Vec<Diagram> = diagram_rows
-        .into_iter()
-        .map(|row| Diagram {
-            id: row.get("id"),
-            name: row.get("name"),
-            database_type: row.get("database_type"),
-            database_edition: row.get("database_edition"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            tables: None, // Don't include tables in list view
-            relationships: None,
-            dependencies: None,
-            areas: None,
-            custom_types: None,
-            notes: None,
-        })
-        .collect();
+    let deleted = db::delete_diagram(&mut conn, &id)
+        .await
+        .map_err(db_error("Failed to delete diagram"))?;
+    if !deleted {
+        return Err(AppError::DiagramNotFound);
+    }
 
-    Ok(Json(diagrams))
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn health() -> &'static str {
-    "ok"
+/// Renames a diagram or changes its database type, as a new revision.
+pub async fn update_diagram(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<DiagramPatch>,
+) -> Result<Json<PushResponse>, AppError> {
+    if payload.name.is_none()
+        && payload.database_type.is_none()
+        && payload.database_edition.is_none()
+    {
+        return Err(AppError::BadRequest(
+            "Specify a `name`, `databaseType` or `databaseEdition` to change".to_string(),
+        ));
+    }
+    if payload.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+        return Err(AppError::BadRequest("`name` must not be empty".to_string()));
+    }
+
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+
+    let version = db::update_diagram_details(&mut tx, &id, &payload)
+        .await
+        .map_err(db_error("Failed to update diagram"))?
+        .ok_or(AppError::DiagramNotFound)?;
+
+    let message = payload
+        .message
+        .as_deref()
+        .unwrap_or("Updated diagram details");
+    db::save_revision(
+        &mut tx,
+        &id,
+        version,
+        payload.author.as_deref(),
+        Some(message),
+    )
+    .await
+    .map_err(db_error("Failed to save revision"))?;
+
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok(Json(PushResponse {
+        success: true,
+        diagram_id: id,
+        version,
+        merged: false,
+    }))
 }
 
+/// Copies the current state of a diagram, and its lint settings, into a new
+/// diagram with fresh ids.
+pub async fn duplicate_diagram(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    OptionalJson(payload): OptionalJson<DuplicateRequest>,
+) -> Result<(StatusCode, Json<PushResponse>), AppError> {
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    let original = load_diagram_at(&mut tx, &id, None).await?;
+
+    let name = payload
+        .name
+        .unwrap_or_else(|| format!("{} (copy)", original.name));
+    let copy = duplicate::duplicate(&original, &name);
+
+    let message = payload.message.unwrap_or_else(|| {
+        format!(
+            "Duplicated from {} at version {}",
+            original.id,
+            original.version.unwrap_or(1)
+        )
+    });
+    let version = save_diagram(&mut tx, &copy, payload.author.as_deref(), Some(&message)).await?;
+    db::copy_lint_overrides(&mut tx, &original.id, &copy.id)
+        .await
+        .map_err(db_error("Failed to copy lint rules"))?;
+
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PushResponse {
+            success: true,
+            diagram_id: copy.id,
+            version,
+            merged: false,
+        }),
+    ))
+}
+
+pub async fn list_entities<T: Entity>(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<T>>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    let exists = db::diagram_exists(&mut conn, &id)
+        .await
+        .map_err(db_error("Database error"))?;
+    if !exists {
+        return Err(AppError::DiagramNotFound);
+    }
+
+    let rows = db::fetch_rows(&mut conn, T::KIND, &id, None)
+        .await
+        .map_err(db_error("Failed to fetch entities"))?;
+    Ok(Json(rows.iter().map(T::from_row).collect()))
+}
+
+pub async fn get_entity<T: Entity>(
+    State(pool): State<PgPool>,
+    Path((id, entity_id)): Path<(String, String)>,
+) -> Result<Json<T>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    let exists = db::diagram_exists(&mut conn, &id)
+        .await
+        .map_err(db_error("Database error"))?;
+    if !exists {
+        return Err(AppError::DiagramNotFound);
+    }
+
+    db::fetch_row(&mut conn, T::KIND, &id, &entity_id)
+        .await
+        .map_err(db_error("Failed to fetch entity"))?
+        .map(|row| Json(T::from_row(&row)))
+        .ok_or_else(|| entity_not_found::<T>(&entity_id))
+}
+
+/// Adds an entity to a diagram. Without an `id` in the body it gets a fresh
+/// one.
+pub async fn create_entity<T: Entity>(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<EntityQuery>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<EntityResponse<T>>), AppError> {
+    let entity: T = entity_from_body(body, &id, None)?;
+    let version = write_entity(&pool, &id, EntityWrite::Create(&entity), query).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(EntityResponse {
+            diagram_id: id,
+            version,
+            entity,
+        }),
+    ))
+}
+
+/// Replaces an entity of a diagram with the request body.
+pub async fn replace_entity<T: Entity>(
+    State(pool): State<PgPool>,
+    Path((id, entity_id)): Path<(String, String)>,
+    Query(query): Query<EntityQuery>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<EntityResponse<T>>, AppError> {
+    let entity: T = entity_from_body(body, &id, Some(&entity_id))?;
+    let version = write_entity(&pool, &id, EntityWrite::Replace(&entity), query).await?;
+
+    Ok(Json(EntityResponse {
+        diagram_id: id,
+        version,
+        entity,
+    }))
+}
+
+pub async fn delete_entity<T: Entity>(
+    State(pool): State<PgPool>,
+    Path((id, entity_id)): Path<(String, String)>,
+    Query(query): Query<EntityQuery>,
+) -> Result<Json<PushResponse>, AppError> {
+    let version = write_entity::<T>(&pool, &id, EntityWrite::Delete(&entity_id), query).await?;
+
+    Ok(Json(PushResponse {
+        success: true,
+        diagram_id: id,
+        version,
+        merged: false,
+    }))
+}
+
+enum EntityWrite<'a, T> {
+    Create(&'a T),
+    Replace(&'a T),
+    Delete(&'a str),
+}
+
+/// Writes a single entity as a delta push of its own, so that it is checked,
+/// logged for incremental pulls and recorded as a revision like one.
+async fn write_entity<T: Entity>(
+    pool: &PgPool,
+    diagram_id: &str,
+    write: EntityWrite<'_, T>,
+    query: EntityQuery,
+) -> Result<i32, AppError> {
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+
+    db::lock_diagram_version(&mut tx, diagram_id)
+        .await
+        .map_err(db_error("Failed to read diagram version"))?
+        .ok_or(AppError::DiagramNotFound)?;
+    let current = load_diagram_at(&mut tx, diagram_id, None).await?;
+
+    let (entity_id, verb) = match write {
+        EntityWrite::Create(entity) => (entity.id(), "Added"),
+        EntityWrite::Replace(entity) => (entity.id(), "Updated"),
+        EntityWrite::Delete(entity_id) => (entity_id, "Deleted"),
+    };
+    let exists = T::all(&current).iter().any(|e| e.id() == entity_id);
+    let mut changes = DeltaPushRequest {
+        diagram_id: diagram_id.to_string(),
+        ..Default::default()
+    };
+    match write {
+        EntityWrite::Create(_) if exists => {
+            return Err(AppError::AlreadyExists(format!(
+                "The diagram already has a {} `{}`",
+                T::KIND.label(),
+                entity_id
+            )))
+        }
+        EntityWrite::Replace(_) | EntityWrite::Delete(_) if !exists => {
+            return Err(entity_not_found::<T>(entity_id))
+        }
+        EntityWrite::Create(entity) | EntityWrite::Replace(entity) => {
+            T::changes(&mut changes).upserts.push(entity.clone())
+        }
+        EntityWrite::Delete(entity_id) => {
+            T::changes(&mut changes).deletes.push(entity_id.to_string())
+        }
+    }
+
+    let version = write_changes(&mut tx, &current, &changes).await?;
+
+    let message = query
+        .message
+        .unwrap_or_else(|| format!("{} {} {}", verb, T::KIND.label(), entity_id));
+    db::save_revision(
+        &mut tx,
+        diagram_id,
+        version,
+        query.author.as_deref(),
+        Some(&message),
+    )
+    .await
+    .map_err(db_error("Failed to save revision"))?;
+
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok(version)
+}
+
+/// Reads an entity from a request body. Its diagram comes from the path, and
+/// so does its id when the path names one; ids in the body must agree.
+fn entity_from_body<T: Entity>(
+    mut body: serde_json::Value,
+    diagram_id: &str,
+    entity_id: Option<&str>,
+) -> Result<T, AppError> {
+    let label = T::KIND.label();
+    let object = body
+        .as_object_mut()
+        .ok_or_else(|| AppError::BadRequest("The body must be a JSON object".to_string()))?;
+
+    let given = |key: &str| {
+        object
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    if let Some(other) = given("diagramId").filter(|other| other != diagram_id) {
+        return Err(AppError::BadRequest(format!(
+            "The {} belongs to diagram `{}`, not `{}`",
+            label, other, diagram_id
+        )));
+    }
+    let id = match (entity_id, given("id")) {
+        (Some(path), Some(other)) if other != path => {
+            return Err(AppError::BadRequest(format!(
+                "The {} id `{}` does not match `{}` in the path",
+                label, other, path
+            )))
+        }
+        (Some(path), _) => path.to_string(),
+        (None, Some(id)) => id,
+        (None, None) => new_id(),
+    };
+    object.insert("id".to_string(), id.into());
+    object.insert("diagramId".to_string(), diagram_id.into());
+
+    serde_json::from_value(body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid {}: {}", label, e)))
+}
+
+fn entity_not_found<T: Entity>(entity_id: &str) -> AppError {
+    AppError::NotFound(format!(
+        "The diagram has no {} `{}`",
+        T::KIND.label(),
+        entity_id
+    ))
+}
+
+/// Loads a diagram either at a stored revision or in its current state.
+async fn load_diagram_at(
+    conn: &mut PgConnection,
+    id: &str,
+    version: Option<i32>,
+) -> Result<Diagram, AppError> {
+    match version {
+        Some(version) => db::load_revision(conn, id, version)
+            .await
+            .map_err(db_error("Failed to load revision"))?
+            .ok_or_else(|| AppError::RevisionNotFound {
+                diagram_id: id.to_string(),
+                version,
+            }),
+        None => db::load_diagram(conn, id)
+            .await
+            .map_err(db_error("Failed to fetch diagram"))?
+            .ok_or(AppError::DiagramNotFound),
+    }
+}
+
+pub async fn diff_diagrams(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Response, AppError> {
+    if query.from.is_none() && query.to.is_none() && query.against.is_none() {
+        return Err(AppError::BadRequest(
+            "Specify a `from` or `to` revision, or another diagram to compare `against`"
+                .to_string(),
+        ));
+    }
+
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    let other_id = query.against.unwrap_or_else(|| id.clone());
+    let old = load_diagram_at(&mut conn, &id, query.from).await?;
+    let new = load_diagram_at(&mut conn, &other_id, query.to).await?;
+
+    let diff = diff::compare(&old, &new);
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(Json(DiffResponse {
+            from: DiffSide {
+                diagram_id: old.id,
+                version: old.version,
+            },
+            to: DiffSide {
+                diagram_id: new.id,
+                version: new.version,
+            },
+            diff,
+        })
+        .into_response()),
+        Some("text") => {
+            let report = format!(
+                "Diff {} v{} -> {} v{}\n\n{}",
+                old.id,
+                old.version.unwrap_or_default(),
+                new.id,
+                new.version.unwrap_or_default(),
+                diff::to_text(&diff)
+            );
+            Ok((
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                report,
+            )
+                .into_response())
+        }
+        Some(other) => Err(AppError::BadRequest(format!(
+            "Unknown format `{}`; expected `json` or `text`",
+            other
+        ))),
+    }
+}
+
+pub async fn migration_script(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<MigrationQuery>,
+) -> Result<Json<MigrationResponse>, AppError> {
+    if query.from.is_none() && query.to.is_none() && query.against.is_none() {
+        return Err(AppError::BadRequest(
+            "Specify a `from` or `to` revision, or another diagram to migrate `against`"
+                .to_string(),
+        ));
+    }
+
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+
+    let other_id = query.against.unwrap_or_else(|| id.clone());
+    let old = load_diagram_at(&mut conn, &id, query.from).await?;
+    let new = load_diagram_at(&mut conn, &other_id, query.to).await?;
+
+    let dialect = Dialect::parse(&new.database_type).ok_or_else(|| AppError::Unsupported {
+        message: format!(
+            "Cannot generate SQL for database type `{}`",
+            new.database_type
+        ),
+        details: None,
+    })?;
+
+    let forward = migration::generate(&old, &new, dialect);
+    let rollback = query
+        .rollback
+        .then(|| migration::generate(&new, &old, dialect));
+
+    Ok(Json(MigrationResponse {
+        from: DiffSide {
+            diagram_id: old.id,
+            version: old.version,
+        },
+        to: DiffSide {
+            diagram_id: new.id,
+            version: new.version,
+        },
+        dialect,
+        forward,
+        rollback,
+    }))
+}
+
+pub async fn export_sql(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let diagram = load_diagram_at(&mut conn, &id, None).await?;
+
+    let requested = query.dialect.as_deref().unwrap_or(&diagram.database_type);
+    let dialect = Dialect::parse(requested)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown SQL dialect `{}`", requested)))?;
+
+    let sql = export::export(&diagram, dialect).map_err(|e| {
+        let export::ExportError::Unrepresentable { problems, .. } = &e;
+        AppError::Unsupported {
+            message: e.to_string(),
+            details: Some(serde_json::json!({ "unsupported": problems })),
+        }
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], sql).into_response())
+}
+
+pub async fn export_dbml(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let diagram = load_diagram_at(&mut conn, &id, None).await?;
+
+    let dbml = dbml::export(&diagram);
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], dbml).into_response())
+}
+
+/// Loads the current diagram limited to the schema and area of an ERD export.
+async fn load_erd_scope(pool: &PgPool, id: &str, query: &ErdQuery) -> Result<Diagram, AppError> {
+    let scope = erd::Scope {
+        schema: query.schema.as_deref(),
+        area: query.area.as_deref(),
+    };
+    load_scope(pool, id, &scope).await
+}
+
+async fn load_scope(pool: &PgPool, id: &str, scope: &erd::Scope<'_>) -> Result<Diagram, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let diagram = load_diagram_at(&mut conn, id, None).await?;
+    erd::select(&diagram, scope)
+        .map_err(|area| AppError::NotFound(format!("Area `{}` not found", area)))
+}
+
+fn keys_only(query: &ErdQuery) -> Result<bool, AppError> {
+    match query.fields.as_deref() {
+        None | Some("all") => Ok(false),
+        Some("keys") => Ok(true),
+        Some(other) => Err(AppError::BadRequest(format!(
+            "Unknown fields option `{}`; expected `all` or `keys`",
+            other
+        ))),
+    }
+}
+
+pub async fn export_mermaid(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ErdQuery>,
+) -> Result<Response, AppError> {
+    let keys_only = keys_only(&query)?;
+    let diagram = load_erd_scope(&pool, &id, &query).await?;
+    let mermaid = erd::mermaid(&diagram, keys_only);
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        mermaid,
+    )
+        .into_response())
+}
+
+pub async fn export_plantuml(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ErdQuery>,
+) -> Result<Response, AppError> {
+    let keys_only = keys_only(&query)?;
+    let diagram = load_erd_scope(&pool, &id, &query).await?;
+    let plantuml = erd::plantuml(&diagram, keys_only);
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        plantuml,
+    )
+        .into_response())
+}
+
+pub async fn export_dot(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ErdQuery>,
+) -> Result<Response, AppError> {
+    let keys_only = keys_only(&query)?;
+    let diagram = load_erd_scope(&pool, &id, &query).await?;
+    let dot = erd::dot(&diagram, keys_only);
+    Ok((
+        [(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")],
+        dot,
+    )
+        .into_response())
+}
+
+async fn load_render_scope(
+    pool: &PgPool,
+    id: &str,
+    query: &RenderQuery,
+) -> Result<Diagram, AppError> {
+    let scope = erd::Scope {
+        schema: query.schema.as_deref(),
+        area: query.area.as_deref(),
+    };
+    load_scope(pool, id, &scope).await
+}
+
+pub async fn render_svg(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<RenderQuery>,
+) -> Result<Response, AppError> {
+    let diagram = load_render_scope(&pool, &id, &query).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "image/svg+xml; charset=utf-8")],
+        erd::svg(&diagram),
+    )
+        .into_response())
+}
+
+pub async fn render_png(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<RenderQuery>,
+) -> Result<Response, AppError> {
+    let scale = query.scale.unwrap_or(2.0);
+    if !(scale > 0.0 && scale <= 4.0) {
+        return Err(AppError::BadRequest(
+            "scale must be greater than 0 and at most 4".to_string(),
+        ));
+    }
+    let diagram = load_render_scope(&pool, &id, &query).await?;
+    let svg = erd::svg(&diagram);
+    let png = tokio::task::spawn_blocking(move || erd::png(&svg, scale))
+        .await
+        .map_err(|e| AppError::internal("PNG rendering failed", e))?
+        .map_err(|e| match e {
+            erd::RasterError::TooLarge => AppError::Unsupported {
+                message: "The diagram is too large to rasterize at this scale".to_string(),
+                details: None,
+            },
+            erd::RasterError::Svg(e) => AppError::internal("PNG rendering failed", e),
+        })?;
+    Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+}
+
+pub async fn export_dictionary(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<DictionaryQuery>,
+) -> Result<Response, AppError> {
+    let html = match query.format.as_deref() {
+        None | Some("markdown") => false,
+        Some("html") => true,
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown dictionary format `{}`; expected `markdown` or `html`",
+                other
+            )))
+        }
+    };
+    let scope = erd::Scope {
+        schema: query.schema.as_deref(),
+        area: query.area.as_deref(),
+    };
+    let diagram = load_scope(&pool, &id, &scope).await?;
+    if html {
+        Ok((
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            dictionary::html(&diagram),
+        )
+            .into_response())
+    } else {
+        Ok((
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            dictionary::markdown(&diagram),
+        )
+            .into_response())
+    }
+}
+
+pub async fn export_orm(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Query(query): Query<OrmQuery>,
+) -> Result<Response, AppError> {
+    let expected = "expected `prisma`, `diesel`, `seaorm` or `sqlalchemy`";
+    let requested = query
+        .target
+        .as_deref()
+        .ok_or_else(|| AppError::BadRequest(format!("Missing ORM `target`; {}", expected)))?;
+    let target = codegen::Target::parse(requested).ok_or_else(|| {
+        AppError::BadRequest(format!("Unknown ORM target `{}`; {}", requested, expected))
+    })?;
+
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let diagram = load_diagram_at(&mut conn, &id, None).await?;
+
+    let files = codegen::generate(&diagram, target);
+    let archive =
+        codegen::zip(&files).map_err(|e| AppError::internal("Failed to build archive", e))?;
+
+    let disposition = format!(
+        "attachment; filename=\"{}-{}.zip\"",
+        file_stem(&diagram.name),
+        target.as_str()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// A diagram name reduced to characters safe in a download file name.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "diagram".to_string()
+    } else {
+        stem
+    }
+}
+
+pub async fn import_dbml(
+    State(pool): State<PgPool>,
+    Json(payload): Json<DbmlImportRequest>,
+) -> Result<(StatusCode, Json<PushResponse>), AppError> {
+    let diagram = dbml::import(
+        &payload.dbml,
+        payload.name.as_deref(),
+        payload.database_type.as_deref(),
+    )
+    .map_err(|e| AppError::InvalidSource {
+        message: format!("Invalid DBML: {}", e),
+        line: e.line as u64,
+        column: e.column as u64,
+    })?;
+
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    let version = store_diagram(
+        &mut tx,
+        &diagram,
+        payload.author.as_deref(),
+        payload.message.as_deref(),
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PushResponse {
+            success: true,
+            diagram_id: diagram.id,
+            version,
+            merged: false,
+        }),
+    ))
+}
+
+pub async fn import_sql(
+    State(pool): State<PgPool>,
+    Json(payload): Json<SqlImportRequest>,
+) -> Result<(StatusCode, Json<SqlImportResponse>), AppError> {
+    let requested = payload
+        .dialect
+        .as_deref()
+        .or(payload.database_type.as_deref())
+        .unwrap_or("postgresql");
+    let dialect = Dialect::parse(requested)
+        .ok_or_else(|| AppError::BadRequest(format!("Unsupported SQL dialect: {}", requested)))?;
+    let imported = ddl::import::import(
+        &payload.sql,
+        dialect,
+        payload.name.as_deref(),
+        payload.database_type.as_deref(),
+    )
+    .map_err(|e| match e {
+        ImportError::Syntax { line, column, .. } => AppError::InvalidSource {
+            message: format!("Invalid SQL: {}", e),
+            line,
+            column,
+        },
+        ImportError::Empty => AppError::Unsupported {
+            message: e.to_string(),
+            details: None,
+        },
+    })?;
+
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    let version = store_diagram(
+        &mut tx,
+        &imported.diagram,
+        payload.author.as_deref(),
+        payload.message.as_deref(),
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SqlImportResponse {
+            push: PushResponse {
+                success: true,
+                diagram_id: imported.diagram.id,
+                version,
+                merged: false,
+            },
+            warnings: imported.warnings,
+        }),
+    ))
+}
+
+/// Reads a live PostgreSQL database into a new diagram, or into an existing
+/// one when `diagramId` names it.
+pub async fn introspect_database(
+    State(pool): State<PgPool>,
+    Json(payload): Json<IntrospectRequest>,
+) -> Result<(StatusCode, Json<PushResponse>), AppError> {
+    let name = payload.name.clone().unwrap_or_else(|| {
+        database_name(&payload.connection_string).unwrap_or_else(|| "Database".to_string())
+    });
+    let fresh = introspect::introspect(
+        &payload.connection_string,
+        &payload.schemas,
+        payload.diagram_id.as_deref(),
+        &name,
+    )
+    .await?;
+
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    let existing = match &payload.diagram_id {
+        Some(id) => {
+            db::lock_diagram_version(&mut tx, id)
+                .await
+                .map_err(db_error("Failed to read diagram version"))?;
+            db::load_diagram(&mut tx, id)
+                .await
+                .map_err(db_error("Failed to load diagram"))?
+        }
+        None => None,
+    };
+    let (diagram, status) = match existing {
+        Some(existing) => (introspect::reconcile(fresh, &existing), StatusCode::OK),
+        None => (fresh, StatusCode::CREATED),
+    };
+
+    let version = store_diagram(
+        &mut tx,
+        &diagram,
+        payload.author.as_deref(),
+        payload.message.as_deref(),
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok((
+        status,
+        Json(PushResponse {
+            success: true,
+            diagram_id: diagram.id,
+            version,
+            merged: false,
+        }),
+    ))
+}
+
+/// Compares the current state of a diagram with the live database it designs.
+pub async fn check_drift(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<DriftRequest>,
+) -> Result<Json<DriftResponse>, AppError> {
+    let diagram = {
+        let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+        load_diagram_at(&mut conn, &id, None).await?
+    };
+    let schemas = payload.schemas.unwrap_or_else(|| drift::schemas(&diagram));
+    let live =
+        introspect::introspect(&payload.connection_string, &schemas, None, &diagram.name).await?;
+
+    Ok(Json(DriftResponse {
+        diagram_id: diagram.id.clone(),
+        version: diagram.version,
+        checked_at: chrono::Utc::now(),
+        report: drift::check(&diagram, &live),
+    }))
+}
+
+/// Runs the diagram's enabled lint rules over its current state.
+pub async fn lint_diagram(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+) -> Result<Json<LintResponse>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let diagram = load_diagram_at(&mut conn, &id, None).await?;
+    let overrides = db::load_lint_overrides(&mut conn, &id)
+        .await
+        .map_err(db_error("Failed to load lint rules"))?;
+
+    Ok(Json(LintResponse {
+        diagram_id: diagram.id.clone(),
+        version: diagram.version,
+        report: lint::lint(&diagram, &lint::configure(&overrides)),
+    }))
+}
+
+pub async fn lint_rules(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+) -> Result<Json<LintRulesResponse>, AppError> {
+    let mut conn = pool.acquire().await.map_err(db_error("Database error"))?;
+    let exists = db::diagram_exists(&mut conn, &id)
+        .await
+        .map_err(db_error("Database error"))?;
+    if !exists {
+        return Err(AppError::DiagramNotFound);
+    }
+
+    let overrides = db::load_lint_overrides(&mut conn, &id)
+        .await
+        .map_err(db_error("Failed to load lint rules"))?;
+
+    Ok(Json(LintRulesResponse {
+        diagram_id: id,
+        rules: lint::configure(&overrides),
+    }))
+}
+
+/// Changes how the listed lint rules apply to a diagram; other rules keep
+/// their settings.
+pub async fn update_lint_rules(
+    State(pool): State<PgPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<LintRulesRequest>,
+) -> Result<Json<LintRulesResponse>, AppError> {
+    let mut changes = Vec::new();
+    for (rule, change) in payload.rules {
+        if lint::rule(&rule).is_none() {
+            let known: Vec<&str> = lint::RULES.iter().map(|r| r.id).collect();
+            return Err(AppError::BadRequest(format!(
+                "Unknown lint rule `{}`; expected one of {}",
+                rule,
+                known.join(", ")
+            )));
+        }
+        let change = match change {
+            Some(change) => {
+                let severity = match change.severity.as_deref() {
+                    Some(value) => Some(lint::Severity::parse(value).ok_or_else(|| {
+                        AppError::BadRequest(format!(
+                            "Unknown severity `{}` for `{}`; expected error, warning or info",
+                            value, rule
+                        ))
+                    })?),
+                    None => None,
+                };
+                Some(lint::Override {
+                    rule: rule.clone(),
+                    enabled: change.enabled,
+                    severity,
+                    options: change.options,
+                })
+            }
+            None => None,
+        };
+        changes.push((rule, change));
+    }
+
+    let mut tx = pool.begin().await.map_err(db_error("Database error"))?;
+    let exists = db::diagram_exists(&mut tx, &id)
+        .await
+        .map_err(db_error("Database error"))?;
+    if !exists {
+        return Err(AppError::DiagramNotFound);
+    }
+
+    // A null setting resets the rule to its defaults.
+    for (rule, change) in &changes {
+        match change {
+            Some(change) => db::save_lint_override(&mut tx, &id, change).await,
+            None => db::delete_lint_override(&mut tx, &id, rule).await,
+        }
+        .map_err(db_error("Failed to save lint rules"))?;
+    }
+    let overrides = db::load_lint_overrides(&mut tx, &id)
+        .await
+        .map_err(db_error("Failed to load lint rules"))?;
+
+    tx.commit()
+        .await
+        .map_err(db_error("Failed to commit transaction"))?;
+
+    Ok(Json(LintRulesResponse {
+        diagram_id: id,
+        rules: lint::configure(&overrides),
+    }))
+}
+
+/// The database name at the end of a `postgres://.../name?...` URL.
+fn database_name(url: &str) -> Option<String> {
+    let path = url.split('?').next()?.rsplit_once('/')?.1;
+    (!path.is_empty() && !path.contains('@')).then(|| path.to_string())
+}
+
+pub async fn health() -> &'static str {
+    "ok"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, call, database, fresh, relationship, shop, table_id};
+    use crate::models::{Note, Table};
+    use axum::http::Method;
+    use serde_json::{json, Value};
+
+    fn bad_request(result: Result<Table, AppError>) -> String {
+        match result {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {:?}", other.map(|t| t.id)),
+        }
+    }
+
+    #[test]
+    fn entity_ids_come_from_the_path() {
+        let table: Table = entity_from_body(json!({ "name": "users" }), "d1", Some("t1")).unwrap();
+        assert_eq!((table.id.as_str(), table.diagram_id.as_str()), ("t1", "d1"));
+
+        let note: Note = entity_from_body(json!({ "content": "hi" }), "d1", None).unwrap();
+        assert!(!note.id.is_empty());
+
+        assert_eq!(
+            bad_request(entity_from_body(json!({ "id": "t2" }), "d1", Some("t1"))),
+            "The table id `t2` does not match `t1` in the path"
+        );
+        assert_eq!(
+            bad_request(entity_from_body(json!({ "diagramId": "d2" }), "d1", None)),
+            "The table belongs to diagram `d2`, not `d1`"
+        );
+        assert_eq!(
+            bad_request(entity_from_body(json!([]), "d1", None)),
+            "The body must be a JSON object"
+        );
+    }
+
+    #[test]
+    fn only_other_versions_are_stale() {
+        assert_eq!(stale_base(None, 3), None);
+        assert_eq!(stale_base(Some(3), 3), None);
+        assert_eq!(stale_base(Some(2), 3), Some(2));
+        // Ahead of the server is stale too, and fails to find its revision
+        assert_eq!(stale_base(Some(4), 3), Some(4));
+    }
+
+    #[test]
+    fn cursors_are_versions_or_timestamps() {
+        assert!(matches!(parse_cursor(None), Ok(Cursor::Version(0))));
+        assert!(matches!(parse_cursor(Some("7")), Ok(Cursor::Version(7))));
+        match parse_cursor(Some("2024-05-01T12:00:00+02:00")) {
+            Ok(Cursor::Timestamp(at)) => assert_eq!(at.to_rfc3339(), "2024-05-01T10:00:00+00:00"),
+            other => panic!("expected a timestamp, got {:?}", other),
+        }
+        for since in ["yesterday", "7.5", ""] {
+            assert!(
+                matches!(parse_cursor(Some(since)), Err(AppError::BadRequest(_))),
+                "{}",
+                since
+            );
+        }
+    }
+
+    async fn push(pool: &PgPool, diagram: &Diagram) -> (StatusCode, Value) {
+        call(
+            pool,
+            Method::POST,
+            "/api/sync/push",
+            Some(json!({ "diagram": diagram })),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn entities_are_written_one_at_a_time() {
+        let pool = database().await;
+        let diagram = fresh(&shop());
+        let notes = format!("/api/diagrams/{}/notes", diagram.id);
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+
+        let (status, body) = call(
+            &pool,
+            Method::POST,
+            &notes,
+            Some(json!({ "content": "hi" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["version"], 2);
+        let note = format!("{}/{}", notes, body["entity"]["id"].as_str().unwrap());
+
+        let (status, body) = call(&pool, Method::GET, &note, None).await;
+        assert_eq!((status, &body["content"]), (StatusCode::OK, &json!("hi")));
+
+        let (status, body) =
+            call(&pool, Method::PUT, &note, Some(json!({ "content": "bye" }))).await;
+        assert_eq!((status, &body["version"]), (StatusCode::OK, &json!(3)));
+        let (_, body) = call(&pool, Method::GET, &note, None).await;
+        assert_eq!(body["content"], "bye");
+
+        let id = note.rsplit('/').next().unwrap();
+        let (status, body) = call(&pool, Method::POST, &notes, Some(json!({ "id": id }))).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::CONFLICT, &json!("already_exists"))
+        );
+
+        assert_eq!(
+            call(&pool, Method::DELETE, &note, None).await.0,
+            StatusCode::OK
+        );
+        let (status, body) = call(&pool, Method::GET, &note, None).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::NOT_FOUND, &json!("not_found"))
+        );
+        let (status, _) = call(&pool, Method::DELETE, &note, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, body) = call(&pool, Method::GET, &notes, None).await;
+        assert_eq!(body, json!([]));
+
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+        let (status, _) = call(&pool, Method::GET, &notes, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn stored_problems_do_not_block_other_changes() {
+        let pool = database().await;
+        // Written straight to the database, as before integrity checks existed
+        let mut diagram = fresh(&shop());
+        diagram.relationships.as_mut().unwrap()[0].target_table_id = "gone".to_string();
+        let mut tx = pool.begin().await.unwrap();
+        let version = db::upsert_diagram(&mut tx, &diagram).await.unwrap();
+        db::replace_children(&mut tx, &diagram, version)
+            .await
+            .unwrap();
+        db::save_revision(&mut tx, &diagram.id, version, None, None)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        let uri = format!("/api/diagrams/{}", diagram.id);
+
+        let (status, _) = call(
+            &pool,
+            Method::POST,
+            &format!("{}/notes", uri),
+            Some(json!({ "content": "hi" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+
+        // A new dangling reference is still refused, and reported alone
+        let users = table_id(&diagram, "users");
+        let mut dangling = relationship(&new_id(), (&users, "nope"), (&users, "nope"));
+        dangling.diagram_id = diagram.id.clone();
+        let (status, body) = call(
+            &pool,
+            Method::POST,
+            &format!("{}/relationships", uri),
+            Some(json!(dangling)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let paths: Vec<&str> = body["details"]["problems"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                format!("/relationships/{}/sourceFieldId", dangling.id),
+                format!("/relationships/{}/targetFieldId", dangling.id),
+            ]
+        );
+
+        let (status, body) = call(&pool, Method::POST, &format!("{}/duplicate", uri), None).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let copy = format!("/api/diagrams/{}", body["diagramId"].as_str().unwrap());
+        for uri in [uri, copy] {
+            assert_eq!(
+                call(&pool, Method::DELETE, &uri, None).await.0,
+                StatusCode::NO_CONTENT
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn only_versions_catch_pushes_in_flight() {
+        let pool = database().await;
+        let diagram = fresh(&shop());
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        let (_, body) = call(
+            &pool,
+            Method::POST,
+            &format!("{}/notes", uri),
+            Some(json!({ "content": "hi" })),
+        )
+        .await;
+        let note = body["entity"]["id"].clone();
+
+        // As if the push had started a minute before a pull that was answered
+        // before it committed: its rows are stamped with its start
+        sqlx::query(
+            "UPDATE notes SET updated_at = NOW() - INTERVAL '1 minute' WHERE diagram_id = $1",
+        )
+        .bind(&diagram.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let pulled_at = chrono::Utc::now() - chrono::Duration::seconds(30);
+
+        let changes = |since: String| {
+            let pool = pool.clone();
+            let uri = format!("/api/sync/changes/{}?since={}", diagram.id, since);
+            async move { call(&pool, Method::GET, &uri, None).await.1 }
+        };
+        let by_version = changes("1".to_string()).await;
+        assert_eq!(by_version["notes"]["upserts"][0]["id"], note);
+        assert_eq!(by_version["tables"]["upserts"], json!([]));
+        let by_time = changes(pulled_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)).await;
+        assert_eq!(by_time["notes"]["upserts"], json!([]));
+
+        // Deletions are logged with the version that made them
+        let (status, _) = call(
+            &pool,
+            Method::DELETE,
+            &format!("{}/notes/{}", uri, note.as_str().unwrap()),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            changes("2".to_string()).await["notes"]["deletes"],
+            json!([note])
+        );
+
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn stale_pushes_are_merged_or_rejected() {
+        let pool = database().await;
+        let mut diagram = fresh(&shop());
+        let push_at = |diagram: &Diagram, base: i32| {
+            let body = json!({ "diagram": diagram, "baseVersion": base });
+            let pool = pool.clone();
+            async move { call(&pool, Method::POST, "/api/sync/push", Some(body)).await }
+        };
+        let (status, body) = push(&pool, &diagram).await;
+        assert_eq!((status, &body["version"]), (StatusCode::OK, &json!(1)));
+
+        // Another client renames the diagram
+        let mut client = diagram.clone();
+        diagram.name = "Store".to_string();
+        let (_, body) = push_at(&diagram, 1).await;
+        assert_eq!(
+            (&body["version"], &body["merged"]),
+            (&json!(2), &json!(false))
+        );
+
+        // This one, still at version 1, adds a note: both changes are kept
+        client.notes = Some(vec![serde_json::from_value(json!({
+            "id": new_id(),
+            "diagramId": diagram.id,
+            "content": "hi",
+        }))
+        .unwrap()]);
+        let (_, body) = push_at(&client, 1).await;
+        assert_eq!(
+            (&body["version"], &body["merged"]),
+            (&json!(3), &json!(true))
+        );
+        let uri = format!("/api/sync/pull/{}", diagram.id);
+        let (_, pulled) = call(&pool, Method::GET, &uri, None).await;
+        assert_eq!(pulled["name"], "Store");
+        assert_eq!(pulled["notes"][0]["content"], "hi");
+
+        // Renaming it again from version 1 overlaps with the first rename
+        client.name = "Shop 2".to_string();
+        let (status, body) = push_at(&client, 1).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::CONFLICT, &json!("merge_conflict"))
+        );
+        assert_eq!(body["details"]["currentVersion"], 3);
+
+        let (status, body) = push_at(&client, 9).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::CONFLICT, &json!("version_conflict"))
+        );
+
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn delta_pushes_touch_only_what_they_name() {
+        let pool = database().await;
+        let mut diagram = fresh(&shop());
+        diagram.database_edition = Some("postgresql_supabase".to_string());
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+        let (users, orders) = (table_id(&diagram, "users"), table_id(&diagram, "orders"));
+        let mut renamed = diagram.tables.as_ref().unwrap()[0].clone();
+        renamed.name = "people".to_string();
+        let mut sales = area(&new_id(), "Sales", 0.0, 0.0);
+        let delta = |body: Value| {
+            let pool = pool.clone();
+            async move { call(&pool, Method::POST, "/api/sync/push/changes", Some(body)).await }
+        };
+
+        // Entities must belong to the diagram they are pushed to
+        let (status, body) = delta(json!({
+            "diagramId": diagram.id,
+            "areas": { "upserts": [sales] },
+        }))
+        .await;
+        assert_eq!(
+            (status, &body["code"]),
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &json!("validation_failed")
+            )
+        );
+
+        sales.diagram_id = diagram.id.clone();
+        let (status, body) = delta(json!({
+            "diagramId": diagram.id,
+            "baseVersion": 1,
+            "databaseEdition": null,
+            "tables": { "upserts": [renamed] },
+            "areas": { "upserts": [sales] },
+        }))
+        .await;
+        assert_eq!((status, &body["version"]), (StatusCode::OK, &json!(2)));
+        let uri = format!("/api/sync/pull/{}", diagram.id);
+        let (_, pulled) = call(&pool, Method::GET, &uri, None).await;
+        assert_eq!(pulled["name"], json!(diagram.name));
+        assert_eq!(pulled["databaseEdition"], Value::Null);
+        let names: Vec<&str> = pulled["tables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"people") && names.contains(&"orders"));
+        assert_eq!(pulled["areas"][0]["name"], "Sales");
+
+        // Deleting a table still referenced breaks the diagram
+        let (status, body) = delta(json!({
+            "diagramId": diagram.id,
+            "tables": { "deletes": [users] },
+        }))
+        .await;
+        assert_eq!(
+            (status, &body["code"]),
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &json!("validation_failed")
+            )
+        );
+
+        let rel = &diagram.relationships.as_ref().unwrap()[0].id;
+        let (status, body) = delta(json!({
+            "diagramId": diagram.id,
+            "tables": { "deletes": [orders] },
+            "relationships": { "deletes": [rel] },
+        }))
+        .await;
+        assert_eq!((status, &body["version"]), (StatusCode::OK, &json!(3)));
+        let (_, pulled) = call(&pool, Method::GET, &uri, None).await;
+        assert_eq!(pulled["tables"].as_array().unwrap().len(), 1);
+        assert_eq!(pulled["relationships"], json!([]));
+
+        let (status, body) = delta(json!({ "diagramId": new_id() })).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::NOT_FOUND, &json!("diagram_not_found"))
+        );
+
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn full_pushes_leave_tombstones() {
+        let pool = database().await;
+        let mut diagram = fresh(&shop());
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+        let orders = table_id(&diagram, "orders");
+        let rel = diagram.relationships.as_ref().unwrap()[0].id.clone();
+
+        // Pushed again without `orders` and the relationship to it
+        diagram.tables.as_mut().unwrap().retain(|t| t.id != orders);
+        diagram.relationships = Some(Vec::new());
+        let (_, body) = push(&pool, &diagram).await;
+        assert_eq!(body["version"], 2);
+
+        let changes = |since: i32| {
+            let pool = pool.clone();
+            let uri = format!("/api/sync/changes/{}?since={}", diagram.id, since);
+            async move { call(&pool, Method::GET, &uri, None).await.1 }
+        };
+        let since_first = changes(1).await;
+        assert_eq!(since_first["version"], 2);
+        assert_eq!(since_first["tables"]["deletes"], json!([orders]));
+        assert_eq!(since_first["relationships"]["deletes"], json!([rel]));
+        // `users` was written again unchanged, so it is not sent again
+        assert_eq!(since_first["tables"]["upserts"], json!([]));
+
+        let since_last = changes(2).await;
+        assert_eq!(since_last["tables"]["deletes"], json!([]));
+        assert_eq!(since_last["relationships"]["deletes"], json!([]));
+
+        // From the start, rows deleted since are listed too
+        let everything = changes(0).await;
+        assert_eq!(everything["tables"]["upserts"][0]["name"], "users");
+        assert_eq!(everything["tables"]["deletes"], json!([orders]));
+
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local PostgreSQL (DATABASE_URL)"]
+    async fn revisions_record_and_restore_history() {
+        let pool = database().await;
+        let mut diagram = fresh(&shop());
+        let uri = format!("/api/diagrams/{}", diagram.id);
+        let body = json!({ "diagram": diagram, "author": "ana", "message": "First" });
+        call(&pool, Method::POST, "/api/sync/push", Some(body)).await;
+        diagram.name = "Store".to_string();
+        diagram.relationships = Some(Vec::new());
+        assert_eq!(push(&pool, &diagram).await.0, StatusCode::OK);
+
+        let (_, list) = call(&pool, Method::GET, &format!("{}/revisions", uri), None).await;
+        let versions: Vec<_> = list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| &r["version"])
+            .collect();
+        assert_eq!(versions, [2, 1]);
+        assert_eq!(
+            (&list[1]["author"], &list[1]["message"]),
+            (&json!("ana"), &json!("First"))
+        );
+
+        let (_, first) = call(&pool, Method::GET, &format!("{}/revisions/1", uri), None).await;
+        assert_eq!(first["diagram"]["name"], "Test");
+        assert_eq!(
+            first["diagram"]["relationships"].as_array().unwrap().len(),
+            1
+        );
+
+        let restore = format!("{}/revisions/1/restore", uri);
+        let (status, body) = call(&pool, Method::POST, &restore, None).await;
+        assert_eq!((status, &body["version"]), (StatusCode::OK, &json!(3)));
+        let (_, pulled) = call(
+            &pool,
+            Method::GET,
+            &format!("/api/sync/pull/{}", diagram.id),
+            None,
+        )
+        .await;
+        assert_eq!(pulled["name"], "Test");
+        assert_eq!(pulled["relationships"].as_array().unwrap().len(), 1);
+        let (_, latest) = call(&pool, Method::GET, &format!("{}/revisions/3", uri), None).await;
+        assert_eq!(latest["message"], "Restored revision 1");
+
+        let (status, body) = call(&pool, Method::GET, &format!("{}/revisions/9", uri), None).await;
+        assert_eq!(
+            (status, &body["code"]),
+            (StatusCode::NOT_FOUND, &json!("revision_not_found"))
+        );
+        let (status, _) = call(
+            &pool,
+            Method::POST,
+            &format!("{}/revisions/9/restore", uri),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        assert_eq!(
+            call(&pool, Method::DELETE, &uri, None).await.0,
+            StatusCode::NO_CONTENT
+        );
+        let (status, _) = call(&pool, Method::GET, &format!("{}/revisions", uri), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
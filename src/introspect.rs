@@ -0,0 +1,729 @@
+//! Reading the schema of a live PostgreSQL database into a diagram.
+//!
+//! Everything comes from `pg_catalog`: tables, views and materialized views
+//! with their columns, primary and foreign keys, indexes, enum and composite
+//! types, and the tables each view reads from. When the database was read
+//! into a diagram before, [`reconcile`] keeps the ids, positions and
+//! decorations of what is still there.
+
+use crate::error::AppError;
+use crate::layout;
+use crate::models::{
+    new_id, CustomType, CustomTypeField, Dependency, Diagram, Index, Relationship, Table,
+};
+use crate::schema::{self, FieldInfo, IndexInfo};
+use sqlx::postgres::PgRow;
+use sqlx::types::Json;
+use sqlx::{Connection, PgConnection, Row};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum IntrospectError {
+    /// The connection string is invalid or the server could not be reached.
+    Connect(sqlx::Error),
+    /// A catalog query failed, e.g. for lack of privileges.
+    Query(sqlx::Error),
+}
+
+impl fmt::Display for IntrospectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntrospectError::Connect(e) => write!(f, "Could not connect to the database: {}", e),
+            IntrospectError::Query(e) => write!(f, "Could not read the database schema: {}", e),
+        }
+    }
+}
+
+impl From<IntrospectError> for AppError {
+    fn from(error: IntrospectError) -> Self {
+        match error {
+            IntrospectError::Connect(e) => AppError::ConnectionFailed(e),
+            IntrospectError::Query(e) => AppError::UpstreamFailed(e),
+        }
+    }
+}
+
+/// Limits the catalog queries to the requested schemas, or to every schema
+/// that is not PostgreSQL's own when none are given.
+const SCHEMA_FILTER: &str = "n.nspname NOT IN ('pg_catalog', 'information_schema') \
+     AND n.nspname NOT LIKE 'pg\\_%' \
+     AND (cardinality($1::text[]) = 0 OR n.nspname = ANY($1))";
+
+/// Reads the schema of the database at `url` into a new diagram, with the
+/// given id or a generated one. Tables are laid out on a grid.
+pub async fn introspect(
+    url: &str,
+    schemas: &[String],
+    diagram_id: Option<&str>,
+    name: &str,
+) -> Result<Diagram, IntrospectError> {
+    let mut conn = PgConnection::connect(url)
+        .await
+        .map_err(IntrospectError::Connect)?;
+    let catalog = read_catalog(&mut conn, schemas)
+        .await
+        .map_err(IntrospectError::Query)?;
+    let _ = conn.close().await;
+    Ok(catalog.into_diagram(diagram_id.map_or_else(new_id, str::to_string), name))
+}
+
+struct Relation {
+    oid: i64,
+    schema: String,
+    name: String,
+    kind: String,
+    comment: Option<String>,
+    /// Columns by attribute number.
+    columns: Vec<(i16, FieldInfo)>,
+    indexes: Vec<IndexInfo>,
+}
+
+struct ForeignKey {
+    name: String,
+    table: i64,
+    columns: Vec<i16>,
+    referenced: i64,
+    referenced_columns: Vec<i16>,
+}
+
+struct Catalog {
+    relations: Vec<Relation>,
+    foreign_keys: Vec<ForeignKey>,
+    custom_types: Vec<CustomType>,
+    /// (view, table it reads from)
+    view_sources: Vec<(i64, i64)>,
+}
+
+async fn read_catalog(conn: &mut PgConnection, schemas: &[String]) -> Result<Catalog, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT c.oid::bigint AS oid, n.nspname AS schema, c.relname AS name,
+                c.relkind::text AS kind, obj_description(c.oid, 'pg_class') AS comment
+         FROM pg_class c
+         JOIN pg_namespace n ON n.oid = c.relnamespace
+         WHERE c.relkind IN ('r', 'p', 'v', 'm') AND NOT c.relispartition AND {}
+         ORDER BY n.nspname, c.relname",
+        SCHEMA_FILTER
+    ))
+    .bind(schemas)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut relations: Vec<Relation> = rows
+        .iter()
+        .map(|row| Relation {
+            oid: row.get("oid"),
+            schema: row.get("schema"),
+            name: row.get("name"),
+            kind: row.get("kind"),
+            comment: row.get("comment"),
+            columns: Vec::new(),
+            indexes: Vec::new(),
+        })
+        .collect();
+    let oids: Vec<i64> = relations.iter().map(|r| r.oid).collect();
+    let position: HashMap<i64, usize> = oids.iter().enumerate().map(|(i, o)| (*o, i)).collect();
+
+    let rows = sqlx::query(
+        "SELECT a.attrelid::bigint AS relation, a.attnum AS num, a.attname AS name,
+                format_type(a.atttypid, a.atttypmod) AS type, a.attnotnull AS not_null,
+                pg_get_expr(d.adbin, d.adrelid) AS default_value,
+                a.attidentity::text AS identity, a.attgenerated::text AS generated,
+                col_description(a.attrelid, a.attnum) AS comment,
+                CASE WHEN a.attcollation <> t.typcollation THEN co.collname END AS collation
+         FROM pg_attribute a
+         JOIN pg_type t ON t.oid = a.atttypid
+         LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+         LEFT JOIN pg_collation co ON co.oid = a.attcollation
+         WHERE a.attrelid::bigint = ANY($1) AND a.attnum > 0 AND NOT a.attisdropped
+         ORDER BY a.attrelid, a.attnum",
+    )
+    .bind(&oids)
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in &rows {
+        let relation = &mut relations[position[&row.get::<i64, _>("relation")]];
+        relation.columns.push((row.get("num"), column(row)));
+    }
+
+    let rows = sqlx::query(
+        "SELECT con.conrelid::bigint AS relation, con.conname AS name,
+                con.contype::text AS kind, con.conkey AS columns,
+                con.confrelid::bigint AS referenced, con.confkey AS referenced_columns
+         FROM pg_constraint con
+         WHERE con.contype IN ('p', 'f') AND con.conrelid::bigint = ANY($1)
+         ORDER BY con.conrelid, con.conname",
+    )
+    .bind(&oids)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut foreign_keys = Vec::new();
+    for row in &rows {
+        let table: i64 = row.get("relation");
+        let columns: Vec<i16> = row.get("columns");
+        if row.get::<String, _>("kind") == "p" {
+            for (num, field) in &mut relations[position[&table]].columns {
+                if columns.contains(num) {
+                    field.primary_key = true;
+                }
+            }
+        } else {
+            foreign_keys.push(ForeignKey {
+                name: row.get("name"),
+                table,
+                columns,
+                referenced: row.get("referenced"),
+                referenced_columns: row
+                    .get::<Option<Vec<i16>>, _>("referenced_columns")
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    // Indexes backing a single-column unique constraint are shown as the
+    // column's unique flag instead. Expression indexes cannot be shown.
+    let rows = sqlx::query(
+        "SELECT i.indrelid::bigint AS relation, ic.relname AS name, i.indisunique AS is_unique,
+                (i.indkey::int2[])[0:i.indnkeyatts - 1] AS columns,
+                EXISTS (SELECT 1 FROM pg_constraint con
+                        WHERE con.conindid = i.indexrelid AND con.contype = 'u') AS is_constraint
+         FROM pg_index i
+         JOIN pg_class ic ON ic.oid = i.indexrelid
+         WHERE NOT i.indisprimary AND i.indexprs IS NULL AND i.indrelid::bigint = ANY($1)
+         ORDER BY ic.relname",
+    )
+    .bind(&oids)
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in &rows {
+        let relation = &mut relations[position[&row.get::<i64, _>("relation")]];
+        let columns: Vec<i16> = row.get("columns");
+        let unique: bool = row.get("is_unique");
+        if let ([num], true) = (columns.as_slice(), row.get::<bool, _>("is_constraint")) {
+            if let Some((_, field)) = relation.columns.iter_mut().find(|(n, _)| n == num) {
+                field.unique = true;
+            }
+            continue;
+        }
+        let field_ids = columns
+            .iter()
+            .filter_map(|num| relation.columns.iter().find(|(n, _)| n == num))
+            .map(|(_, f)| f.id.clone())
+            .collect();
+        relation.indexes.push(IndexInfo {
+            id: new_id(),
+            name: row.get("name"),
+            unique,
+            field_ids,
+        });
+    }
+
+    let mut custom_types = Vec::new();
+    let rows = sqlx::query(&format!(
+        "SELECT n.nspname AS schema, t.typname AS name,
+                array_agg(e.enumlabel::text ORDER BY e.enumsortorder) AS labels
+         FROM pg_type t
+         JOIN pg_namespace n ON n.oid = t.typnamespace
+         JOIN pg_enum e ON e.enumtypid = t.oid
+         WHERE {}
+         GROUP BY n.nspname, t.typname
+         ORDER BY n.nspname, t.typname",
+        SCHEMA_FILTER
+    ))
+    .bind(schemas)
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in &rows {
+        let labels: Vec<String> = row.get("labels");
+        custom_types.push(custom_type(row, "enum", Some(labels), None));
+    }
+
+    let rows = sqlx::query(&format!(
+        "SELECT n.nspname AS schema, t.typname AS name,
+                json_agg(json_build_object('field', a.attname,
+                                           'type', format_type(a.atttypid, a.atttypmod))
+                         ORDER BY a.attnum) AS fields
+         FROM pg_type t
+         JOIN pg_namespace n ON n.oid = t.typnamespace
+         JOIN pg_class c ON c.oid = t.typrelid AND c.relkind = 'c'
+         JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+         WHERE t.typtype = 'c' AND {}
+         GROUP BY n.nspname, t.typname
+         ORDER BY n.nspname, t.typname",
+        SCHEMA_FILTER
+    ))
+    .bind(schemas)
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in &rows {
+        let Json(fields): Json<Vec<CustomTypeField>> = row.try_get("fields")?;
+        custom_types.push(custom_type(row, "composite", None, Some(fields)));
+    }
+
+    let rows = sqlx::query(
+        "SELECT DISTINCT r.ev_class::bigint AS view, d.refobjid::bigint AS source
+         FROM pg_depend d
+         JOIN pg_rewrite r ON r.oid = d.objid
+         WHERE d.classid = 'pg_rewrite'::regclass AND d.refclassid = 'pg_class'::regclass
+           AND d.deptype = 'n' AND d.refobjid <> r.ev_class
+           AND r.ev_class::bigint = ANY($1)
+         ORDER BY 1, 2",
+    )
+    .bind(&oids)
+    .fetch_all(&mut *conn)
+    .await?;
+    let view_sources = rows
+        .iter()
+        .map(|row| (row.get("view"), row.get("source")))
+        .collect();
+
+    Ok(Catalog {
+        relations,
+        foreign_keys,
+        custom_types,
+        view_sources,
+    })
+}
+
+fn column(row: &PgRow) -> FieldInfo {
+    let mut field = FieldInfo {
+        id: new_id(),
+        name: row.get("name"),
+        nullable: !row.get::<bool, _>("not_null"),
+        collation: row.get("collation"),
+        comment: row.get("comment"),
+        ..FieldInfo::default()
+    };
+    field.set_type_sql(row.get("type"));
+    let identity: String = row.get("identity");
+    let generated: String = row.get("generated");
+    let default: Option<String> = row.get("default_value");
+    field.increment = !identity.is_empty()
+        || default
+            .as_deref()
+            .is_some_and(|d| d.starts_with("nextval("));
+    // The expression of a generated column is not a default
+    if generated.is_empty() {
+        field.default = default;
+    }
+    field
+}
+
+fn custom_type(
+    row: &PgRow,
+    kind: &str,
+    values: Option<Vec<String>>,
+    fields: Option<Vec<CustomTypeField>>,
+) -> CustomType {
+    CustomType {
+        id: new_id(),
+        diagram_id: String::new(),
+        schema: row.get("schema"),
+        r#type: row.get("name"),
+        kind: Some(kind.to_string()),
+        values,
+        fields,
+        extra: Default::default(),
+    }
+}
+
+impl Catalog {
+    fn into_diagram(self, diagram_id: String, name: &str) -> Diagram {
+        let now = chrono::Utc::now();
+        let created_at = now.timestamp_millis();
+        let relation = |oid: i64| self.relations.iter().find(|r| r.oid == oid);
+        let table_ids: HashMap<i64, String> =
+            self.relations.iter().map(|r| (r.oid, new_id())).collect();
+
+        let mut relationships = Vec::new();
+        for fk in &self.foreign_keys {
+            let (Some(source), Some(target)) = (relation(fk.table), relation(fk.referenced)) else {
+                // The referenced table is in a schema that was not read
+                continue;
+            };
+            let field = |r: &'_ Relation, num: i16| {
+                r.columns
+                    .iter()
+                    .find(|(n, _)| *n == num)
+                    .map(|(_, f)| f.clone())
+            };
+            let one_to_one = match fk.columns.as_slice() {
+                [num] => field(source, *num).is_some_and(|f| f.unique || f.primary_key),
+                _ => false,
+            };
+            for (num, referenced_num) in fk.columns.iter().zip(&fk.referenced_columns) {
+                let (Some(source_field), Some(target_field)) =
+                    (field(source, *num), field(target, *referenced_num))
+                else {
+                    continue;
+                };
+                relationships.push(Relationship {
+                    id: new_id(),
+                    diagram_id: diagram_id.clone(),
+                    name: Some(fk.name.clone()),
+                    source_schema: Some(source.schema.clone()),
+                    source_table_id: table_ids[&source.oid].clone(),
+                    target_schema: Some(target.schema.clone()),
+                    target_table_id: table_ids[&target.oid].clone(),
+                    source_field_id: Some(source_field.id),
+                    target_field_id: Some(target_field.id),
+                    source_cardinality: Some(if one_to_one { "one" } else { "many" }.to_string()),
+                    target_cardinality: Some("one".to_string()),
+                });
+            }
+        }
+
+        let dependencies = self
+            .view_sources
+            .iter()
+            .filter_map(|(view, source)| {
+                let (view, source) = (relation(*view)?, relation(*source)?);
+                Some(Dependency {
+                    id: new_id(),
+                    diagram_id: diagram_id.clone(),
+                    schema: Some(source.schema.clone()),
+                    table_id: table_ids[&source.oid].clone(),
+                    dependent_schema: Some(view.schema.clone()),
+                    dependent_table_id: table_ids[&view.oid].clone(),
+                })
+            })
+            .collect();
+
+        let mut tables: Vec<Table> = self
+            .relations
+            .iter()
+            .enumerate()
+            .map(|(order, r)| Table {
+                id: table_ids[&r.oid].clone(),
+                diagram_id: diagram_id.clone(),
+                name: r.name.clone(),
+                schema: Some(r.schema.clone()),
+                x: None,
+                y: None,
+                width: None,
+                color: None,
+                comment: r.comment.clone(),
+                is_view: Some(r.kind == "v" || r.kind == "m"),
+                is_materialized_view: Some(r.kind == "m"),
+                order: Some(order as i32),
+                fields: r
+                    .columns
+                    .iter()
+                    .map(|(_, f)| f.to_field(created_at))
+                    .collect(),
+                indexes: r.indexes.iter().map(|i| i.to_index(created_at)).collect(),
+            })
+            .collect();
+        layout::arrange(&diagram_id, &mut tables, &[]);
+
+        let mut custom_types = self.custom_types;
+        for ct in &mut custom_types {
+            ct.diagram_id = diagram_id.clone();
+        }
+
+        Diagram {
+            id: diagram_id,
+            name: name.to_string(),
+            database_type: "postgresql".to_string(),
+            database_edition: None,
+            created_at: now,
+            updated_at: now,
+            version: None,
+            tables: Some(tables),
+            relationships: Some(relationships),
+            dependencies: Some(dependencies),
+            areas: Some(Vec::new()),
+            custom_types: Some(custom_types),
+            notes: Some(Vec::new()),
+        }
+    }
+}
+
+/// Turns a freshly introspected diagram into the next state of `existing`:
+/// tables, fields, indexes, relationships, dependencies and types that were
+/// there before keep their ids, and tables keep their position, width and
+/// color. Tables and types are matched by schema and name, a missing schema
+/// reading as `public`. New tables are placed below the others. Areas and
+/// notes are kept as they are.
+pub fn reconcile(mut fresh: Diagram, existing: &Diagram) -> Diagram {
+    let old_tables = existing.tables.as_deref().unwrap_or_default();
+    let mut ids: HashMap<String, String> = HashMap::new();
+
+    let tables = fresh.tables.take().unwrap_or_default();
+    let (mut kept, mut added): (Vec<Table>, Vec<Table>) = (Vec::new(), Vec::new());
+    for mut table in tables {
+        let Some(old) = old_tables
+            .iter()
+            .find(|t| schema::same_name(&t.schema, &t.name, &table.schema, &table.name))
+        else {
+            table.diagram_id = existing.id.clone();
+            added.push(table);
+            continue;
+        };
+        ids.insert(table.id.clone(), old.id.clone());
+        table.id = old.id.clone();
+        table.diagram_id = existing.id.clone();
+        table.x = old.x;
+        table.y = old.y;
+        table.width = old.width;
+        table.color = old.color.clone();
+
+        let old_fields = schema::fields(old);
+        for field in &mut table.fields {
+            if let Some(old_field) = old_fields.iter().find(|f| f.name == field.name) {
+                ids.insert(field.id.clone(), old_field.id.clone());
+                field.id = old_field.id.clone();
+            }
+        }
+        let old_indexes = schema::indexes(old);
+        for index in &mut table.indexes {
+            if let Some(old_index) = old_indexes.iter().find(|i| i.name == index.name) {
+                index.id = old_index.id.clone();
+            }
+        }
+        kept.push(table);
+    }
+    layout::append(&mut added, &kept);
+    for table in &mut kept {
+        remap_field_ids(&mut table.indexes, &ids);
+    }
+    kept.append(&mut added);
+
+    let remap = |id: &mut String| {
+        if let Some(old) = ids.get(id) {
+            *id = old.clone();
+        }
+    };
+    let mut relationships = fresh.relationships.take().unwrap_or_default();
+    for rel in &mut relationships {
+        remap(&mut rel.source_table_id);
+        remap(&mut rel.target_table_id);
+        rel.source_field_id.iter_mut().for_each(remap);
+        rel.target_field_id.iter_mut().for_each(remap);
+        rel.diagram_id = existing.id.clone();
+        if let Some(old) = existing.relationships.iter().flatten().find(|r| {
+            r.source_field_id == rel.source_field_id && r.target_field_id == rel.target_field_id
+        }) {
+            rel.id = old.id.clone();
+        }
+    }
+    let mut dependencies = fresh.dependencies.take().unwrap_or_default();
+    for dep in &mut dependencies {
+        remap(&mut dep.table_id);
+        remap(&mut dep.dependent_table_id);
+        dep.diagram_id = existing.id.clone();
+        if let Some(old) =
+            existing.dependencies.iter().flatten().find(|d| {
+                d.table_id == dep.table_id && d.dependent_table_id == dep.dependent_table_id
+            })
+        {
+            dep.id = old.id.clone();
+        }
+    }
+    let mut custom_types = fresh.custom_types.take().unwrap_or_default();
+    for ct in &mut custom_types {
+        ct.diagram_id = existing.id.clone();
+        if let Some(old) = existing
+            .custom_types
+            .iter()
+            .flatten()
+            .find(|o| schema::same_name(&o.schema, &o.r#type, &ct.schema, &ct.r#type))
+        {
+            ct.id = old.id.clone();
+        }
+    }
+
+    Diagram {
+        id: existing.id.clone(),
+        name: existing.name.clone(),
+        database_type: existing.database_type.clone(),
+        database_edition: existing.database_edition.clone(),
+        created_at: existing.created_at,
+        version: existing.version,
+        tables: Some(kept),
+        relationships: Some(relationships),
+        dependencies: Some(dependencies),
+        areas: existing.areas.clone(),
+        custom_types: Some(custom_types),
+        notes: existing.notes.clone(),
+        ..fresh
+    }
+}
+
+/// Points the `fieldIds` of each index at the reused field ids.
+fn remap_field_ids(indexes: &mut [Index], ids: &HashMap<String, String>) {
+    for id in indexes.iter_mut().flat_map(|i| &mut i.field_ids) {
+        if let Some(old) = ids.get(id) {
+            *id = old.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, enumeration, key, shop};
+
+    fn relation(oid: i64, name: &str, kind: &str, columns: Vec<FieldInfo>) -> Relation {
+        Relation {
+            oid,
+            schema: "public".to_string(),
+            name: name.to_string(),
+            kind: kind.to_string(),
+            comment: None,
+            columns: (1..).zip(columns).collect(),
+            indexes: Vec::new(),
+        }
+    }
+
+    /// `shop()` as the database has it, plus a `tags` column, a `coupons`
+    /// table, a view of `users` and a foreign key into a schema not read.
+    fn catalog() -> Catalog {
+        let mut user_id = column("c_user_id", "user_id", "integer");
+        user_id.unique = true;
+        Catalog {
+            relations: vec![
+                relation(
+                    1,
+                    "users",
+                    "r",
+                    vec![
+                        key("c_id", "id", "integer"),
+                        column("c_email", "email", "varchar(255)"),
+                        column("c_tags", "tags", "text[]"),
+                    ],
+                ),
+                relation(
+                    2,
+                    "orders",
+                    "r",
+                    vec![key("c_orders_id", "id", "integer"), user_id],
+                ),
+                relation(
+                    3,
+                    "coupons",
+                    "r",
+                    vec![key("c_coupons_id", "id", "integer")],
+                ),
+                relation(
+                    4,
+                    "active_users",
+                    "v",
+                    vec![column("c_view_id", "id", "integer")],
+                ),
+            ],
+            foreign_keys: vec![
+                ForeignKey {
+                    name: "orders_user_id_fkey".to_string(),
+                    table: 2,
+                    columns: vec![2],
+                    referenced: 1,
+                    referenced_columns: vec![1],
+                },
+                ForeignKey {
+                    name: "orders_audit_fkey".to_string(),
+                    table: 2,
+                    columns: vec![1],
+                    referenced: 99,
+                    referenced_columns: vec![1],
+                },
+            ],
+            custom_types: vec![enumeration("c_status", "status", &["new", "paid"])],
+            view_sources: vec![(4, 1)],
+        }
+    }
+
+    fn table<'a>(diagram: &'a Diagram, name: &str) -> &'a Table {
+        diagram
+            .tables
+            .iter()
+            .flatten()
+            .find(|t| t.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn catalogs_become_diagrams() {
+        let diagram = catalog().into_diagram("d9".to_string(), "Live");
+        assert_eq!((diagram.id.as_str(), diagram.name.as_str()), ("d9", "Live"));
+
+        let (users, orders) = (table(&diagram, "users"), table(&diagram, "orders"));
+        assert_eq!(users.fields.len(), 3);
+        assert_eq!(table(&diagram, "active_users").is_view, Some(true));
+        assert!(diagram
+            .tables
+            .iter()
+            .flatten()
+            .all(|t| t.x.is_some() && t.y.is_some()));
+
+        // The key into an unread schema is left out
+        let relationships = diagram.relationships.as_ref().unwrap();
+        assert_eq!(relationships.len(), 1);
+        let rel = &relationships[0];
+        assert_eq!(
+            (rel.source_table_id.as_str(), rel.target_table_id.as_str()),
+            (orders.id.as_str(), users.id.as_str())
+        );
+        assert_eq!(
+            rel.source_field_id.as_deref(),
+            Some(orders.fields[1].id.as_str())
+        );
+        // A unique referencing column makes it one-to-one
+        assert_eq!(rel.source_cardinality.as_deref(), Some("one"));
+
+        let dependencies = diagram.dependencies.as_ref().unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies[0].table_id, users.id);
+        assert_eq!(
+            dependencies[0].dependent_table_id,
+            table(&diagram, "active_users").id
+        );
+
+        assert_eq!(diagram.custom_types.as_ref().unwrap()[0].diagram_id, "d9");
+    }
+
+    #[test]
+    fn reconciling_keeps_what_was_there() {
+        let mut existing = shop();
+        existing.custom_types = Some(vec![enumeration("c_status", "status", &["new"])]);
+        for table in existing.tables.iter_mut().flatten() {
+            table.x = Some(500.0);
+            table.y = Some(40.0);
+            table.color = Some("#ff0000".to_string());
+        }
+        // Stored without a schema, as ChartDB does for some databases
+        existing.tables.as_mut().unwrap()[0].schema = None;
+        existing.custom_types.as_mut().unwrap()[0].schema = None;
+
+        let fresh = catalog().into_diagram("d_new".to_string(), "Live");
+        let merged = reconcile(fresh, &existing);
+        assert_eq!(merged.id, existing.id);
+        assert_eq!(merged.name, existing.name);
+
+        let users = table(&merged, "users");
+        assert_eq!(users.id, "t_users");
+        assert_eq!((users.x, users.y), (Some(500.0), Some(40.0)));
+        assert_eq!(users.color.as_deref(), Some("#ff0000"));
+        let ids: Vec<&str> = users.fields.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(&ids[..2], ["f_users_id", "f_users_email"]);
+        assert!(!ids[2].starts_with("f_"));
+        assert_eq!(table(&merged, "orders").id, "t_orders");
+        assert_eq!(merged.tables.as_ref().unwrap().len(), 4);
+
+        // New tables go below the kept ones
+        let coupons = table(&merged, "coupons");
+        assert_eq!(coupons.diagram_id, existing.id);
+        assert!(coupons.y.unwrap() > 40.0 + layout::height(users));
+
+        let rel = &merged.relationships.as_ref().unwrap()[0];
+        assert_eq!(rel.id, "r_orders_user");
+        assert_eq!(
+            (rel.source_table_id.as_str(), rel.target_table_id.as_str()),
+            ("t_orders", "t_users")
+        );
+        assert_eq!(merged.dependencies.as_ref().unwrap()[0].table_id, "t_users");
+
+        let status = &merged.custom_types.as_ref().unwrap()[0];
+        assert_eq!(status.id, "c_status");
+        assert_eq!(status.values.as_ref().unwrap().len(), 2);
+    }
+}
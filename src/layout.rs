@@ -0,0 +1,172 @@
+//! Positions for diagrams created on the server, which have none of their
+//! own: tables are placed on a grid, with the members of each group kept
+//! together inside an area drawn around them.
+
+use crate::models::{new_id, Area, Table};
+
+/// Width of a table ChartDB has not been told otherwise about.
+pub const TABLE_WIDTH: f64 = 224.0;
+const COLUMN_WIDTH: f64 = 320.0;
+const GRID_COLUMNS: usize = 4;
+pub const HEADER_HEIGHT: f64 = 48.0;
+pub const FIELD_HEIGHT: f64 = 32.0;
+const ROW_GAP: f64 = 80.0;
+const AREA_PADDING: f64 = 40.0;
+const BLOCK_GAP: f64 = 120.0;
+
+/// Left edge of the column beside the grid, where sticky notes go.
+pub const SIDE_X: f64 = GRID_COLUMNS as f64 * COLUMN_WIDTH + ROW_GAP;
+
+/// Tables (by index) to place together and surround with an area.
+pub struct Group {
+    pub name: String,
+    pub color: Option<String>,
+    pub tables: Vec<usize>,
+}
+
+/// Sets the position of every table, one block per group followed by the
+/// ungrouped tables, and returns an area for each group.
+pub fn arrange(diagram_id: &str, tables: &mut [Table], groups: &[Group]) -> Vec<Area> {
+    let mut placed = vec![false; tables.len()];
+    let mut blocks: Vec<(Option<&Group>, Vec<usize>)> = Vec::new();
+    for group in groups {
+        let members: Vec<usize> = group
+            .tables
+            .iter()
+            .copied()
+            .filter(|t| *t < tables.len() && !std::mem::replace(&mut placed[*t], true))
+            .collect();
+        blocks.push((Some(group), members));
+    }
+    blocks.push((None, (0..tables.len()).filter(|t| !placed[*t]).collect()));
+
+    let mut areas = Vec::new();
+    let mut top = 0.0;
+    for (group, members) in blocks {
+        if members.is_empty() && group.is_none() {
+            continue;
+        }
+        let mut bottom = top;
+        for (row, chunk) in members.chunks(GRID_COLUMNS).enumerate() {
+            let y = if row == 0 { top } else { bottom + ROW_GAP };
+            for (column, t) in chunk.iter().enumerate() {
+                let table = &mut tables[*t];
+                table.x = Some(column as f64 * COLUMN_WIDTH);
+                table.y = Some(y);
+                bottom = f64::max(bottom, y + height(table));
+            }
+        }
+        if let Some(group) = group {
+            let columns = members.len().clamp(1, GRID_COLUMNS) as f64;
+            areas.push(Area {
+                id: new_id(),
+                diagram_id: diagram_id.to_string(),
+                name: Some(group.name.clone()),
+                x: Some(-AREA_PADDING),
+                y: Some(top - AREA_PADDING),
+                width: Some((columns - 1.0) * COLUMN_WIDTH + TABLE_WIDTH + 2.0 * AREA_PADDING),
+                height: Some(bottom - top + 2.0 * AREA_PADDING),
+                color: group.color.clone(),
+            });
+            bottom += AREA_PADDING;
+        }
+        top = bottom + BLOCK_GAP;
+    }
+    areas
+}
+
+/// Places `tables` on a grid below the already positioned `placed` ones.
+pub fn append(tables: &mut [Table], placed: &[Table]) {
+    let top = placed
+        .iter()
+        .filter_map(|t| Some(t.y? + height(t)))
+        .fold(None, |max: Option<f64>, bottom| {
+            Some(max.map_or(bottom, |m| m.max(bottom)))
+        });
+    arrange("", tables, &[]);
+    if let Some(top) = top {
+        for table in tables {
+            table.y = table.y.map(|y| y + top + BLOCK_GAP);
+        }
+    }
+}
+
+/// Height of a table as ChartDB draws it: a header and a row per field.
+pub fn height(table: &Table) -> f64 {
+    HEADER_HEIGHT + FIELD_HEIGHT * table.fields.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{key, table};
+
+    fn tables(count: usize) -> Vec<Table> {
+        (0..count)
+            .map(|i| {
+                table(
+                    &format!("t{}", i),
+                    &format!("t{}", i),
+                    &[key(&format!("f{}", i), "id", "integer")],
+                )
+            })
+            .collect()
+    }
+
+    fn position(table: &Table) -> (f64, f64) {
+        (table.x.unwrap(), table.y.unwrap())
+    }
+
+    #[test]
+    fn rows_wrap_after_the_last_column() {
+        let mut tables = tables(5);
+        assert!(arrange("d1", &mut tables, &[]).is_empty());
+
+        assert_eq!(position(&tables[0]), (0.0, 0.0));
+        assert_eq!(position(&tables[3]), (3.0 * COLUMN_WIDTH, 0.0));
+        assert_eq!(
+            position(&tables[4]),
+            (0.0, HEADER_HEIGHT + FIELD_HEIGHT + ROW_GAP)
+        );
+    }
+
+    #[test]
+    fn groups_come_first_inside_their_areas() {
+        let mut tables = tables(3);
+        let groups = [Group {
+            name: "billing".to_string(),
+            color: Some("#ff0000".to_string()),
+            // A table listed twice, or out of range, is placed once
+            tables: vec![2, 2, 7],
+        }];
+        let areas = arrange("d1", &mut tables, &groups);
+
+        assert_eq!(areas.len(), 1);
+        let area = &areas[0];
+        assert_eq!(area.name.as_deref(), Some("billing"));
+        assert_eq!(area.diagram_id, "d1");
+        assert_eq!((area.x, area.y), (Some(-AREA_PADDING), Some(-AREA_PADDING)));
+        assert_eq!(area.width, Some(TABLE_WIDTH + 2.0 * AREA_PADDING));
+        assert_eq!(
+            area.height,
+            Some(HEADER_HEIGHT + FIELD_HEIGHT + 2.0 * AREA_PADDING)
+        );
+
+        assert_eq!(position(&tables[2]), (0.0, 0.0));
+        let below = HEADER_HEIGHT + FIELD_HEIGHT + AREA_PADDING + BLOCK_GAP;
+        assert_eq!(position(&tables[0]), (0.0, below));
+        assert_eq!(position(&tables[1]), (COLUMN_WIDTH, below));
+    }
+
+    #[test]
+    fn appended_tables_go_below_the_placed_ones() {
+        let mut placed = tables(1);
+        placed[0].y = Some(100.0);
+        let mut added = tables(2);
+        append(&mut added, &placed);
+
+        let top = 100.0 + HEADER_HEIGHT + FIELD_HEIGHT + BLOCK_GAP;
+        assert_eq!(position(&added[0]), (0.0, top));
+        assert_eq!(position(&added[1]), (COLUMN_WIDTH, top));
+    }
+}
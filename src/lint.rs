@@ -0,0 +1,780 @@
+//! Schema lint: design rules run over a stored diagram.
+//!
+//! Each rule has a default severity and can be turned off, given another
+//! severity or, for rules that take them, options, per diagram. Findings are
+//! located with the same id-addressed paths as validation problems, e.g.
+//! `/tables/t1/fields/f2`.
+
+use crate::drift::canonical_type;
+use crate::erd;
+use crate::models::{Diagram, Table};
+use crate::schema::{self, FieldInfo};
+use crate::validate::escape;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+pub struct Rule {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub severity: Severity,
+    pub enabled: bool,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "missing-primary-key",
+        description: "Tables should have a primary key",
+        severity: Severity::Error,
+        enabled: true,
+    },
+    Rule {
+        id: "unindexed-foreign-key",
+        description: "Foreign key columns should lead an index",
+        severity: Severity::Warning,
+        enabled: true,
+    },
+    Rule {
+        id: "relationship-type-mismatch",
+        description: "Related fields should have the same type",
+        severity: Severity::Error,
+        enabled: true,
+    },
+    Rule {
+        id: "snake-case-names",
+        description: "Table and field names should be snake_case",
+        severity: Severity::Warning,
+        enabled: true,
+    },
+    Rule {
+        id: "table-name-plurality",
+        description:
+            "Table names should all be plural, or all singular with `{\"style\": \"singular\"}`",
+        severity: Severity::Info,
+        enabled: false,
+    },
+    Rule {
+        id: "reserved-words",
+        description: "Table and field names should not be SQL reserved words",
+        severity: Severity::Warning,
+        enabled: true,
+    },
+    Rule {
+        id: "duplicate-index",
+        description: "Indexes should not repeat another index or the primary key",
+        severity: Severity::Warning,
+        enabled: true,
+    },
+    Rule {
+        id: "orphan-table",
+        description: "Tables should be related to at least one other table",
+        severity: Severity::Info,
+        enabled: true,
+    },
+    Rule {
+        id: "unused-enum",
+        description: "Enum types should be used by at least one field",
+        severity: Severity::Warning,
+        enabled: true,
+    },
+];
+
+pub fn rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|r| r.id == id)
+}
+
+/// A diagram's stored changes to a rule's defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Override {
+    pub rule: String,
+    pub enabled: Option<bool>,
+    pub severity: Option<Severity>,
+    pub options: Option<Value>,
+}
+
+/// A rule as it applies to one diagram.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleConfig {
+    pub rule: &'static str,
+    pub description: &'static str,
+    pub enabled: bool,
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Value>,
+}
+
+/// Every rule with the diagram's overrides applied.
+pub fn configure(overrides: &[Override]) -> Vec<RuleConfig> {
+    RULES
+        .iter()
+        .map(|rule| {
+            let stored = overrides.iter().find(|o| o.rule == rule.id);
+            RuleConfig {
+                rule: rule.id,
+                description: rule.description,
+                enabled: stored.and_then(|o| o.enabled).unwrap_or(rule.enabled),
+                severity: stored.and_then(|o| o.severity).unwrap_or(rule.severity),
+                options: stored.and_then(|o| o.options.clone()),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub error: usize,
+    pub warning: usize,
+    pub info: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LintReport {
+    pub summary: Summary,
+    pub findings: Vec<Finding>,
+}
+
+pub fn lint(diagram: &Diagram, rules: &[RuleConfig]) -> LintReport {
+    let mut findings = Vec::new();
+    let context = Context::new(diagram);
+    for config in rules.iter().filter(|r| r.enabled) {
+        let mut report = |path: String, message: String| {
+            findings.push(Finding {
+                rule: config.rule,
+                severity: config.severity,
+                path,
+                message,
+            })
+        };
+        match config.rule {
+            "missing-primary-key" => missing_primary_key(&context, &mut report),
+            "unindexed-foreign-key" => unindexed_foreign_keys(&context, &mut report),
+            "relationship-type-mismatch" => type_mismatches(&context, &mut report),
+            "snake-case-names" => snake_case_names(&context, &mut report),
+            "table-name-plurality" => {
+                let singular = config
+                    .options
+                    .as_ref()
+                    .and_then(|o| o.get("style"))
+                    .and_then(Value::as_str)
+                    == Some("singular");
+                plurality(&context, singular, &mut report)
+            }
+            "reserved-words" => reserved_words(&context, &mut report),
+            "duplicate-index" => duplicate_indexes(&context, &mut report),
+            "orphan-table" => orphan_tables(&context, &mut report),
+            "unused-enum" => unused_enums(&context, &mut report),
+            _ => {}
+        }
+    }
+
+    let mut summary = Summary::default();
+    for finding in &findings {
+        match finding.severity {
+            Severity::Error => summary.error += 1,
+            Severity::Warning => summary.warning += 1,
+            Severity::Info => summary.info += 1,
+        }
+    }
+    LintReport { summary, findings }
+}
+
+/// The diagram with each table's fields read once.
+struct Context<'a> {
+    diagram: &'a Diagram,
+    tables: Vec<(&'a Table, Vec<FieldInfo>)>,
+}
+
+impl<'a> Context<'a> {
+    fn new(diagram: &'a Diagram) -> Self {
+        let tables = diagram
+            .tables
+            .iter()
+            .flatten()
+            .map(|t| (t, schema::fields(t)))
+            .collect();
+        Context { diagram, tables }
+    }
+
+    fn table(&self, id: &str) -> Option<&(&'a Table, Vec<FieldInfo>)> {
+        self.tables.iter().find(|(t, _)| t.id == id)
+    }
+}
+
+fn is_view(table: &Table) -> bool {
+    table.is_view == Some(true) || table.is_materialized_view == Some(true)
+}
+
+fn table_path(table: &Table) -> String {
+    format!("/tables/{}", escape(&table.id))
+}
+
+fn field_path(table: &Table, field: &FieldInfo) -> String {
+    format!("/tables/{}/fields/{}", escape(&table.id), escape(&field.id))
+}
+
+fn name(table: &Table) -> String {
+    schema::qualified_name(table.schema.as_deref(), &table.name)
+}
+
+fn missing_primary_key(context: &Context, report: &mut impl FnMut(String, String)) {
+    for (table, fields) in &context.tables {
+        if !is_view(table) && !fields.iter().any(|f| f.primary_key) {
+            report(
+                table_path(table),
+                format!("Table `{}` has no primary key", name(table)),
+            );
+        }
+    }
+}
+
+fn unindexed_foreign_keys(context: &Context, report: &mut impl FnMut(String, String)) {
+    let mut seen = HashSet::new();
+    for rel in context.diagram.relationships.iter().flatten() {
+        let Some((table_id, field_id)) = erd::foreign_key_end(rel) else {
+            continue;
+        };
+        let Some((table, fields)) = context.table(table_id) else {
+            continue;
+        };
+        let Some(field) = fields.iter().find(|f| f.id == field_id) else {
+            continue;
+        };
+        if is_view(table) || !seen.insert((table_id, field_id)) {
+            continue;
+        }
+        // The primary key and unique columns are indexed by their constraint
+        let leads_primary_key =
+            fields.iter().find(|f| f.primary_key).map(|f| &f.id) == Some(&field.id);
+        let leads_index = schema::indexes(table)
+            .iter()
+            .any(|i| i.field_ids.first() == Some(&field.id));
+        if !(leads_primary_key || field.unique || leads_index) {
+            report(
+                field_path(table, field),
+                format!(
+                    "Foreign key column `{}.{}` is not the first column of any index",
+                    name(table),
+                    field.name
+                ),
+            );
+        }
+    }
+}
+
+fn type_mismatches(context: &Context, report: &mut impl FnMut(String, String)) {
+    for rel in context.diagram.relationships.iter().flatten() {
+        let end = |table_id: &str, field_id: Option<&str>| {
+            let (table, fields) = context.table(table_id)?;
+            let field = fields.iter().find(|f| Some(f.id.as_str()) == field_id)?;
+            Some((*table, field))
+        };
+        let (Some(source), Some(target)) = (
+            end(&rel.source_table_id, rel.source_field_id.as_deref()),
+            end(&rel.target_table_id, rel.target_field_id.as_deref()),
+        ) else {
+            continue;
+        };
+        let (source_type, target_type) = (
+            (canonical_type(&source.1.type_name), source.1.is_array),
+            (canonical_type(&target.1.type_name), target.1.is_array),
+        );
+        if source_type != target_type {
+            report(
+                format!("/relationships/{}", escape(&rel.id)),
+                format!(
+                    "`{}.{}` ({}) is related to `{}.{}` ({})",
+                    name(source.0),
+                    source.1.name,
+                    source.1.type_sql(),
+                    name(target.0),
+                    target.1.name,
+                    target.1.type_sql()
+                ),
+            );
+        }
+    }
+}
+
+fn is_snake_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn snake_case_names(context: &Context, report: &mut impl FnMut(String, String)) {
+    for (table, fields) in &context.tables {
+        if !is_snake_case(&table.name) {
+            report(
+                table_path(table),
+                format!("Table name `{}` is not snake_case", table.name),
+            );
+        }
+        for field in fields.iter().filter(|f| !is_snake_case(&f.name)) {
+            report(
+                field_path(table, field),
+                format!(
+                    "Field name `{}.{}` is not snake_case",
+                    table.name, field.name
+                ),
+            );
+        }
+    }
+}
+
+/// Whether the last word of a name reads as a plural noun. Guesses from
+/// English endings, so it is meant as a nudge rather than a rule of law.
+fn is_plural(name: &str) -> bool {
+    let word = name
+        .rsplit(['_', '.', ' '])
+        .next()
+        .unwrap_or(name)
+        .to_ascii_lowercase();
+    const PLURAL: &[&str] = &[
+        "people", "children", "men", "women", "data", "media", "criteria",
+    ];
+    const SINGULAR_ENDINGS: &[&str] = &["ss", "us", "is", "status"];
+    if PLURAL.contains(&word.as_str()) {
+        return true;
+    }
+    word.ends_with('s') && !SINGULAR_ENDINGS.iter().any(|e| word.ends_with(e))
+}
+
+fn plurality(context: &Context, singular: bool, report: &mut impl FnMut(String, String)) {
+    let expected = if singular { "singular" } else { "plural" };
+    for (table, _) in &context.tables {
+        if is_plural(&table.name) == singular {
+            report(
+                table_path(table),
+                format!("Table name `{}` is not {}", table.name, expected),
+            );
+        }
+    }
+}
+
+/// Words reserved in standard SQL or by PostgreSQL, MySQL or SQL Server
+/// that are likely as table or column names.
+const RESERVED: &[&str] = &[
+    "all",
+    "alter",
+    "and",
+    "any",
+    "as",
+    "asc",
+    "between",
+    "by",
+    "case",
+    "check",
+    "column",
+    "constraint",
+    "create",
+    "cross",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "except",
+    "exists",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "full",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "index",
+    "inner",
+    "insert",
+    "intersect",
+    "into",
+    "is",
+    "join",
+    "key",
+    "left",
+    "like",
+    "limit",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "outer",
+    "primary",
+    "references",
+    "right",
+    "select",
+    "session_user",
+    "set",
+    "some",
+    "table",
+    "then",
+    "to",
+    "true",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "using",
+    "values",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+fn reserved_words(context: &Context, report: &mut impl FnMut(String, String)) {
+    let reserved = |name: &str| RESERVED.contains(&name.to_ascii_lowercase().as_str());
+    for (table, fields) in &context.tables {
+        if reserved(&table.name) {
+            report(
+                table_path(table),
+                format!(
+                    "Table name `{}` is a reserved word and must be quoted in SQL",
+                    table.name
+                ),
+            );
+        }
+        for field in fields.iter().filter(|f| reserved(&f.name)) {
+            report(
+                field_path(table, field),
+                format!(
+                    "Field name `{}.{}` is a reserved word and must be quoted in SQL",
+                    table.name, field.name
+                ),
+            );
+        }
+    }
+}
+
+fn duplicate_indexes(context: &Context, report: &mut impl FnMut(String, String)) {
+    for (table, fields) in &context.tables {
+        let primary_key: Vec<&String> = fields
+            .iter()
+            .filter(|f| f.primary_key)
+            .map(|f| &f.id)
+            .collect();
+        let indexes = schema::indexes(table);
+        for (i, index) in indexes.iter().enumerate() {
+            let path = format!(
+                "/tables/{}/indexes/{}",
+                escape(&table.id),
+                escape(&index.id)
+            );
+            let columns: Vec<&String> = index.field_ids.iter().collect();
+            if columns.is_empty() {
+                continue;
+            }
+            if let Some(earlier) = indexes[..i].iter().find(|e| e.field_ids == index.field_ids) {
+                report(
+                    path,
+                    format!(
+                        "Index `{}` on `{}` has the same columns as `{}`",
+                        index.name,
+                        name(table),
+                        earlier.name
+                    ),
+                );
+            } else if columns == primary_key {
+                report(
+                    path,
+                    format!(
+                        "Index `{}` on `{}` repeats the primary key",
+                        index.name,
+                        name(table)
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn orphan_tables(context: &Context, report: &mut impl FnMut(String, String)) {
+    if context.tables.len() < 2 {
+        return;
+    }
+    let mut related: HashSet<&str> = HashSet::new();
+    for rel in context.diagram.relationships.iter().flatten() {
+        related.insert(&rel.source_table_id);
+        related.insert(&rel.target_table_id);
+    }
+    for dep in context.diagram.dependencies.iter().flatten() {
+        related.insert(&dep.table_id);
+        related.insert(&dep.dependent_table_id);
+    }
+    for (table, _) in &context.tables {
+        if !related.contains(table.id.as_str()) {
+            report(
+                table_path(table),
+                format!("Table `{}` has no relationships", name(table)),
+            );
+        }
+    }
+}
+
+fn unused_enums(context: &Context, report: &mut impl FnMut(String, String)) {
+    let custom_types = context.diagram.custom_types.as_deref().unwrap_or_default();
+    // Type names in use, both bare and schema-qualified
+    let mut used: HashSet<String> = HashSet::new();
+    let type_names = context
+        .tables
+        .iter()
+        .flat_map(|(_, fields)| fields.iter().map(|f| f.type_name.clone()))
+        .chain(
+            custom_types
+                .iter()
+                .flat_map(schema::composite_fields)
+                .map(|f| f.type_name),
+        );
+    for type_name in type_names {
+        let type_name = type_name.trim_end_matches("[]").replace('"', "");
+        used.insert(type_name.to_ascii_lowercase());
+    }
+    for ct in custom_types {
+        let is_enum = ct.kind.as_deref() == Some("enum")
+            || (ct.kind.is_none() && !schema::enum_values(ct).is_empty());
+        if !is_enum {
+            continue;
+        }
+        let qualified = schema::qualified_name(ct.schema.as_deref(), &ct.r#type);
+        if !used.contains(&ct.r#type.to_ascii_lowercase())
+            && !used.contains(&qualified.to_ascii_lowercase())
+        {
+            report(
+                format!("/customTypes/{}", escape(&ct.id)),
+                format!("Enum `{}` is not used by any field", qualified),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, enumeration, field_mut, indexed, key, shop, table};
+
+    /// The rule and path of each finding with the default rules.
+    fn findings(diagram: &Diagram) -> Vec<(&'static str, String)> {
+        lint(diagram, &configure(&[]))
+            .findings
+            .into_iter()
+            .map(|f| (f.rule, f.path))
+            .collect()
+    }
+
+    fn found(rule: &'static str, path: &str) -> (&'static str, String) {
+        (rule, path.to_string())
+    }
+
+    #[test]
+    fn a_tidy_diagram_has_no_findings() {
+        assert!(findings(&shop()).is_empty());
+    }
+
+    #[test]
+    fn tables_without_keys_or_relationships() {
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap().push(table(
+            "t_audit",
+            "audit",
+            &[column("f_audit_at", "at", "timestamptz")],
+        ));
+        assert_eq!(
+            findings(&diagram),
+            [
+                found("missing-primary-key", "/tables/t_audit"),
+                found("orphan-table", "/tables/t_audit"),
+            ]
+        );
+    }
+
+    #[test]
+    fn foreign_keys_need_an_index_and_a_matching_type() {
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap()[1].indexes.clear();
+        field_mut(&mut diagram, "t_orders", "f_orders_user")
+            .r#type
+            .name = "bigint".to_string();
+        assert_eq!(
+            findings(&diagram),
+            [
+                found(
+                    "unindexed-foreign-key",
+                    "/tables/t_orders/fields/f_orders_user"
+                ),
+                found("relationship-type-mismatch", "/relationships/r_orders_user"),
+            ]
+        );
+
+        // Aliases of the same type are not a mismatch
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_orders", "f_orders_user")
+            .r#type
+            .name = "int4".to_string();
+        assert!(findings(&diagram).is_empty());
+    }
+
+    #[test]
+    fn names_should_be_snake_case_and_not_reserved() {
+        let mut diagram = shop();
+        field_mut(&mut diagram, "t_users", "f_users_email").name = "eMail".to_string();
+        field_mut(&mut diagram, "t_orders", "f_orders_total").name = "order".to_string();
+        assert_eq!(
+            findings(&diagram),
+            [
+                found("snake-case-names", "/tables/t_users/fields/f_users_email"),
+                found("reserved-words", "/tables/t_orders/fields/f_orders_total"),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_indexes() {
+        let mut diagram = shop();
+        let tables = diagram.tables.as_mut().unwrap();
+        tables[1] = indexed(
+            table(
+                "t_orders",
+                "orders",
+                &[
+                    key("f_orders_id", "id", "integer"),
+                    column("f_orders_user", "user_id", "integer"),
+                ],
+            ),
+            &[
+                ("i_user", "orders_user_id", false, &["f_orders_user"]),
+                ("i_user_again", "orders_user", false, &["f_orders_user"]),
+                ("i_id", "orders_id", true, &["f_orders_id"]),
+            ],
+        );
+        assert_eq!(
+            findings(&diagram),
+            [
+                found("duplicate-index", "/tables/t_orders/indexes/i_user_again"),
+                found("duplicate-index", "/tables/t_orders/indexes/i_id"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_enums() {
+        let mut diagram = shop();
+        diagram.custom_types = Some(vec![
+            enumeration("c_status", "status", &["new", "paid"]),
+            enumeration("c_mood", "mood", &["happy"]),
+        ]);
+        field_mut(&mut diagram, "t_orders", "f_orders_total")
+            .r#type
+            .name = "public.status".to_string();
+        assert_eq!(
+            findings(&diagram),
+            [found("unused-enum", "/customTypes/c_mood")]
+        );
+    }
+
+    #[test]
+    fn overrides_change_what_is_reported() {
+        let overrides = [
+            Override {
+                rule: "table-name-plurality".to_string(),
+                enabled: Some(true),
+                options: Some(serde_json::json!({ "style": "singular" })),
+                ..Override::default()
+            },
+            Override {
+                rule: "orphan-table".to_string(),
+                enabled: Some(false),
+                ..Override::default()
+            },
+            Override {
+                rule: "missing-primary-key".to_string(),
+                severity: Some(Severity::Warning),
+                ..Override::default()
+            },
+        ];
+        let mut diagram = shop();
+        diagram
+            .tables
+            .as_mut()
+            .unwrap()
+            .push(table("t_audit", "audit", &[]));
+
+        let report = lint(&diagram, &configure(&overrides));
+        let findings: Vec<(&str, Severity, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.rule, f.severity, f.path.as_str()))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                ("missing-primary-key", Severity::Warning, "/tables/t_audit"),
+                ("table-name-plurality", Severity::Info, "/tables/t_users"),
+                ("table-name-plurality", Severity::Info, "/tables/t_orders"),
+            ]
+        );
+        assert_eq!(
+            (
+                report.summary.error,
+                report.summary.warning,
+                report.summary.info
+            ),
+            (0, 1, 2)
+        );
+    }
+
+    #[test]
+    fn plurality_guesses() {
+        for plural in ["users", "order_items", "people", "user_data"] {
+            assert!(is_plural(plural), "{}", plural);
+        }
+        for singular in ["user", "address", "status", "order_status", "bus"] {
+            assert!(!is_plural(singular), "{}", singular);
+        }
+    }
+}
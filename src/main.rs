@@ -1,6 +1,26 @@
+mod cli;
+mod codegen;
+mod db;
+mod dbml;
+mod ddl;
+mod dictionary;
+mod diff;
+mod drift;
+mod duplicate;
+mod erd;
+mod error;
+mod extract;
+#[cfg(test)]
+mod fixtures;
 mod handlers;
+mod introspect;
+mod layout;
+mod lint;
+mod merge;
 mod models;
 mod routes;
+mod schema;
+mod validate;
 
 use anyhow::Result;
 use sqlx::PgPool;
@@ -23,6 +43,11 @@ async fn main() -> Result<()> {
         .await
         .map_err(|e| anyhow::anyhow!("Migration failed: {}", e))?;
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if cli::run(&pool, &args).await? {
+        return Ok(());
+    }
+
     let app = routes::create_router(pool).layer(CorsLayer::permissive());
 
     let port = env::var("PORT")
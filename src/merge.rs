@@ -0,0 +1,539 @@
+use crate::models::{DeltaPushRequest, Diagram, EntityChanges};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Diagram-level keys that take part in a merge. Everything else (timestamps,
+/// version) is owned by the server copy.
+const METADATA_KEYS: [&str; 3] = ["name", "databaseType", "databaseEdition"];
+
+/// Keys of the child entities that refer to a table by id.
+const TABLE_REFERENCES: [(&str, [&str; 2]); 2] = [
+    ("relationships", ["sourceTableId", "targetTableId"]),
+    ("dependencies", ["tableId", "dependentTableId"]),
+];
+
+/// Child collections merged entity by entity, keyed on their `id`.
+const COLLECTION_KEYS: [&str; 6] = [
+    "tables",
+    "relationships",
+    "dependencies",
+    "areas",
+    "customTypes",
+    "notes",
+];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// Both sides changed the same value differently.
+    BothModified,
+    /// Both sides added an entity with the same id but different content.
+    BothAdded,
+    /// The server deleted an entity the client modified.
+    DeletedOnServer,
+    /// The client deleted an entity the server modified.
+    DeletedOnClient,
+    /// The client added or changed a reference to a table the server deleted.
+    ReferenceDeleted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergeConflict {
+    /// JSON-pointer-style location, with array entries addressed by id,
+    /// e.g. `/tables/t1/fields/f2/name`.
+    pub path: String,
+    pub kind: ConflictKind,
+    pub base: Option<Value>,
+    pub server: Option<Value>,
+    pub client: Option<Value>,
+}
+
+pub struct MergeOutcome {
+    pub diagram: Diagram,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Three-way merges the client's copy of a diagram into the server's, using
+/// the revision the client started from as the common ancestor.
+///
+/// Entities are matched by id, and entities changed on both sides are merged
+/// key by key, recursing into nested id-keyed arrays such as `Table.fields`
+/// and `Table.indexes`. Where both sides disagree the server value is kept
+/// and a conflict is reported, as is a relationship or dependency that would
+/// be left pointing at a table the server deleted.
+pub fn three_way(
+    base: &Diagram,
+    server: &Diagram,
+    client: &Diagram,
+) -> Result<MergeOutcome, serde_json::Error> {
+    let base = serde_json::to_value(base)?;
+    let server = serde_json::to_value(server)?;
+    let client = serde_json::to_value(client)?;
+
+    let mut conflicts = Vec::new();
+    let mut merged = server.clone();
+
+    for key in METADATA_KEYS.iter().chain(COLLECTION_KEYS.iter()) {
+        let path = format!("/{}", key);
+        let value = merge_value(
+            &path,
+            base.get(*key),
+            server.get(*key),
+            client.get(*key),
+            &mut conflicts,
+        );
+        merged[*key] = value.unwrap_or(Value::Null);
+    }
+    dangling_references(&base, &server, &client, &merged, &mut conflicts);
+
+    Ok(MergeOutcome {
+        diagram: serde_json::from_value(merged)?,
+        conflicts,
+    })
+}
+
+/// Reports references in the merged diagram to tables the server deleted.
+/// Each side is consistent on its own, so these only come from the client
+/// adding or changing a relationship or dependency the server never saw.
+fn dangling_references(
+    base: &Value,
+    server: &Value,
+    client: &Value,
+    merged: &Value,
+    conflicts: &mut Vec<MergeConflict>,
+) {
+    let entities = |diagram: &Value, key: &str| -> Vec<Value> {
+        diagram
+            .get(key)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let table_ids = |diagram: &Value| -> Vec<String> {
+        entities(diagram, "tables")
+            .iter()
+            .filter_map(entity_id)
+            .map(str::to_string)
+            .collect()
+    };
+    let server_tables = table_ids(server);
+    let merged_tables = table_ids(merged);
+    let deleted: Vec<String> = table_ids(base)
+        .into_iter()
+        .filter(|id| !server_tables.contains(id) && !merged_tables.contains(id))
+        .collect();
+    if deleted.is_empty() {
+        return;
+    }
+
+    for (collection, keys) in TABLE_REFERENCES {
+        let sides = [base, server, client].map(|side| entities(side, collection));
+        for entity in entities(merged, collection) {
+            let Some(id) = entity_id(&entity) else {
+                continue;
+            };
+            for key in keys {
+                let target = entity.get(key).and_then(Value::as_str);
+                if !target.is_some_and(|t| deleted.iter().any(|d| d == t)) {
+                    continue;
+                }
+                let [base, server, client] = sides
+                    .each_ref()
+                    .map(|side| find_entity(Some(side), id).and_then(|e| e.get(key)));
+                conflicts.push(conflict(
+                    &format!("/{}/{}/{}", collection, id, key),
+                    ConflictKind::ReferenceDeleted,
+                    base,
+                    server,
+                    client,
+                ));
+            }
+        }
+    }
+}
+
+fn merge_value(
+    path: &str,
+    base: Option<&Value>,
+    server: Option<&Value>,
+    client: Option<&Value>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Option<Value> {
+    // A null collection and a missing one mean the same thing
+    let base = base.filter(|v| !v.is_null());
+    let server = server.filter(|v| !v.is_null());
+    let client = client.filter(|v| !v.is_null());
+
+    if server == client || client == base {
+        return server.cloned();
+    }
+    if server == base {
+        return client.cloned();
+    }
+
+    match (server, client) {
+        (Some(Value::Object(s)), Some(Value::Object(c))) => {
+            let b = base.and_then(Value::as_object);
+            Some(Value::Object(merge_objects(path, b, s, c, conflicts)))
+        }
+        (Some(Value::Array(s)), Some(Value::Array(c))) => {
+            let b = base.and_then(Value::as_array);
+            let keyed = [Some(s), Some(c), b]
+                .into_iter()
+                .flatten()
+                .all(|items| items.iter().all(|item| entity_id(item).is_some()));
+            if keyed {
+                Some(Value::Array(merge_entities(path, b, s, c, conflicts)))
+            } else {
+                conflicts.push(conflict(
+                    path,
+                    ConflictKind::BothModified,
+                    base,
+                    server,
+                    client,
+                ));
+                server.cloned()
+            }
+        }
+        (None, Some(_)) => {
+            conflicts.push(conflict(
+                path,
+                ConflictKind::DeletedOnServer,
+                base,
+                server,
+                client,
+            ));
+            None
+        }
+        (Some(_), None) => {
+            conflicts.push(conflict(
+                path,
+                ConflictKind::DeletedOnClient,
+                base,
+                server,
+                client,
+            ));
+            server.cloned()
+        }
+        _ => {
+            let kind = if base.is_none() {
+                ConflictKind::BothAdded
+            } else {
+                ConflictKind::BothModified
+            };
+            conflicts.push(conflict(path, kind, base, server, client));
+            server.cloned()
+        }
+    }
+}
+
+fn merge_objects(
+    path: &str,
+    base: Option<&Map<String, Value>>,
+    server: &Map<String, Value>,
+    client: &Map<String, Value>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Map<String, Value> {
+    let mut merged = Map::new();
+    let keys = server
+        .keys()
+        .chain(client.keys().filter(|k| !server.contains_key(*k)));
+    for key in keys {
+        let value = merge_value(
+            &format!("{}/{}", path, key),
+            base.and_then(|b| b.get(key)),
+            server.get(key),
+            client.get(key),
+            conflicts,
+        );
+        if let Some(value) = value {
+            merged.insert(key.clone(), value);
+        }
+    }
+    merged
+}
+
+fn merge_entities(
+    path: &str,
+    base: Option<&Vec<Value>>,
+    server: &[Value],
+    client: &[Value],
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Value> {
+    let base = base.map(Vec::as_slice);
+
+    // Keep the server's ordering, then append entities only the client knows
+    // about, then anything the server dropped that the client still changed.
+    let mut ids: Vec<&str> = server.iter().filter_map(entity_id).collect();
+    for id in client.iter().filter_map(entity_id) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    for id in base.unwrap_or_default().iter().filter_map(entity_id) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    ids.into_iter()
+        .filter_map(|id| {
+            merge_value(
+                &format!("{}/{}", path, id),
+                find_entity(base, id),
+                find_entity(Some(server), id),
+                find_entity(Some(client), id),
+                conflicts,
+            )
+        })
+        .collect()
+}
+
+fn find_entity<'a>(items: Option<&'a [Value]>, id: &str) -> Option<&'a Value> {
+    items.and_then(|items| items.iter().find(|item| entity_id(item) == Some(id)))
+}
+
+fn entity_id(value: &Value) -> Option<&str> {
+    value.get("id").and_then(Value::as_str)
+}
+
+fn conflict(
+    path: &str,
+    kind: ConflictKind,
+    base: Option<&Value>,
+    server: Option<&Value>,
+    client: Option<&Value>,
+) -> MergeConflict {
+    MergeConflict {
+        path: path.to_string(),
+        kind,
+        base: base.cloned(),
+        server: server.cloned(),
+        client: client.cloned(),
+    }
+}
+
+/// Replays a delta push on top of the revision it was made against, giving
+/// the diagram as the client sees it.
+pub fn apply_delta(base: &Diagram, changes: &DeltaPushRequest) -> Diagram {
+    fn apply<T: Clone>(
+        current: &Option<Vec<T>>,
+        changes: &EntityChanges<T>,
+        id: impl Fn(&T) -> &str,
+    ) -> Option<Vec<T>> {
+        let mut items: Vec<T> = current
+            .iter()
+            .flatten()
+            .filter(|item| !changes.deletes.iter().any(|d| d == id(item)))
+            .cloned()
+            .collect();
+        for upsert in &changes.upserts {
+            match items.iter_mut().find(|item| id(item) == id(upsert)) {
+                Some(item) => *item = upsert.clone(),
+                None => items.push(upsert.clone()),
+            }
+        }
+        Some(items)
+    }
+
+    Diagram {
+        id: base.id.clone(),
+        name: changes.name.clone().unwrap_or_else(|| base.name.clone()),
+        database_type: changes
+            .database_type
+            .clone()
+            .unwrap_or_else(|| base.database_type.clone()),
+        database_edition: changes
+            .database_edition
+            .clone()
+            .unwrap_or_else(|| base.database_edition.clone()),
+        created_at: base.created_at,
+        updated_at: base.updated_at,
+        version: base.version,
+        tables: apply(&base.tables, &changes.tables, |t| &t.id),
+        relationships: apply(&base.relationships, &changes.relationships, |r| &r.id),
+        dependencies: apply(&base.dependencies, &changes.dependencies, |d| &d.id),
+        areas: apply(&base.areas, &changes.areas, |a| &a.id),
+        custom_types: apply(&base.custom_types, &changes.custom_types, |c| &c.id),
+        notes: apply(&base.notes, &changes.notes, |n| &n.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{area, column, dependency, field_mut, relationship, shop, table};
+    use serde_json::json;
+
+    fn paths(outcome: &MergeOutcome) -> Vec<&str> {
+        outcome.conflicts.iter().map(|c| c.path.as_str()).collect()
+    }
+
+    /// `shop()` with `users` and the relationship to it deleted.
+    fn without_users() -> Diagram {
+        let mut diagram = shop();
+        diagram.tables.as_mut().unwrap().remove(0);
+        diagram.relationships = Some(Vec::new());
+        diagram
+    }
+
+    #[test]
+    fn changes_to_different_entities_are_merged() {
+        let base = shop();
+        let mut server = shop();
+        server.name = "Store".to_string();
+        field_mut(&mut server, "t_users", "f_users_email").nullable = false;
+        let mut client = shop();
+        client.tables.as_mut().unwrap().push(table(
+            "t_items",
+            "items",
+            &[column("f_items_name", "name", "text")],
+        ));
+        field_mut(&mut client, "t_orders", "f_orders_total").name = "amount".to_string();
+
+        let outcome = three_way(&base, &server, &client).unwrap();
+        assert!(outcome.conflicts.is_empty());
+        let merged = outcome.diagram;
+        assert_eq!(merged.name, "Store");
+        let tables = merged.tables.as_deref().unwrap();
+        let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["users", "orders", "items"]);
+        assert!(!tables[0].fields[1].nullable);
+        assert_eq!(tables[1].fields[2].name, "amount");
+    }
+
+    #[test]
+    fn both_sides_changing_a_value_keeps_the_server_one() {
+        let base = shop();
+        let mut server = shop();
+        field_mut(&mut server, "t_orders", "f_orders_total").name = "sum".to_string();
+        let mut client = shop();
+        field_mut(&mut client, "t_orders", "f_orders_total").name = "amount".to_string();
+
+        let outcome = three_way(&base, &server, &client).unwrap();
+        assert_eq!(
+            paths(&outcome),
+            ["/tables/t_orders/fields/f_orders_total/name"]
+        );
+        assert!(matches!(
+            outcome.conflicts[0].kind,
+            ConflictKind::BothModified
+        ));
+        assert_eq!(outcome.diagram.tables.unwrap()[1].fields[2].name, "sum");
+    }
+
+    #[test]
+    fn editing_a_table_the_server_deleted_conflicts() {
+        let mut client = shop();
+        field_mut(&mut client, "t_users", "f_users_email").name = "mail".to_string();
+
+        let outcome = three_way(&shop(), &without_users(), &client).unwrap();
+        assert_eq!(paths(&outcome), ["/tables/t_users"]);
+        assert!(matches!(
+            outcome.conflicts[0].kind,
+            ConflictKind::DeletedOnServer
+        ));
+    }
+
+    #[test]
+    fn new_relationships_to_tables_the_server_deleted_conflict() {
+        let mut client = shop();
+        client.relationships.as_mut().unwrap().push(relationship(
+            "r_orders_user_again",
+            ("t_orders", "f_orders_id"),
+            ("t_users", "f_users_id"),
+        ));
+
+        let outcome = three_way(&shop(), &without_users(), &client).unwrap();
+        assert_eq!(
+            paths(&outcome),
+            ["/relationships/r_orders_user_again/targetTableId"]
+        );
+        let conflict = &outcome.conflicts[0];
+        assert!(matches!(conflict.kind, ConflictKind::ReferenceDeleted));
+        assert_eq!(conflict.base, None);
+        assert_eq!(conflict.client, Some(Value::from("t_users")));
+    }
+
+    #[test]
+    fn new_dependencies_on_tables_the_server_deleted_conflict() {
+        let mut client = shop();
+        client.dependencies = Some(vec![dependency("d_orders_users", "t_users", "t_orders")]);
+
+        let outcome = three_way(&shop(), &without_users(), &client).unwrap();
+        assert_eq!(paths(&outcome), ["/dependencies/d_orders_users/tableId"]);
+    }
+
+    #[test]
+    fn references_to_tables_the_client_deleted_are_left_to_validation() {
+        let mut client = without_users();
+        client.relationships = shop().relationships;
+
+        let outcome = three_way(&shop(), &shop(), &client).unwrap();
+        assert!(outcome.conflicts.is_empty());
+    }
+
+    fn ids<T>(items: &Option<Vec<T>>, id: impl Fn(&T) -> &str) -> Vec<&str> {
+        items.iter().flatten().map(id).collect()
+    }
+
+    #[test]
+    fn deltas_replace_append_and_delete_by_id() {
+        let mut base = shop();
+        base.database_edition = Some("postgresql_supabase".to_string());
+        let mut users = base.tables.as_ref().unwrap()[0].clone();
+        users.name = "people".to_string();
+        let changes: DeltaPushRequest = serde_json::from_value(json!({
+            "diagramId": base.id,
+            "name": "Store",
+            "databaseEdition": null,
+            "tables": {
+                "upserts": [users, table("t_coupons", "coupons", &[])],
+                "deletes": ["t_orders"],
+            },
+            "relationships": { "deletes": ["r_orders_user", "r_never_there"] },
+            "areas": { "upserts": [area("a_sales", "Sales", 0.0, 0.0)] },
+        }))
+        .unwrap();
+
+        let applied = apply_delta(&base, &changes);
+        assert_eq!(applied.name, "Store");
+        assert_eq!(applied.database_type, base.database_type);
+        assert_eq!(applied.database_edition, None);
+        // Replaced in place, new ones last
+        assert_eq!(ids(&applied.tables, |t| &t.id), ["t_users", "t_coupons"]);
+        assert_eq!(applied.tables.as_ref().unwrap()[0].name, "people");
+        assert_eq!(ids(&applied.relationships, |r| &r.id), Vec::<&str>::new());
+        assert_eq!(ids(&applied.areas, |a| &a.id), ["a_sales"]);
+        assert_eq!(applied.version, base.version);
+    }
+
+    #[test]
+    fn empty_deltas_change_nothing() {
+        let base = shop();
+        let changes: DeltaPushRequest =
+            serde_json::from_value(json!({ "diagramId": base.id })).unwrap();
+        let applied = apply_delta(&base, &changes);
+        assert_eq!(
+            serde_json::to_value(&applied).unwrap(),
+            serde_json::to_value(&base).unwrap()
+        );
+    }
+
+    #[test]
+    fn ids_deleted_and_upserted_are_kept() {
+        let base = shop();
+        let mut orders = base.tables.as_ref().unwrap()[1].clone();
+        orders.comment = Some("recreated".to_string());
+        let changes: DeltaPushRequest = serde_json::from_value(json!({
+            "diagramId": base.id,
+            "tables": { "upserts": [orders], "deletes": ["t_orders"] },
+        }))
+        .unwrap();
+
+        let applied = apply_delta(&base, &changes);
+        let tables = applied.tables.unwrap();
+        assert_eq!(tables[1].id, "t_orders");
+        assert_eq!(tables[1].comment.as_deref(), Some("recreated"));
+    }
+}
@@ -1,6 +1,19 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A new entity id in ChartDB's format: 25 lowercase alphanumerics.
+pub fn new_id() -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut value = uuid::Uuid::new_v4().as_u128();
+    (0..25)
+        .map(|_| {
+            let c = ALPHABET[(value % 36) as usize] as char;
+            value /= 36;
+            c
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Diagram {
     pub id: String,
     pub name: String,
@@ -12,6 +25,9 @@ pub struct Diagram {
     pub created_at: chrono::DateTime<chrono::Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Server-side revision counter, bumped on every successful push.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
     pub tables: Option<Vec<Table>>,
     pub relationships: Option<Vec<Relationship>>,
     pub dependencies: Option<Vec<Dependency>>,
@@ -21,7 +37,7 @@ pub struct Diagram {
     pub notes: Option<Vec<Note>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Table {
     pub id: String,
     #[serde(rename = "diagramId")]
@@ -38,11 +54,216 @@ pub struct Table {
     #[serde(rename = "isMaterializedView")]
     pub is_materialized_view: Option<bool>,
     pub order: Option<i32>,
-    pub fields: serde_json::Value,
-    pub indexes: serde_json::Value,
+    #[serde(default, deserialize_with = "lenient::list")]
+    pub fields: Vec<Field>,
+    #[serde(default, deserialize_with = "lenient::list")]
+    pub indexes: Vec<Index>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A column in `Table.fields`. Values of the wrong JSON type are coerced
+/// where they can be, and keys the server does not know about are kept in
+/// `extra`, as are values it cannot read as given (see [`lenient::Reader`]),
+/// so that they survive a round-trip.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Field {
+    pub id: String,
+    pub name: String,
+    pub r#type: FieldType,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub nullable: bool,
+    /// Auto-incrementing column.
+    pub increment: Option<bool>,
+    /// An array of `type`.
+    pub is_array: Option<bool>,
+    pub default: Option<String>,
+    pub collation: Option<String>,
+    pub comments: Option<String>,
+    pub character_maximum_length: Option<String>,
+    pub precision: Option<i64>,
+    pub scale: Option<i64>,
+    pub created_at: Option<i64>,
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// An entry of `Table.fields` that is not an object, kept as it is.
+    pub raw: Option<serde_json::Value>,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut object = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Object(map) => lenient::Reader::new(map),
+            other => {
+                return Ok(Field {
+                    raw: Some(other),
+                    ..Field::default()
+                })
+            }
+        };
+        Ok(Field {
+            id: object.read("id", lenient::string).unwrap_or_default(),
+            name: object.read("name", lenient::string).unwrap_or_default(),
+            r#type: object.read("type", FieldType::read).unwrap_or_default(),
+            primary_key: object.read("primaryKey", lenient::boolean).unwrap_or(false),
+            unique: object.read("unique", lenient::boolean).unwrap_or(false),
+            nullable: object
+                .read("nullable", lenient::boolean_or_yes)
+                .unwrap_or(true),
+            increment: object
+                .read("increment", lenient::optional_boolean)
+                .flatten(),
+            is_array: object.read("isArray", lenient::optional_boolean).flatten(),
+            default: object.read("default", lenient::optional_string).flatten(),
+            collation: object.read("collation", lenient::optional_string).flatten(),
+            comments: object.read("comments", lenient::optional_string).flatten(),
+            character_maximum_length: object
+                .read("characterMaximumLength", lenient::optional_string)
+                .flatten(),
+            precision: object
+                .read("precision", lenient::optional_integer)
+                .flatten(),
+            scale: object.read("scale", lenient::optional_integer).flatten(),
+            created_at: object
+                .read("createdAt", lenient::optional_integer)
+                .flatten(),
+            extra: object.extra(),
+            raw: None,
+        })
+    }
+}
+
+impl Serialize for Field {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(raw) = &self.raw {
+            return raw.serialize(serializer);
+        }
+        let mut object = lenient::Writer::new(serializer, &self.extra)?;
+        object.entry("id", &self.id, lenient::string)?;
+        object.entry("name", &self.name, lenient::string)?;
+        object.entry("type", &self.r#type, FieldType::read)?;
+        object.entry("primaryKey", &self.primary_key, lenient::boolean)?;
+        object.entry("unique", &self.unique, lenient::boolean)?;
+        object.entry("nullable", &self.nullable, lenient::boolean_or_yes)?;
+        object.optional_entry("increment", &self.increment, lenient::optional_boolean)?;
+        object.optional_entry("isArray", &self.is_array, lenient::optional_boolean)?;
+        object.optional_entry("default", &self.default, lenient::optional_string)?;
+        object.optional_entry("collation", &self.collation, lenient::optional_string)?;
+        object.optional_entry("comments", &self.comments, lenient::optional_string)?;
+        object.optional_entry(
+            "characterMaximumLength",
+            &self.character_maximum_length,
+            lenient::optional_string,
+        )?;
+        object.optional_entry("precision", &self.precision, lenient::optional_integer)?;
+        object.optional_entry("scale", &self.scale, lenient::optional_integer)?;
+        object.optional_entry("createdAt", &self.created_at, lenient::optional_integer)?;
+        object.end()
+    }
+}
+
+/// A field's data type, `{ "id": "varchar", "name": "varchar" }` in ChartDB.
+/// Older payloads give the type as a plain string, which is read as both and
+/// written back as a string.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldType {
+    pub id: String,
+    pub name: String,
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl FieldType {
+    fn read(value: &serde_json::Value) -> FieldType {
+        let serde_json::Value::Object(map) = value else {
+            let name = lenient::string(value);
+            return FieldType {
+                id: name.clone(),
+                name,
+                extra: serde_json::Map::new(),
+            };
+        };
+        let mut object = lenient::Reader::new(map.clone());
+        let id = object.read("id", lenient::optional_string).flatten();
+        let name = object.read("name", lenient::optional_string).flatten();
+        FieldType {
+            id: id.clone().or_else(|| name.clone()).unwrap_or_default(),
+            name: name.or(id).unwrap_or_default(),
+            extra: object.extra(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(FieldType::read(&serde_json::Value::deserialize(
+            deserializer,
+        )?))
+    }
+}
+
+impl Serialize for FieldType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut object = lenient::Writer::new(serializer, &self.extra)?;
+        object.entry("id", &self.id, lenient::string)?;
+        object.entry("name", &self.name, lenient::string)?;
+        object.end()
+    }
+}
+
+/// An index in `Table.indexes`, read as leniently as [`Field`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Index {
+    pub id: String,
+    pub name: String,
+    pub unique: bool,
+    pub field_ids: Vec<String>,
+    pub created_at: Option<i64>,
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// An entry of `Table.indexes` that is not an object, kept as it is.
+    pub raw: Option<serde_json::Value>,
+}
+
+impl<'de> Deserialize<'de> for Index {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut object = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Object(map) => lenient::Reader::new(map),
+            other => {
+                return Ok(Index {
+                    raw: Some(other),
+                    ..Index::default()
+                })
+            }
+        };
+        Ok(Index {
+            id: object.read("id", lenient::string).unwrap_or_default(),
+            name: object.read("name", lenient::string).unwrap_or_default(),
+            unique: object.read("unique", lenient::boolean).unwrap_or(false),
+            field_ids: object
+                .read("fieldIds", lenient::strings)
+                .unwrap_or_default(),
+            created_at: object
+                .read("createdAt", lenient::optional_integer)
+                .flatten(),
+            extra: object.extra(),
+            raw: None,
+        })
+    }
+}
+
+impl Serialize for Index {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(raw) = &self.raw {
+            return raw.serialize(serializer);
+        }
+        let mut object = lenient::Writer::new(serializer, &self.extra)?;
+        object.entry("id", &self.id, lenient::string)?;
+        object.entry("name", &self.name, lenient::string)?;
+        object.entry("unique", &self.unique, lenient::boolean)?;
+        object.entry("fieldIds", &self.field_ids, lenient::strings)?;
+        object.optional_entry("createdAt", &self.created_at, lenient::optional_integer)?;
+        object.end()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Relationship {
     pub id: String,
     #[serde(rename = "diagramId")]
@@ -66,7 +287,7 @@ pub struct Relationship {
     pub target_cardinality: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dependency {
     pub id: String,
     #[serde(rename = "diagramId")]
@@ -80,7 +301,7 @@ pub struct Dependency {
     pub dependent_table_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Area {
     pub id: String,
     #[serde(rename = "diagramId")]
@@ -93,20 +314,148 @@ pub struct Area {
     pub color: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Area {
+    /// Whether a table sits inside the area. ChartDB does not store area
+    /// membership, so it is derived from the table's position.
+    pub fn contains(&self, table: &Table) -> bool {
+        let (Some(x), Some(y)) = (table.x, table.y) else {
+            return false;
+        };
+        let (Some(left), Some(top)) = (self.x, self.y) else {
+            return false;
+        };
+        let right = left + self.width.unwrap_or_default();
+        let bottom = top + self.height.unwrap_or_default();
+        (left..=right).contains(&x) && (top..=bottom).contains(&y)
+    }
+}
+
+/// A custom type, read as leniently as [`Field`]: enum labels that are not
+/// strings are read as their text where they have one and otherwise left
+/// out of `values`, and the list is kept as sent in `extra`.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct CustomType {
     pub id: String,
-    #[serde(rename = "diagramId")]
     pub diagram_id: String,
     pub schema: Option<String>,
-    #[serde(rename = "type")]
     pub r#type: String,
     pub kind: Option<String>,
-    pub values: Option<serde_json::Value>,
-    pub fields: Option<serde_json::Value>,
+    /// Labels of an enum type.
+    pub values: Option<Vec<String>>,
+    /// Attributes of a composite type.
+    pub fields: Option<Vec<CustomTypeField>>,
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl CustomType {
+    /// `values` the way it is written back, which is how it is stored.
+    pub fn values_json(&self) -> Option<serde_json::Value> {
+        match self.extra.get("values") {
+            Some(original) if lenient::optional_strings(original) == self.values => {
+                Some(original.clone())
+            }
+            _ => self.values.as_ref().map(|values| serde_json::json!(values)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serde_json::Value::Object(map) = serde_json::Value::deserialize(deserializer)? else {
+            return Err(serde::de::Error::custom("expected a custom type object"));
+        };
+        let mut object = lenient::Reader::new(map);
+        Ok(CustomType {
+            id: object.read("id", lenient::string).unwrap_or_default(),
+            diagram_id: object
+                .read("diagramId", lenient::string)
+                .unwrap_or_default(),
+            schema: object.read("schema", lenient::optional_string).flatten(),
+            r#type: object.read("type", lenient::string).unwrap_or_default(),
+            kind: object.read("kind", lenient::optional_string).flatten(),
+            values: object.read("values", lenient::optional_strings).flatten(),
+            fields: object
+                .read("fields", CustomTypeField::optional_list)
+                .flatten(),
+            extra: object.extra(),
+        })
+    }
+}
+
+impl Serialize for CustomType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut object = lenient::Writer::new(serializer, &self.extra)?;
+        object.entry("id", &self.id, lenient::string)?;
+        object.entry("diagramId", &self.diagram_id, lenient::string)?;
+        object.optional_entry("schema", &self.schema, lenient::optional_string)?;
+        object.entry("type", &self.r#type, lenient::string)?;
+        object.optional_entry("kind", &self.kind, lenient::optional_string)?;
+        object.optional_entry("values", &self.values, lenient::optional_strings)?;
+        object.optional_entry("fields", &self.fields, CustomTypeField::optional_list)?;
+        object.end()
+    }
+}
+
+/// An attribute of a composite type, read as leniently as [`Field`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomTypeField {
+    pub field: String,
+    /// A plain type name, or read from a `{ "id", "name" }` object.
+    pub r#type: String,
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// An entry of `CustomType.fields` that is not an object, kept as it is.
+    pub raw: Option<serde_json::Value>,
+}
+
+impl CustomTypeField {
+    /// The attributes listed in `CustomType.fields`.
+    fn optional_list(value: &serde_json::Value) -> Option<Vec<CustomTypeField>> {
+        let serde_json::Value::Array(items) = value else {
+            return None;
+        };
+        let read = |item: &serde_json::Value| {
+            serde_json::from_value(item.clone()).unwrap_or_else(|_| CustomTypeField {
+                raw: Some(item.clone()),
+                ..CustomTypeField::default()
+            })
+        };
+        Some(items.iter().map(read).collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomTypeField {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut object = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Object(map) => lenient::Reader::new(map),
+            other => {
+                return Ok(CustomTypeField {
+                    raw: Some(other),
+                    ..CustomTypeField::default()
+                })
+            }
+        };
+        Ok(CustomTypeField {
+            field: object.read("field", lenient::string).unwrap_or_default(),
+            r#type: object.read("type", lenient::type_name).unwrap_or_default(),
+            extra: object.extra(),
+            raw: None,
+        })
+    }
+}
+
+impl Serialize for CustomTypeField {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(raw) = &self.raw {
+            return raw.serialize(serializer);
+        }
+        let mut object = lenient::Writer::new(serializer, &self.extra)?;
+        object.entry("field", &self.field, lenient::string)?;
+        object.entry("type", &self.r#type, lenient::type_name)?;
+        object.end()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Note {
     pub id: String,
     #[serde(rename = "diagramId")]
@@ -122,6 +471,107 @@ pub struct Note {
 #[derive(Debug, Deserialize)]
 pub struct PushRequest {
     pub diagram: Diagram,
+    /// Version the client last pulled. When the server copy has moved on
+    /// since, the push is three-way merged with it, using this revision as
+    /// the common ancestor; only overlapping changes are rejected with 409.
+    #[serde(rename = "baseVersion")]
+    pub base_version: Option<i32>,
+    /// Recorded on the revision created by this push.
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Upserts and deletes (by id) for one entity kind, used both by delta pushes
+/// and by incremental pulls.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+pub struct EntityChanges<T> {
+    #[serde(default)]
+    pub upserts: Vec<T>,
+    #[serde(default)]
+    pub deletes: Vec<String>,
+}
+
+impl<T> Default for EntityChanges<T> {
+    fn default() -> Self {
+        Self {
+            upserts: Vec::new(),
+            deletes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeltaPushRequest {
+    #[serde(rename = "diagramId")]
+    pub diagram_id: String,
+    #[serde(rename = "baseVersion")]
+    pub base_version: Option<i32>,
+    pub author: Option<String>,
+    pub message: Option<String>,
+    pub name: Option<String>,
+    #[serde(rename = "databaseType")]
+    pub database_type: Option<String>,
+    /// `null` clears the edition; a missing key leaves it as it is.
+    #[serde(default, rename = "databaseEdition", deserialize_with = "present")]
+    pub database_edition: Option<Option<String>>,
+    #[serde(default)]
+    pub tables: EntityChanges<Table>,
+    #[serde(default)]
+    pub relationships: EntityChanges<Relationship>,
+    #[serde(default)]
+    pub dependencies: EntityChanges<Dependency>,
+    #[serde(default)]
+    pub areas: EntityChanges<Area>,
+    #[serde(default, rename = "customTypes")]
+    pub custom_types: EntityChanges<CustomType>,
+    #[serde(default)]
+    pub notes: EntityChanges<Note>,
+}
+
+/// Revision metadata for writes to a single entity, whose body is the
+/// entity itself.
+#[derive(Debug, Deserialize)]
+pub struct EntityQuery {
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityResponse<T> {
+    pub diagram_id: String,
+    pub version: i32,
+    pub entity: T,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    /// Diagram version or RFC 3339 timestamp; omitted means "everything".
+    /// Timestamps can miss pushes that were in flight at that time.
+    pub since: Option<String>,
+}
+
+/// Entities changed after a cursor, in the same shape as a delta push.
+#[derive(Debug, Serialize)]
+pub struct ChangesResponse {
+    #[serde(rename = "diagramId")]
+    pub diagram_id: String,
+    pub version: i32,
+    pub name: String,
+    #[serde(rename = "databaseType")]
+    pub database_type: String,
+    #[serde(rename = "databaseEdition")]
+    pub database_edition: Option<String>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub tables: EntityChanges<Table>,
+    pub relationships: EntityChanges<Relationship>,
+    pub dependencies: EntityChanges<Dependency>,
+    pub areas: EntityChanges<Area>,
+    #[serde(rename = "customTypes")]
+    pub custom_types: EntityChanges<CustomType>,
+    pub notes: EntityChanges<Note>,
 }
 
 #[derive(Debug, Serialize)]
@@ -129,10 +579,619 @@ pub struct PushResponse {
     pub success: bool,
     #[serde(rename = "diagramId")]
     pub diagram_id: String,
+    pub version: i32,
+    /// True when the push was made against an older version and the server
+    /// merged it with the changes made since; pull to get the merged result.
+    pub merged: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionSummary {
+    pub version: i32,
+    pub author: Option<String>,
+    pub message: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    #[serde(flatten)]
+    pub summary: RevisionSummary,
+    pub diagram: Diagram,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RestoreRequest {
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Diagram details to change. Omitted keys are left as they are; a `null`
+/// `databaseEdition` clears it.
+#[derive(Debug, Deserialize)]
+pub struct DiagramPatch {
+    pub name: Option<String>,
+    #[serde(rename = "databaseType")]
+    pub database_type: Option<String>,
+    #[serde(default, rename = "databaseEdition", deserialize_with = "present")]
+    pub database_edition: Option<Option<String>>,
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Tells a key that is `null` (`Some(None)`) apart from one that is missing
+/// (`None`, through `#[serde(default)]`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DuplicateRequest {
+    /// Name of the copy; the original's name with ` (copy)` if omitted.
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    /// Revision of the diagram to compare from; the current state if omitted.
+    pub from: Option<i32>,
+    /// Revision to compare to; the current state if omitted.
+    pub to: Option<i32>,
+    /// Compare against another diagram instead of the same one.
+    pub against: Option<String>,
+    /// `json` (default) or `text`.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffSide {
+    #[serde(rename = "diagramId")]
+    pub diagram_id: String,
+    pub version: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffResponse {
+    pub from: DiffSide,
+    pub to: DiffSide,
+    #[serde(flatten)]
+    pub diff: crate::diff::DiagramDiff,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MigrationQuery {
+    /// Revision the database is currently at; the current state if omitted.
+    pub from: Option<i32>,
+    /// Revision to migrate to; the current state if omitted.
+    pub to: Option<i32>,
+    /// Migrate towards another diagram instead of a revision of the same one.
+    pub against: Option<String>,
+    /// Also generate the script that undoes the migration.
+    #[serde(default)]
+    pub rollback: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationResponse {
+    pub from: DiffSide,
+    pub to: DiffSide,
+    pub dialect: crate::ddl::Dialect,
+    pub forward: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Target SQL dialect; defaults to the diagram's `databaseType`.
+    pub dialect: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErdQuery {
+    /// Only draw the tables of this schema.
+    pub schema: Option<String>,
+    /// Only draw the tables inside this area (id or name).
+    pub area: Option<String>,
+    /// `keys` to list only key columns; `all` by default.
+    pub fields: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DictionaryQuery {
+    /// `markdown` (default) or `html`.
+    pub format: Option<String>,
+    /// Only document the tables of this schema.
+    pub schema: Option<String>,
+    /// Only document the tables inside this area (id or name).
+    pub area: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrmQuery {
+    /// `prisma`, `diesel`, `seaorm` or `sqlalchemy`.
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    /// Only draw the tables of this schema.
+    pub schema: Option<String>,
+    /// Only draw the tables inside this area (id or name).
+    pub area: Option<String>,
+    /// Pixels per canvas unit for PNG; 2 by default.
+    pub scale: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DbmlImportRequest {
+    pub dbml: String,
+    /// Name of the new diagram; defaults to the DBML `Project` name.
+    pub name: Option<String>,
+    /// Defaults to the `database_type` of the DBML `Project`.
+    #[serde(rename = "databaseType")]
+    pub database_type: Option<String>,
+    /// Recorded on the diagram's first revision.
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SqlImportRequest {
+    pub sql: String,
+    /// Dialect the script is written in; defaults to `databaseType`, then
+    /// `postgresql`.
+    pub dialect: Option<String>,
+    /// Name of the new diagram; defaults to "Imported schema".
+    pub name: Option<String>,
+    /// Defaults to the dialect.
+    #[serde(rename = "databaseType")]
+    pub database_type: Option<String>,
+    /// Recorded on the diagram's first revision.
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntrospectRequest {
+    /// PostgreSQL connection string of the database to read.
+    #[serde(rename = "connectionString")]
+    pub connection_string: String,
+    /// Diagram to update; a new diagram is created when absent or unknown.
+    #[serde(rename = "diagramId")]
+    pub diagram_id: Option<String>,
+    /// Name of a new diagram; defaults to the database name.
+    pub name: Option<String>,
+    /// Schemas to read; all but the system schemas by default.
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// Recorded on the resulting revision.
+    pub author: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriftRequest {
+    /// PostgreSQL connection string of the database to check.
+    #[serde(rename = "connectionString")]
+    pub connection_string: String,
+    /// Schemas to check; by default those the diagram's tables are in.
+    pub schemas: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftResponse {
+    pub diagram_id: String,
+    pub version: Option<i32>,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    pub report: crate::drift::DriftReport,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LintResponse {
+    pub diagram_id: String,
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    pub report: crate::lint::LintReport,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LintRulesResponse {
+    pub diagram_id: String,
+    pub rules: Vec<crate::lint::RuleConfig>,
+}
+
+/// Changes to a diagram's lint rules, by rule id. `null` restores a rule's
+/// defaults; rules that are not listed are left as they are.
+#[derive(Debug, Deserialize)]
+pub struct LintRulesRequest {
+    pub rules: std::collections::BTreeMap<String, Option<LintRuleChange>>,
+}
+
+/// A rule's settings; omitted keys use the rule's default.
+#[derive(Debug, Deserialize)]
+pub struct LintRuleChange {
+    pub enabled: Option<bool>,
+    /// `error`, `warning` or `info`.
+    pub severity: Option<String>,
+    pub options: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqlImportResponse {
+    #[serde(flatten)]
+    pub push: PushResponse,
+    /// Statements that were skipped or only partly imported.
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
+    /// Stable, machine-readable error code, e.g. `diagram_not_found`.
+    pub code: &'static str,
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Readers for the JSON shapes ChartDB owns. Clients of different versions
+/// send them, so values of the wrong type are coerced where there is an
+/// obvious reading. What cannot be read is not an error: it is kept as sent,
+/// in `extra` or as a `raw` list entry, and written back unchanged.
+pub mod lenient {
+    use serde::de::{DeserializeOwned, Error};
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::{Map, Value};
+
+    pub fn coerce_string(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+
+    fn coerce_boolean(value: &Value) -> Option<bool> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) => s.parse().ok(),
+            Value::Number(n) => n.as_i64().map(|n| n != 0),
+            _ => None,
+        }
+    }
+
+    fn coerce_integer(value: &Value) -> Option<i64> {
+        match value {
+            Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn string(value: &Value) -> String {
+        coerce_string(value).unwrap_or_default()
+    }
+
+    pub fn optional_string(value: &Value) -> Option<String> {
+        coerce_string(value)
+    }
+
+    pub fn boolean(value: &Value) -> bool {
+        coerce_boolean(value).unwrap_or(false)
+    }
+
+    /// A flag that is set unless it says otherwise, such as `nullable`.
+    pub fn boolean_or_yes(value: &Value) -> bool {
+        coerce_boolean(value).unwrap_or(true)
+    }
+
+    pub fn optional_boolean(value: &Value) -> Option<bool> {
+        coerce_boolean(value)
+    }
+
+    pub fn optional_integer(value: &Value) -> Option<i64> {
+        coerce_integer(value)
+    }
+
+    /// A type name, from a plain string or a `{ "id", "name" }` object.
+    pub fn type_name(value: &Value) -> String {
+        match value {
+            Value::Object(t) => t
+                .get("name")
+                .and_then(coerce_string)
+                .or_else(|| t.get("id").and_then(coerce_string))
+                .unwrap_or_default(),
+            other => string(other),
+        }
+    }
+
+    /// The entries of a list that read as strings.
+    pub fn strings(value: &Value) -> Vec<String> {
+        match value {
+            Value::Array(items) => items.iter().filter_map(coerce_string).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A JSON object being read into a struct. Each key is taken out as it is
+    /// read, unless the struct would not write its value back the same way,
+    /// like `"increment": "yes"`, `"default": null` or a `type` given as a
+    /// plain string. Those stay, together with the keys that are not read,
+    /// as the struct's `extra`, from where [`Writer`] writes them back.
+    pub struct Reader(Map<String, Value>);
+
+    impl Reader {
+        pub fn new(object: Map<String, Value>) -> Self {
+            Reader(object)
+        }
+
+        pub fn read<T: Serialize>(&mut self, key: &str, read: fn(&Value) -> T) -> Option<T> {
+            let original = self.0.get(key)?;
+            let value = read(original);
+            // A missing value is written by leaving its key out, so `null`
+            // has to stay
+            if !original.is_null() && serde_json::to_value(&value).ok().as_ref() == Some(original) {
+                self.0.remove(key);
+            }
+            Some(value)
+        }
+
+        pub fn extra(self) -> Map<String, Value> {
+            self.0
+        }
+    }
+
+    /// Writes a struct read with [`Reader`]. Each key gets the struct's value,
+    /// or the original spelling kept in `extra` while that still reads as the
+    /// same value; the struct's own value wins once it has been changed.
+    pub struct Writer<'a, M> {
+        map: M,
+        extra: &'a Map<String, Value>,
+        written: Vec<&'static str>,
+    }
+
+    impl<'a, M: SerializeMap> Writer<'a, M> {
+        pub fn new<S>(serializer: S, extra: &'a Map<String, Value>) -> Result<Self, S::Error>
+        where
+            S: Serializer<SerializeMap = M>,
+        {
+            Ok(Writer {
+                map: serializer.serialize_map(None)?,
+                extra,
+                written: Vec::new(),
+            })
+        }
+
+        pub fn entry<T: Serialize + PartialEq>(
+            &mut self,
+            key: &'static str,
+            value: &T,
+            read: fn(&Value) -> T,
+        ) -> Result<(), M::Error> {
+            self.write(key, value, read, false)
+        }
+
+        /// Like [`Self::entry`], leaving the key out for `None`.
+        pub fn optional_entry<T: Serialize + PartialEq>(
+            &mut self,
+            key: &'static str,
+            value: &Option<T>,
+            read: fn(&Value) -> Option<T>,
+        ) -> Result<(), M::Error> {
+            self.write(key, value, read, value.is_none())
+        }
+
+        fn write<T: Serialize + PartialEq>(
+            &mut self,
+            key: &'static str,
+            value: &T,
+            read: fn(&Value) -> T,
+            omit: bool,
+        ) -> Result<(), M::Error> {
+            self.written.push(key);
+            match self.extra.get(key) {
+                Some(original) if read(original) == *value => {
+                    self.map.serialize_entry(key, original)
+                }
+                _ if omit => Ok(()),
+                _ => self.map.serialize_entry(key, value),
+            }
+        }
+
+        /// Writes the keys of `extra` that are not the struct's own.
+        pub fn end(mut self) -> Result<M::Ok, M::Error> {
+            for (key, value) in self.extra {
+                if !self.written.contains(&key.as_str()) {
+                    self.map.serialize_entry(key, value)?;
+                }
+            }
+            self.map.end()
+        }
+    }
+
+    /// A list of strings, such as enum labels, as [`strings`] reads it.
+    pub fn optional_strings(value: &Value) -> Option<Vec<String>> {
+        match value {
+            Value::Array(_) => Some(strings(value)),
+            _ => None,
+        }
+    }
+
+    /// A list of ChartDB objects. Entries that are not objects are kept as
+    /// they are by the entry types.
+    pub fn list<'de, D: Deserializer<'de>, T: DeserializeOwned>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error> {
+        Ok(optional_list(deserializer)?.unwrap_or_default())
+    }
+
+    pub fn optional_list<'de, D: Deserializer<'de>, T: DeserializeOwned>(
+        deserializer: D,
+    ) -> Result<Option<Vec<T>>, D::Error> {
+        Option::<Vec<Value>>::deserialize(deserializer)?
+            .map(|items| {
+                items
+                    .into_iter()
+                    .map(|item| serde_json::from_value(item).map_err(D::Error::custom))
+                    .collect()
+            })
+            .transpose()
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn revisions_carry_their_snapshot() {
+        let diagram = crate::fixtures::shop();
+        // Snapshots are stored as JSON and read back as the diagram they were
+        let snapshot = serde_json::to_value(&diagram).unwrap();
+        let restored: Diagram = serde_json::from_value(snapshot.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&restored).unwrap(), snapshot);
+
+        let revision = Revision {
+            summary: RevisionSummary {
+                version: 3,
+                author: Some("ana".to_string()),
+                message: None,
+                created_at: chrono::DateTime::UNIX_EPOCH,
+            },
+            diagram,
+        };
+        let json = serde_json::to_value(&revision).unwrap();
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["author"], "ana");
+        assert_eq!(json["message"], json!(null));
+        assert_eq!(json["createdAt"], "1970-01-01T00:00:00Z");
+        assert_eq!(json["diagram"], snapshot);
+    }
+
+    #[test]
+    fn odd_fields_round_trip_unchanged() {
+        let payload = concat!(
+            r#"{"id":7,"name":"price","type":"numeric","primaryKey":"true","unique":false,"#,
+            r#""nullable":"no","increment":"yes","isArray":true,"default":{"fn":"now"},"#,
+            r#""collation":null,"comments":"","characterMaximumLength":255,"#,
+            r#""precision":"10","scale":2.0,"createdAt":1700000000000,"comment":"old"}"#
+        );
+        let field: Field = serde_json::from_str(payload).unwrap();
+        assert_eq!(field.id, "7");
+        assert_eq!(field.r#type.name, "numeric");
+        assert!(field.primary_key);
+        assert_eq!(field.increment, None);
+        assert_eq!(field.default, None);
+        assert_eq!(field.precision, Some(10));
+        assert_eq!(serde_json::to_string(&field).unwrap(), payload);
+    }
+
+    #[test]
+    fn odd_indexes_round_trip_unchanged() {
+        let payload = r#"{"id":"i1","name":"idx","unique":1,"fieldIds":["f1",2,null,{"id":"f3"}],"extra":[1]}"#;
+        let index: Index = serde_json::from_str(payload).unwrap();
+        assert_eq!(index.field_ids, ["f1", "2"]);
+        assert!(index.unique);
+        assert_eq!(serde_json::to_string(&index).unwrap(), payload);
+    }
+
+    #[test]
+    fn changed_values_replace_their_original_spelling() {
+        let mut field: Field =
+            serde_json::from_value(json!({ "id": 7, "type": "int", "nullable": "no" })).unwrap();
+        field.id = "f7".to_string();
+        field.r#type = FieldType {
+            id: "bigint".to_string(),
+            name: "bigint".to_string(),
+            extra: Default::default(),
+        };
+        field.nullable = false;
+        assert_eq!(
+            serde_json::to_value(&field).unwrap(),
+            json!({
+                "id": "f7",
+                "name": "",
+                "type": { "id": "bigint", "name": "bigint" },
+                "primaryKey": false,
+                "unique": false,
+                "nullable": false,
+            })
+        );
+    }
+
+    #[test]
+    fn entries_that_are_not_objects_are_kept() {
+        let table: Table = serde_json::from_value(json!({
+            "id": "t1",
+            "diagramId": "d1",
+            "name": "t",
+            "schema": null,
+            "x": null,
+            "y": null,
+            "width": null,
+            "color": null,
+            "comment": null,
+            "isView": null,
+            "isMaterializedView": null,
+            "order": null,
+            "fields": [{ "id": "f1" }, "junk", 3],
+            "indexes": [null],
+        }))
+        .unwrap();
+        assert_eq!(table.fields.len(), 3);
+        assert_eq!(table.fields[1].raw, Some(json!("junk")));
+        let value = serde_json::to_value(&table).unwrap();
+        assert_eq!(value["fields"][1], json!("junk"));
+        assert_eq!(value["fields"][2], json!(3));
+        assert_eq!(value["indexes"], json!([null]));
+    }
+
+    #[test]
+    fn composite_attribute_types_keep_their_shape() {
+        let payload = r#"{"field":"amount","type":{"id":"numeric","name":"numeric"}}"#;
+        let field: CustomTypeField = serde_json::from_str(payload).unwrap();
+        assert_eq!(field.r#type, "numeric");
+        assert_eq!(serde_json::to_string(&field).unwrap(), payload);
+    }
+
+    #[test]
+    fn odd_enum_labels_round_trip_unchanged() {
+        let payload = r#"{"id":"c1","diagramId":"d1","schema":null,"type":"mood","kind":"enum","values":["happy",1,null,{"x":1}]}"#;
+        let mut custom_type: CustomType = serde_json::from_str(payload).unwrap();
+        assert_eq!(
+            custom_type.values,
+            Some(vec!["happy".to_string(), "1".to_string()])
+        );
+        assert_eq!(serde_json::to_string(&custom_type).unwrap(), payload);
+        assert_eq!(
+            custom_type.values_json(),
+            Some(json!(["happy", 1, null, { "x": 1 }]))
+        );
+
+        custom_type.values = Some(vec!["sad".to_string()]);
+        assert_eq!(custom_type.values_json(), Some(json!(["sad"])));
+    }
+
+    #[test]
+    fn new_ids_use_the_chartdb_alphabet() {
+        let id = new_id();
+        assert_eq!(id.len(), 25);
+        assert!(id
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase()));
+    }
+}
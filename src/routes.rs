@@ -1,14 +1,129 @@
-use axum::{routing::get, Router};
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
 use sqlx::PgPool;
 
+use crate::db::Entity;
 use crate::handlers;
+use crate::models::{Area, CustomType, Dependency, Note, Relationship, Table};
 
 pub fn create_router(pool: PgPool) -> Router {
-    Router::new()
+    let router = Router::new()
         .route("/health", get(handlers::health))
-        .route("/api/sync/push", axum::routing::post(handlers::push_diagram))
+        .route("/api/sync/push", post(handlers::push_diagram))
+        .route("/api/sync/push/changes", post(handlers::push_changes))
         .route("/api/sync/pull/:id", get(handlers::pull_diagram))
+        .route("/api/sync/changes/:id", get(handlers::pull_changes))
         .route("/api/sync/diagrams", get(handlers::list_diagrams))
-        .with_state(pool)
+        .route(
+            "/api/diagrams/:id",
+            delete(handlers::delete_diagram).patch(handlers::update_diagram),
+        )
+        .route(
+            "/api/diagrams/:id/duplicate",
+            post(handlers::duplicate_diagram),
+        )
+        .route("/api/diagrams/:id/revisions", get(handlers::list_revisions))
+        .route(
+            "/api/diagrams/:id/revisions/:version",
+            get(handlers::get_revision),
+        )
+        .route(
+            "/api/diagrams/:id/revisions/:version/restore",
+            post(handlers::restore_revision),
+        )
+        .route("/api/diagrams/:id/diff", get(handlers::diff_diagrams))
+        .route(
+            "/api/diagrams/:id/migration",
+            get(handlers::migration_script),
+        )
+        .route("/api/diagrams/:id/export/sql", get(handlers::export_sql))
+        .route("/api/diagrams/:id/export/dbml", get(handlers::export_dbml))
+        .route(
+            "/api/diagrams/:id/export/mermaid",
+            get(handlers::export_mermaid),
+        )
+        .route(
+            "/api/diagrams/:id/export/plantuml",
+            get(handlers::export_plantuml),
+        )
+        .route("/api/diagrams/:id/export/dot", get(handlers::export_dot))
+        .route(
+            "/api/diagrams/:id/export/dictionary",
+            get(handlers::export_dictionary),
+        )
+        .route("/api/diagrams/:id/export/orm", get(handlers::export_orm))
+        .route("/api/diagrams/:id/render.svg", get(handlers::render_svg))
+        .route("/api/diagrams/:id/render.png", get(handlers::render_png))
+        .route("/api/diagrams/:id/drift", post(handlers::check_drift))
+        .route("/api/diagrams/:id/lint", get(handlers::lint_diagram))
+        .route(
+            "/api/diagrams/:id/lint/rules",
+            get(handlers::lint_rules).put(handlers::update_lint_rules),
+        )
+        .route("/api/diagrams/import/dbml", post(handlers::import_dbml))
+        .route("/api/diagrams/import/sql", post(handlers::import_sql))
+        // Outside `/api/diagrams/`: its last segment would read as a diagram id
+        .route("/api/introspect", post(handlers::introspect_database));
+
+    let router = entity_routes::<Table>(router, "tables");
+    let router = entity_routes::<Relationship>(router, "relationships");
+    let router = entity_routes::<Dependency>(router, "dependencies");
+    let router = entity_routes::<Area>(router, "areas");
+    let router = entity_routes::<CustomType>(router, "custom-types");
+    let router = entity_routes::<Note>(router, "notes");
+
+    router.with_state(pool)
+}
+
+/// Routes for reading and writing one kind of child entity on its own.
+fn entity_routes<T: Entity>(router: Router<PgPool>, collection: &str) -> Router<PgPool> {
+    let path = format!("/api/diagrams/:id/{}", collection);
+    router
+        .route(
+            &path,
+            get(handlers::list_entities::<T>).post(handlers::create_entity::<T>),
+        )
+        .route(
+            &format!("{}/:entity_id", path),
+            get(handlers::get_entity::<T>)
+                .put(handlers::replace_entity::<T>)
+                .delete(handlers::delete_entity::<T>),
+        )
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{call, offline};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn imports_live_beside_diagram_ids() {
+        let pool = offline();
+        // Both reach their handler, which rejects the input before storing
+        let (status, _) = call(
+            &pool,
+            Method::POST,
+            "/api/diagrams/import/sql",
+            Some(json!({ "sql": "", "dialect": "oracle" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(
+            &pool,
+            Method::POST,
+            "/api/diagrams/import/dbml",
+            Some(json!({ "dbml": "Table {" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // A diagram called `import` still has its own routes
+        let (status, _) = call(&pool, Method::GET, "/api/diagrams/import/dbml", None).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        let (status, _) = call(&pool, Method::GET, "/api/diagrams/import/tables", None).await;
+        assert_ne!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,373 @@
+//! Flattened views of the ChartDB field, index and custom type shapes in
+//! `models`, and writers for diagrams built on the server.
+
+use crate::models::{CustomType, Field, FieldType, Index, Table};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub nullable: bool,
+    /// Auto-incrementing column (`increment` in ChartDB).
+    pub increment: bool,
+    /// An array of `type_name` (`isArray` in ChartDB).
+    pub is_array: bool,
+    pub default: Option<String>,
+    pub character_maximum_length: Option<String>,
+    pub precision: Option<i64>,
+    pub scale: Option<i64>,
+    pub collation: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl FieldInfo {
+    /// The column type including its length or precision, e.g. `varchar(255)`
+    /// or `numeric(10,2)[]`.
+    pub fn type_sql(&self) -> String {
+        let element = self.element_type_sql();
+        if self.is_array {
+            format!("{}[]", element)
+        } else {
+            element
+        }
+    }
+
+    /// [`Self::type_sql`] without the array suffix.
+    pub fn element_type_sql(&self) -> String {
+        if let Some(length) = &self.character_maximum_length {
+            return format!("{}({})", self.type_name, length);
+        }
+        match (self.precision, self.scale) {
+            (Some(p), Some(s)) => format!("{}({},{})", self.type_name, p, s),
+            (Some(p), None) => format!("{}({})", self.type_name, p),
+            _ => self.type_name.clone(),
+        }
+    }
+
+    /// Sets the type from its name and the arguments written after it, e.g.
+    /// `varchar` and `["255"]`. Lengths and precision/scale go to the keys
+    /// ChartDB keeps them in; other arguments stay part of the type name.
+    pub fn set_type(&mut self, name: &str, args: &[String]) {
+        self.type_name = name.to_string();
+        self.character_maximum_length = None;
+        self.precision = None;
+        self.scale = None;
+        let base = name.to_ascii_lowercase();
+        match args {
+            [] => {}
+            [length] if is_sized(&base) => self.character_maximum_length = Some(length.clone()),
+            [precision, rest @ ..]
+                if matches!(base.as_str(), "numeric" | "decimal" | "number")
+                    && precision.parse::<i64>().is_ok() =>
+            {
+                self.precision = precision.parse().ok();
+                self.scale = rest.first().and_then(|s| s.parse().ok());
+            }
+            args => self.type_name = format!("{}({})", name, args.join(",")),
+        }
+    }
+
+    /// Sets the type from its SQL spelling, e.g. `VARCHAR(255)` or
+    /// `numeric(10,2)[]`, lower-cased the way ChartDB stores built-in types.
+    /// Quoted (custom) type names keep their case.
+    pub fn set_type_sql(&mut self, sql: &str) {
+        let (text, array) = match sql.strip_suffix("[]") {
+            Some(element) => (element, true),
+            None => (sql, false),
+        };
+        let (base, args) = match (text.find('('), text.rfind(')')) {
+            // Arguments in the middle, as in `timestamp(3) with time zone`,
+            // stay part of the name
+            (Some(open), Some(close)) if open < close && close == text.len() - 1 => {
+                (text[..open].trim(), split_args(&text[open + 1..close]))
+            }
+            _ => (text, Vec::new()),
+        };
+        let base = if base.contains('"') || base.contains('`') {
+            base.replace(['"', '`'], "")
+        } else {
+            base.to_lowercase()
+        };
+        self.set_type(&base, &args);
+        self.is_array = array;
+    }
+
+    /// The field in the shape ChartDB stores in `Table.fields`.
+    pub fn to_field(&self, created_at: i64) -> Field {
+        Field {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            r#type: FieldType {
+                id: self.type_name.to_ascii_lowercase().replace(' ', "_"),
+                name: self.type_name.clone(),
+                extra: Default::default(),
+            },
+            primary_key: self.primary_key,
+            unique: self.unique,
+            nullable: self.nullable,
+            increment: self.increment.then_some(true),
+            is_array: self.is_array.then_some(true),
+            default: self.default.clone(),
+            collation: self.collation.clone(),
+            comments: self.comment.clone(),
+            character_maximum_length: self.character_maximum_length.clone(),
+            precision: self.precision,
+            scale: self.scale,
+            created_at: Some(created_at),
+            extra: Default::default(),
+            raw: None,
+        }
+    }
+}
+
+impl From<&Field> for FieldInfo {
+    fn from(f: &Field) -> Self {
+        // Diagrams stored before `isArray` existed kept the suffix in the name
+        let (type_name, suffixed) = match f.r#type.name.strip_suffix("[]") {
+            Some(element) => (element.to_string(), true),
+            None => (f.r#type.name.clone(), false),
+        };
+        FieldInfo {
+            id: f.id.clone(),
+            name: f.name.clone(),
+            type_name,
+            primary_key: f.primary_key,
+            unique: f.unique,
+            nullable: f.nullable,
+            increment: f.increment.unwrap_or(false),
+            is_array: f.is_array.unwrap_or(false) || suffixed,
+            default: f.default.clone(),
+            character_maximum_length: f.character_maximum_length.clone(),
+            precision: f.precision,
+            scale: f.scale,
+            collation: f.collation.clone(),
+            // Some older payloads spell it `comment`
+            comment: f.comments.clone().or_else(|| {
+                f.extra
+                    .get("comment")
+                    .and_then(|c| c.as_str())
+                    .map(str::to_string)
+            }),
+        }
+    }
+}
+
+/// Splits type arguments at the commas outside of string literals.
+fn split_args(args: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in args.chars() {
+        match c {
+            ',' if !quoted => parts.push(std::mem::take(&mut current).trim().to_string()),
+            _ => {
+                if c == '\'' {
+                    quoted = !quoted;
+                }
+                current.push(c);
+            }
+        }
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+/// Types whose single argument is a length.
+fn is_sized(base: &str) -> bool {
+    matches!(
+        base,
+        "varchar"
+            | "character varying"
+            | "char"
+            | "character"
+            | "bpchar"
+            | "nvarchar"
+            | "nchar"
+            | "varbinary"
+            | "binary"
+            | "bit"
+            | "varbit"
+            | "bit varying"
+    )
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexInfo {
+    pub id: String,
+    pub name: String,
+    pub unique: bool,
+    pub field_ids: Vec<String>,
+}
+
+impl IndexInfo {
+    /// The index in the shape ChartDB stores in `Table.indexes`.
+    pub fn to_index(&self, created_at: i64) -> Index {
+        Index {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            unique: self.unique,
+            field_ids: self.field_ids.clone(),
+            created_at: Some(created_at),
+            extra: Default::default(),
+            raw: None,
+        }
+    }
+}
+
+impl From<&Index> for IndexInfo {
+    fn from(i: &Index) -> Self {
+        IndexInfo {
+            id: i.id.clone(),
+            name: i.name.clone(),
+            unique: i.unique,
+            field_ids: i.field_ids.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompositeField {
+    pub field: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+/// The fields of a table, leaving out entries that are not objects.
+pub fn fields(table: &Table) -> Vec<FieldInfo> {
+    table
+        .fields
+        .iter()
+        .filter(|f| f.raw.is_none())
+        .map(FieldInfo::from)
+        .collect()
+}
+
+pub fn indexes(table: &Table) -> Vec<IndexInfo> {
+    table
+        .indexes
+        .iter()
+        .filter(|i| i.raw.is_none())
+        .map(IndexInfo::from)
+        .collect()
+}
+
+pub fn enum_values(custom_type: &CustomType) -> Vec<String> {
+    custom_type.values.clone().unwrap_or_default()
+}
+
+pub fn composite_fields(custom_type: &CustomType) -> Vec<CompositeField> {
+    custom_type
+        .fields
+        .iter()
+        .flatten()
+        .filter(|f| f.raw.is_none())
+        .map(|f| CompositeField {
+            field: f.field.clone(),
+            type_name: f.r#type.clone(),
+        })
+        .collect()
+}
+
+/// The schema-qualified display name of a table, e.g. `public.users`.
+pub fn qualified_name(schema: Option<&str>, name: &str) -> String {
+    match schema {
+        Some(schema) if !schema.is_empty() => format!("{}.{}", schema, name),
+        _ => name.to_string(),
+    }
+}
+
+/// Whether two objects have the same name. Those without a schema are in
+/// `public`.
+pub fn same_name(schema_a: &Option<String>, a: &str, schema_b: &Option<String>, b: &str) -> bool {
+    a == b && schema_or_default(schema_a) == schema_or_default(schema_b)
+}
+
+pub fn schema_or_default(schema: &Option<String>) -> &str {
+    schema
+        .as_deref()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("public")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(sql: &str) -> FieldInfo {
+        let mut field = FieldInfo::default();
+        field.set_type_sql(sql);
+        field
+    }
+
+    #[test]
+    fn array_types_round_trip() {
+        for sql in ["varchar(255)[]", "numeric(10,2)[]", "integer[]"] {
+            let field = parsed(sql);
+            assert!(field.is_array, "{}", sql);
+            assert_eq!(field.type_sql(), sql);
+        }
+        let field = parsed("numeric(10,2)[]");
+        assert_eq!(field.type_name, "numeric");
+        assert_eq!((field.precision, field.scale), (Some(10), Some(2)));
+        assert_eq!(field.element_type_sql(), "numeric(10,2)");
+    }
+
+    #[test]
+    fn array_flag_survives_the_chartdb_shape() {
+        let field = parsed("VARCHAR(255)[]");
+        let stored = field.to_field(0);
+        assert_eq!(stored.r#type.name, "varchar");
+        assert_eq!(stored.is_array, Some(true));
+        assert_eq!(FieldInfo::from(&stored), field);
+    }
+
+    #[test]
+    fn legacy_array_suffix_in_type_name_is_read_as_flag() {
+        let mut stored = parsed("integer").to_field(0);
+        stored.r#type.name = "integer[]".to_string();
+        let field = FieldInfo::from(&stored);
+        assert_eq!(field.type_name, "integer");
+        assert!(field.is_array);
+        assert_eq!(field.type_sql(), "integer[]");
+    }
+
+    #[test]
+    fn type_arguments() {
+        let field = parsed("VARCHAR(255)");
+        assert_eq!(field.type_name, "varchar");
+        assert_eq!(field.character_maximum_length.as_deref(), Some("255"));
+        assert_eq!(parsed("numeric(10)").precision, Some(10));
+        assert_eq!(parsed("\"MyType\"").type_name, "MyType");
+        // Arguments in the middle stay part of the name
+        assert_eq!(
+            parsed("timestamp(3) with time zone").type_name,
+            "timestamp(3) with time zone"
+        );
+        // Unknown arguments are kept in the name
+        assert_eq!(
+            parsed("geometry(Point, 4326)").type_name,
+            "geometry(Point,4326)"
+        );
+    }
+
+    #[test]
+    fn split_args_keeps_commas_in_literals() {
+        assert_eq!(split_args("10, 2"), ["10", "2"]);
+        assert_eq!(split_args("'a,b', 'c'"), ["'a,b'", "'c'"]);
+        assert_eq!(split_args(""), [""]);
+    }
+
+    #[test]
+    fn qualified_names() {
+        assert_eq!(qualified_name(Some("public"), "users"), "public.users");
+        assert_eq!(qualified_name(Some(""), "users"), "users");
+        assert_eq!(qualified_name(None, "users"), "users");
+    }
+}
@@ -0,0 +1,279 @@
+//! Referential integrity checks run on a diagram before it is stored, so a
+//! client with corrupt local state cannot write dangling references into the
+//! server copy.
+//!
+//! Problems are located with JSON-pointer-style paths that address entities
+//! by id, the same way merge conflicts do: `/relationships/r1/sourceFieldId`
+//! or `/tables/t1/indexes/i1/fieldIds/0`. Only arrays of plain values, such as
+//! `fieldIds`, are addressed by position.
+
+use crate::models::{Diagram, Table};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+pub fn diagram(diagram: &Diagram) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |path: String, message: String| problems.push(Problem { path, message });
+
+    let tables = diagram.tables.as_deref().unwrap_or_default();
+    let mut by_id: HashMap<&str, &Table> = HashMap::new();
+    for table in tables {
+        by_id.entry(table.id.as_str()).or_insert(table);
+    }
+
+    // Every child must belong to this diagram and have an id of its own.
+    let children = [
+        ("tables", owners(tables, |t| (&t.id, &t.diagram_id))),
+        (
+            "relationships",
+            owners(diagram.relationships.as_deref().unwrap_or_default(), |r| {
+                (&r.id, &r.diagram_id)
+            }),
+        ),
+        (
+            "dependencies",
+            owners(diagram.dependencies.as_deref().unwrap_or_default(), |d| {
+                (&d.id, &d.diagram_id)
+            }),
+        ),
+        (
+            "areas",
+            owners(diagram.areas.as_deref().unwrap_or_default(), |a| {
+                (&a.id, &a.diagram_id)
+            }),
+        ),
+        (
+            "customTypes",
+            owners(diagram.custom_types.as_deref().unwrap_or_default(), |c| {
+                (&c.id, &c.diagram_id)
+            }),
+        ),
+        (
+            "notes",
+            owners(diagram.notes.as_deref().unwrap_or_default(), |n| {
+                (&n.id, &n.diagram_id)
+            }),
+        ),
+    ];
+    for (collection, entities) in children {
+        let mut seen = HashSet::new();
+        for (id, diagram_id) in entities {
+            let path = format!("/{}/{}", collection, escape(id));
+            if id.is_empty() {
+                report(path.clone(), "Entity has no id".to_string());
+            } else if !seen.insert(id) {
+                report(path.clone(), format!("Duplicate id `{}`", id));
+            }
+            if diagram_id != &diagram.id {
+                report(
+                    format!("{}/diagramId", path),
+                    format!("Belongs to diagram `{}`, not `{}`", diagram_id, diagram.id),
+                );
+            }
+        }
+    }
+
+    for table in tables {
+        let path = format!("/tables/{}", escape(&table.id));
+        let mut fields = HashSet::new();
+        // Entries that are not objects are kept as the client sent them (see
+        // `Field::raw`); they have no id to check or refer to.
+        for field in table.fields.iter().filter(|f| f.raw.is_none()) {
+            let field_path = format!("{}/fields/{}", path, escape(&field.id));
+            if field.id.is_empty() {
+                report(field_path, "Field has no id".to_string());
+            } else if !fields.insert(field.id.as_str()) {
+                report(field_path, format!("Duplicate field id `{}`", field.id));
+            }
+        }
+        for index in table.indexes.iter().filter(|i| i.raw.is_none()) {
+            let index_path = format!("{}/indexes/{}", path, escape(&index.id));
+            for (i, field_id) in index.field_ids.iter().enumerate() {
+                if !fields.contains(field_id.as_str()) {
+                    report(
+                        format!("{}/fieldIds/{}", index_path, i),
+                        format!("No field `{}` in table `{}`", field_id, table.name),
+                    );
+                }
+            }
+        }
+    }
+
+    for rel in diagram.relationships.iter().flatten() {
+        let path = format!("/relationships/{}", escape(&rel.id));
+        let ends = [
+            ("source", &rel.source_table_id, &rel.source_field_id),
+            ("target", &rel.target_table_id, &rel.target_field_id),
+        ];
+        for (end, table_id, field_id) in ends {
+            let Some(table) = by_id.get(table_id.as_str()) else {
+                report(
+                    format!("{}/{}TableId", path, end),
+                    format!("No table `{}`", table_id),
+                );
+                continue;
+            };
+            if let Some(field_id) = field_id {
+                if !table.fields.iter().any(|f| &f.id == field_id) {
+                    report(
+                        format!("{}/{}FieldId", path, end),
+                        format!("No field `{}` in table `{}`", field_id, table.name),
+                    );
+                }
+            }
+        }
+    }
+
+    for dep in diagram.dependencies.iter().flatten() {
+        let path = format!("/dependencies/{}", escape(&dep.id));
+        for (key, table_id) in [
+            ("tableId", &dep.table_id),
+            ("dependentTableId", &dep.dependent_table_id),
+        ] {
+            if !by_id.contains_key(table_id.as_str()) {
+                report(
+                    format!("{}/{}", path, key),
+                    format!("No table `{}`", table_id),
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+/// The problems of `after` that `before` does not have already, so that a
+/// change is only held to what it breaks itself and not to problems the
+/// stored copy was written with.
+pub fn introduced(before: Option<&Diagram>, after: &Diagram) -> Vec<Problem> {
+    let problems = diagram(after);
+    if problems.is_empty() {
+        return problems;
+    }
+    let existing: HashSet<Problem> = before
+        .map(diagram)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    problems
+        .into_iter()
+        .filter(|p| !existing.contains(p))
+        .collect()
+}
+
+/// The id and owning diagram id of each entity in a collection.
+fn owners<T>(items: &[T], key: impl Fn(&T) -> (&String, &String)) -> Vec<(&String, &String)> {
+    items.iter().map(key).collect()
+}
+
+/// Escapes an id for use as a JSON pointer segment.
+pub fn escape(id: &str) -> String {
+    id.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{column, dependency, relationship, shop, table};
+    use crate::models::{Field, Index};
+    use serde_json::json;
+
+    fn paths(diagram: &Diagram) -> Vec<String> {
+        super::diagram(diagram)
+            .into_iter()
+            .map(|p| p.path)
+            .collect()
+    }
+
+    #[test]
+    fn a_consistent_diagram_passes() {
+        assert!(paths(&shop()).is_empty());
+    }
+
+    #[test]
+    fn dangling_references_are_located_by_id() {
+        let mut diagram = shop();
+        diagram.relationships.as_mut().unwrap().push(relationship(
+            "r_lost",
+            ("t_orders", "f_gone"),
+            ("t_gone", "f_users_id"),
+        ));
+        diagram.dependencies = Some(vec![dependency("d_lost", "t_users", "t_gone")]);
+        diagram.tables.as_mut().unwrap()[1].indexes[0]
+            .field_ids
+            .push("f_gone".to_string());
+
+        assert_eq!(
+            paths(&diagram),
+            [
+                "/tables/t_orders/indexes/i_orders_user/fieldIds/1",
+                "/relationships/r_lost/sourceFieldId",
+                "/relationships/r_lost/targetTableId",
+                "/dependencies/d_lost/dependentTableId",
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_must_be_unique_and_belong_to_the_diagram() {
+        let mut diagram = shop();
+        let tables = diagram.tables.as_mut().unwrap();
+        tables.push(table(
+            "t_users",
+            "people",
+            &[column("f/1", "a", "text"), column("f/1", "b", "text")],
+        ));
+        tables[1].diagram_id = "other".to_string();
+
+        assert_eq!(
+            paths(&diagram),
+            [
+                "/tables/t_orders/diagramId",
+                "/tables/t_users",
+                "/tables/t_users/fields/f~11",
+            ]
+        );
+    }
+
+    #[test]
+    fn entries_that_are_not_objects_pass() {
+        let mut diagram = shop();
+        let users = &mut diagram.tables.as_mut().unwrap()[0];
+        users.fields.push(Field {
+            raw: Some(json!("email2")),
+            ..Field::default()
+        });
+        users.indexes.push(Index {
+            raw: Some(json!(null)),
+            ..Index::default()
+        });
+        assert!(paths(&diagram).is_empty());
+    }
+
+    #[test]
+    fn only_new_problems_are_introduced() {
+        let mut before = shop();
+        before.relationships.as_mut().unwrap()[0].target_table_id = "t_gone".to_string();
+        let mut after = before.clone();
+        after.dependencies = Some(vec![dependency("dep", "t_users", "t_view")]);
+
+        let new: Vec<String> = introduced(Some(&before), &after)
+            .into_iter()
+            .map(|p| p.path)
+            .collect();
+        assert_eq!(new, ["/dependencies/dep/dependentTableId"]);
+        assert_eq!(introduced(None, &before).len(), 1);
+        assert!(introduced(Some(&before), &before).is_empty());
+    }
+
+    #[test]
+    fn escapes_pointer_segments() {
+        assert_eq!(escape("a/b~c"), "a~1b~0c");
+    }
+}